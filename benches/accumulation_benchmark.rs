@@ -0,0 +1,47 @@
+use bempp::boundary_assemblers::{BoundaryAssemblerOptions, DenseAccumulation};
+use bempp::function::FunctionSpace;
+use bempp::function::FunctionSpaceTrait;
+use bempp::laplace::assembler::single_layer;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ndelement::ciarlet::LagrangeElementFamily;
+use ndelement::types::{Continuity, ReferenceCellType};
+
+pub fn accumulation_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("accumulation");
+    group.sample_size(10);
+
+    let _ = mpi::initialize().unwrap();
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+
+    for i in 3..5 {
+        let grid = bempp::shapes::regular_sphere(i, 1, &comm);
+        let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+
+        let space = FunctionSpace::new(&grid, &element);
+
+        for (name, accumulation) in [
+            ("colouring", DenseAccumulation::Colouring),
+            ("tiled", DenseAccumulation::Tiled),
+        ] {
+            let mut options = BoundaryAssemblerOptions::default();
+            options.set_regular_quadrature_degree(ReferenceCellType::Triangle, 16);
+            options.set_batch_size(128);
+            options.set_dense_accumulation(accumulation);
+
+            let assembler = single_layer(&options);
+
+            group.bench_function(
+                format!(
+                    "Assembly of {}x{} P1 matrix using {name} accumulation",
+                    space.global_size(),
+                    space.global_size()
+                ),
+                |b| b.iter(|| black_box(assembler.assemble(&space, &space))),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, accumulation_benchmark);
+criterion_main!(benches);
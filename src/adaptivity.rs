@@ -0,0 +1,527 @@
+//! A posteriori error estimation and adaptive refinement
+//!
+//! The error indicators in this module are averaging (ZZ-type) estimators: the discrete solution (or its surface
+//! gradient) is compared to a smoothed version of itself computed by averaging at the vertices of the grid. The square
+//! of the indicator for each cell is the squared L2 norm of this difference on the cell multiplied by the local mesh
+//! size h = sqrt(area), so that the sum of the squared indicators estimates the squared error in the energy norm of the
+//! single layer (H^{-1/2}) or hypersingular (H^{1/2}) operator.
+use crate::boundary_assemblers::BoundaryAssemblerOptions;
+use crate::function::{is_excluded, FunctionSpace, FunctionSpaceTrait};
+use crate::laplace;
+use crate::refinement::{refine_marked, RefinedGrid};
+use crate::solvers::{cg, SolverOptions};
+use mpi::traits::{Communicator, Equivalence};
+use ndelement::ciarlet::LagrangeElementFamily;
+use ndelement::quadrature::simplex_rule;
+use ndelement::traits::FiniteElement;
+use ndelement::types::{Continuity, ReferenceCellType};
+use ndgrid::traits::{Entity, GeometryMap, Grid, Topology};
+use ndgrid::types::RealScalar;
+use num::{Float, One, Zero};
+use rlst::{
+    rlst_dynamic_array2, rlst_dynamic_array4, MatrixInverse, RandomAccessByRef, RandomAccessMut,
+    RlstScalar,
+};
+
+/// Number of quadrature points used on each cell when computing error indicators and right-hand sides
+const NPOINTS: usize = 12;
+
+/// Quadrature points, weights and geometry on a cell
+struct CellQuadrature<T: RlstScalar> {
+    /// Quadrature points on the reference cell, stored as [x0, y0, x1, y1, ...]
+    points: Vec<T::Real>,
+    /// Quadrature weights multiplied by the Jacobian determinant at each point
+    weights: Vec<T::Real>,
+    /// Jacobian at each point, where jacobians[6 * p + i + 3 * j] is the derivative of x_i with respect to X_j
+    jacobians: Vec<T::Real>,
+}
+
+impl<T: RlstScalar> CellQuadrature<T> {
+    /// Compute the quadrature data for every cell of a triangle grid
+    fn compute<G: Grid<T = T::Real, EntityDescriptor = ReferenceCellType>>(grid: &G) -> Vec<Self> {
+        for cell_type in grid.entity_types(2) {
+            if *cell_type != ReferenceCellType::Triangle {
+                panic!("Error indicators are only implemented for triangle grids");
+            }
+        }
+        let rule = simplex_rule(ReferenceCellType::Triangle, NPOINTS).unwrap();
+        let points = rule
+            .points
+            .iter()
+            .map(|p| num::cast::<f64, T::Real>(*p).unwrap())
+            .collect::<Vec<_>>();
+        let evaluator = grid.geometry_map(ReferenceCellType::Triangle, &points);
+
+        let mut jdets = vec![T::Real::zero(); NPOINTS];
+        let mut normals = vec![T::Real::zero(); 3 * NPOINTS];
+        let mut data = (0..grid.entity_count(ReferenceCellType::Triangle))
+            .map(|_| Self {
+                points: points.clone(),
+                weights: vec![],
+                jacobians: vec![T::Real::zero(); 6 * NPOINTS],
+            })
+            .collect::<Vec<_>>();
+        for cell in grid.entity_iter(2) {
+            let q = &mut data[cell.local_index()];
+            evaluator.jacobians_dets_normals(
+                cell.local_index(),
+                &mut q.jacobians,
+                &mut jdets,
+                &mut normals,
+            );
+            q.weights = rule
+                .weights
+                .iter()
+                .zip(&jdets)
+                .map(|(w, j)| num::cast::<f64, T::Real>(*w).unwrap() * *j)
+                .collect();
+        }
+        data
+    }
+
+    /// The area of the cell
+    fn area(&self) -> T::Real {
+        self.weights.iter().fold(T::Real::zero(), |a, w| a + *w)
+    }
+
+    /// The values of the linear hat functions at a quadrature point
+    fn hat_functions(&self, point: usize) -> [T::Real; 3] {
+        let x = self.points[2 * point];
+        let y = self.points[2 * point + 1];
+        [T::Real::one() - x - y, x, y]
+    }
+
+    /// The surface gradient at a quadrature point of the function with the given values at the vertices
+    fn surface_gradient(&self, point: usize, values: &[T]) -> [T; 3] {
+        let j = &self.jacobians[6 * point..6 * point + 6];
+        let g00 = j[0] * j[0] + j[1] * j[1] + j[2] * j[2];
+        let g01 = j[0] * j[3] + j[1] * j[4] + j[2] * j[5];
+        let g11 = j[3] * j[3] + j[4] * j[4] + j[5] * j[5];
+        let det = g00 * g11 - g01 * g01;
+        let du = [values[1] - values[0], values[2] - values[0]];
+        let a = (du[0] * T::from_real(g11) - du[1] * T::from_real(g01)) / T::from_real(det);
+        let b = (du[1] * T::from_real(g00) - du[0] * T::from_real(g01)) / T::from_real(det);
+        [0, 1, 2].map(|i| a * T::from_real(j[i]) + b * T::from_real(j[3 + i]))
+    }
+}
+
+/// Check that a space is stored in serial, is defined on every cell and has the given number of DOFs on each cell
+fn check_space<Space: FunctionSpaceTrait>(space: &Space, dofs_per_cell: usize, name: &str) {
+    if !space.is_serial() {
+        panic!("Error indicators can only be computed for function spaces stored in serial");
+    }
+    if (0..space.grid().entity_count(ReferenceCellType::Triangle))
+        .any(|cell| space.cell_dofs(cell).unwrap_or(&[]).is_empty())
+    {
+        panic!("Error indicators can only be computed for function spaces defined on every cell");
+    }
+    for cell_type in space.grid().entity_types(2) {
+        for degree in space.cell_type_degrees(*cell_type) {
+            if space.element_with_degree(*cell_type, degree).dim() != dofs_per_cell {
+                panic!("Error indicators for {name} can only be computed for spaces with {dofs_per_cell} DOF(s) per cell");
+            }
+        }
+    }
+}
+
+/// Compute error indicators for the single layer equation
+///
+/// `space` must be a piecewise constant (DP0) space on a triangle grid and `coefficients` must be the coefficients of
+/// the discrete solution. The piecewise constant solution is compared to the continuous piecewise linear function
+/// obtained by taking the area-weighted average of the solution around each vertex. The returned vector contains the
+/// indicator for each cell, indexed by the cells' local indices.
+pub fn single_layer_indicators<T: RlstScalar, Space: FunctionSpaceTrait<T = T>>(
+    space: &Space,
+    coefficients: &[T],
+) -> Vec<T::Real> {
+    check_space(space, 1, "the single layer equation");
+    assert_eq!(coefficients.len(), space.global_size());
+    let grid = space.grid();
+    let quadrature = CellQuadrature::<T>::compute(grid);
+
+    let cells = grid
+        .entity_iter(2)
+        .map(|cell| {
+            let index = cell.local_index();
+            let dof = space.global_dof_index(space.cell_dofs(index).unwrap()[0]);
+            (
+                index,
+                cell.topology().sub_entity_iter(0).collect::<Vec<_>>(),
+                coefficients[dof],
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Average the solution at each vertex
+    let nvertices = grid.entity_count(ReferenceCellType::Point);
+    let mut averages = vec![T::zero(); nvertices];
+    let mut areas = vec![T::Real::zero(); nvertices];
+    for (index, vertices, value) in &cells {
+        let area = quadrature[*index].area();
+        for v in vertices {
+            averages[*v] += T::from_real(area) * *value;
+            areas[*v] += area;
+        }
+    }
+    for (a, area) in averages.iter_mut().zip(&areas) {
+        *a /= T::from_real(*area);
+    }
+
+    let mut indicators = vec![T::Real::zero(); cells.len()];
+    for (index, vertices, value) in &cells {
+        let q = &quadrature[*index];
+        let mut error = T::Real::zero();
+        for (p, w) in q.weights.iter().enumerate() {
+            let smoothed = q
+                .hat_functions(p)
+                .iter()
+                .zip(vertices)
+                .fold(T::zero(), |s, (phi, v)| {
+                    s + T::from_real(*phi) * averages[*v]
+                });
+            error += *w * (*value - smoothed).abs() * (*value - smoothed).abs();
+        }
+        indicators[*index] = Float::sqrt(Float::sqrt(q.area()) * error);
+    }
+    indicators
+}
+
+/// Compute error indicators for the hypersingular equation
+///
+/// `space` must be a continuous piecewise linear (P1) space on a triangle grid and `coefficients` must be the
+/// coefficients of the discrete solution. The piecewise constant surface gradient of the solution is compared to the
+/// continuous piecewise linear vector field obtained by taking the area-weighted average of the gradient around each
+/// vertex. The space may be zero on the boundary of an open surface. The returned vector contains the indicator for
+/// each cell, indexed by the cells' local indices.
+pub fn hypersingular_indicators<T: RlstScalar, Space: FunctionSpaceTrait<T = T>>(
+    space: &Space,
+    coefficients: &[T],
+) -> Vec<T::Real> {
+    check_space(space, 3, "the hypersingular equation");
+    assert_eq!(coefficients.len(), space.global_size());
+    let grid = space.grid();
+    let quadrature = CellQuadrature::<T>::compute(grid);
+
+    let cells = grid
+        .entity_iter(2)
+        .map(|cell| {
+            let index = cell.local_index();
+            // Basis functions that are excluded from the space are zero
+            let excluded = space.excluded_dofs(index);
+            let values = space
+                .cell_dofs(index)
+                .unwrap()
+                .iter()
+                .enumerate()
+                .map(|(i, dof)| {
+                    if is_excluded(excluded, i) {
+                        T::zero()
+                    } else {
+                        coefficients[space.global_dof_index(*dof)]
+                    }
+                })
+                .collect::<Vec<_>>();
+            (
+                index,
+                cell.topology().sub_entity_iter(0).collect::<Vec<_>>(),
+                values,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Average the mean gradient on each cell at each vertex
+    let nvertices = grid.entity_count(ReferenceCellType::Point);
+    let mut averages = vec![[T::zero(); 3]; nvertices];
+    let mut areas = vec![T::Real::zero(); nvertices];
+    for (index, vertices, values) in &cells {
+        let q = &quadrature[*index];
+        let mut integral = [T::zero(); 3];
+        for (p, w) in q.weights.iter().enumerate() {
+            for (i, g) in q.surface_gradient(p, values).iter().enumerate() {
+                integral[i] += T::from_real(*w) * *g;
+            }
+        }
+        for v in vertices {
+            for (a, i) in averages[*v].iter_mut().zip(&integral) {
+                *a += *i;
+            }
+            areas[*v] += q.area();
+        }
+    }
+    for (a, area) in averages.iter_mut().zip(&areas) {
+        for a_i in a.iter_mut() {
+            *a_i /= T::from_real(*area);
+        }
+    }
+
+    let mut indicators = vec![T::Real::zero(); cells.len()];
+    for (index, vertices, values) in &cells {
+        let q = &quadrature[*index];
+        let mut error = T::Real::zero();
+        for (p, w) in q.weights.iter().enumerate() {
+            let gradient = q.surface_gradient(p, values);
+            let phi = q.hat_functions(p);
+            for (i, g) in gradient.iter().enumerate() {
+                let smoothed = phi.iter().zip(vertices).fold(T::zero(), |s, (phi, v)| {
+                    s + T::from_real(*phi) * averages[*v][i]
+                });
+                error += *w * (*g - smoothed).abs() * (*g - smoothed).abs();
+            }
+        }
+        indicators[*index] = Float::sqrt(Float::sqrt(q.area()) * error);
+    }
+    indicators
+}
+
+/// Mark cells for refinement using Dörfler marking
+///
+/// Returns a set of cells with the largest error indicators, such that the sum of the squares of their indicators is at
+/// least `theta` times the sum of the squares of all the indicators.
+pub fn dorfler_marking<T: RealScalar>(indicators: &[T], theta: T) -> Vec<usize> {
+    let mut order = (0..indicators.len()).collect::<Vec<_>>();
+    // NaN indicators are ordered before all other values, as they are by total_cmp, so that sorting cannot panic
+    order.sort_by(|a, b| {
+        let (a, b) = (indicators[*a], indicators[*b]);
+        b.partial_cmp(&a)
+            .unwrap_or_else(|| Float::is_nan(b).cmp(&Float::is_nan(a)))
+    });
+    let total = indicators.iter().fold(T::zero(), |a, i| a + *i * *i);
+
+    let mut marked = vec![];
+    let mut sum = T::zero();
+    for cell in order {
+        if sum >= theta * total {
+            break;
+        }
+        sum += indicators[cell] * indicators[cell];
+        marked.push(cell);
+    }
+    marked
+}
+
+/// Compute the vector whose entries are the integrals of a function times each basis function of a space
+fn load_vector<T: RlstScalar, Space: FunctionSpaceTrait<T = T>>(
+    space: &Space,
+    f: &impl Fn(&[T::Real]) -> T,
+) -> Vec<T> {
+    let grid = space.grid();
+    let quadrature = CellQuadrature::<T>::compute(grid);
+    let mut output = vec![T::zero(); space.global_size()];
+
+    let points = &quadrature[0].points;
+    let mut reference_points = rlst_dynamic_array2!(T::Real, [2, NPOINTS]);
+    for p in 0..NPOINTS {
+        for j in 0..2 {
+            *reference_points.get_mut([j, p]).unwrap() = points[2 * p + j];
+        }
+    }
+    let element = space.element(ReferenceCellType::Triangle);
+    let mut table = rlst_dynamic_array4!(T, element.tabulate_array_shape(0, NPOINTS));
+    element.tabulate(&reference_points, 0, &mut table);
+
+    let evaluator = grid.geometry_map(ReferenceCellType::Triangle, points);
+    let mut mapped_points = vec![T::Real::zero(); 3 * NPOINTS];
+    for cell in grid.entity_iter(2) {
+        let index = cell.local_index();
+        let excluded = space.excluded_dofs(index);
+        evaluator.points(index, &mut mapped_points);
+        for (p, w) in quadrature[index].weights.iter().enumerate() {
+            let value = f(&mapped_points[3 * p..3 * p + 3]) * T::from_real(*w);
+            for (i, dof) in space.cell_dofs(index).unwrap().iter().enumerate() {
+                if is_excluded(excluded, i) {
+                    continue;
+                }
+                output[space.global_dof_index(*dof)] += value * *table.get([0, p, i, 0]).unwrap();
+            }
+        }
+    }
+    output
+}
+
+/// Options for an adaptive solver
+#[derive(Clone)]
+pub struct AdaptiveOptions {
+    /// Dörfler marking parameter
+    pub theta: f64,
+    /// Maximum number of solve-estimate-mark-refine steps
+    pub max_steps: usize,
+    /// The adaptive loop stops when the error estimate is below this value
+    pub tolerance: f64,
+    /// Relative tolerance used by the iterative solver
+    pub solver_tolerance: f64,
+    /// Options used when assembling operators
+    pub assembler_options: BoundaryAssemblerOptions,
+}
+
+impl Default for AdaptiveOptions {
+    fn default() -> Self {
+        Self {
+            theta: 0.5,
+            max_steps: 10,
+            tolerance: 0.0,
+            solver_tolerance: 1e-8,
+            assembler_options: BoundaryAssemblerOptions::default(),
+        }
+    }
+}
+
+impl AdaptiveOptions {
+    /// Set the Dörfler marking parameter.
+    pub fn set_theta(&mut self, theta: f64) {
+        self.theta = theta;
+    }
+
+    /// Get the Dörfler marking parameter.
+    pub fn get_theta(&self) -> f64 {
+        self.theta
+    }
+
+    /// Set the maximum number of steps.
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+
+    /// Get the maximum number of steps.
+    pub fn get_max_steps(&self) -> usize {
+        self.max_steps
+    }
+
+    /// Set the tolerance for the error estimate.
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance;
+    }
+
+    /// Get the tolerance for the error estimate.
+    pub fn get_tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    /// Set the relative tolerance used by the iterative solver.
+    pub fn set_solver_tolerance(&mut self, tolerance: f64) {
+        self.solver_tolerance = tolerance;
+    }
+
+    /// Get the relative tolerance used by the iterative solver.
+    pub fn get_solver_tolerance(&self) -> f64 {
+        self.solver_tolerance
+    }
+}
+
+/// A boundary integral equation for Laplace's equation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaplaceEquation {
+    /// The single layer equation Vφ = f, discretised using piecewise constant (DP0) functions
+    SingleLayer,
+    /// The hypersingular equation Wu = g, discretised using continuous piecewise linear (P1) functions
+    ///
+    /// On an open surface, the functions are zero on the boundary of the surface.
+    Hypersingular,
+}
+
+/// Information about one step of an adaptive loop
+#[derive(Debug, Clone)]
+pub struct AdaptiveStep<T> {
+    /// The number of cells in the grid
+    pub cells: usize,
+    /// The number of DOFs in the function space
+    pub dofs: usize,
+    /// The error estimate: the square root of the sum of the squared error indicators
+    pub estimate: T,
+    /// Did the iterative solver converge?
+    pub converged: bool,
+}
+
+/// The result of an adaptive solve
+pub struct AdaptiveSolution<'a, C: Communicator, T: RealScalar + Equivalence> {
+    /// The final grid
+    pub grid: RefinedGrid<'a, C, T>,
+    /// The coefficients of the solution on the final grid
+    pub coefficients: Vec<T>,
+    /// The error indicators of the solution on the final grid
+    pub indicators: Vec<T>,
+    /// Information about each step of the adaptive loop
+    pub history: Vec<AdaptiveStep<T>>,
+}
+
+/// Solve a Laplace boundary integral equation using adaptive refinement
+///
+/// In each step, the equation is solved on the current grid, error indicators are computed, cells are marked using
+/// Dörfler marking, then the marked cells are refined using [refine_marked]. The loop stops when the error estimate is
+/// below the tolerance or the maximum number of steps has been performed. `rhs` gives the value of the right-hand side
+/// at a point, and `projection` is passed to the refinement routine to move new points onto the exact surface.
+///
+/// For the hypersingular equation on a closed surface, the right-hand side must have zero mean. On an open surface, the
+/// hypersingular equation is solved in the space of functions that are zero on the boundary (see
+/// [FunctionSpace::new_zero_trace]).
+pub fn solve_laplace_adaptively<
+    'a,
+    T: RealScalar + RlstScalar<Real = T> + Equivalence + MatrixInverse,
+    C: Communicator,
+>(
+    equation: LaplaceEquation,
+    grid: RefinedGrid<'a, C, T>,
+    rhs: impl Fn(&[T]) -> T,
+    projection: Option<&dyn Fn(&mut [T; 3])>,
+    options: &AdaptiveOptions,
+    comm: &'a C,
+) -> AdaptiveSolution<'a, C, T> {
+    let mut grid = grid;
+    let mut history = vec![];
+    let mut solver_options = SolverOptions::default();
+    solver_options.set_tolerance(num::cast::<f64, T>(options.solver_tolerance).unwrap());
+
+    loop {
+        let family = match equation {
+            LaplaceEquation::SingleLayer => {
+                LagrangeElementFamily::<T>::new(0, Continuity::Discontinuous)
+            }
+            LaplaceEquation::Hypersingular => {
+                LagrangeElementFamily::<T>::new(1, Continuity::Standard)
+            }
+        };
+        let space = match equation {
+            LaplaceEquation::SingleLayer => FunctionSpace::new(&grid, &family),
+            LaplaceEquation::Hypersingular => FunctionSpace::new_zero_trace(&grid, &family),
+        };
+        let matrix = match equation {
+            LaplaceEquation::SingleLayer => {
+                laplace::assembler::single_layer(&options.assembler_options)
+                    .assemble(&space, &space)
+            }
+            LaplaceEquation::Hypersingular => {
+                laplace::assembler::hypersingular(&options.assembler_options)
+                    .assemble(&space, &space)
+            }
+        };
+        let b = load_vector(&space, &rhs);
+        let mut coefficients = vec![T::zero(); space.global_size()];
+        let result = cg(&matrix, &b, &mut coefficients, &solver_options);
+
+        let indicators = match equation {
+            LaplaceEquation::SingleLayer => single_layer_indicators(&space, &coefficients),
+            LaplaceEquation::Hypersingular => hypersingular_indicators(&space, &coefficients),
+        };
+        let estimate = Float::sqrt(indicators.iter().fold(T::zero(), |a, i| a + *i * *i));
+        history.push(AdaptiveStep {
+            cells: indicators.len(),
+            dofs: space.global_size(),
+            estimate,
+            converged: result.converged,
+        });
+        drop(space);
+
+        if estimate < num::cast::<f64, T>(options.tolerance).unwrap()
+            || history.len() >= options.max_steps
+        {
+            return AdaptiveSolution {
+                grid,
+                coefficients,
+                indicators,
+                history,
+            };
+        }
+
+        let marked = dorfler_marking(&indicators, num::cast::<f64, T>(options.theta).unwrap());
+        grid = refine_marked(&grid, &marked, projection, comm).0;
+    }
+}
@@ -2,13 +2,20 @@
 mod cell_pair_assemblers;
 pub(crate) mod helpers;
 pub(crate) mod integrands;
+mod subdivision;
 
 use crate::boundary_assemblers::cell_pair_assemblers::{
     NonsingularCellPairAssemblerWithTestCaching, SingularCellPairAssembler,
 };
 use crate::boundary_assemblers::helpers::KernelEvaluator;
-use crate::boundary_assemblers::helpers::{equal_grids, RawData2D, RlstArray, SparseMatrixData};
-use crate::function::FunctionSpaceTrait;
+use crate::boundary_assemblers::helpers::{
+    equal_grids, CsrMatrixData, RawData2D, RlstArray, SparseMatrixData,
+};
+use crate::error::BemppError;
+use crate::function::{is_excluded, FunctionSpaceTrait, MappedFunctionSpace};
+use crate::helpers::{
+    cell_bounding_spheres, close_spheres, coincident_vertices, reference_vertices,
+};
 use bempp_quadrature::duffy::{
     quadrilateral_duffy, quadrilateral_triangle_duffy, triangle_duffy, triangle_quadrilateral_duffy,
 };
@@ -20,14 +27,126 @@ use ndelement::quadrature::simplex_rule;
 use ndelement::reference_cell;
 use ndelement::traits::FiniteElement;
 use ndelement::types::ReferenceCellType;
-use ndgrid::traits::{Entity, Grid, Topology};
+use ndgrid::traits::{Entity, Geometry, Grid, Topology};
 use ndgrid::types::Ownership;
+use num::{Float, Zero};
 use rayon::prelude::*;
 use rlst::{
     rlst_dynamic_array2, rlst_dynamic_array4, CsrMatrix, DefaultIterator, DynamicArray,
     MatrixInverse, RandomAccessMut, RawAccess, RawAccessMut, RlstScalar, Shape,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use subdivision::{can_subdivide, cell_sample_points, separation_ratio, subdivision_rule};
+
+/// The way in which a pair of cells is adjacent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Adjacency {
+    /// The cells share a single vertex
+    Vertex,
+    /// The cells share an edge
+    Edge,
+    /// The cells are the same cell
+    Coincident,
+}
+
+impl Adjacency {
+    /// Get the adjacency of a pair of cells from their connectivity
+    pub fn from_connectivity(connectivity: &CellToCellConnectivity) -> Self {
+        match connectivity.connectivity_dimension {
+            0 => Adjacency::Vertex,
+            1 => Adjacency::Edge,
+            _ => Adjacency::Coincident,
+        }
+    }
+}
+
+/// Choose the quadrature degree for singular integrals between a pair of adjacent cells
+///
+/// `element_degree` is the sum of the polynomial degrees of the test and trial elements and `geometry_degree` is the
+/// polynomial degree of the cell geometry. Cells that share only a vertex need fewer points than cells that share an
+/// edge, which need fewer than coincident cells.
+pub fn automatic_singular_quadrature_degree(
+    adjacency: Adjacency,
+    element_degree: usize,
+    geometry_degree: usize,
+) -> usize {
+    let base = match adjacency {
+        Adjacency::Vertex => 2,
+        Adjacency::Edge => 3,
+        Adjacency::Coincident => 4,
+    };
+    // The jacobian determinants and normals of a curved cell increase the degree of the integrand
+    let degree = element_degree + 2 * geometry_degree.saturating_sub(1);
+    base + degree.div_ceil(2)
+}
+
+/// The kernel of an operator, used to choose quadrature orders for a target accuracy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KernelType {
+    /// The Laplace kernel
+    Laplace,
+    /// The Helmholtz kernel
+    Helmholtz {
+        /// The wavenumber multiplied by the mesh size
+        wavenumber_times_mesh_size: f64,
+    },
+}
+
+/// An error caused by missing or invalid information in a set of assembler options
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuadratureOptionsError {
+    /// No regular quadrature degree is set for a cell type
+    MissingRegularDegree(ReferenceCellType),
+    /// No singular quadrature degree is set for a pair of cell types
+    MissingSingularDegree(ReferenceCellType, ReferenceCellType, Adjacency),
+    /// A target accuracy is not between 0 and 1
+    InvalidTolerance(String),
+    /// There is no regular quadrature rule with the given number of points on a cell type
+    InvalidPointCount(ReferenceCellType, usize),
+}
+
+impl std::fmt::Display for QuadratureOptionsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            QuadratureOptionsError::MissingRegularDegree(cell_type) => {
+                write!(f, "No regular quadrature degree set for cell type {cell_type:?}")
+            }
+            QuadratureOptionsError::MissingSingularDegree(test, trial, adjacency) => write!(
+                f,
+                "No singular quadrature degree set for {adjacency:?}-adjacent cell types {test:?} and {trial:?}"
+            ),
+            QuadratureOptionsError::InvalidTolerance(tolerance) => {
+                write!(f, "Tolerance must be between 0 and 1, but {tolerance} was given")
+            }
+            QuadratureOptionsError::InvalidPointCount(cell_type, npoints) => write!(
+                f,
+                "There is no quadrature rule with {npoints} points on cell type {cell_type:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuadratureOptionsError {}
+
+/// The way in which contributions from pairs of non-adjacent cells are added to a dense matrix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenseAccumulation {
+    /// Assemble each pair of cell colours in turn
+    ///
+    /// Cells of the same colour do not share any DOFs, so contributions are written directly to the matrix. Batches
+    /// of cells are assembled in parallel, but each pair of colours must be finished before the next is started.
+    Colouring,
+    /// Assemble batches of all the cells in parallel
+    ///
+    /// The batches are shared between one task per thread. Each task adds the contributions of its batches to its own
+    /// copies of the tiles of columns of the matrix that they touch. Once all the batches of a pair of elements are
+    /// assembled, the tiles of every task are added to the matrix in parallel. As every task may touch every tile,
+    /// this can use up to one extra copy of the dense matrix per thread.
+    Tiled,
+}
+
+/// The number of columns in each tile of a dense matrix when using [DenseAccumulation::Tiled]
+const TILE_COLUMNS: usize = 64;
 
 /// Options for a boundary assembler
 #[derive(Clone)]
@@ -36,32 +155,222 @@ pub struct BoundaryAssemblerOptions {
     pub quadrature_degrees: HashMap<ReferenceCellType, usize>,
     /// Quadrature degrees to be used for singular integrals
     pub singular_quadrature_degrees: HashMap<(ReferenceCellType, ReferenceCellType), usize>,
+    /// Quadrature degrees to be used for singular integrals between cells with a given adjacency
+    ///
+    /// If no degree is given here for a pair of cells, the degree in `singular_quadrature_degrees` is used.
+    pub adjacency_quadrature_degrees:
+        HashMap<(ReferenceCellType, ReferenceCellType, Adjacency), usize>,
+    /// Choose the singular quadrature degrees using the degrees of the elements and the cell geometry
+    ///
+    /// If this is true, `singular_quadrature_degrees` and `adjacency_quadrature_degrees` are ignored.
+    pub automatic_singular_quadrature: bool,
     /// Maximum size of each batch of cells to send to an assembly function
     pub batch_size: usize,
+    /// Choose the number of points used for each non-singular integral using the distance between the two cells
+    pub distance_adaptive_quadrature: bool,
+    /// Thresholds used by distance-adaptive quadrature
+    ///
+    /// Each entry is a pair `(ratio, degree)`. If this is the first entry for which the distance between a pair of
+    /// cells divided by the larger cell diameter is less than `ratio`, each cell is integrated using the rule with the
+    /// fewest points for its cell type that integrates polynomials of degree `degree` exactly. Pairs of cells that do
+    /// not satisfy any of these use the points in `quadrature_degrees`.
+    pub distance_quadrature_thresholds: Vec<(f64, usize)>,
+    /// Integrate nearly-singular pairs of non-adjacent cells by subdividing the cells
+    ///
+    /// The nearly-singular pairs are found using a spatial hash of the cells' bounding spheres.
+    pub near_field_subdivision: bool,
+    /// Pairs of non-adjacent cells whose distance divided by the larger cell diameter is less than this are nearly
+    /// singular
+    pub near_field_ratio: f64,
+    /// Nearly-singular pairs of cells are subdivided until the distance between each pair of sub-cells divided by the
+    /// larger sub-cell diameter is at least this value
+    pub subdivision_admissibility: f64,
+    /// Maximum number of times each cell in a nearly-singular pair can be subdivided
+    pub max_subdivision_level: usize,
+    /// Evaluate the kernel in single precision for pairs of cells that are far apart
+    ///
+    /// The geometry and quadrature are still computed using the precision of the assembler, and the results are
+    /// accumulated in this precision.
+    pub mixed_precision: bool,
+    /// Pairs of non-adjacent cells whose distance divided by the larger cell diameter is at least this use single
+    /// precision kernel evaluations if `mixed_precision` is true
+    pub mixed_precision_cutoff: f64,
+    /// The way in which contributions from pairs of non-adjacent cells are added to a dense matrix
+    pub dense_accumulation: DenseAccumulation,
+    /// Entries of the singular part whose absolute value is less than this are removed from the sparse matrix
+    ///
+    /// If this is `None`, no entries are removed.
+    pub singular_drop_tolerance: Option<f64>,
+    /// Only assemble pairs of cells whose test cell index is at most their trial cell index, and mirror the result
+    ///
+    /// This is only used if the operator is symmetric and the same space is used as the test and trial space.
+    pub symmetric_assembly: bool,
+    /// Vertices of different test and trial grids are shared if the distance between them divided by the smallest
+    /// cell diameter is less than this
+    ///
+    /// Pairs of cells of different grids that share vertices are integrated using singular quadrature.
+    pub coincidence_tolerance: f64,
 }
 
 impl Default for BoundaryAssemblerOptions {
     fn default() -> Self {
         use ReferenceCellType::{Quadrilateral, Triangle};
         Self {
-            quadrature_degrees: HashMap::from([(Triangle, 37), (Quadrilateral, 37)]),
+            quadrature_degrees: HashMap::from([(Triangle, 37), (Quadrilateral, 49)]),
             singular_quadrature_degrees: HashMap::from([
                 ((Triangle, Triangle), 4),
                 ((Quadrilateral, Quadrilateral), 4),
                 ((Quadrilateral, Triangle), 4),
                 ((Triangle, Quadrilateral), 4),
             ]),
+            adjacency_quadrature_degrees: HashMap::new(),
+            automatic_singular_quadrature: false,
             batch_size: 128,
+            distance_adaptive_quadrature: false,
+            distance_quadrature_thresholds: vec![
+                (0.5, 20),
+                (1.0, 13),
+                (2.0, 9),
+                (4.0, 6),
+                (f64::INFINITY, 3),
+            ],
+            near_field_subdivision: true,
+            near_field_ratio: 0.1,
+            subdivision_admissibility: 1.0,
+            max_subdivision_level: 4,
+            mixed_precision: false,
+            mixed_precision_cutoff: 2.0,
+            dense_accumulation: DenseAccumulation::Colouring,
+            singular_drop_tolerance: None,
+            symmetric_assembly: false,
+            coincidence_tolerance: 1e-8,
         }
     }
 }
 
 impl BoundaryAssemblerOptions {
+    /// Create options that use low order quadrature for fast assembly.
+    pub fn fast() -> Self {
+        use ReferenceCellType::{Quadrilateral, Triangle};
+        let mut options = Self::default();
+        options.quadrature_degrees = HashMap::from([(Triangle, 12), (Quadrilateral, 16)]);
+        for degree in options.singular_quadrature_degrees.values_mut() {
+            *degree = 3;
+        }
+        options
+    }
+
+    /// Create options that use high order quadrature for accurate assembly.
+    pub fn accurate() -> Self {
+        use ReferenceCellType::{Quadrilateral, Triangle};
+        let mut options = Self::default();
+        options.quadrature_degrees = HashMap::from([(Triangle, 79), (Quadrilateral, 121)]);
+        for degree in options.singular_quadrature_degrees.values_mut() {
+            *degree = 6;
+        }
+        options
+    }
+
+    /// Create options that use quadrature orders chosen to give a target relative accuracy.
+    ///
+    /// `test_degree` and `trial_degree` are the polynomial degrees of the test and trial elements and
+    /// `geometry_degree` is the polynomial degree of the cell geometry. The orders are chosen using heuristic
+    /// estimates of the quadrature error, so the target accuracy is not guaranteed. An error is returned if
+    /// `tolerance` is not between 0 and 1.
+    pub fn for_accuracy(
+        tolerance: f64,
+        test_degree: usize,
+        trial_degree: usize,
+        geometry_degree: usize,
+        kernel: KernelType,
+    ) -> Result<Self, QuadratureOptionsError> {
+        use ReferenceCellType::{Quadrilateral, Triangle};
+        if !(tolerance > 0.0 && tolerance < 1.0) {
+            return Err(QuadratureOptionsError::InvalidTolerance(format!(
+                "{tolerance}"
+            )));
+        }
+        let digits = (-tolerance.log10()).ceil() as usize;
+        // Extra degree needed to resolve the oscillation of the kernel over a cell
+        let oscillation = match kernel {
+            KernelType::Laplace => 0,
+            KernelType::Helmholtz {
+                wavenumber_times_mesh_size,
+            } => wavenumber_times_mesh_size.abs().ceil() as usize,
+        };
+        let polynomial_degree = test_degree + trial_degree + 2 * geometry_degree.saturating_sub(1);
+
+        let regular_degree = (3 * digits).div_ceil(2) + polynomial_degree + oscillation;
+        let singular_degree =
+            digits.div_ceil(2) + 2 + polynomial_degree.div_ceil(2) + oscillation.div_ceil(2);
+
+        let mut options = Self::default();
+        options.quadrature_degrees = HashMap::from([
+            (Triangle, triangle_npoints(regular_degree)),
+            (Quadrilateral, quadrilateral_npoints(regular_degree)),
+        ]);
+        for degree in options.singular_quadrature_degrees.values_mut() {
+            *degree = singular_degree;
+        }
+        for cell_types in options.singular_quadrature_degrees.keys() {
+            for (adjacency, reduction) in [(Adjacency::Edge, 1), (Adjacency::Vertex, 2)] {
+                options.adjacency_quadrature_degrees.insert(
+                    (cell_types.0, cell_types.1, adjacency),
+                    std::cmp::max(singular_degree - reduction, 1),
+                );
+            }
+        }
+        Ok(options)
+    }
+
+    /// Check that these options contain quadrature degrees for every pair of the given cell types.
+    ///
+    /// This also checks that a regular quadrature rule exists with each number of points that will be used.
+    pub fn validate(&self, cell_types: &[ReferenceCellType]) -> Result<(), QuadratureOptionsError> {
+        for test_cell_type in cell_types {
+            match self.quadrature_degrees.get(test_cell_type) {
+                Some(npoints) => check_point_count(*test_cell_type, *npoints)?,
+                None => {
+                    return Err(QuadratureOptionsError::MissingRegularDegree(
+                        *test_cell_type,
+                    ))
+                }
+            }
+            if self.distance_adaptive_quadrature {
+                for (_, degree) in &self.distance_quadrature_thresholds {
+                    check_point_count(*test_cell_type, regular_npoints(*test_cell_type, *degree))?;
+                }
+            }
+            if self.automatic_singular_quadrature {
+                continue;
+            }
+            for trial_cell_type in cell_types {
+                for adjacency in [Adjacency::Vertex, Adjacency::Edge, Adjacency::Coincident] {
+                    if self
+                        .get_adjacency_quadrature_degree(
+                            (*test_cell_type, *trial_cell_type),
+                            adjacency,
+                        )
+                        .is_none()
+                    {
+                        return Err(QuadratureOptionsError::MissingSingularDegree(
+                            *test_cell_type,
+                            *trial_cell_type,
+                            adjacency,
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Set the regular quadrature order.
+    ///
+    /// If no order is set for this cell type, the cell type is added, so this can be used to fix the options after
+    /// [BoundaryAssemblerOptions::validate] reports a missing degree.
     pub fn set_regular_quadrature_degree(&mut self, cell_type: ReferenceCellType, npoints: usize) {
-        self.quadrature_degrees
-            .entry(cell_type)
-            .and_modify(|x| *x = npoints);
+        self.quadrature_degrees.insert(cell_type, npoints);
     }
 
     /// Get the regular quadrature order.
@@ -70,14 +379,14 @@ impl BoundaryAssemblerOptions {
     }
 
     /// Set the singular quadrature order.
+    ///
+    /// If no order is set for this pair of cell types, the pair is added.
     pub fn set_singular_quadrature_degree(
         &mut self,
         cell_type: (ReferenceCellType, ReferenceCellType),
         npoints: usize,
     ) {
-        self.singular_quadrature_degrees
-            .entry(cell_type)
-            .and_modify(|x| *x = npoints);
+        self.singular_quadrature_degrees.insert(cell_type, npoints);
     }
 
     /// Get the singular quadrature order.
@@ -88,6 +397,39 @@ impl BoundaryAssemblerOptions {
         self.singular_quadrature_degrees.get(&cell_type).copied()
     }
 
+    /// Set the singular quadrature order for pairs of cells with a given adjacency.
+    pub fn set_adjacency_quadrature_degree(
+        &mut self,
+        cell_type: (ReferenceCellType, ReferenceCellType),
+        adjacency: Adjacency,
+        npoints: usize,
+    ) {
+        self.adjacency_quadrature_degrees
+            .insert((cell_type.0, cell_type.1, adjacency), npoints);
+    }
+
+    /// Get the singular quadrature order for pairs of cells with a given adjacency.
+    pub fn get_adjacency_quadrature_degree(
+        &self,
+        cell_type: (ReferenceCellType, ReferenceCellType),
+        adjacency: Adjacency,
+    ) -> Option<usize> {
+        self.adjacency_quadrature_degrees
+            .get(&(cell_type.0, cell_type.1, adjacency))
+            .copied()
+            .or_else(|| self.get_singular_quadrature_degree(cell_type))
+    }
+
+    /// Set whether the singular quadrature orders are chosen automatically.
+    pub fn set_automatic_singular_quadrature(&mut self, automatic: bool) {
+        self.automatic_singular_quadrature = automatic;
+    }
+
+    /// Get whether the singular quadrature orders are chosen automatically.
+    pub fn get_automatic_singular_quadrature(&self) -> bool {
+        self.automatic_singular_quadrature
+    }
+
     /// Set the batch size.
     pub fn set_batch_size(&mut self, batch_size: usize) {
         self.batch_size = batch_size;
@@ -97,6 +439,197 @@ impl BoundaryAssemblerOptions {
     pub fn get_batch_size(&self) -> usize {
         self.batch_size
     }
+
+    /// Set whether distance-adaptive quadrature is used for non-singular integrals.
+    pub fn set_distance_adaptive_quadrature(&mut self, adaptive: bool) {
+        self.distance_adaptive_quadrature = adaptive;
+    }
+
+    /// Get whether distance-adaptive quadrature is used for non-singular integrals.
+    pub fn get_distance_adaptive_quadrature(&self) -> bool {
+        self.distance_adaptive_quadrature
+    }
+
+    /// Set the thresholds used by distance-adaptive quadrature.
+    ///
+    /// Each threshold is a pair `(ratio, degree)`, where `degree` is the polynomial degree that the rule on each cell
+    /// integrates exactly. The thresholds are sorted so that the ratios are increasing.
+    pub fn set_distance_quadrature_thresholds(&mut self, mut thresholds: Vec<(f64, usize)>) {
+        thresholds.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        self.distance_quadrature_thresholds = thresholds;
+    }
+
+    /// Get the thresholds used by distance-adaptive quadrature.
+    pub fn get_distance_quadrature_thresholds(&self) -> &[(f64, usize)] {
+        &self.distance_quadrature_thresholds
+    }
+
+    /// Set whether nearly-singular pairs of cells are integrated using subdivision.
+    pub fn set_near_field_subdivision(&mut self, subdivision: bool) {
+        self.near_field_subdivision = subdivision;
+    }
+
+    /// Get whether nearly-singular pairs of cells are integrated using subdivision.
+    pub fn get_near_field_subdivision(&self) -> bool {
+        self.near_field_subdivision
+    }
+
+    /// Set the distance ratio below which pairs of cells are nearly singular.
+    pub fn set_near_field_ratio(&mut self, ratio: f64) {
+        self.near_field_ratio = ratio;
+    }
+
+    /// Get the distance ratio below which pairs of cells are nearly singular.
+    pub fn get_near_field_ratio(&self) -> f64 {
+        self.near_field_ratio
+    }
+
+    /// Set the distance ratio at which sub-cells are no longer subdivided.
+    pub fn set_subdivision_admissibility(&mut self, admissibility: f64) {
+        self.subdivision_admissibility = admissibility;
+    }
+
+    /// Get the distance ratio at which sub-cells are no longer subdivided.
+    pub fn get_subdivision_admissibility(&self) -> f64 {
+        self.subdivision_admissibility
+    }
+
+    /// Set the maximum number of times a cell can be subdivided.
+    pub fn set_max_subdivision_level(&mut self, level: usize) {
+        self.max_subdivision_level = level;
+    }
+
+    /// Get the maximum number of times a cell can be subdivided.
+    pub fn get_max_subdivision_level(&self) -> usize {
+        self.max_subdivision_level
+    }
+
+    /// Set whether the kernel is evaluated in single precision for pairs of cells that are far apart.
+    pub fn set_mixed_precision(&mut self, mixed_precision: bool) {
+        self.mixed_precision = mixed_precision;
+    }
+
+    /// Get whether the kernel is evaluated in single precision for pairs of cells that are far apart.
+    pub fn get_mixed_precision(&self) -> bool {
+        self.mixed_precision
+    }
+
+    /// Set the distance ratio at and above which the kernel is evaluated in single precision.
+    pub fn set_mixed_precision_cutoff(&mut self, cutoff: f64) {
+        self.mixed_precision_cutoff = cutoff;
+    }
+
+    /// Get the distance ratio at and above which the kernel is evaluated in single precision.
+    pub fn get_mixed_precision_cutoff(&self) -> f64 {
+        self.mixed_precision_cutoff
+    }
+
+    /// Set the way in which contributions from pairs of non-adjacent cells are added to a dense matrix.
+    pub fn set_dense_accumulation(&mut self, accumulation: DenseAccumulation) {
+        self.dense_accumulation = accumulation;
+    }
+
+    /// Get the way in which contributions from pairs of non-adjacent cells are added to a dense matrix.
+    pub fn get_dense_accumulation(&self) -> DenseAccumulation {
+        self.dense_accumulation
+    }
+
+    /// Set the tolerance below which entries are removed from the singular part.
+    pub fn set_singular_drop_tolerance(&mut self, tolerance: Option<f64>) {
+        self.singular_drop_tolerance = tolerance;
+    }
+
+    /// Get the tolerance below which entries are removed from the singular part.
+    pub fn get_singular_drop_tolerance(&self) -> Option<f64> {
+        self.singular_drop_tolerance
+    }
+
+    /// Set whether to exploit the symmetry of symmetric operators.
+    pub fn set_symmetric_assembly(&mut self, symmetric: bool) {
+        self.symmetric_assembly = symmetric;
+    }
+
+    /// Get whether to exploit the symmetry of symmetric operators.
+    pub fn get_symmetric_assembly(&self) -> bool {
+        self.symmetric_assembly
+    }
+
+    /// Set the tolerance used to find the shared vertices of different test and trial grids.
+    pub fn set_coincidence_tolerance(&mut self, tolerance: f64) {
+        self.coincidence_tolerance = tolerance;
+    }
+
+    /// Get the tolerance used to find the shared vertices of different test and trial grids.
+    pub fn get_coincidence_tolerance(&self) -> f64 {
+        self.coincidence_tolerance
+    }
+}
+
+/// The number of points in the smallest Xiao-Gimbutas rule on a triangle that integrates polynomials of a given degree
+fn triangle_npoints(degree: usize) -> usize {
+    const NPOINTS: [usize; 30] = [
+        1, 3, 6, 6, 7, 12, 15, 16, 19, 25, 28, 33, 37, 42, 49, 55, 60, 67, 73, 79, 87, 96, 103,
+        112, 120, 130, 141, 150, 159, 171,
+    ];
+    NPOINTS[degree.clamp(1, NPOINTS.len()) - 1]
+}
+
+/// The number of points in the smallest Gauss-Jacobi rule on a quadrilateral that integrates polynomials of a given
+/// degree
+fn quadrilateral_npoints(degree: usize) -> usize {
+    let m = (degree + 2) / 2;
+    m * m
+}
+
+/// The number of points in the smallest rule on a cell type that integrates polynomials of a given degree
+///
+/// Cell types other than triangles use a Gauss-Jacobi rule.
+fn regular_npoints(cell_type: ReferenceCellType, degree: usize) -> usize {
+    match cell_type {
+        ReferenceCellType::Triangle => triangle_npoints(degree),
+        _ => quadrilateral_npoints(degree),
+    }
+}
+
+/// Check that there is a regular quadrature rule with the given number of points on a cell type
+fn check_point_count(
+    cell_type: ReferenceCellType,
+    npoints: usize,
+) -> Result<(), QuadratureOptionsError> {
+    if simplex_rule(cell_type, npoints).is_ok() {
+        Ok(())
+    } else {
+        Err(QuadratureOptionsError::InvalidPointCount(
+            cell_type, npoints,
+        ))
+    }
+}
+
+/// The test and trial cell types of a pair of adjacent cells, and the pairs of local indices of their shared vertices
+type CellPairKey = (ReferenceCellType, ReferenceCellType, Vec<(usize, usize)>);
+
+/// A cell type and the polynomial degree of the element used on cells of that type
+///
+/// The degree is `None` if a space uses the same element on every cell of each type.
+type ElementKey = (ReferenceCellType, Option<usize>);
+
+/// A singular quadrature rule for a pair of adjacent cells
+struct SingularRule<T: RlstScalar> {
+    test_cell_type: ReferenceCellType,
+    trial_cell_type: ReferenceCellType,
+    test_points: RlstArray<T::Real, 2>,
+    trial_points: RlstArray<T::Real, 2>,
+    weights: Vec<T::Real>,
+}
+
+/// A regular quadrature rule for a pair of non-adjacent cells, and the test and trial elements tabulated at its points
+struct RegularRule<T: RlstScalar> {
+    test_points: RlstArray<T::Real, 2>,
+    test_weights: Vec<T::Real>,
+    trial_points: RlstArray<T::Real, 2>,
+    trial_weights: Vec<T::Real>,
+    test_table: RlstArray<T, 4>,
+    trial_table: RlstArray<T, 4>,
 }
 
 /// Boundary assembler
@@ -119,98 +652,233 @@ impl<'o, T: RlstScalar + MatrixInverse, Integrand: BoundaryIntegrand<T = T>, K:
     BoundaryAssembler<'o, T, Integrand, K>
 {
     /// Assemble the singular part into a CSR matrix.
-    pub fn assemble_singular<Space: FunctionSpaceTrait<T = T> + Sync>(
+    pub fn assemble_singular<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
         &self,
-        trial_space: &Space,
-        test_space: &Space,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
     ) -> CsrMatrix<T> {
-        let shape = [test_space.global_size(), trial_space.global_size()];
-        let sparse_matrix = self.assemble_singular_part(shape, trial_space, test_space);
+        self.try_assemble_singular(trial_space, test_space)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
 
-        if sparse_matrix.data.is_empty()
-            || sparse_matrix
-                .data
-                .iter()
-                .map(|i| i.abs())
-                .filter(|i| *i > T::from(1e-10).unwrap().re())
-                .count()
-                == 0
-        {
-            // TODO: remove this hack once https://github.com/linalg-rs/rlst/pull/100 is merged and there a new release of RLST
-            CsrMatrix::<T>::new(
-                sparse_matrix.shape,
-                vec![],
-                vec![0; sparse_matrix.shape[0] + 1],
-                vec![],
-            )
-        } else {
-            CsrMatrix::<T>::from_aij(
-                sparse_matrix.shape,
-                &sparse_matrix.rows,
-                &sparse_matrix.cols,
-                &sparse_matrix.data,
-            )
-            .unwrap()
+    /// Assemble the singular part into a CSR matrix, returning an error if the input is invalid.
+    pub fn try_assemble_singular<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+    ) -> Result<CsrMatrix<T>, BemppError> {
+        let shape = [test_space.global_size(), trial_space.global_size()];
+        let touching_pairs = self.touching_cell_pairs(trial_space, test_space);
+        let mut matrix =
+            self.assemble_singular_part(shape, trial_space, test_space, &touching_pairs)?;
+        if let Some(tolerance) = self.options.singular_drop_tolerance {
+            matrix.drop_entries(num::cast::<f64, T::Real>(tolerance).unwrap());
         }
+        Ok(matrix.into_csr_matrix())
     }
 
     /// Assemble into a dense matrix.
-    pub fn assemble<Space: FunctionSpaceTrait<T = T> + Sync>(
+    pub fn assemble<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
         &self,
-        trial_space: &Space,
-        test_space: &Space,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
     ) -> DynamicArray<T, 2> {
-        if !trial_space.is_serial() || !test_space.is_serial() {
-            panic!("Dense assembly can only be used for function spaces stored in serial");
-        }
+        self.try_assemble(trial_space, test_space)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Assemble into a dense matrix, returning an error if the input is invalid.
+    pub fn try_assemble<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+    ) -> Result<DynamicArray<T, 2>, BemppError> {
+        check_serial(trial_space, test_space)?;
 
         let mut output =
             rlst_dynamic_array2!(T, [test_space.global_size(), trial_space.global_size()]);
 
-        self.assemble_into_memory(trial_space, test_space, output.data_mut());
+        self.try_assemble_into_memory(trial_space, test_space, output.data_mut())?;
 
-        output
+        Ok(output)
     }
 
     /// Assemble into a dense matrix.
-    pub fn assemble_into_memory<Space: FunctionSpaceTrait<T = T> + Sync>(
+    pub fn assemble_into_memory<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
         &self,
-        trial_space: &Space,
-        test_space: &Space,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
         output: &mut [T],
     ) {
-        assert_eq!(
-            output.len(),
-            test_space.global_size() * trial_space.global_size()
-        );
-        if !trial_space.is_serial() || !test_space.is_serial() {
-            panic!("Dense assembly can only be used for function spaces stored in serial");
+        self.try_assemble_into_memory(trial_space, test_space, output)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Assemble into a dense matrix, returning an error if the input is invalid.
+    pub fn try_assemble_into_memory<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+        output: &mut [T],
+    ) -> Result<(), BemppError> {
+        if output.len() != test_space.global_size() * trial_space.global_size() {
+            return Err(BemppError::WrongShape {
+                expected: [test_space.global_size(), trial_space.global_size()],
+                actual: [output.len(), 1],
+            });
         }
+        check_serial(trial_space, test_space)?;
 
-        let test_colouring = test_space.cell_colouring();
-        let trial_colouring = trial_space.cell_colouring();
+        let test_colouring = test_space.cell_colouring()?;
+        let trial_colouring = trial_space.cell_colouring()?;
         let shape = [test_space.global_size(), trial_space.global_size()];
         let output_raw = RawData2D {
             data: output.as_mut_ptr(),
             shape,
         };
 
+        let touching_pairs = self.touching_cell_pairs(trial_space, test_space);
+
         self.assemble_nonsingular_part(
             &output_raw,
             trial_space,
             test_space,
             &trial_colouring,
             &test_colouring,
-        );
+            &touching_pairs,
+        )?;
 
-        let sparse_matrix = self.assemble_singular_part(shape, trial_space, test_space);
+        let sparse_matrix =
+            self.assemble_singular_part(shape, trial_space, test_space, &touching_pairs)?;
+        for (i, j, value) in sparse_matrix.iter() {
+            *output.get_mut(i + shape[0] * j).unwrap() += value;
+        }
+        Ok(())
+    }
+
+    /// Assemble into a dense matrix using mapped function spaces.
+    ///
+    /// The operator is assembled using the underlying spaces, then mapped to the DOFs of the mapped spaces.
+    pub fn assemble_mapped<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        trial_space: &MappedFunctionSpace<TrialSpace>,
+        test_space: &MappedFunctionSpace<TestSpace>,
+    ) -> DynamicArray<T, 2> {
+        self.try_assemble_mapped(trial_space, test_space)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
 
-        let data = sparse_matrix.data;
-        let rows = sparse_matrix.rows;
-        let cols = sparse_matrix.cols;
-        for ((i, j), value) in rows.iter().zip(cols.iter()).zip(data.iter()) {
-            *output.get_mut(*i + shape[0] * *j).unwrap() += *value;
+    /// Assemble into a dense matrix using mapped function spaces, returning an error if the input is invalid.
+    pub fn try_assemble_mapped<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        trial_space: &MappedFunctionSpace<TrialSpace>,
+        test_space: &MappedFunctionSpace<TestSpace>,
+    ) -> Result<DynamicArray<T, 2>, BemppError> {
+        let matrix = self.try_assemble(trial_space.space(), test_space.space())?;
+        let matrix = matrix.data();
+        let fine_shape = [
+            test_space.space().global_size(),
+            trial_space.space().global_size(),
+        ];
+
+        // Apply the trial space map to the columns of the matrix
+        let mut trial_mapped = vec![T::zero(); fine_shape[0] * trial_space.global_size()];
+        for j in 0..fine_shape[1] {
+            for (dof, c) in trial_space.coefficients(j) {
+                for i in 0..fine_shape[0] {
+                    trial_mapped[i + fine_shape[0] * dof] += *c * matrix[i + fine_shape[0] * j];
+                }
+            }
         }
+
+        // Apply the test space map to the rows of the matrix
+        let shape = [test_space.global_size(), trial_space.global_size()];
+        let mut output = rlst_dynamic_array2!(T, shape);
+        let data = output.data_mut();
+        for i in 0..fine_shape[0] {
+            for (dof, c) in test_space.coefficients(i) {
+                for j in 0..shape[1] {
+                    data[dof + shape[0] * j] += *c * trial_mapped[i + fine_shape[0] * j];
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    /// Assemble the singular part into a CSR matrix using mapped function spaces.
+    ///
+    /// The singular part is assembled using the underlying spaces, then mapped to the DOFs of the mapped spaces.
+    pub fn assemble_singular_mapped<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        trial_space: &MappedFunctionSpace<TrialSpace>,
+        test_space: &MappedFunctionSpace<TestSpace>,
+    ) -> CsrMatrix<T> {
+        self.try_assemble_singular_mapped(trial_space, test_space)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Assemble the singular part into a CSR matrix using mapped function spaces, returning an error if the input is
+    /// invalid.
+    pub fn try_assemble_singular_mapped<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        trial_space: &MappedFunctionSpace<TrialSpace>,
+        test_space: &MappedFunctionSpace<TestSpace>,
+    ) -> Result<CsrMatrix<T>, BemppError> {
+        let fine_shape = [
+            test_space.space().global_size(),
+            trial_space.space().global_size(),
+        ];
+        let touching_pairs = self.touching_cell_pairs(trial_space.space(), test_space.space());
+        let fine_matrix = self.assemble_singular_part(
+            fine_shape,
+            trial_space.space(),
+            test_space.space(),
+            &touching_pairs,
+        )?;
+
+        let mut sparse_matrix =
+            SparseMatrixData::new([test_space.global_size(), trial_space.global_size()]);
+        for (i, j, value) in fine_matrix.iter() {
+            for (test_dof, test_c) in test_space.coefficients(i) {
+                for (trial_dof, trial_c) in trial_space.coefficients(j) {
+                    sparse_matrix.rows.push(*test_dof);
+                    sparse_matrix.cols.push(*trial_dof);
+                    sparse_matrix.data.push(*test_c * value * *trial_c);
+                }
+            }
+        }
+
+        Ok(CsrMatrixData::from_entries(sparse_matrix).into_csr_matrix())
     }
 
     /// Create new Boundary assembler
@@ -230,158 +898,900 @@ impl<'o, T: RlstScalar + MatrixInverse, Integrand: BoundaryIntegrand<T = T>, K:
         }
     }
 
-    /// Assemble the singular contributions
-    fn assemble_singular_part<Space: FunctionSpaceTrait<T = T> + Sync>(
+    /// Check if only pairs of cells with test cell index at most the trial cell index need to be assembled
+    fn use_symmetry<TestSpace: FunctionSpaceTrait<T = T>, TrialSpace: FunctionSpaceTrait<T = T>>(
+        &self,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+    ) -> bool {
+        self.options.symmetric_assembly
+            && self.integrand.is_symmetric()
+            && std::ptr::addr_eq(trial_space, test_space)
+            && check_serial(trial_space, test_space).is_ok()
+    }
+
+    /// Check that the options contain quadrature degrees for every cell type in the test and trial grids
+    fn check_options<
+        TestSpace: FunctionSpaceTrait<T = T>,
+        TrialSpace: FunctionSpaceTrait<T = T>,
+    >(
+        &self,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+    ) -> Result<(), BemppError> {
+        let mut cell_types = test_space.grid().entity_types(2).to_vec();
+        for cell_type in trial_space.grid().entity_types(2) {
+            if !cell_types.contains(cell_type) {
+                cell_types.push(*cell_type);
+            }
+        }
+        for cell_type in &cell_types {
+            if !matches!(
+                cell_type,
+                ReferenceCellType::Triangle | ReferenceCellType::Quadrilateral
+            ) {
+                return Err(BemppError::UnsupportedCellType(*cell_type));
+            }
+        }
+        self.options.validate(&cell_types)?;
+        Ok(())
+    }
+
+    /// Get the singular quadrature degree for each adjacency of a pair of cell types
+    fn singular_quadrature_degrees<
+        TestSpace: FunctionSpaceTrait<T = T>,
+        TrialSpace: FunctionSpaceTrait<T = T>,
+    >(
+        &self,
+        test_cell_type: ReferenceCellType,
+        trial_cell_type: ReferenceCellType,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+    ) -> HashMap<Adjacency, usize> {
+        let cell_types = (test_cell_type, trial_cell_type);
+        let geometry_degree = if self.options.automatic_singular_quadrature {
+            test_space
+                .grid()
+                .entity_iter(2)
+                .filter(|cell| {
+                    cell.entity_type() == test_cell_type || cell.entity_type() == trial_cell_type
+                })
+                .map(|cell| cell.geometry().degree())
+                .max()
+                .unwrap_or(1)
+        } else {
+            1
+        };
+        let element_degree = test_space.element(test_cell_type).embedded_superdegree()
+            + trial_space.element(trial_cell_type).embedded_superdegree();
+
+        [Adjacency::Vertex, Adjacency::Edge, Adjacency::Coincident]
+            .into_iter()
+            .map(|adjacency| {
+                let degree = if self.options.automatic_singular_quadrature {
+                    automatic_singular_quadrature_degree(adjacency, element_degree, geometry_degree)
+                } else {
+                    self.options
+                        .get_adjacency_quadrature_degree(cell_types, adjacency)
+                        .unwrap()
+                };
+                (adjacency, degree)
+            })
+            .collect()
+    }
+
+    /// Assemble the singular contributions
+    fn assemble_singular_part<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        shape: [usize; 2],
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+        touching_pairs: &[(CellPairKey, (usize, usize))],
+    ) -> Result<CsrMatrixData<T>, BemppError> {
+        let same_grid = equal_grids(test_space.grid(), trial_space.grid());
+        if !same_grid && touching_pairs.is_empty() {
+            // If the test and trial grids are different and do not touch, there are no neighbouring cells
+            return Ok(CsrMatrixData::new(shape));
+        }
+
+        check_shape(shape, trial_space, test_space)?;
+        self.check_options(trial_space, test_space)?;
+
+        let mut rules = vec![];
+        let mut pair_indices = HashMap::new();
+
+        if same_grid {
+            let grid = test_space.grid();
+            for test_cell_type in grid.entity_types(2) {
+                for trial_cell_type in grid.entity_types(2) {
+                    let qdegrees = self.singular_quadrature_degrees(
+                        *test_cell_type,
+                        *trial_cell_type,
+                        trial_space,
+                        test_space,
+                    );
+
+                    let mut possible_pairs = vec![];
+                    // Vertex-adjacent
+                    for i in 0..reference_cell::entity_counts(*test_cell_type)[0] {
+                        for j in 0..reference_cell::entity_counts(*trial_cell_type)[0] {
+                            possible_pairs.push(vec![(i, j)]);
+                        }
+                    }
+                    // edge-adjacent
+                    for test_e in reference_cell::edges(*test_cell_type) {
+                        for trial_e in reference_cell::edges(*trial_cell_type) {
+                            possible_pairs
+                                .push(vec![(test_e[0], trial_e[0]), (test_e[1], trial_e[1])]);
+                            possible_pairs
+                                .push(vec![(test_e[1], trial_e[0]), (test_e[0], trial_e[1])]);
+                        }
+                    }
+                    // Same cell
+                    if test_cell_type == trial_cell_type {
+                        possible_pairs.push(
+                            (0..reference_cell::entity_counts(*test_cell_type)[0])
+                                .map(&|i| (i, i))
+                                .collect::<Vec<_>>(),
+                        );
+                    }
+
+                    for pairs in possible_pairs {
+                        rules.push(self.singular_rule(
+                            *test_cell_type,
+                            *trial_cell_type,
+                            &pairs,
+                            &qdegrees,
+                        )?);
+                        pair_indices
+                            .insert((*test_cell_type, *trial_cell_type, pairs), rules.len() - 1);
+                    }
+                }
+            }
+        } else {
+            // Rules are only created for the adjacencies that appear between the two grids
+            for ((test_cell_type, trial_cell_type, pairs), _) in touching_pairs {
+                let key = (*test_cell_type, *trial_cell_type, pairs.clone());
+                if !pair_indices.contains_key(&key) {
+                    let qdegrees = self.singular_quadrature_degrees(
+                        *test_cell_type,
+                        *trial_cell_type,
+                        trial_space,
+                        test_space,
+                    );
+                    rules.push(self.singular_rule(
+                        *test_cell_type,
+                        *trial_cell_type,
+                        pairs,
+                        &qdegrees,
+                    )?);
+                    pair_indices.insert(key, rules.len() - 1);
+                }
+            }
+        }
+
+        let symmetric = self.use_symmetry(trial_space, test_space);
+        let cell_blocks = if same_grid {
+            make_cell_blocks(
+                |test_cell_type, trial_cell_type, pairs| {
+                    pair_indices[&(test_cell_type, trial_cell_type, pairs)]
+                },
+                pair_indices.len(),
+                test_space.grid(),
+                self.options.batch_size,
+                symmetric,
+            )
+        } else {
+            let mut cell_pairs = vec![vec![]; pair_indices.len()];
+            for (key, cells) in touching_pairs {
+                cell_pairs[pair_indices[key]].push(*cells);
+            }
+            batch_cell_pairs(cell_pairs, self.options.batch_size)
+        };
+
+        // Pairs of cells where either cell has no DOFs do not contribute to the matrix
+        let cell_blocks = cell_blocks
+            .into_iter()
+            .map(|(i, cell_block)| {
+                (
+                    i,
+                    cell_block
+                        .into_iter()
+                        .filter(|(test_cell, trial_cell)| {
+                            !test_space.cell_dofs(*test_cell).unwrap().is_empty()
+                                && !trial_space.cell_dofs(*trial_cell).unwrap().is_empty()
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .filter(|(_, cell_block)| !cell_block.is_empty())
+            .collect::<Vec<_>>();
+
+        // If either space uses elements of different degrees on cells of the same type, the blocks are split so that
+        // the test cells in each block use the same element and the trial cells in each block use the same element
+        let cell_blocks = cell_blocks
+            .into_iter()
+            .flat_map(|(i, cell_block)| {
+                let mut degree_blocks = HashMap::new();
+                for (test_cell, trial_cell) in cell_block {
+                    degree_blocks
+                        .entry((
+                            test_space.cell_degree(test_cell),
+                            trial_space.cell_degree(trial_cell),
+                        ))
+                        .or_insert(vec![])
+                        .push((test_cell, trial_cell));
+                }
+                let mut degree_blocks = degree_blocks.into_iter().collect::<Vec<_>>();
+                degree_blocks.sort_by_key(|(degrees, _)| *degrees);
+                degree_blocks
+                    .into_iter()
+                    .map(move |(degrees, cell_block)| (i, degrees, cell_block))
+            })
+            .collect::<Vec<_>>();
+
+        // Tabulate the test and trial elements at the points of each rule that is used
+        let mut tables = HashMap::new();
+        for (i, (test_degree, trial_degree), _) in &cell_blocks {
+            tables
+                .entry((*i, *test_degree, *trial_degree))
+                .or_insert_with(|| {
+                    let rule = &rules[*i];
+                    let npts = rule.weights.len();
+                    let test_element =
+                        test_space.element_with_degree(rule.test_cell_type, *test_degree);
+                    let mut test_table = rlst_dynamic_array4!(
+                        T,
+                        test_element.tabulate_array_shape(self.table_derivs, npts)
+                    );
+                    test_element.tabulate(&rule.test_points, self.table_derivs, &mut test_table);
+                    let trial_element =
+                        trial_space.element_with_degree(rule.trial_cell_type, *trial_degree);
+                    let mut trial_table = rlst_dynamic_array4!(
+                        T,
+                        trial_element.tabulate_array_shape(self.table_derivs, npts)
+                    );
+                    trial_element.tabulate(&rule.trial_points, self.table_derivs, &mut trial_table);
+                    (test_table, trial_table)
+                });
+        }
+
+        // The sparsity pattern contains every pair of DOFs in a pair of adjacent cells
+        let mut pattern = vec![vec![]; shape[0]];
+        for (_, _, cell_block) in &cell_blocks {
+            for (test_cell, trial_cell) in cell_block {
+                let trial_dofs = trial_space.cell_dofs(*trial_cell).unwrap();
+                let test_excluded = test_space.excluded_dofs(*test_cell);
+                let trial_excluded = trial_space.excluded_dofs(*trial_cell);
+                for (i, test_dof) in test_space.cell_dofs(*test_cell).unwrap().iter().enumerate() {
+                    if is_excluded(test_excluded, i) {
+                        continue;
+                    }
+                    let row = test_space.global_dof_index(*test_dof);
+                    for (j, trial_dof) in trial_dofs.iter().enumerate() {
+                        if is_excluded(trial_excluded, j) {
+                            continue;
+                        }
+                        let col = trial_space.global_dof_index(*trial_dof);
+                        pattern[row].push(col);
+                        if symmetric {
+                            pattern[col].push(row);
+                        }
+                    }
+                }
+            }
+        }
+        let mut matrix = CsrMatrixData::from_pattern(shape, pattern);
+
+        matrix.add_in_parallel(cell_blocks, |(i, degrees, cell_block)| {
+            // Each block only contains one type of adjacency, so either every pair in the block is a coincident pair
+            // of cells or none are
+            let mirror = symmetric && cell_block[0].0 != cell_block[0].1;
+            let (test_table, trial_table) = &tables[&(i, degrees.0, degrees.1)];
+            let mut entries = vec![];
+            assemble_batch_singular(
+                self,
+                self.deriv_size,
+                &mut |row, col, value| {
+                    entries.push((row, col, value));
+                    if mirror {
+                        entries.push((col, row, value));
+                    }
+                },
+                rules[i].trial_cell_type,
+                rules[i].test_cell_type,
+                trial_space,
+                test_space,
+                &cell_block,
+                &rules[i].trial_points,
+                &rules[i].test_points,
+                &rules[i].weights,
+                trial_table,
+                test_table,
+            );
+            entries
+        });
+        Ok(matrix)
+    }
+
+    /// Create a singular quadrature rule for a pair of adjacent cells
+    ///
+    /// `pairs` contains the pairs `(test_vertex, trial_vertex)` of local indices of the vertices that the cells share.
+    fn singular_rule(
+        &self,
+        test_cell_type: ReferenceCellType,
+        trial_cell_type: ReferenceCellType,
+        pairs: &[(usize, usize)],
+        qdegrees: &HashMap<Adjacency, usize>,
+    ) -> Result<SingularRule<T>, BemppError> {
+        // The rules for coincident cells assume that the vertices of the two cells are numbered in the same way, so
+        // the trial points are permuted if this is not the case
+        let permuted = pairs.len() > 2 && pairs.iter().any(|(i, j)| i != j);
+        let qrule = if permuted {
+            get_singular_quadrature_rule(
+                test_cell_type,
+                trial_cell_type,
+                &(0..pairs.len()).map(|i| (i, i)).collect::<Vec<_>>(),
+                qdegrees,
+            )?
+        } else {
+            get_singular_quadrature_rule(test_cell_type, trial_cell_type, pairs, qdegrees)?
+        };
+        let qrule_trial_points = if permuted {
+            permute_reference_points(trial_cell_type, pairs, &qrule.trial_points)?
+        } else {
+            qrule.trial_points.to_vec()
+        };
+        let npts = qrule.weights.len();
+
+        let mut trial_points = rlst_dynamic_array2!(<T as RlstScalar>::Real, [2, npts]);
+        for i in 0..npts {
+            for j in 0..2 {
+                *trial_points.get_mut([j, i]).unwrap() =
+                    num::cast::<f64, <T as RlstScalar>::Real>(qrule_trial_points[2 * i + j])
+                        .unwrap();
+            }
+        }
+
+        let mut test_points = rlst_dynamic_array2!(<T as RlstScalar>::Real, [2, npts]);
+        for i in 0..npts {
+            for j in 0..2 {
+                *test_points.get_mut([j, i]).unwrap() =
+                    num::cast::<f64, <T as RlstScalar>::Real>(qrule.test_points[2 * i + j])
+                        .unwrap();
+            }
+        }
+
+        Ok(SingularRule {
+            test_cell_type,
+            trial_cell_type,
+            test_points,
+            trial_points,
+            weights: qrule
+                .weights
+                .iter()
+                .map(|w| num::cast::<f64, <T as RlstScalar>::Real>(*w).unwrap())
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Find the pairs of cells of two different grids that share at least one vertex
+    ///
+    /// Vertices of the two grids are shared if the distance between them divided by the smallest cell diameter is less
+    /// than `coincidence_tolerance`. Each item contains the test and trial cell types, the pairs `(test_vertex,
+    /// trial_vertex)` of local indices of the shared vertices sorted by the trial vertex, and the test and trial cells.
+    ///
+    /// If the two spaces are on the same grid, no pairs are returned as adjacent cells are found from the topology.
+    fn touching_cell_pairs<
+        TestSpace: FunctionSpaceTrait<T = T>,
+        TrialSpace: FunctionSpaceTrait<T = T>,
+    >(
+        &self,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+    ) -> Vec<(CellPairKey, (usize, usize))> {
+        if self.options.coincidence_tolerance <= 0.0
+            || equal_grids(test_space.grid(), trial_space.grid())
+        {
+            return vec![];
+        }
+        let test_grid = test_space.grid();
+        let trial_grid = trial_space.grid();
+
+        let (_, test_radii) = cell_bounding_spheres(test_grid);
+        let (_, trial_radii) = cell_bounding_spheres(trial_grid);
+        let min_radius =
+            test_radii
+                .iter()
+                .chain(&trial_radii)
+                .fold(
+                    <T::Real as Float>::infinity(),
+                    |a, b| if *b < a { *b } else { a },
+                );
+        let tolerance = min_radius
+            * num::cast::<f64, T::Real>(2.0 * self.options.coincidence_tolerance).unwrap();
+
+        let vertex_map = coincident_vertices(test_grid, trial_grid, tolerance);
+        if vertex_map.is_empty() {
+            return vec![];
+        }
+
+        // The trial cells connected to each vertex, and the local index of the vertex in each cell
+        let mut vertex_cells = HashMap::<usize, Vec<(usize, usize)>>::new();
+        for cell in trial_grid.entity_iter(2) {
+            for (i, v) in cell.topology().sub_entity_iter(0).enumerate() {
+                vertex_cells
+                    .entry(v)
+                    .or_default()
+                    .push((cell.local_index(), i));
+            }
+        }
+
+        let mut touching = vec![];
+        for test_cell in test_grid.entity_iter(2) {
+            if test_cell.ownership() != Ownership::Owned {
+                continue;
+            }
+            let mut shared = HashMap::<usize, Vec<(usize, usize)>>::new();
+            for (test_i, v) in test_cell.topology().sub_entity_iter(0).enumerate() {
+                if let Some(trial_v) = vertex_map.get(&v) {
+                    for (trial_cell, trial_i) in &vertex_cells[trial_v] {
+                        shared
+                            .entry(*trial_cell)
+                            .or_default()
+                            .push((test_i, *trial_i));
+                    }
+                }
+            }
+            for (trial_cell, mut pairs) in shared {
+                pairs.sort_by_key(|(_, trial_i)| *trial_i);
+                let trial_cell_type = trial_grid.entity(2, trial_cell).unwrap().entity_type();
+                touching.push((
+                    (test_cell.entity_type(), trial_cell_type, pairs),
+                    (test_cell.local_index(), trial_cell),
+                ));
+            }
+        }
+        touching.sort_by_key(|(_, cells)| *cells);
+        touching
+    }
+
+    /// Assemble the non-singular contributions into a dense matrix
+    fn assemble_nonsingular_part<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        output: &RawData2D<T>,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+        trial_colouring: &HashMap<ReferenceCellType, Vec<Vec<usize>>>,
+        test_colouring: &HashMap<ReferenceCellType, Vec<Vec<usize>>>,
+        touching_pairs: &[(CellPairKey, (usize, usize))],
+    ) -> Result<(), BemppError> {
+        check_serial(trial_space, test_space)?;
+        check_shape(output.shape, trial_space, test_space)?;
+        self.check_options(trial_space, test_space)?;
+
+        let batch_size = self.options.batch_size;
+        let symmetric = self.use_symmetry(trial_space, test_space);
+
+        // When using symmetry, the contributions are assembled into a separate matrix so that only they are mirrored
+        let matrix = output;
+        let mut symmetric_part = if symmetric {
+            vec![T::zero(); matrix.shape[0] * matrix.shape[1]]
+        } else {
+            vec![]
+        };
+        let symmetric_raw = RawData2D {
+            data: symmetric_part.as_mut_ptr(),
+            shape: matrix.shape,
+        };
+        let output = if symmetric { &symmetric_raw } else { matrix };
+
+        let thresholds = if self.options.distance_adaptive_quadrature {
+            &self.options.distance_quadrature_thresholds[..]
+        } else {
+            &[]
+        };
+        let mixed_precision = self.options.mixed_precision && self.kernel.has_single_precision();
+        let use_distances = !thresholds.is_empty() || mixed_precision;
+        let (test_centres, test_radii) = if use_distances {
+            cell_bounding_spheres(test_space.grid())
+        } else {
+            (vec![], vec![])
+        };
+        let (trial_centres, trial_radii) = if use_distances {
+            cell_bounding_spheres(trial_space.grid())
+        } else {
+            (vec![], vec![])
+        };
+        let ratio = |test_cell: usize, trial_cell: usize| {
+            distance_ratio(
+                &test_centres[test_cell],
+                test_radii[test_cell],
+                &trial_centres[trial_cell],
+                trial_radii[trial_cell],
+            )
+        };
+        // The index of the quadrature rule that will be used for a pair of cells
+        let rule_index = |test_cell: usize, trial_cell: usize| {
+            if thresholds.is_empty() {
+                0
+            } else {
+                let ratio = ratio(test_cell, trial_cell);
+                thresholds
+                    .iter()
+                    .position(|(r, _)| ratio < *r)
+                    .unwrap_or(thresholds.len())
+            }
+        };
+        // Whether the kernel will be evaluated in single precision for a pair of cells
+        let single_precision = |test_cell: usize, trial_cell: usize| {
+            mixed_precision && ratio(test_cell, trial_cell) >= self.options.mixed_precision_cutoff
+        };
+
+        let mut near_pairs = if self.options.near_field_subdivision {
+            self.near_field_pairs(trial_space, test_space, trial_colouring, test_colouring)
+        } else {
+            HashMap::new()
+        };
+        if symmetric {
+            for pairs in near_pairs.values_mut() {
+                pairs.retain(|(test_cell, trial_cell)| test_cell < trial_cell);
+            }
+            near_pairs.retain(|_, pairs| !pairs.is_empty());
+        }
+
+        // Pairs of cells of different grids that touch are included in the singular part
+        let touching_set = touching_pairs
+            .iter()
+            .map(|(_, cells)| *cells)
+            .collect::<HashSet<_>>();
+        if !touching_set.is_empty() {
+            for pairs in near_pairs.values_mut() {
+                pairs.retain(|cells| !touching_set.contains(cells));
+            }
+            near_pairs.retain(|_, pairs| !pairs.is_empty());
+        }
+        let near_set = near_pairs
+            .values()
+            .flatten()
+            .copied()
+            .collect::<HashSet<_>>();
+
+        // The cells of each colour are split by the element used on them
+        let test_colouring = element_colouring(test_space, test_colouring);
+        let trial_colouring = element_colouring(trial_space, trial_colouring);
+
+        // When using tiled accumulation, all the cells that use each element are assembled together
+        let (all_test_cells, all_trial_cells) = match self.options.dense_accumulation {
+            DenseAccumulation::Colouring => (HashMap::new(), HashMap::new()),
+            DenseAccumulation::Tiled => (
+                test_colouring
+                    .iter()
+                    .map(|(key, colours)| (*key, colours.concat()))
+                    .collect::<HashMap<_, _>>(),
+                trial_colouring
+                    .iter()
+                    .map(|(key, colours)| (*key, colours.concat()))
+                    .collect::<HashMap<_, _>>(),
+            ),
+        };
+
+        for (test_key, test_colours) in &test_colouring {
+            let (test_cell_type, test_degree) = test_key;
+            for (trial_key, trial_colours) in &trial_colouring {
+                let (trial_cell_type, trial_degree) = trial_key;
+                let mut npoints = thresholds
+                    .iter()
+                    .map(|(_, degree)| {
+                        (
+                            regular_npoints(*test_cell_type, *degree),
+                            regular_npoints(*trial_cell_type, *degree),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                npoints.push((
+                    self.options.quadrature_degrees[test_cell_type],
+                    self.options.quadrature_degrees[trial_cell_type],
+                ));
+                // Each rule is tabulated once, and is used for the pairs of cells for which rule_index gives its index
+                let rules = npoints
+                    .iter()
+                    .map(|(npts_test, npts_trial)| {
+                        let (test_points, test_weights, test_table) =
+                            self.tabulate_regular_rule(test_space, *test_key, *npts_test);
+                        let (trial_points, trial_weights, trial_table) =
+                            self.tabulate_regular_rule(trial_space, *trial_key, *npts_trial);
+                        RegularRule {
+                            test_points,
+                            test_weights,
+                            trial_points,
+                            trial_weights,
+                            test_table,
+                            trial_table,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                let include_pair = |test_cell: usize, trial_cell: usize| {
+                    (!symmetric || test_cell < trial_cell)
+                        && (near_set.is_empty() || !near_set.contains(&(test_cell, trial_cell)))
+                        && (touching_set.is_empty()
+                            || !touching_set.contains(&(test_cell, trial_cell)))
+                        && !neighbours(test_space.grid(), trial_space.grid(), test_cell, trial_cell)
+                };
+
+                // The groups of cells whose batches can be assembled at the same time
+                let groups = match self.options.dense_accumulation {
+                    DenseAccumulation::Colouring => test_colours
+                        .iter()
+                        .flat_map(|test_c| {
+                            trial_colours
+                                .iter()
+                                .map(move |trial_c| (&test_c[..], &trial_c[..]))
+                        })
+                        .collect::<Vec<_>>(),
+                    DenseAccumulation::Tiled => {
+                        vec![(
+                            &all_test_cells[test_key][..],
+                            &all_trial_cells[trial_key][..],
+                        )]
+                    }
+                };
+
+                for (test_c, trial_c) in groups {
+                    let mut test_cells: Vec<&[usize]> = vec![];
+                    let mut trial_cells: Vec<&[usize]> = vec![];
+
+                    let mut test_start = 0;
+                    while test_start < test_c.len() {
+                        let test_end = if test_start + batch_size < test_c.len() {
+                            test_start + batch_size
+                        } else {
+                            test_c.len()
+                        };
+
+                        let mut trial_start = 0;
+                        while trial_start < trial_c.len() {
+                            let trial_end = if trial_start + batch_size < trial_c.len() {
+                                trial_start + batch_size
+                            } else {
+                                trial_c.len()
+                            };
+                            test_cells.push(&test_c[test_start..test_end]);
+                            trial_cells.push(&trial_c[trial_start..trial_end]);
+                            trial_start = trial_end;
+                        }
+                        test_start = test_end
+                    }
+
+                    let numtasks = test_cells.len();
+                    let assemble_batch = |t: usize, accumulate: &mut dyn FnMut(usize, usize, T)| {
+                        // Split the pairs of cells in the batch by the quadrature rule used for them
+                        let mut buckets = vec![vec![]; rules.len()];
+                        for trial_cell in trial_cells[t] {
+                            for test_cell in test_cells[t] {
+                                if include_pair(*test_cell, *trial_cell) {
+                                    buckets[rule_index(*test_cell, *trial_cell)]
+                                        .push((*test_cell, *trial_cell));
+                                }
+                            }
+                        }
+                        for (pairs, rule) in buckets.iter().zip(&rules) {
+                            if !pairs.is_empty() {
+                                assemble_batch_nonadjacent(
+                                    self,
+                                    self.deriv_size,
+                                    accumulate,
+                                    *test_cell_type,
+                                    *trial_cell_type,
+                                    trial_space,
+                                    test_space,
+                                    pairs,
+                                    &single_precision,
+                                    rule,
+                                );
+                            }
+                        }
+                        1
+                    };
+                    match self.options.dense_accumulation {
+                        // Cells of the same colour do not share DOFs, so the contributions of each batch can be
+                        // written directly
+                        DenseAccumulation::Colouring => {
+                            let r: usize = (0..numtasks)
+                                .into_par_iter()
+                                .map(&|t| {
+                                    assemble_batch(t, &mut |test_dof, trial_dof, value| unsafe {
+                                        *output.data.add(test_dof + output.shape[0] * trial_dof) +=
+                                            value;
+                                    })
+                                })
+                                .sum();
+                            assert_eq!(r, numtasks);
+                        }
+                        // Each thread adds contributions to its own tiles, which are added to the matrix once the
+                        // batches are assembled
+                        DenseAccumulation::Tiled => {
+                            let nthreads = rayon::current_num_threads().min(numtasks);
+                            let column_tiles = (0..nthreads)
+                                .into_par_iter()
+                                .map(|thread| {
+                                    let mut tiles = ColumnTiles::new(output.shape);
+                                    for t in (thread..numtasks).step_by(nthreads) {
+                                        assemble_batch(t, &mut |test_dof, trial_dof, value| {
+                                            tiles.add(test_dof, trial_dof, value)
+                                        });
+                                    }
+                                    tiles
+                                })
+                                .collect::<Vec<_>>();
+                            add_column_tiles(output, column_tiles);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !near_pairs.is_empty() {
+            let sparse_matrix =
+                self.assemble_near_field_part(output.shape, trial_space, test_space, &near_pairs);
+            for ((i, j), value) in sparse_matrix
+                .rows
+                .iter()
+                .zip(&sparse_matrix.cols)
+                .zip(&sparse_matrix.data)
+            {
+                unsafe {
+                    *output.data.add(*i + output.shape[0] * *j) += *value;
+                }
+            }
+        }
+
+        if symmetric {
+            // Each pair of cells was assembled with the test cell index less than the trial cell index, so the
+            // contributions of the other pairs are obtained by adding the transpose
+            let n = matrix.shape[0];
+            for j in 0..n {
+                for i in 0..n {
+                    unsafe {
+                        *matrix.data.add(i + n * j) +=
+                            symmetric_part[i + n * j] + symmetric_part[j + n * i];
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a regular quadrature rule with `npts` points on a cell, and tabulate the element used by a space on the
+    /// cells with the given key at its points
+    fn tabulate_regular_rule<Space: FunctionSpaceTrait<T = T>>(
+        &self,
+        space: &Space,
+        (cell_type, degree): ElementKey,
+        npts: usize,
+    ) -> (RlstArray<T::Real, 2>, Vec<T::Real>, RlstArray<T, 4>) {
+        let (points, weights) = regular_quadrature_rule::<T>(cell_type, npts);
+        let element = space.element_with_degree(cell_type, degree);
+        let mut table =
+            rlst_dynamic_array4!(T, element.tabulate_array_shape(self.table_derivs, npts));
+        element.tabulate(&points, self.table_derivs, &mut table);
+        (points, weights, table)
+    }
+
+    /// Find the pairs of non-adjacent cells that are nearly singular
+    ///
+    /// The pairs are grouped by the test and trial cell types.
+    fn near_field_pairs<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+        trial_colouring: &HashMap<ReferenceCellType, Vec<Vec<usize>>>,
+        test_colouring: &HashMap<ReferenceCellType, Vec<Vec<usize>>>,
+    ) -> HashMap<(ReferenceCellType, ReferenceCellType), Vec<(usize, usize)>> {
+        let test_grid = test_space.grid();
+        let trial_grid = trial_space.grid();
+        let (test_centres, test_radii) = cell_bounding_spheres(test_grid);
+        let (trial_centres, trial_radii) = cell_bounding_spheres(trial_grid);
+        let test_samples = cell_sample_points(test_grid);
+        let trial_samples = cell_sample_points(trial_grid);
+        let ratio = self.options.near_field_ratio;
+        let close_cells = close_spheres(
+            &test_centres,
+            &test_radii,
+            &trial_centres,
+            &trial_radii,
+            num::cast::<f64, <T as RlstScalar>::Real>(ratio).unwrap(),
+        );
+
+        let trial_cell_types = trial_colouring
+            .iter()
+            .filter(|(cell_type, _)| can_subdivide(**cell_type))
+            .flat_map(|(cell_type, colours)| {
+                colours.iter().flatten().map(|cell| (*cell, *cell_type))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut near_pairs = HashMap::new();
+        for (test_cell_type, test_colours) in test_colouring {
+            if !can_subdivide(*test_cell_type) {
+                continue;
+            }
+            let test_cells = test_colours.iter().flatten().copied().collect::<Vec<_>>();
+            let pairs = test_cells
+                .par_iter()
+                .flat_map_iter(|test_cell| {
+                    close_cells[*test_cell]
+                        .iter()
+                        .filter(|trial_cell| {
+                            trial_cell_types.contains_key(*trial_cell)
+                                && separation_ratio(
+                                    &test_samples[*test_cell],
+                                    &trial_samples[**trial_cell],
+                                ) < ratio
+                                && !neighbours(
+                                    test_space.grid(),
+                                    trial_space.grid(),
+                                    *test_cell,
+                                    **trial_cell,
+                                )
+                        })
+                        .map(|trial_cell| (trial_cell_types[trial_cell], (*test_cell, *trial_cell)))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            for (trial_cell_type, pair) in pairs {
+                near_pairs
+                    .entry((*test_cell_type, trial_cell_type))
+                    .or_insert_with(Vec::new)
+                    .push(pair);
+            }
+        }
+        near_pairs
+    }
+
+    /// Assemble the contributions from nearly-singular pairs of non-adjacent cells
+    fn assemble_near_field_part<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
         &self,
         shape: [usize; 2],
-        trial_space: &Space,
-        test_space: &Space,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+        near_pairs: &HashMap<(ReferenceCellType, ReferenceCellType), Vec<(usize, usize)>>,
     ) -> SparseMatrixData<T> {
-        if !equal_grids(test_space.grid(), trial_space.grid()) {
-            // If the test and trial grids are different, there are no neighbouring triangles
-            return SparseMatrixData::new(shape);
-        }
-
-        if shape[0] != test_space.global_size() || shape[1] != trial_space.global_size() {
-            panic!("Matrix has wrong shape");
-        }
-
-        let grid = test_space.grid();
-
-        let mut qweights = vec![];
-        let mut trial_points = vec![];
-        let mut test_points = vec![];
-        let mut trial_tables = vec![];
-        let mut test_tables = vec![];
-        let mut test_cell_types = vec![];
-        let mut trial_cell_types = vec![];
-
-        let mut pair_indices = HashMap::new();
-
-        for test_cell_type in grid.entity_types(2) {
-            for trial_cell_type in grid.entity_types(2) {
-                let qdegree =
-                    self.options.singular_quadrature_degrees[&(*test_cell_type, *trial_cell_type)];
-                let offset = qweights.len();
-
-                let mut possible_pairs = vec![];
-                // Vertex-adjacent
-                for i in 0..reference_cell::entity_counts(*test_cell_type)[0] {
-                    for j in 0..reference_cell::entity_counts(*trial_cell_type)[0] {
-                        possible_pairs.push(vec![(i, j)]);
-                    }
-                }
-                // edge-adjacent
-                for test_e in reference_cell::edges(*test_cell_type) {
-                    for trial_e in reference_cell::edges(*trial_cell_type) {
-                        possible_pairs.push(vec![(test_e[0], trial_e[0]), (test_e[1], trial_e[1])]);
-                        possible_pairs.push(vec![(test_e[1], trial_e[0]), (test_e[0], trial_e[1])]);
-                    }
-                }
-                // Same cell
-                if test_cell_type == trial_cell_type {
-                    possible_pairs.push(
-                        (0..reference_cell::entity_counts(*test_cell_type)[0])
-                            .map(&|i| (i, i))
-                            .collect::<Vec<_>>(),
-                    );
-                }
-
-                for (i, pairs) in possible_pairs.iter().enumerate() {
-                    pair_indices.insert(
-                        (*test_cell_type, *trial_cell_type, pairs.clone()),
-                        offset + i,
-                    );
-                    test_cell_types.push(*test_cell_type);
-                    trial_cell_types.push(*trial_cell_type);
-                }
-
-                for pairs in &possible_pairs {
-                    let qrule = get_singular_quadrature_rule(
-                        *test_cell_type,
-                        *trial_cell_type,
-                        pairs,
-                        qdegree,
-                    );
-                    let npts = qrule.weights.len();
-
-                    let mut points = rlst_dynamic_array2!(<T as RlstScalar>::Real, [2, npts]);
-                    for i in 0..npts {
-                        for j in 0..2 {
-                            *points.get_mut([j, i]).unwrap() =
-                                num::cast::<f64, <T as RlstScalar>::Real>(
-                                    qrule.trial_points[2 * i + j],
-                                )
-                                .unwrap();
-                        }
-                    }
-                    let trial_element = trial_space.element(*trial_cell_type);
-                    let mut table = rlst_dynamic_array4!(
-                        T,
-                        trial_element.tabulate_array_shape(self.table_derivs, points.shape()[1])
-                    );
-                    trial_element.tabulate(&points, self.table_derivs, &mut table);
-                    trial_points.push(points);
-                    trial_tables.push(table);
-
-                    let mut points = rlst_dynamic_array2!(<T as RlstScalar>::Real, [2, npts]);
-                    for i in 0..npts {
-                        for j in 0..2 {
-                            *points.get_mut([j, i]).unwrap() =
-                                num::cast::<f64, <T as RlstScalar>::Real>(
-                                    qrule.test_points[2 * i + j],
-                                )
-                                .unwrap();
-                        }
-                    }
-                    let test_element = test_space.element(*test_cell_type);
-                    let mut table = rlst_dynamic_array4!(
-                        T,
-                        test_element.tabulate_array_shape(self.table_derivs, points.shape()[1])
-                    );
-                    test_element.tabulate(&points, self.table_derivs, &mut table);
-                    test_points.push(points);
-                    test_tables.push(table);
-                    qweights.push(
-                        qrule
-                            .weights
-                            .iter()
-                            .map(|w| num::cast::<f64, <T as RlstScalar>::Real>(*w).unwrap())
-                            .collect::<Vec<_>>(),
-                    );
-                }
+        let mut cell_blocks = vec![];
+        for ((test_cell_type, trial_cell_type), pairs) in near_pairs {
+            for block in pairs.chunks(self.options.batch_size) {
+                cell_blocks.push((*test_cell_type, *trial_cell_type, block));
             }
         }
-        let cell_blocks = make_cell_blocks(
-            |test_cell_type, trial_cell_type, pairs| {
-                pair_indices[&(test_cell_type, trial_cell_type, pairs)]
-            },
-            pair_indices.len(),
-            grid,
-            self.options.batch_size,
-        );
 
-        let map = cell_blocks.into_par_iter().map(|(i, cell_block)| {
-            assemble_batch_singular(
-                self,
-                self.deriv_size,
-                shape,
-                trial_cell_types[i],
-                test_cell_types[i],
-                trial_space,
-                test_space,
-                &cell_block,
-                &trial_points[i],
-                &test_points[i],
-                &qweights[i],
-                &trial_tables[i],
-                &test_tables[i],
-            )
-        });
-        // For some reason rust analyzer threw an error when simply writing
-        // map.reduce(...) even though the code compiled fine. Doing it this
-        // way allows rust analyer to see that the `reduce` method is from
-        // `ParallelIterator` and not from the std::core Iterator
+        let map =
+            cell_blocks
+                .into_par_iter()
+                .map(|(test_cell_type, trial_cell_type, cell_block)| {
+                    assemble_batch_near_field(
+                        self,
+                        self.deriv_size,
+                        shape,
+                        trial_cell_type,
+                        test_cell_type,
+                        trial_space,
+                        test_space,
+                        cell_block,
+                    )
+                });
         ParallelIterator::reduce(
             map,
             || SparseMatrixData::<T>::new(shape),
@@ -391,144 +1801,205 @@ impl<'o, T: RlstScalar + MatrixInverse, Integrand: BoundaryIntegrand<T = T>, K:
             },
         )
     }
+}
 
-    /// Assemble the non-singular contributions into a dense matrix
-    fn assemble_nonsingular_part<Space: FunctionSpaceTrait<T = T> + Sync>(
-        &self,
-        output: &RawData2D<T>,
-        trial_space: &Space,
-        test_space: &Space,
-        trial_colouring: &HashMap<ReferenceCellType, Vec<Vec<usize>>>,
-        test_colouring: &HashMap<ReferenceCellType, Vec<Vec<usize>>>,
-    ) {
-        if !trial_space.is_serial() || !test_space.is_serial() {
-            panic!("Dense assembly can only be used for function spaces stored in serial");
-        }
-        if output.shape[0] != test_space.global_size()
-            || output.shape[1] != trial_space.global_size()
-        {
-            panic!("Matrix has wrong shape");
+/// Get the matrix of an adjoint double layer operator from the matrix of the corresponding double layer operator
+///
+/// If the same space is used as the test and trial space, the adjoint double layer matrix is the transpose of the
+/// double layer matrix. For Helmholtz problems, this is the transpose and not the conjugate transpose.
+pub fn adjoint_double_layer_from_double_layer<T: RlstScalar>(
+    double_layer: &DynamicArray<T, 2>,
+) -> DynamicArray<T, 2> {
+    let [nrows, ncols] = double_layer.shape();
+    let mut output = rlst_dynamic_array2!(T, [ncols, nrows]);
+    let data = double_layer.data();
+    let output_data = output.data_mut();
+    for j in 0..ncols {
+        for i in 0..nrows {
+            output_data[j + ncols * i] = data[i + nrows * j];
         }
+    }
+    output
+}
 
-        let batch_size = self.options.batch_size;
-
-        for test_cell_type in test_space.grid().entity_types(2) {
-            let npts_test = self.options.quadrature_degrees[test_cell_type];
-            for trial_cell_type in trial_space.grid().entity_types(2) {
-                let npts_trial = self.options.quadrature_degrees[trial_cell_type];
-                let qrule_test = simplex_rule(*test_cell_type, npts_test).unwrap();
-                let mut qpoints_test =
-                    rlst_dynamic_array2!(<T as RlstScalar>::Real, [2, npts_test]);
-                for i in 0..npts_test {
-                    for j in 0..2 {
-                        *qpoints_test.get_mut([j, i]).unwrap() =
-                            num::cast::<f64, <T as RlstScalar>::Real>(qrule_test.points[2 * i + j])
-                                .unwrap();
-                    }
-                }
-                let qweights_test = qrule_test
-                    .weights
-                    .iter()
-                    .map(|w| num::cast::<f64, <T as RlstScalar>::Real>(*w).unwrap())
-                    .collect::<Vec<_>>();
-                let qrule_trial = simplex_rule(*trial_cell_type, npts_trial).unwrap();
-                let mut qpoints_trial =
-                    rlst_dynamic_array2!(<T as RlstScalar>::Real, [2, npts_trial]);
-                for i in 0..npts_trial {
-                    for j in 0..2 {
-                        *qpoints_trial.get_mut([j, i]).unwrap() =
-                            num::cast::<f64, <T as RlstScalar>::Real>(
-                                qrule_trial.points[2 * i + j],
-                            )
-                            .unwrap();
-                    }
-                }
-                let qweights_trial = qrule_trial
-                    .weights
-                    .iter()
-                    .map(|w| num::cast::<f64, <T as RlstScalar>::Real>(*w).unwrap())
-                    .collect::<Vec<_>>();
-
-                let test_element = test_space.element(*test_cell_type);
-                let mut test_table = rlst_dynamic_array4!(
-                    T,
-                    test_element.tabulate_array_shape(self.table_derivs, npts_test)
-                );
-                test_element.tabulate(&qpoints_test, self.table_derivs, &mut test_table);
+/// Get the points and weights of a quadrature rule for non-singular integrals
+///
+/// The number of points must have been checked by [BoundaryAssemblerOptions::validate].
+pub(crate) fn regular_quadrature_rule<T: RlstScalar>(
+    cell_type: ReferenceCellType,
+    npts: usize,
+) -> (RlstArray<T::Real, 2>, Vec<T::Real>) {
+    let qrule = simplex_rule(cell_type, npts).unwrap_or_else(|_| {
+        panic!(
+            "{}",
+            QuadratureOptionsError::InvalidPointCount(cell_type, npts)
+        )
+    });
+    let mut points = rlst_dynamic_array2!(<T as RlstScalar>::Real, [2, npts]);
+    for i in 0..npts {
+        for j in 0..2 {
+            *points.get_mut([j, i]).unwrap() =
+                num::cast::<f64, <T as RlstScalar>::Real>(qrule.points[2 * i + j]).unwrap();
+        }
+    }
+    let weights = qrule
+        .weights
+        .iter()
+        .map(|w| num::cast::<f64, <T as RlstScalar>::Real>(*w).unwrap())
+        .collect::<Vec<_>>();
+    (points, weights)
+}
 
-                let trial_element = trial_space.element(*trial_cell_type);
-                let mut trial_table = rlst_dynamic_array4!(
-                    T,
-                    trial_element.tabulate_array_shape(self.table_derivs, npts_trial)
-                );
-                trial_element.tabulate(&qpoints_test, self.table_derivs, &mut trial_table);
+/// Tiles of columns of a dense matrix that one thread adds contributions to
+///
+/// Each tile contains [TILE_COLUMNS] columns of the matrix stored in column-major order, and is allocated when an entry
+/// is first added to it.
+struct ColumnTiles<T: RlstScalar> {
+    nrows: usize,
+    tiles: Vec<Vec<T>>,
+}
 
-                for test_c in &test_colouring[test_cell_type] {
-                    for trial_c in &trial_colouring[trial_cell_type] {
-                        let mut test_cells: Vec<&[usize]> = vec![];
-                        let mut trial_cells: Vec<&[usize]> = vec![];
+impl<T: RlstScalar> ColumnTiles<T> {
+    /// Create tiles for a matrix with the given shape
+    fn new(shape: [usize; 2]) -> Self {
+        Self {
+            nrows: shape[0],
+            tiles: vec![vec![]; shape[1].div_ceil(TILE_COLUMNS)],
+        }
+    }
 
-                        let mut test_start = 0;
-                        while test_start < test_c.len() {
-                            let test_end = if test_start + batch_size < test_c.len() {
-                                test_start + batch_size
-                            } else {
-                                test_c.len()
-                            };
+    /// Add a value to an entry
+    fn add(&mut self, row: usize, col: usize, value: T) {
+        let tile = &mut self.tiles[col / TILE_COLUMNS];
+        if tile.is_empty() {
+            *tile = vec![T::zero(); self.nrows * TILE_COLUMNS];
+        }
+        tile[row + self.nrows * (col % TILE_COLUMNS)] += value;
+    }
+}
 
-                            let mut trial_start = 0;
-                            while trial_start < trial_c.len() {
-                                let trial_end = if trial_start + batch_size < trial_c.len() {
-                                    trial_start + batch_size
-                                } else {
-                                    trial_c.len()
-                                };
-                                test_cells.push(&test_c[test_start..test_end]);
-                                trial_cells.push(&trial_c[trial_start..trial_end]);
-                                trial_start = trial_end;
+/// Add the tiles of every thread to a dense matrix
+///
+/// Each tile of columns of the matrix is written by a single task, so the tiles are added in parallel without locking.
+fn add_column_tiles<T: RlstScalar + MatrixInverse>(
+    output: &RawData2D<T>,
+    column_tiles: Vec<ColumnTiles<T>>,
+) {
+    if column_tiles.is_empty() {
+        return;
+    }
+    let [nrows, ncols] = output.shape;
+    (0..ncols.div_ceil(TILE_COLUMNS))
+        .into_par_iter()
+        .for_each(|tile_index| {
+            let first_col = tile_index * TILE_COLUMNS;
+            let ntile_cols = TILE_COLUMNS.min(ncols - first_col);
+            for tiles in &column_tiles {
+                let tile = &tiles.tiles[tile_index];
+                if !tile.is_empty() {
+                    for (col, values) in tile.chunks(nrows).take(ntile_cols).enumerate() {
+                        for (row, value) in values.iter().enumerate() {
+                            unsafe {
+                                *output.data.add(row + nrows * (first_col + col)) += *value;
                             }
-                            test_start = test_end
                         }
-
-                        let numtasks = test_cells.len();
-                        let r: usize = (0..numtasks)
-                            .into_par_iter()
-                            .map(&|t| {
-                                assemble_batch_nonadjacent(
-                                    self,
-                                    self.deriv_size,
-                                    output,
-                                    *test_cell_type,
-                                    *trial_cell_type,
-                                    trial_space,
-                                    trial_cells[t],
-                                    test_space,
-                                    test_cells[t],
-                                    &qpoints_trial,
-                                    &qweights_trial,
-                                    &qpoints_test,
-                                    &qweights_test,
-                                    &trial_table,
-                                    &test_table,
-                                )
-                            })
-                            .sum();
-                        assert_eq!(r, numtasks);
                     }
                 }
             }
+        });
+}
+
+/// The distance between two cells divided by the larger of their diameters
+///
+/// The cells are represented by bounding spheres, so this is a lower bound for the true ratio.
+fn distance_ratio<T: Float>(
+    test_centre: &[T; 3],
+    test_radius: T,
+    trial_centre: &[T; 3],
+    trial_radius: T,
+) -> f64 {
+    let centre_distance = Float::sqrt(
+        test_centre
+            .iter()
+            .zip(trial_centre)
+            .map(|(a, b)| (*a - *b) * (*a - *b))
+            .fold(T::zero(), |a, b| a + b),
+    );
+    let distance = Float::max(centre_distance - test_radius - trial_radius, T::zero());
+    let diameter = Float::max(test_radius, trial_radius) + Float::max(test_radius, trial_radius);
+    num::cast::<T, f64>(distance / diameter).unwrap()
+}
+
+/// Split the colours of the cells of each type by the element used on each cell
+///
+/// The items are ordered by cell type then by degree, and contain the cell type and degree and the colours of the cells
+/// that use the element of this degree. Colours with no cells are removed.
+fn element_colouring<Space: FunctionSpaceTrait>(
+    space: &Space,
+    colouring: &HashMap<ReferenceCellType, Vec<Vec<usize>>>,
+) -> Vec<(ElementKey, Vec<Vec<usize>>)> {
+    let mut element_colouring = vec![];
+    for cell_type in space.grid().entity_types(2) {
+        if let Some(colours) = colouring.get(cell_type) {
+            for degree in space.cell_type_degrees(*cell_type) {
+                let colours = colours
+                    .iter()
+                    .map(|c| {
+                        c.iter()
+                            .copied()
+                            .filter(|cell| space.cell_degree(*cell) == degree)
+                            .collect::<Vec<_>>()
+                    })
+                    .filter(|c| !c.is_empty())
+                    .collect::<Vec<_>>();
+                if !colours.is_empty() {
+                    element_colouring.push(((*cell_type, degree), colours));
+                }
+            }
         }
     }
+    element_colouring
+}
+
+/// Check that function spaces are stored in serial, as required by dense assembly
+fn check_serial<TestSpace: FunctionSpaceTrait, TrialSpace: FunctionSpaceTrait>(
+    trial_space: &TrialSpace,
+    test_space: &TestSpace,
+) -> Result<(), BemppError> {
+    if !trial_space.is_serial() || !test_space.is_serial() {
+        Err(BemppError::NotSerial("Dense assembly".to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that a matrix has the right shape for a pair of function spaces
+fn check_shape<TestSpace: FunctionSpaceTrait, TrialSpace: FunctionSpaceTrait>(
+    shape: [usize; 2],
+    trial_space: &TrialSpace,
+    test_space: &TestSpace,
+) -> Result<(), BemppError> {
+    let expected = [test_space.global_size(), trial_space.global_size()];
+    if shape != expected {
+        Err(BemppError::WrongShape {
+            expected,
+            actual: shape,
+        })
+    } else {
+        Ok(())
+    }
 }
 
 fn get_singular_quadrature_rule(
     test_celltype: ReferenceCellType,
     trial_celltype: ReferenceCellType,
     pairs: &[(usize, usize)],
-    npoints: usize,
-) -> TestTrialNumericalQuadratureDefinition {
+    npoints: &HashMap<Adjacency, usize>,
+) -> Result<TestTrialNumericalQuadratureDefinition, BemppError> {
     if pairs.is_empty() {
-        panic!("Non-singular rule requested.");
+        return Err(BemppError::QuadratureRule(
+            "a singular rule was requested for a pair of cells that are not adjacent".to_string(),
+        ));
     }
     let con = CellToCellConnectivity {
         connectivity_dimension: match pairs.len() {
@@ -538,27 +2009,24 @@ fn get_singular_quadrature_rule(
         },
         local_indices: pairs.to_vec(),
     };
-    match test_celltype {
+    let adjacency = Adjacency::from_connectivity(&con);
+    let npoints = *npoints.get(&adjacency).ok_or(BemppError::Quadrature(
+        QuadratureOptionsError::MissingSingularDegree(test_celltype, trial_celltype, adjacency),
+    ))?;
+    let rule = match test_celltype {
         ReferenceCellType::Triangle => match trial_celltype {
-            ReferenceCellType::Triangle => triangle_duffy(&con, npoints).unwrap(),
-            ReferenceCellType::Quadrilateral => {
-                triangle_quadrilateral_duffy(&con, npoints).unwrap()
-            }
-            _ => {
-                unimplemented!("Only triangles and quadrilaterals are currently supported");
-            }
+            ReferenceCellType::Triangle => triangle_duffy(&con, npoints),
+            ReferenceCellType::Quadrilateral => triangle_quadrilateral_duffy(&con, npoints),
+            _ => return Err(BemppError::UnsupportedCellType(trial_celltype)),
         },
         ReferenceCellType::Quadrilateral => match trial_celltype {
-            ReferenceCellType::Triangle => quadrilateral_triangle_duffy(&con, npoints).unwrap(),
-            ReferenceCellType::Quadrilateral => quadrilateral_duffy(&con, npoints).unwrap(),
-            _ => {
-                unimplemented!("Only triangles and quadrilaterals are currently supported");
-            }
+            ReferenceCellType::Triangle => quadrilateral_triangle_duffy(&con, npoints),
+            ReferenceCellType::Quadrilateral => quadrilateral_duffy(&con, npoints),
+            _ => return Err(BemppError::UnsupportedCellType(trial_celltype)),
         },
-        _ => {
-            unimplemented!("Only triangles and quadrilaterals are currently supported");
-        }
-    }
+        _ => return Err(BemppError::UnsupportedCellType(test_celltype)),
+    };
+    rule.map_err(|e| BemppError::QuadratureRule(format!("{e:?}")))
 }
 
 fn make_cell_blocks<F>(
@@ -566,6 +2034,7 @@ fn make_cell_blocks<F>(
     size: usize,
     grid: &impl Grid<EntityDescriptor = ReferenceCellType>,
     batch_size: usize,
+    symmetric: bool,
 ) -> Vec<(usize, Vec<(usize, usize)>)>
 where
     F: Fn(ReferenceCellType, ReferenceCellType, Vec<(usize, usize)>) -> usize,
@@ -578,6 +2047,9 @@ where
             let test_cell_type = test_cell.entity_type();
             if test_cell.ownership() == Ownership::Owned {
                 for trial_cell_index in vertex.topology().connected_entity_iter(2) {
+                    if symmetric && test_cell_index > trial_cell_index {
+                        continue;
+                    }
                     let trial_cell = grid.entity(2, trial_cell_index).unwrap();
                     let trial_cell_type = trial_cell.entity_type();
 
@@ -591,6 +2063,14 @@ where
             }
         }
     }
+    batch_cell_pairs(cell_pairs, batch_size)
+}
+
+/// Split the lists of pairs of cells that use each singular quadrature rule into batches
+fn batch_cell_pairs(
+    cell_pairs: Vec<Vec<(usize, usize)>>,
+    batch_size: usize,
+) -> Vec<(usize, Vec<(usize, usize)>)> {
     let mut cell_blocks = vec![];
 
     for (i, cells) in cell_pairs.iter().enumerate() {
@@ -605,45 +2085,73 @@ where
     cell_blocks
 }
 
+/// Map points on a reference cell to the points on the same cell with its vertices renumbered
+///
+/// `pairs` contains pairs `(i, j)` indicating that vertex `i` is renumbered as vertex `j`. The points are stored as
+/// [x0, y0, x1, y1, ...].
+fn permute_reference_points(
+    cell_type: ReferenceCellType,
+    pairs: &[(usize, usize)],
+    points: &[f64],
+) -> Result<Vec<f64>, BemppError> {
+    let vertices = reference_vertices::<f64>(cell_type);
+    let mut permuted = vec![0.0; points.len()];
+    for (p, q) in points.chunks(2).zip(permuted.chunks_mut(2)) {
+        let vertex_functions = match cell_type {
+            ReferenceCellType::Triangle => vec![1.0 - p[0] - p[1], p[0], p[1]],
+            ReferenceCellType::Quadrilateral => vec![
+                (1.0 - p[0]) * (1.0 - p[1]),
+                p[0] * (1.0 - p[1]),
+                (1.0 - p[0]) * p[1],
+                p[0] * p[1],
+            ],
+            _ => return Err(BemppError::UnsupportedCellType(cell_type)),
+        };
+        for (i, j) in pairs {
+            q[0] += vertex_functions[*i] * vertices[2 * j];
+            q[1] += vertex_functions[*i] * vertices[2 * j + 1];
+        }
+    }
+    Ok(permuted)
+}
+
 /// Assemble the contribution to the terms of a matrix for a batch of pairs of adjacent cells
 #[allow(clippy::too_many_arguments)]
 fn assemble_batch_singular<
     T: RlstScalar + MatrixInverse,
-    Space: FunctionSpaceTrait<T = T>,
+    TestSpace: FunctionSpaceTrait<T = T>,
+    TrialSpace: FunctionSpaceTrait<T = T>,
     Integrand: BoundaryIntegrand<T = T>,
     K: Kernel<T = T>,
 >(
     assembler: &BoundaryAssembler<T, Integrand, K>,
     deriv_size: usize,
-    shape: [usize; 2],
+    accumulate: &mut dyn FnMut(usize, usize, T),
     trial_cell_type: ReferenceCellType,
     test_cell_type: ReferenceCellType,
-    trial_space: &Space,
-    test_space: &Space,
+    trial_space: &TrialSpace,
+    test_space: &TestSpace,
     cell_pairs: &[(usize, usize)],
     trial_points: &RlstArray<T::Real, 2>,
     test_points: &RlstArray<T::Real, 2>,
     weights: &[T::Real],
     trial_table: &RlstArray<T, 4>,
     test_table: &RlstArray<T, 4>,
-) -> SparseMatrixData<T> {
-    let mut output = SparseMatrixData::<T>::new_known_size(
-        shape,
-        cell_pairs.len()
-            * trial_space.element(trial_cell_type).dim()
-            * test_space.element(test_cell_type).dim(),
-    );
+) {
     let npts = weights.len();
     debug_assert!(weights.len() == npts);
     debug_assert!(test_points.shape()[1] == npts);
     debug_assert!(trial_points.shape()[1] == npts);
 
-    let grid = test_space.grid();
-    assert_eq!(grid.geometry_dim(), 3);
-    assert_eq!(grid.topology_dim(), 2);
+    let test_grid = test_space.grid();
+    let trial_grid = trial_space.grid();
+    assert_eq!(test_grid.geometry_dim(), 3);
+    assert_eq!(test_grid.topology_dim(), 2);
+    assert_eq!(trial_grid.geometry_dim(), 3);
+    assert_eq!(trial_grid.topology_dim(), 2);
 
-    let test_evaluator = grid.geometry_map(test_cell_type, test_points.data());
-    let trial_evaluator = grid.geometry_map(trial_cell_type, trial_points.data());
+    let test_evaluator = test_grid.geometry_map(test_cell_type, test_points.data());
+    let trial_evaluator = trial_grid.geometry_map(trial_cell_type, trial_points.data());
 
     let mut a = SingularCellPairAssembler::new(
         npts,
@@ -654,64 +2162,78 @@ fn assemble_batch_singular<
         trial_evaluator,
         test_table,
         trial_table,
+        test_space.map_type(test_cell_type),
+        trial_space.map_type(trial_cell_type),
         weights,
     );
 
-    let mut local_mat = rlst_dynamic_array2!(
-        T,
-        [
-            test_space.element(test_cell_type).dim(),
-            trial_space.element(trial_cell_type).dim()
-        ]
-    );
+    let mut local_mat = rlst_dynamic_array2!(T, [test_table.shape()[2], trial_table.shape()[2]]);
     for (test_cell, trial_cell) in cell_pairs {
         a.set_test_cell(*test_cell);
+        if test_space.reversed_normal(*test_cell) {
+            a.reverse_test_orientation();
+        }
         a.set_trial_cell(*trial_cell);
+        if trial_space.reversed_normal(*trial_cell) {
+            a.reverse_trial_orientation();
+        }
         a.assemble(&mut local_mat);
+        apply_dof_signs(
+            &mut local_mat,
+            test_space.reversed_dofs(*test_cell),
+            trial_space.reversed_dofs(*trial_cell),
+        );
 
         let test_dofs = unsafe { test_space.cell_dofs_unchecked(*test_cell) };
         let trial_dofs = unsafe { trial_space.cell_dofs_unchecked(*trial_cell) };
 
-        for (trial_dof, col) in izip!(trial_dofs, local_mat.col_iter()) {
-            for (test_dof, entry) in izip!(test_dofs, col.iter()) {
-                output.rows.push(test_space.global_dof_index(*test_dof));
-                output.cols.push(trial_space.global_dof_index(*trial_dof));
-                output.data.push(entry);
+        let test_excluded = test_space.excluded_dofs(*test_cell);
+        let trial_excluded = trial_space.excluded_dofs(*trial_cell);
+        for (j, (trial_dof, col)) in izip!(trial_dofs, local_mat.col_iter()).enumerate() {
+            if is_excluded(trial_excluded, j) {
+                continue;
+            }
+            for (i, (test_dof, entry)) in izip!(test_dofs, col.iter()).enumerate() {
+                if is_excluded(test_excluded, i) {
+                    continue;
+                }
+                accumulate(
+                    test_space.global_dof_index(*test_dof),
+                    trial_space.global_dof_index(*trial_dof),
+                    entry,
+                );
             }
         }
     }
-
-    output
 }
 
 /// Assemble the contribution to the terms of a matrix for a batch of non-adjacent cells
+///
+/// `pairs` contains the pairs `(test_cell, trial_cell)` of cells in the batch that are integrated using `rule`. The pairs
+/// that share a trial cell must be next to each other.
 #[allow(clippy::too_many_arguments)]
 fn assemble_batch_nonadjacent<
     T: RlstScalar + MatrixInverse,
-    Space: FunctionSpaceTrait<T = T>,
+    TestSpace: FunctionSpaceTrait<T = T>,
+    TrialSpace: FunctionSpaceTrait<T = T>,
     Integrand: BoundaryIntegrand<T = T>,
     K: Kernel<T = T>,
 >(
     assembler: &BoundaryAssembler<T, Integrand, K>,
     deriv_size: usize,
-    output: &RawData2D<T>,
-    trial_cell_type: ReferenceCellType,
+    accumulate: &mut dyn FnMut(usize, usize, T),
     test_cell_type: ReferenceCellType,
-    trial_space: &Space,
-    trial_cells: &[usize],
-    test_space: &Space,
-    test_cells: &[usize],
-    trial_points: &RlstArray<T::Real, 2>,
-    trial_weights: &[T::Real],
-    test_points: &RlstArray<T::Real, 2>,
-    test_weights: &[T::Real],
-    trial_table: &RlstArray<T, 4>,
-    test_table: &RlstArray<T, 4>,
+    trial_cell_type: ReferenceCellType,
+    trial_space: &TrialSpace,
+    test_space: &TestSpace,
+    pairs: &[(usize, usize)],
+    single_precision: &(impl Fn(usize, usize) -> bool + Sync),
+    rule: &RegularRule<T>,
 ) -> usize {
-    let npts_test = test_weights.len();
-    let npts_trial = trial_weights.len();
-    debug_assert!(test_points.shape()[1] == npts_test);
-    debug_assert!(trial_points.shape()[1] == npts_trial);
+    let npts_test = rule.test_weights.len();
+    let npts_trial = rule.trial_weights.len();
+    debug_assert!(rule.test_points.shape()[1] == npts_test);
+    debug_assert!(rule.trial_points.shape()[1] == npts_trial);
 
     let test_grid = test_space.grid();
     let trial_grid = trial_space.grid();
@@ -721,50 +2243,72 @@ fn assemble_batch_nonadjacent<
     assert_eq!(trial_grid.geometry_dim(), 3);
     assert_eq!(trial_grid.topology_dim(), 2);
 
-    let test_evaluator = test_grid.geometry_map(test_cell_type, test_points.data());
-    let trial_evaluator = trial_grid.geometry_map(trial_cell_type, trial_points.data());
+    // Only cache the test cells that are paired with a trial cell in this batch
+    let mut test_cells = pairs
+        .iter()
+        .map(|(test_cell, _)| *test_cell)
+        .collect::<Vec<_>>();
+    test_cells.sort_unstable();
+    test_cells.dedup();
+    if test_cells.is_empty() {
+        return 1;
+    }
+
+    let test_evaluator = test_grid.geometry_map(test_cell_type, rule.test_points.data());
+    let trial_evaluator = trial_grid.geometry_map(trial_cell_type, rule.trial_points.data());
 
     let mut a = NonsingularCellPairAssemblerWithTestCaching::new(
         npts_test,
         npts_trial,
         deriv_size,
-        test_cells,
+        &test_cells,
         &assembler.integrand,
         &assembler.kernel,
         test_evaluator,
         trial_evaluator,
-        test_table,
-        trial_table,
-        test_weights,
-        trial_weights,
-    );
-
-    let mut local_mat = rlst_dynamic_array2!(
-        T,
-        [
-            test_space.element(test_cell_type).dim(),
-            trial_space.element(trial_cell_type).dim()
-        ]
+        &rule.test_table,
+        &rule.trial_table,
+        test_space.map_type(test_cell_type),
+        trial_space.map_type(trial_cell_type),
+        &rule.test_weights,
+        &rule.trial_weights,
+        |cell| test_space.reversed_normal(cell),
     );
 
-    for trial_cell in trial_cells {
-        a.set_trial_cell(*trial_cell);
-        let trial_dofs = unsafe { trial_space.cell_dofs_unchecked(*trial_cell) };
-        for test_cell in test_cells.iter() {
-            if neighbours(test_grid, trial_grid, *test_cell, *trial_cell) {
-                continue;
-            }
+    let mut local_mat =
+        rlst_dynamic_array2!(T, [rule.test_table.shape()[2], rule.trial_table.shape()[2]]);
 
+    for trial_pairs in
+        pairs.chunk_by(|(_, trial_cell0), (_, trial_cell1)| trial_cell0 == trial_cell1)
+    {
+        let trial_cell = trial_pairs[0].1;
+        a.set_trial_cell(trial_cell);
+        if trial_space.reversed_normal(trial_cell) {
+            a.reverse_trial_orientation();
+        }
+        let trial_dofs = unsafe { trial_space.cell_dofs_unchecked(trial_cell) };
+        let trial_excluded = trial_space.excluded_dofs(trial_cell);
+        for (test_cell, _) in trial_pairs {
             a.set_test_cell(*test_cell);
+            a.set_single_precision(single_precision(*test_cell, trial_cell));
             a.assemble(&mut local_mat);
+            apply_dof_signs(
+                &mut local_mat,
+                test_space.reversed_dofs(*test_cell),
+                trial_space.reversed_dofs(trial_cell),
+            );
 
             let test_dofs = unsafe { test_space.cell_dofs_unchecked(*test_cell) };
-
-            for (trial_dof, col) in izip!(trial_dofs, local_mat.col_iter()) {
-                for (test_dof, entry) in izip!(test_dofs, col.iter()) {
-                    unsafe {
-                        *output.data.add(*test_dof + output.shape[0] * *trial_dof) += entry;
+            let test_excluded = test_space.excluded_dofs(*test_cell);
+            for (j, (trial_dof, col)) in izip!(trial_dofs, local_mat.col_iter()).enumerate() {
+                if is_excluded(trial_excluded, j) {
+                    continue;
+                }
+                for (i, (test_dof, entry)) in izip!(test_dofs, col.iter()).enumerate() {
+                    if is_excluded(test_excluded, i) {
+                        continue;
                     }
+                    accumulate(*test_dof, *trial_dof, entry);
                 }
             }
         }
@@ -772,6 +2316,155 @@ fn assemble_batch_nonadjacent<
     1
 }
 
+/// Assemble the contribution to the terms of a matrix for a batch of nearly-singular pairs of non-adjacent cells
+///
+/// Each pair of cells is integrated using a rule created by subdividing the cells.
+#[allow(clippy::too_many_arguments)]
+fn assemble_batch_near_field<
+    T: RlstScalar + MatrixInverse,
+    TestSpace: FunctionSpaceTrait<T = T>,
+    TrialSpace: FunctionSpaceTrait<T = T>,
+    Integrand: BoundaryIntegrand<T = T>,
+    K: Kernel<T = T>,
+>(
+    assembler: &BoundaryAssembler<T, Integrand, K>,
+    deriv_size: usize,
+    shape: [usize; 2],
+    trial_cell_type: ReferenceCellType,
+    test_cell_type: ReferenceCellType,
+    trial_space: &TrialSpace,
+    test_space: &TestSpace,
+    cell_pairs: &[(usize, usize)],
+) -> SparseMatrixData<T> {
+    let mut output = SparseMatrixData::<T>::new_known_size(
+        shape,
+        cell_pairs.len()
+            * trial_space.element(trial_cell_type).dim()
+            * test_space.element(test_cell_type).dim(),
+    );
+
+    let test_grid = test_space.grid();
+    let trial_grid = trial_space.grid();
+
+    let (test_points, test_weights) = regular_quadrature_rule::<T>(
+        test_cell_type,
+        assembler.options.quadrature_degrees[&test_cell_type],
+    );
+    let (trial_points, trial_weights) = regular_quadrature_rule::<T>(
+        trial_cell_type,
+        assembler.options.quadrature_degrees[&trial_cell_type],
+    );
+
+    for (test_cell, trial_cell) in cell_pairs {
+        let test_element =
+            test_space.element_with_degree(test_cell_type, test_space.cell_degree(*test_cell));
+        let trial_element =
+            trial_space.element_with_degree(trial_cell_type, trial_space.cell_degree(*trial_cell));
+        let rule = subdivision_rule(
+            test_grid,
+            test_cell_type,
+            *test_cell,
+            trial_grid,
+            trial_cell_type,
+            *trial_cell,
+            (test_points.data(), &test_weights),
+            (trial_points.data(), &trial_weights),
+            assembler.options.subdivision_admissibility,
+            assembler.options.max_subdivision_level,
+        );
+        let npts = rule.weights.len();
+
+        let mut points = rlst_dynamic_array2!(<T as RlstScalar>::Real, [2, npts]);
+        points.data_mut().copy_from_slice(&rule.test_points);
+        let mut test_table = rlst_dynamic_array4!(
+            T,
+            test_element.tabulate_array_shape(assembler.table_derivs, npts)
+        );
+        test_element.tabulate(&points, assembler.table_derivs, &mut test_table);
+
+        points.data_mut().copy_from_slice(&rule.trial_points);
+        let mut trial_table = rlst_dynamic_array4!(
+            T,
+            trial_element.tabulate_array_shape(assembler.table_derivs, npts)
+        );
+        trial_element.tabulate(&points, assembler.table_derivs, &mut trial_table);
+
+        let mut a = SingularCellPairAssembler::new(
+            npts,
+            deriv_size,
+            &assembler.integrand,
+            &assembler.kernel,
+            test_grid.geometry_map(test_cell_type, &rule.test_points),
+            trial_grid.geometry_map(trial_cell_type, &rule.trial_points),
+            &test_table,
+            &trial_table,
+            test_space.map_type(test_cell_type),
+            trial_space.map_type(trial_cell_type),
+            &rule.weights,
+        );
+        let mut local_mat = rlst_dynamic_array2!(T, [test_element.dim(), trial_element.dim()]);
+        a.set_test_cell(*test_cell);
+        if test_space.reversed_normal(*test_cell) {
+            a.reverse_test_orientation();
+        }
+        a.set_trial_cell(*trial_cell);
+        if trial_space.reversed_normal(*trial_cell) {
+            a.reverse_trial_orientation();
+        }
+        a.assemble(&mut local_mat);
+        apply_dof_signs(
+            &mut local_mat,
+            test_space.reversed_dofs(*test_cell),
+            trial_space.reversed_dofs(*trial_cell),
+        );
+
+        let test_dofs = unsafe { test_space.cell_dofs_unchecked(*test_cell) };
+        let trial_dofs = unsafe { trial_space.cell_dofs_unchecked(*trial_cell) };
+
+        let test_excluded = test_space.excluded_dofs(*test_cell);
+        let trial_excluded = trial_space.excluded_dofs(*trial_cell);
+        for (j, (trial_dof, col)) in izip!(trial_dofs, local_mat.col_iter()).enumerate() {
+            if is_excluded(trial_excluded, j) {
+                continue;
+            }
+            for (i, (test_dof, entry)) in izip!(test_dofs, col.iter()).enumerate() {
+                if is_excluded(test_excluded, i) {
+                    continue;
+                }
+                output.rows.push(test_space.global_dof_index(*test_dof));
+                output.cols.push(trial_space.global_dof_index(*trial_dof));
+                output.data.push(entry);
+            }
+        }
+    }
+
+    output
+}
+
+/// Negate the entries of a local matrix whose test or trial basis function, but not both, is negated
+///
+/// `test_reversed` and `trial_reversed` are the values returned by [FunctionSpaceTrait::reversed_dofs] for the test
+/// and trial cells.
+pub(crate) fn apply_dof_signs<T: RlstScalar>(
+    local_mat: &mut RlstArray<T, 2>,
+    test_reversed: &[bool],
+    trial_reversed: &[bool],
+) {
+    if test_reversed.is_empty() && trial_reversed.is_empty() {
+        return;
+    }
+    let [nrows, ncols] = local_mat.shape();
+    for j in 0..ncols {
+        let trial_sign = trial_reversed.get(j).copied().unwrap_or(false);
+        for i in 0..nrows {
+            if test_reversed.get(i).copied().unwrap_or(false) != trial_sign {
+                let entry = local_mat.get_mut([i, j]).unwrap();
+                *entry = -*entry;
+            }
+        }
+    }
+}
+
 fn get_pairs_if_smallest(
     test_cell: &impl Entity,
     trial_cell: &impl Entity,
@@ -1,8 +1,11 @@
 //! Assemblers that assemble the contributions to the global matrix due to a single pair of cells
 
-use crate::boundary_assemblers::helpers::{AssemblerGeometry, KernelEvaluator, RlstArray};
+use crate::boundary_assemblers::helpers::{
+    AssemblerGeometry, KernelEvaluator, RlstArray, SinglePrecisionBuffers,
+};
 use green_kernels::traits::Kernel;
 use itertools::izip;
+use ndelement::types::MapType;
 use ndgrid::traits::GeometryMap;
 use num::Zero;
 use rlst::{
@@ -13,20 +16,39 @@ use std::collections::HashMap;
 
 use super::integrands::BoundaryIntegrand;
 
+/// Reverse the orientation of a cell
+///
+/// Reversing the orientation of a cell negates its normal and the surface curls of functions on the cell. The surface
+/// curls are computed from the jacobian, so this is done by negating the normals and the jacobians.
+fn reverse_orientation<T: RlstScalar>(
+    jacobians: &mut RlstArray<T, 2>,
+    normals: &mut RlstArray<T, 2>,
+) {
+    for j in jacobians.data_mut() {
+        *j = -*j;
+    }
+    for n in normals.data_mut() {
+        *n = -*n;
+    }
+}
+
 /// Assembler for the contributions from pairs of neighbouring cells
 pub struct SingularCellPairAssembler<
     'a,
     T: RlstScalar,
     I: BoundaryIntegrand<T = T>,
-    G: GeometryMap<T = T::Real>,
+    TestG: GeometryMap<T = T::Real>,
+    TrialG: GeometryMap<T = T::Real>,
     K: Kernel<T = T>,
 > {
     integrand: &'a I,
     kernel: &'a KernelEvaluator<T, K>,
-    test_evaluator: G,
-    trial_evaluator: G,
+    test_evaluator: TestG,
+    trial_evaluator: TrialG,
     test_table: &'a RlstArray<T, 4>,
     trial_table: &'a RlstArray<T, 4>,
+    test_map_type: MapType,
+    trial_map_type: MapType,
     k: RlstArray<T, 2>,
     test_mapped_pts: RlstArray<T::Real, 2>,
     trial_mapped_pts: RlstArray<T::Real, 2>,
@@ -45,9 +67,10 @@ impl<
         'a,
         T: RlstScalar,
         I: BoundaryIntegrand<T = T>,
-        G: GeometryMap<T = T::Real>,
+        TestG: GeometryMap<T = T::Real>,
+        TrialG: GeometryMap<T = T::Real>,
         K: Kernel<T = T>,
-    > SingularCellPairAssembler<'a, T, I, G, K>
+    > SingularCellPairAssembler<'a, T, I, TestG, TrialG, K>
 {
     #[allow(clippy::too_many_arguments)]
     /// Create new
@@ -56,10 +79,12 @@ impl<
         deriv_size: usize,
         integrand: &'a I,
         kernel: &'a KernelEvaluator<T, K>,
-        test_evaluator: G,
-        trial_evaluator: G,
+        test_evaluator: TestG,
+        trial_evaluator: TrialG,
         test_table: &'a RlstArray<T, 4>,
         trial_table: &'a RlstArray<T, 4>,
+        test_map_type: MapType,
+        trial_map_type: MapType,
         weights: &'a [T::Real],
     ) -> Self {
         Self {
@@ -69,6 +94,8 @@ impl<
             trial_evaluator,
             test_table,
             trial_table,
+            test_map_type,
+            trial_map_type,
             k: rlst_dynamic_array2!(T, [deriv_size, npts]),
             test_mapped_pts: rlst_dynamic_array2!(T::Real, [3, npts]),
             trial_mapped_pts: rlst_dynamic_array2!(T::Real, [3, npts]),
@@ -106,6 +133,14 @@ impl<
             self.trial_normals.data_mut(),
         );
     }
+    /// Reverse the orientation of the current test cell
+    pub fn reverse_test_orientation(&mut self) {
+        reverse_orientation(&mut self.test_jacobians, &mut self.test_normals);
+    }
+    /// Reverse the orientation of the current trial cell
+    pub fn reverse_trial_orientation(&mut self) {
+        reverse_orientation(&mut self.trial_jacobians, &mut self.trial_normals);
+    }
     pub fn assemble(&mut self, local_mat: &mut RlstArray<T, 2>) {
         self.kernel.assemble_pairwise_st(
             self.test_mapped_pts.data(),
@@ -133,6 +168,8 @@ impl<
                     *entry += self.integrand.evaluate_singular(
                         self.test_table,
                         self.trial_table,
+                        self.test_map_type,
+                        self.trial_map_type,
                         index,
                         test_i,
                         trial_i,
@@ -165,6 +202,8 @@ pub struct NonsingularCellPairAssemblerWithTestCaching<
     trial_evaluator: TrialG,
     test_table: &'a RlstArray<T, 4>,
     trial_table: &'a RlstArray<T, 4>,
+    test_map_type: MapType,
+    trial_map_type: MapType,
     k: RlstArray<T, 3>,
     test_mapped_pts: Vec<RlstArray<T::Real, 2>>,
     trial_mapped_pts: RlstArray<T::Real, 2>,
@@ -179,6 +218,8 @@ pub struct NonsingularCellPairAssemblerWithTestCaching<
     test_cell: usize,
     trial_cell: usize,
     test_indices: HashMap<usize, usize>,
+    single_precision: bool,
+    single_precision_buffers: SinglePrecisionBuffers,
 }
 
 impl<
@@ -202,8 +243,11 @@ impl<
         trial_evaluator: TrialG,
         test_table: &'a RlstArray<T, 4>,
         trial_table: &'a RlstArray<T, 4>,
+        test_map_type: MapType,
+        trial_map_type: MapType,
         test_weights: &'a [T::Real],
         trial_weights: &'a [T::Real],
+        reversed_test_cell: impl Fn(usize) -> bool,
     ) -> Self {
         let mut test_mapped_pts = test_cells
             .iter()
@@ -232,7 +276,10 @@ impl<
         {
             test_indices.insert(*cell, i);
             test_evaluator.points(*cell, pts.data_mut());
-            test_evaluator.jacobians_dets_normals(*cell, j.data_mut(), jdet, n.data_mut())
+            test_evaluator.jacobians_dets_normals(*cell, j.data_mut(), jdet, n.data_mut());
+            if reversed_test_cell(*cell) {
+                reverse_orientation(j, n);
+            }
         }
 
         Self {
@@ -241,6 +288,8 @@ impl<
             trial_evaluator,
             test_table,
             trial_table,
+            test_map_type,
+            trial_map_type,
             k: rlst_dynamic_array3!(T, [deriv_size, npts_test, npts_trial]),
             test_mapped_pts,
             trial_mapped_pts: rlst_dynamic_array2!(T::Real, [3, npts_trial]),
@@ -255,6 +304,8 @@ impl<
             test_cell: 0,
             trial_cell: 0,
             test_indices,
+            single_precision: false,
+            single_precision_buffers: SinglePrecisionBuffers::default(),
         }
     }
 }
@@ -269,6 +320,10 @@ impl<
     pub fn set_test_cell(&mut self, test_cell: usize) {
         self.test_cell = self.test_indices[&test_cell];
     }
+    /// Set whether the kernel is evaluated in single precision
+    pub fn set_single_precision(&mut self, single_precision: bool) {
+        self.single_precision = single_precision;
+    }
     pub fn set_trial_cell(&mut self, trial_cell: usize) {
         self.trial_cell = trial_cell;
         self.trial_evaluator
@@ -280,12 +335,26 @@ impl<
             self.trial_normals.data_mut(),
         );
     }
+    /// Reverse the orientation of the current trial cell
+    pub fn reverse_trial_orientation(&mut self) {
+        reverse_orientation(&mut self.trial_jacobians, &mut self.trial_normals);
+    }
     pub fn assemble(&mut self, local_mat: &mut RlstArray<T, 2>) {
-        self.kernel.assemble_st(
-            unsafe { self.test_mapped_pts.get_unchecked(self.test_cell).data() },
-            self.trial_mapped_pts.data(),
-            self.k.data_mut(),
-        );
+        let test_mapped_pts = unsafe { self.test_mapped_pts.get_unchecked(self.test_cell).data() };
+        if self.single_precision {
+            self.kernel.assemble_st_single_precision(
+                test_mapped_pts,
+                self.trial_mapped_pts.data(),
+                self.k.data_mut(),
+                &mut self.single_precision_buffers,
+            );
+        } else {
+            self.kernel.assemble_st(
+                test_mapped_pts,
+                self.trial_mapped_pts.data(),
+                self.k.data_mut(),
+            );
+        }
 
         let test_geometry = unsafe {
             AssemblerGeometry::new(
@@ -320,6 +389,8 @@ impl<
                         *entry += self.integrand.evaluate_nonsingular(
                             self.test_table,
                             self.trial_table,
+                            self.test_map_type,
+                            self.trial_map_type,
                             test_index,
                             trial_index,
                             test_i,
@@ -2,18 +2,56 @@
 use green_kernels::traits::Kernel;
 pub(crate) use green_kernels::types::GreenKernelEvalType;
 use ndgrid::traits::Grid;
-use rlst::{Array, BaseArray, MatrixInverse, RlstScalar, VectorContainer};
+use rlst::{Array, BaseArray, CsrMatrix, MatrixInverse, RlstScalar, VectorContainer};
+use std::sync::Mutex;
+
+/// A function that evaluates a kernel in single precision
+///
+/// The inputs are the evaluation type, the sources, the targets and the output, as in [Kernel::assemble_st], followed
+/// by a buffer that the function can use to store the single precision result before it is converted to `T`.
+pub type SinglePrecisionEvaluator<T> =
+    Box<dyn Fn(GreenKernelEvalType, &[f32], &[f32], &mut [T], &mut Vec<f32>) + Send + Sync>;
+
+/// Buffers that are reused each time a kernel is evaluated in single precision
+#[derive(Default)]
+pub struct SinglePrecisionBuffers {
+    sources: Vec<f32>,
+    targets: Vec<f32>,
+    result: Vec<f32>,
+}
 
 /// Kernel evaluator
 pub struct KernelEvaluator<T: RlstScalar, K: Kernel<T = T>> {
     pub(crate) kernel: K,
     eval_type: GreenKernelEvalType,
+    single_precision: Option<SinglePrecisionEvaluator<T>>,
 }
 
 impl<T: RlstScalar, K: Kernel<T = T>> KernelEvaluator<T, K> {
     /// Create new
     pub fn new(kernel: K, eval_type: GreenKernelEvalType) -> Self {
-        Self { kernel, eval_type }
+        Self {
+            kernel,
+            eval_type,
+            single_precision: None,
+        }
+    }
+
+    /// Add a function that evaluates the kernel in single precision
+    pub fn with_single_precision(
+        mut self,
+        evaluator: impl Fn(GreenKernelEvalType, &[f32], &[f32], &mut [T], &mut Vec<f32>)
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.single_precision = Some(Box::new(evaluator));
+        self
+    }
+
+    /// Check if the kernel can be evaluated in single precision
+    pub fn has_single_precision(&self) -> bool {
+        self.single_precision.is_some()
     }
 
     /// Assemble pairwise.
@@ -37,6 +75,36 @@ impl<T: RlstScalar, K: Kernel<T = T>> KernelEvaluator<T, K> {
         self.kernel
             .assemble_st(self.eval_type, sources, targets, result);
     }
+
+    /// Assemble all sources against all targets, evaluating the kernel in single precision.
+    ///
+    /// The sources and targets are rounded to single precision and the result is converted back to `T`. The single
+    /// precision values are stored in `buffers`, so no memory is allocated once the buffers are large enough. If the
+    /// kernel cannot be evaluated in single precision, this is the same as [KernelEvaluator::assemble_st].
+    pub fn assemble_st_single_precision(
+        &self,
+        sources: &[<T as RlstScalar>::Real],
+        targets: &[<T as RlstScalar>::Real],
+        result: &mut [T],
+        buffers: &mut SinglePrecisionBuffers,
+    ) {
+        if let Some(evaluator) = &self.single_precision {
+            let to_f32 = |x: &<T as RlstScalar>::Real| num::cast::<_, f32>(*x).unwrap();
+            buffers.sources.clear();
+            buffers.sources.extend(sources.iter().map(to_f32));
+            buffers.targets.clear();
+            buffers.targets.extend(targets.iter().map(to_f32));
+            evaluator(
+                self.eval_type,
+                &buffers.sources,
+                &buffers.targets,
+                result,
+                &mut buffers.result,
+            );
+        } else {
+            self.assemble_st(sources, targets, result);
+        }
+    }
 }
 
 pub trait CellGeometry {
@@ -116,6 +184,156 @@ impl<T: RlstScalar + MatrixInverse> SparseMatrixData<T> {
 
 unsafe impl<T: RlstScalar + MatrixInverse> Sync for SparseMatrixData<T> {}
 
+/// The number of rows in each tile of a [CsrMatrixData] when entries are added in parallel
+const TILE_ROWS: usize = 256;
+
+/// Data for a sparse matrix in compressed sparse row (CSR) format
+///
+/// The sparsity pattern is fixed when the matrix is created, and entries are added to the matrix in place.
+pub(crate) struct CsrMatrixData<T: RlstScalar> {
+    /// Shape of the matrix
+    pub(crate) shape: [usize; 2],
+    /// The position in `indices` and `data` where each row starts
+    pub(crate) indptr: Vec<usize>,
+    /// Column of each entry
+    pub(crate) indices: Vec<usize>,
+    /// Data
+    pub(crate) data: Vec<T>,
+}
+
+impl<T: RlstScalar> CsrMatrixData<T> {
+    /// Create a matrix with no entries
+    pub(crate) fn new(shape: [usize; 2]) -> Self {
+        Self {
+            shape,
+            indptr: vec![0; shape[0] + 1],
+            indices: vec![],
+            data: vec![],
+        }
+    }
+
+    /// Create a matrix with all entries equal to zero from a sparsity pattern
+    ///
+    /// `pattern[i]` contains the columns of the entries in row `i`. These can be in any order and may be repeated.
+    pub(crate) fn from_pattern(shape: [usize; 2], mut pattern: Vec<Vec<usize>>) -> Self {
+        debug_assert!(pattern.len() == shape[0]);
+        let mut indptr = Vec::with_capacity(shape[0] + 1);
+        indptr.push(0);
+        for row in pattern.iter_mut() {
+            row.sort_unstable();
+            row.dedup();
+            indptr.push(indptr[indptr.len() - 1] + row.len());
+        }
+        let indices = pattern.concat();
+        Self {
+            shape,
+            data: vec![T::zero(); indices.len()],
+            indptr,
+            indices,
+        }
+    }
+
+    /// Create a matrix from the rows, columns and values of its entries, adding together repeated entries
+    pub(crate) fn from_entries(entries: SparseMatrixData<T>) -> Self
+    where
+        T: MatrixInverse,
+    {
+        let mut pattern = vec![vec![]; entries.shape[0]];
+        for (row, col) in entries.rows.iter().zip(&entries.cols) {
+            pattern[*row].push(*col);
+        }
+        let mut matrix = Self::from_pattern(entries.shape, pattern);
+        for ((row, col), value) in entries.rows.iter().zip(&entries.cols).zip(&entries.data) {
+            let columns = &matrix.indices[matrix.indptr[*row]..matrix.indptr[*row + 1]];
+            let position = matrix.indptr[*row]
+                + columns
+                    .binary_search(col)
+                    .expect("Entry is not in the sparsity pattern");
+            matrix.data[position] += *value;
+        }
+        matrix
+    }
+
+    /// Add entries to the matrix in parallel
+    ///
+    /// Each call to `assemble` computes entries `(row, column, value)` that are added to the matrix. Calls are made in
+    /// parallel, and each tile of rows of the matrix is locked while entries are added to it. Every entry must be part
+    /// of the sparsity pattern of the matrix.
+    pub(crate) fn add_in_parallel<Input: Send>(
+        &mut self,
+        inputs: Vec<Input>,
+        assemble: impl Fn(Input) -> Vec<(usize, usize, T)> + Sync,
+    ) {
+        use rayon::prelude::*;
+
+        let mut tiles = vec![];
+        let mut data = &mut self.data[..];
+        let mut offset = 0;
+        for start in (TILE_ROWS..self.shape[0]).step_by(TILE_ROWS) {
+            let (tile, rest) = data.split_at_mut(self.indptr[start] - offset);
+            tiles.push(Mutex::new(tile));
+            data = rest;
+            offset = self.indptr[start];
+        }
+        tiles.push(Mutex::new(data));
+
+        let indptr = &self.indptr;
+        let indices = &self.indices;
+        inputs.into_par_iter().for_each(|input| {
+            let mut entries = assemble(input);
+            entries.sort_unstable_by_key(|(row, _, _)| *row / TILE_ROWS);
+            for tile_entries in entries
+                .chunk_by(|(row0, _, _), (row1, _, _)| *row0 / TILE_ROWS == *row1 / TILE_ROWS)
+            {
+                let tile_index = tile_entries[0].0 / TILE_ROWS;
+                let tile_offset = indptr[tile_index * TILE_ROWS];
+                let mut tile = tiles[tile_index].lock().unwrap();
+                for (row, col, value) in tile_entries {
+                    let columns = &indices[indptr[*row]..indptr[*row + 1]];
+                    let position = indptr[*row]
+                        + columns
+                            .binary_search(col)
+                            .expect("Entry is not in the sparsity pattern");
+                    tile[position - tile_offset] += *value;
+                }
+            }
+        });
+    }
+
+    /// Remove all entries whose absolute value is less than `tolerance`
+    pub(crate) fn drop_entries(&mut self, tolerance: T::Real) {
+        let mut indptr = Vec::with_capacity(self.indptr.len());
+        indptr.push(0);
+        let mut position = 0;
+        for row in 0..self.shape[0] {
+            for i in self.indptr[row]..self.indptr[row + 1] {
+                if self.data[i].abs() >= tolerance {
+                    self.indices[position] = self.indices[i];
+                    self.data[position] = self.data[i];
+                    position += 1;
+                }
+            }
+            indptr.push(position);
+        }
+        self.indptr = indptr;
+        self.indices.truncate(position);
+        self.data.truncate(position);
+    }
+
+    /// Iterate over the entries of the matrix as `(row, column, value)`
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (usize, usize, T)> + '_ {
+        (0..self.shape[0]).flat_map(move |row| {
+            (self.indptr[row]..self.indptr[row + 1])
+                .map(move |i| (row, self.indices[i], self.data[i]))
+        })
+    }
+
+    /// Convert into a CSR matrix
+    pub(crate) fn into_csr_matrix(self) -> CsrMatrix<T> {
+        CsrMatrix::new(self.shape, self.indices, self.indptr, self.data)
+    }
+}
+
 pub(crate) struct AssemblerGeometry<'a, T: RlstScalar<Real = T>> {
     points: &'a RlstArray<T, 2>,
     normals: &'a RlstArray<T, 2>,
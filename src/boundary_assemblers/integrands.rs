@@ -1,20 +1,30 @@
 //! Integrands
 mod adjoint_double_layer;
+mod closure;
 mod double_layer;
 mod hypersingular;
+mod local;
 mod single_layer;
 
 use std::marker::PhantomData;
 
 pub use adjoint_double_layer::AdjointDoubleLayerBoundaryIntegrand;
+pub use closure::{
+    BasisValues, ClosureBoundaryIntegrand, IntegrandFunction, KernelValues, PointGeometry,
+};
 pub use double_layer::DoubleLayerBoundaryIntegrand;
 pub use hypersingular::{
     HypersingularCurlCurlBoundaryIntegrand, HypersingularNormalNormalBoundaryIntegrand,
 };
+pub use local::{
+    LaplaceBeltramiLocalIntegrand, MassLocalIntegrand, SurfaceDivergenceLocalIntegrand,
+};
 pub use single_layer::SingleLayerBoundaryIntegrand;
 
 use crate::boundary_assemblers::helpers::{CellGeometry, RlstArray};
-use rlst::{RlstScalar, UnsafeRandomAccessByRef};
+use ndelement::types::MapType;
+use num::{One, Zero};
+use rlst::{RlstScalar, Shape, UnsafeRandomAccessByRef};
 
 /// 1D access
 pub trait Access1D {
@@ -28,6 +38,11 @@ pub trait Access1D {
 }
 
 /// 2D access
+///
+/// This gives access to the values and derivatives of a basis function on the reference cell, and to the values of the
+/// basis function after they have been pushed forward to the physical cell. Scalar-valued basis functions are pushed
+/// forward using the identity map, basis functions in H(div) using the contravariant Piola map, and basis functions in
+/// H(curl) using the covariant Piola map.
 pub trait Access2D {
     /// Value tyoe
     type T;
@@ -36,6 +51,29 @@ pub trait Access2D {
     /// # Safety
     /// This function uses unsafe memory access
     unsafe fn get(&self, i: usize, j: usize) -> Self::T;
+    /// Get the number of components of the value of the basis function on the physical cell
+    fn value_size(&self) -> usize;
+    /// Get component of the value of the basis function on the physical cell
+    ///
+    /// # Safety
+    /// This function uses unsafe memory access
+    unsafe fn value(&self, i: usize) -> Self::T;
+    /// Get the surface divergence of the basis function on the physical cell
+    ///
+    /// This is zero unless the basis function is pushed forward using the contravariant Piola map. The table must
+    /// include first derivatives.
+    ///
+    /// # Safety
+    /// This function uses unsafe memory access
+    unsafe fn surface_divergence(&self) -> Self::T;
+    /// Get the component in the direction of the normal of the surface curl of the basis function on the physical cell
+    ///
+    /// This is zero unless the basis function is pushed forward using the covariant Piola map. The table must include
+    /// first derivatives.
+    ///
+    /// # Safety
+    /// This function uses unsafe memory access
+    unsafe fn surface_curl(&self) -> Self::T;
 }
 
 /// Geometry access
@@ -113,28 +151,163 @@ impl<T: RlstScalar> Access1D for SingularKernel<'_, T> {
 }
 
 /// Entry in tabulated data
-struct Table<'a, T: RlstScalar> {
+struct Table<'a, T: RlstScalar, G: CellGeometry<T = T::Real>> {
     table: &'a RlstArray<T, 4>,
+    map_type: MapType,
+    geometry: Geometry<'a, T, G>,
     point_index: usize,
     basis_index: usize,
 }
 
-impl<'a, T: RlstScalar> Table<'a, T> {
-    fn new(table: &'a RlstArray<T, 4>, point_index: usize, basis_index: usize) -> Self {
+impl<'a, T: RlstScalar, G: CellGeometry<T = T::Real>> Table<'a, T, G> {
+    fn new(
+        table: &'a RlstArray<T, 4>,
+        map_type: MapType,
+        geometry: &'a G,
+        point_index: usize,
+        basis_index: usize,
+    ) -> Self {
         Self {
             table,
+            map_type,
+            geometry: Geometry::new(geometry, point_index),
             point_index,
             basis_index,
         }
     }
+
+    /// Get the orientation of the cell at the point
+    unsafe fn orientation(&self) -> T {
+        orientation(&self.geometry)
+    }
+}
+
+/// Get 1 if the normal to a cell has the orientation given by the jacobian, or -1 if it has been reversed
+///
+/// Reversing the orientation of a cell negates both the normal and the jacobian.
+///
+/// # Safety
+/// This function uses unsafe memory access
+unsafe fn orientation<T: RlstScalar>(g: &impl GeometryAccess<T = T>) -> T {
+    let n = [
+        g.jacobian(1) * g.jacobian(5) - g.jacobian(2) * g.jacobian(4),
+        g.jacobian(2) * g.jacobian(3) - g.jacobian(0) * g.jacobian(5),
+        g.jacobian(0) * g.jacobian(4) - g.jacobian(1) * g.jacobian(3),
+    ];
+    if (n[0] * g.normal(0) + n[1] * g.normal(1) + n[2] * g.normal(2)).re() < T::Real::zero() {
+        -T::one()
+    } else {
+        T::one()
+    }
 }
-impl<T: RlstScalar> Access2D for Table<'_, T> {
+
+impl<T: RlstScalar, G: CellGeometry<T = T::Real>> Access2D for Table<'_, T, G> {
     type T = T;
     unsafe fn get(&self, i: usize, j: usize) -> Self::T {
         *self
             .table
             .get_unchecked([i, self.point_index, self.basis_index, j])
     }
+    fn value_size(&self) -> usize {
+        match self.map_type {
+            MapType::ContravariantPiola | MapType::CovariantPiola => 3,
+            _ => self.table.shape()[3],
+        }
+    }
+    unsafe fn value(&self, i: usize) -> Self::T {
+        let g = &self.geometry;
+        match self.map_type {
+            MapType::ContravariantPiola => {
+                (g.jacobian(i) * self.get(0, 0) + g.jacobian(3 + i) * self.get(0, 1)) / g.jdet()
+            }
+            MapType::CovariantPiola => {
+                // The value is J (J^T J)^{-1} applied to the reference value, and det(J^T J) is the square of the
+                // jacobian determinant
+                let g00 = g.jacobian(0) * g.jacobian(0)
+                    + g.jacobian(1) * g.jacobian(1)
+                    + g.jacobian(2) * g.jacobian(2);
+                let g01 = g.jacobian(0) * g.jacobian(3)
+                    + g.jacobian(1) * g.jacobian(4)
+                    + g.jacobian(2) * g.jacobian(5);
+                let g11 = g.jacobian(3) * g.jacobian(3)
+                    + g.jacobian(4) * g.jacobian(4)
+                    + g.jacobian(5) * g.jacobian(5);
+                let det = g.jdet() * g.jdet();
+                let a = (g11 * self.get(0, 0) - g01 * self.get(0, 1)) / det;
+                let b = (g00 * self.get(0, 1) - g01 * self.get(0, 0)) / det;
+                // Tangential components do not depend on the orientation of the cell
+                self.orientation() * (g.jacobian(i) * a + g.jacobian(3 + i) * b)
+            }
+            MapType::L2Piola => self.get(0, i) / g.jdet(),
+            _ => self.get(0, i),
+        }
+    }
+    unsafe fn surface_divergence(&self) -> Self::T {
+        match self.map_type {
+            MapType::ContravariantPiola => {
+                self.orientation() * (self.get(1, 0) + self.get(2, 1)) / self.geometry.jdet()
+            }
+            _ => T::zero(),
+        }
+    }
+    unsafe fn surface_curl(&self) -> Self::T {
+        match self.map_type {
+            MapType::CovariantPiola => {
+                self.orientation() * (self.get(1, 1) - self.get(2, 0)) / self.geometry.jdet()
+            }
+            _ => T::zero(),
+        }
+    }
+}
+
+/// The dot product of the values of a test and a trial basis function on the physical cells
+///
+/// # Safety
+/// This function uses unsafe memory access
+unsafe fn dot<T: RlstScalar>(
+    test_table: &impl Access2D<T = T>,
+    trial_table: &impl Access2D<T = T>,
+) -> T {
+    debug_assert!(test_table.value_size() == trial_table.value_size());
+    if test_table.value_size() == 1 {
+        test_table.value(0) * trial_table.value(0)
+    } else {
+        (0..test_table.value_size()).fold(T::zero(), |a, i| {
+            a + test_table.value(i) * trial_table.value(i)
+        })
+    }
+}
+
+/// The surface gradient of a scalar-valued basis function
+///
+/// This is J (J^T J)^{-1} applied to the reference gradient. Reversing the orientation of a cell negates the jacobian,
+/// so the result is multiplied by the orientation of the cell.
+///
+/// # Safety
+/// The table must include first derivatives
+unsafe fn surface_gradient<T: RlstScalar>(
+    table: &impl Access2D<T = T>,
+    geometry: &impl GeometryAccess<T = T>,
+) -> [T; 3] {
+    let g = geometry;
+    let g00 = g.jacobian(0) * g.jacobian(0)
+        + g.jacobian(1) * g.jacobian(1)
+        + g.jacobian(2) * g.jacobian(2);
+    let g01 = g.jacobian(0) * g.jacobian(3)
+        + g.jacobian(1) * g.jacobian(4)
+        + g.jacobian(2) * g.jacobian(5);
+    let g11 = g.jacobian(3) * g.jacobian(3)
+        + g.jacobian(4) * g.jacobian(4)
+        + g.jacobian(5) * g.jacobian(5);
+    let det = g.jdet() * g.jdet();
+    let a = (g11 * table.get(1, 0) - g01 * table.get(2, 0)) / det;
+    let b = (g00 * table.get(2, 0) - g01 * table.get(1, 0)) / det;
+    let o = orientation(g);
+    [
+        o * (g.jacobian(0) * a + g.jacobian(3) * b),
+        o * (g.jacobian(1) * a + g.jacobian(4) * b),
+        o * (g.jacobian(2) * a + g.jacobian(5) * b),
+    ]
 }
 
 /// Geometry for a point
@@ -194,12 +367,21 @@ pub unsafe trait BoundaryIntegrand: Sync {
         trial_geometry: &impl GeometryAccess<T = Self::T>,
     ) -> Self::T;
 
+    /// Is the integrand unchanged when the test and trial tables and geometries are swapped?
+    ///
+    /// The kernel is assumed to be symmetric.
+    fn is_symmetric(&self) -> bool {
+        false
+    }
+
     #[allow(clippy::too_many_arguments)]
     /// Evaluate integrand for a singular quadrature rule
     fn evaluate_nonsingular(
         &self,
         test_table: &RlstArray<Self::T, 4>,
         trial_table: &RlstArray<Self::T, 4>,
+        test_map_type: MapType,
+        trial_map_type: MapType,
         test_point_index: usize,
         trial_point_index: usize,
         test_basis_index: usize,
@@ -210,8 +392,20 @@ pub unsafe trait BoundaryIntegrand: Sync {
     ) -> Self::T {
         self.evaluate(
             &NonSingularKernel::new(k, test_point_index, trial_point_index),
-            &Table::new(test_table, test_point_index, test_basis_index),
-            &Table::new(trial_table, trial_point_index, trial_basis_index),
+            &Table::new(
+                test_table,
+                test_map_type,
+                test_geometry,
+                test_point_index,
+                test_basis_index,
+            ),
+            &Table::new(
+                trial_table,
+                trial_map_type,
+                trial_geometry,
+                trial_point_index,
+                trial_basis_index,
+            ),
             &Geometry::new(test_geometry, test_point_index),
             &Geometry::new(trial_geometry, trial_point_index),
         )
@@ -223,6 +417,8 @@ pub unsafe trait BoundaryIntegrand: Sync {
         &self,
         test_table: &RlstArray<Self::T, 4>,
         trial_table: &RlstArray<Self::T, 4>,
+        test_map_type: MapType,
+        trial_map_type: MapType,
         point_index: usize,
         test_basis_index: usize,
         trial_basis_index: usize,
@@ -232,8 +428,20 @@ pub unsafe trait BoundaryIntegrand: Sync {
     ) -> Self::T {
         self.evaluate(
             &SingularKernel::new(k, point_index),
-            &Table::new(test_table, point_index, test_basis_index),
-            &Table::new(trial_table, point_index, trial_basis_index),
+            &Table::new(
+                test_table,
+                test_map_type,
+                test_geometry,
+                point_index,
+                test_basis_index,
+            ),
+            &Table::new(
+                trial_table,
+                trial_map_type,
+                trial_geometry,
+                point_index,
+                trial_basis_index,
+            ),
             &Geometry::new(test_geometry, point_index),
             &Geometry::new(trial_geometry, point_index),
         )
@@ -281,6 +489,10 @@ unsafe impl<T: RlstScalar, I0: BoundaryIntegrand<T = T>, I1: BoundaryIntegrand<T
                 .integrand1
                 .evaluate(k, test_table, trial_table, test_geometry, trial_geometry)
     }
+
+    fn is_symmetric(&self) -> bool {
+        self.integrand0.is_symmetric() && self.integrand1.is_symmetric()
+    }
 }
 
 /// An integrand multiplied by a scalar
@@ -314,4 +526,64 @@ unsafe impl<T: RlstScalar, I: BoundaryIntegrand<T = T>> BoundaryIntegrand
                 .integrand
                 .evaluate(k, test_table, trial_table, test_geometry, trial_geometry)
     }
+
+    fn is_symmetric(&self) -> bool {
+        self.integrand.is_symmetric()
+    }
+}
+
+pub unsafe trait LocalIntegrand: Sync {
+    //! Integrand for a local operator, which only couples basis functions on the same cell
+    //!
+    //! # Safety
+    //! This trait's methods use unsafe access
+
+    /// Scalar type
+    type T: RlstScalar;
+
+    /// Evaluate integrand
+    ///
+    /// The geometry is the geometry of the test cell.
+    fn evaluate(
+        &self,
+        test_table: &impl Access2D<T = Self::T>,
+        trial_table: &impl Access2D<T = Self::T>,
+        geometry: &impl GeometryAccess<T = Self::T>,
+    ) -> Self::T;
+
+    /// Check if the integrand can be used with basis functions that are pushed forward using the given maps
+    fn supports_map_types(&self, test_map_type: MapType, trial_map_type: MapType) -> bool;
+
+    #[allow(clippy::too_many_arguments)]
+    /// Evaluate integrand at a quadrature point
+    fn evaluate_local(
+        &self,
+        test_table: &RlstArray<Self::T, 4>,
+        trial_table: &RlstArray<Self::T, 4>,
+        test_map_type: MapType,
+        trial_map_type: MapType,
+        point_index: usize,
+        test_basis_index: usize,
+        trial_basis_index: usize,
+        test_geometry: &impl CellGeometry<T = <Self::T as RlstScalar>::Real>,
+        trial_geometry: &impl CellGeometry<T = <Self::T as RlstScalar>::Real>,
+    ) -> Self::T {
+        self.evaluate(
+            &Table::new(
+                test_table,
+                test_map_type,
+                test_geometry,
+                point_index,
+                test_basis_index,
+            ),
+            &Table::new(
+                trial_table,
+                trial_map_type,
+                trial_geometry,
+                point_index,
+                trial_basis_index,
+            ),
+            &Geometry::new(test_geometry, point_index),
+        )
+    }
 }
@@ -0,0 +1,307 @@
+//! Integrand defined by a closure
+use rlst::RlstScalar;
+
+use super::{surface_gradient, Access1D, Access2D, BoundaryIntegrand, GeometryAccess};
+
+/// A function that evaluates an integrand at a pair of points
+///
+/// The inputs are the kernel, the test and trial basis functions and the test and trial geometries.
+pub type IntegrandFunction<T> = Box<
+    dyn Fn(
+            &KernelValues<T>,
+            &BasisValues<T>,
+            &BasisValues<T>,
+            &PointGeometry<T>,
+            &PointGeometry<T>,
+        ) -> T
+        + Send
+        + Sync,
+>;
+
+/// The value and gradient of a kernel at a pair of points
+pub struct KernelValues<T: RlstScalar> {
+    value: T,
+    gradient: Option<[T; 3]>,
+}
+
+impl<T: RlstScalar> KernelValues<T> {
+    /// Create new
+    ///
+    /// # Safety
+    /// The kernel must include the gradient if `gradient` is true
+    unsafe fn new(k: &impl Access1D<T = T>, gradient: bool) -> Self {
+        Self {
+            value: k.get(0),
+            gradient: if gradient {
+                Some([k.get(1), k.get(2), k.get(3)])
+            } else {
+                None
+            },
+        }
+    }
+
+    /// The value of the kernel
+    pub fn value(&self) -> T {
+        self.value
+    }
+
+    /// The gradient of the kernel with respect to the trial point
+    ///
+    /// # Panics
+    /// Panics if the integrand was not created using [ClosureBoundaryIntegrand::with_kernel_gradient]
+    pub fn gradient(&self) -> [T; 3] {
+        self.gradient
+            .expect("The kernel gradient is only available if the integrand uses it")
+    }
+}
+
+/// Derivatives of a basis function on the physical cell
+struct BasisDerivatives<T: RlstScalar> {
+    surface_gradient: Option<[T; 3]>,
+    surface_divergence: T,
+    surface_curl: T,
+}
+
+/// The value and derivatives of a basis function at a point, pushed forward to the physical cell
+pub struct BasisValues<T: RlstScalar> {
+    values: [T; 3],
+    value_size: usize,
+    derivatives: Option<BasisDerivatives<T>>,
+}
+
+impl<T: RlstScalar> BasisValues<T> {
+    /// Create new
+    ///
+    /// # Safety
+    /// The table must include first derivatives if `derivatives` is true
+    unsafe fn new(
+        table: &impl Access2D<T = T>,
+        geometry: &impl GeometryAccess<T = T>,
+        derivatives: bool,
+    ) -> Self {
+        let value_size = table.value_size();
+        assert!(
+            value_size <= 3,
+            "Basis functions with more than three components are not supported"
+        );
+        let mut values = [T::zero(); 3];
+        for (i, v) in values.iter_mut().take(value_size).enumerate() {
+            *v = table.value(i);
+        }
+        let derivatives = if derivatives {
+            Some(BasisDerivatives {
+                surface_gradient: if value_size == 1 {
+                    Some(surface_gradient(table, geometry))
+                } else {
+                    None
+                },
+                surface_divergence: table.surface_divergence(),
+                surface_curl: table.surface_curl(),
+            })
+        } else {
+            None
+        };
+        Self {
+            values,
+            value_size,
+            derivatives,
+        }
+    }
+
+    /// The number of components of the value of the basis function
+    pub fn value_size(&self) -> usize {
+        self.value_size
+    }
+
+    /// A component of the value of the basis function
+    ///
+    /// # Panics
+    /// Panics if `i` is not less than [BasisValues::value_size]
+    pub fn value(&self, i: usize) -> T {
+        self.values[..self.value_size][i]
+    }
+
+    /// The dot product of the values of this basis function and another basis function
+    pub fn dot(&self, other: &Self) -> T {
+        assert_eq!(self.value_size, other.value_size);
+        self.values[..self.value_size]
+            .iter()
+            .zip(&other.values)
+            .fold(T::zero(), |a, (v, w)| a + *v * *w)
+    }
+
+    fn derivatives(&self) -> &BasisDerivatives<T> {
+        self.derivatives
+            .as_ref()
+            .expect("Derivatives of basis functions are only available if the integrand uses them")
+    }
+
+    /// The surface gradient of a scalar-valued basis function
+    ///
+    /// # Panics
+    /// Panics if the basis function is vector-valued or if the integrand was not created using
+    /// [ClosureBoundaryIntegrand::with_basis_derivatives]
+    pub fn surface_gradient(&self) -> [T; 3] {
+        self.derivatives()
+            .surface_gradient
+            .expect("The surface gradient is only available for scalar-valued basis functions")
+    }
+
+    /// The surface divergence of the basis function
+    ///
+    /// This is zero unless the basis function is pushed forward using the contravariant Piola map.
+    ///
+    /// # Panics
+    /// Panics if the integrand was not created using [ClosureBoundaryIntegrand::with_basis_derivatives]
+    pub fn surface_divergence(&self) -> T {
+        self.derivatives().surface_divergence
+    }
+
+    /// The component in the direction of the normal of the surface curl of the basis function
+    ///
+    /// This is zero unless the basis function is pushed forward using the covariant Piola map.
+    ///
+    /// # Panics
+    /// Panics if the integrand was not created using [ClosureBoundaryIntegrand::with_basis_derivatives]
+    pub fn surface_curl(&self) -> T {
+        self.derivatives().surface_curl
+    }
+}
+
+/// The geometry of a cell at a point
+pub struct PointGeometry<T: RlstScalar> {
+    point: [T; 3],
+    normal: [T; 3],
+    jacobian: [[T; 3]; 2],
+    jdet: T,
+}
+
+impl<T: RlstScalar> PointGeometry<T> {
+    /// Create new
+    ///
+    /// # Safety
+    /// The geometry must be three-dimensional
+    unsafe fn new(geometry: &impl GeometryAccess<T = T>) -> Self {
+        Self {
+            point: [geometry.point(0), geometry.point(1), geometry.point(2)],
+            normal: [geometry.normal(0), geometry.normal(1), geometry.normal(2)],
+            jacobian: [
+                [
+                    geometry.jacobian(0),
+                    geometry.jacobian(1),
+                    geometry.jacobian(2),
+                ],
+                [
+                    geometry.jacobian(3),
+                    geometry.jacobian(4),
+                    geometry.jacobian(5),
+                ],
+            ],
+            jdet: geometry.jdet(),
+        }
+    }
+
+    /// The point
+    pub fn point(&self) -> [T; 3] {
+        self.point
+    }
+
+    /// The unit normal to the cell at the point
+    pub fn normal(&self) -> [T; 3] {
+        self.normal
+    }
+
+    /// A column of the jacobian of the map from the reference cell
+    ///
+    /// # Panics
+    /// Panics if `i` is not 0 or 1
+    pub fn jacobian(&self, i: usize) -> [T; 3] {
+        self.jacobian[i]
+    }
+
+    /// The determinant of the jacobian
+    pub fn jdet(&self) -> T {
+        self.jdet
+    }
+}
+
+/// An integrand defined by a closure
+///
+/// The closure is called at each pair of quadrature points with the kernel, the test and trial basis functions, and the
+/// test and trial geometries. The kernel gradient and the derivatives of the basis functions are only computed if they
+/// are requested using [ClosureBoundaryIntegrand::with_kernel_gradient] and
+/// [ClosureBoundaryIntegrand::with_basis_derivatives].
+pub struct ClosureBoundaryIntegrand<T: RlstScalar> {
+    integrand: IntegrandFunction<T>,
+    kernel_gradient: bool,
+    basis_derivatives: bool,
+}
+
+impl<T: RlstScalar> ClosureBoundaryIntegrand<T> {
+    /// Create new
+    pub fn new(
+        integrand: impl Fn(
+                &KernelValues<T>,
+                &BasisValues<T>,
+                &BasisValues<T>,
+                &PointGeometry<T>,
+                &PointGeometry<T>,
+            ) -> T
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self {
+            integrand: Box::new(integrand),
+            kernel_gradient: false,
+            basis_derivatives: false,
+        }
+    }
+
+    /// Make the gradient of the kernel available to the integrand
+    pub fn with_kernel_gradient(mut self) -> Self {
+        self.kernel_gradient = true;
+        self
+    }
+
+    /// Make the derivatives of the basis functions available to the integrand
+    pub fn with_basis_derivatives(mut self) -> Self {
+        self.basis_derivatives = true;
+        self
+    }
+
+    /// Check if the integrand uses the gradient of the kernel
+    pub fn uses_kernel_gradient(&self) -> bool {
+        self.kernel_gradient
+    }
+
+    /// Check if the integrand uses the derivatives of the basis functions
+    pub fn uses_basis_derivatives(&self) -> bool {
+        self.basis_derivatives
+    }
+}
+
+unsafe impl<T: RlstScalar> BoundaryIntegrand for ClosureBoundaryIntegrand<T> {
+    type T = T;
+
+    fn evaluate(
+        &self,
+        k: &impl Access1D<T = T>,
+        test_table: &impl Access2D<T = T>,
+        trial_table: &impl Access2D<T = T>,
+        test_geometry: &impl GeometryAccess<T = T>,
+        trial_geometry: &impl GeometryAccess<T = T>,
+    ) -> T {
+        // The assembler evaluates the kernel gradient and tabulates the derivatives of the basis functions when the
+        // integrand uses them
+        unsafe {
+            (self.integrand)(
+                &KernelValues::new(k, self.kernel_gradient),
+                &BasisValues::new(test_table, test_geometry, self.basis_derivatives),
+                &BasisValues::new(trial_table, trial_geometry, self.basis_derivatives),
+                &PointGeometry::new(test_geometry),
+                &PointGeometry::new(trial_geometry),
+            )
+        }
+    }
+}
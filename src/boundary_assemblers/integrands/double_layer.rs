@@ -1,7 +1,7 @@
 //! Double layer integrand
 use rlst::RlstScalar;
 
-use super::{Access1D, Access2D, BoundaryIntegrand, GeometryAccess};
+use super::{dot, Access1D, Access2D, BoundaryIntegrand, GeometryAccess};
 
 /// Integrand for a double layer boundary operator
 pub struct DoubleLayerBoundaryIntegrand<T: RlstScalar> {
@@ -32,8 +32,7 @@ unsafe impl<T: RlstScalar> BoundaryIntegrand for DoubleLayerBoundaryIntegrand<T>
             (k.get(1) * trial_geometry.normal(0)
                 + k.get(2) * trial_geometry.normal(1)
                 + k.get(3) * trial_geometry.normal(2))
-                * test_table.get(0, 0)
-                * trial_table.get(0, 0)
+                * dot(test_table, trial_table)
         }
     }
 }
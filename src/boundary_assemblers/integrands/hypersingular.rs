@@ -1,9 +1,10 @@
 //! Hypersingular integrand
+use ndelement::types::MapType;
 use rlst::RlstScalar;
 
 use crate::boundary_assemblers::helpers::CellGeometry;
 
-use super::{Access1D, Access2D, BoundaryIntegrand, GeometryAccess};
+use super::{dot, Access1D, Access2D, BoundaryIntegrand, GeometryAccess};
 
 /// Integrand for the curl curl term of a hypersingular boundary operator
 pub struct HypersingularCurlCurlBoundaryIntegrand<T: RlstScalar> {
@@ -55,6 +56,10 @@ unsafe impl<T: RlstScalar> BoundaryIntegrand for HypersingularCurlCurlBoundaryIn
                 / trial_geometry.jdet()
         }
     }
+
+    fn is_symmetric(&self) -> bool {
+        true
+    }
 }
 
 /// Integrand for the normal normal term of a hypersingular boundary operator
@@ -93,15 +98,20 @@ unsafe impl<T: RlstScalar> BoundaryIntegrand for HypersingularNormalNormalBounda
                 * (trial_geometry.normal(0) * test_geometry.normal(0)
                     + trial_geometry.normal(1) * test_geometry.normal(1)
                     + trial_geometry.normal(2) * test_geometry.normal(2))
-                * test_table.get(0, 0)
-                * trial_table.get(0, 0)
+                * dot(test_table, trial_table)
         }
     }
 
+    fn is_symmetric(&self) -> bool {
+        true
+    }
+
     fn evaluate_nonsingular(
         &self,
         test_table: &crate::boundary_assemblers::helpers::RlstArray<Self::T, 4>,
         trial_table: &crate::boundary_assemblers::helpers::RlstArray<Self::T, 4>,
+        test_map_type: MapType,
+        trial_map_type: MapType,
         test_point_index: usize,
         trial_point_index: usize,
         test_basis_index: usize,
@@ -112,8 +122,20 @@ unsafe impl<T: RlstScalar> BoundaryIntegrand for HypersingularNormalNormalBounda
     ) -> Self::T {
         self.evaluate(
             &super::NonSingularKernel::new(k, test_point_index, trial_point_index),
-            &super::Table::new(test_table, test_point_index, test_basis_index),
-            &super::Table::new(trial_table, trial_point_index, trial_basis_index),
+            &super::Table::new(
+                test_table,
+                test_map_type,
+                test_geometry,
+                test_point_index,
+                test_basis_index,
+            ),
+            &super::Table::new(
+                trial_table,
+                trial_map_type,
+                trial_geometry,
+                trial_point_index,
+                trial_basis_index,
+            ),
             &super::Geometry::new(test_geometry, test_point_index),
             &super::Geometry::new(trial_geometry, trial_point_index),
         )
@@ -123,6 +145,8 @@ unsafe impl<T: RlstScalar> BoundaryIntegrand for HypersingularNormalNormalBounda
         &self,
         test_table: &crate::boundary_assemblers::helpers::RlstArray<Self::T, 4>,
         trial_table: &crate::boundary_assemblers::helpers::RlstArray<Self::T, 4>,
+        test_map_type: MapType,
+        trial_map_type: MapType,
         point_index: usize,
         test_basis_index: usize,
         trial_basis_index: usize,
@@ -132,8 +156,20 @@ unsafe impl<T: RlstScalar> BoundaryIntegrand for HypersingularNormalNormalBounda
     ) -> Self::T {
         self.evaluate(
             &super::SingularKernel::new(k, point_index),
-            &super::Table::new(test_table, point_index, test_basis_index),
-            &super::Table::new(trial_table, point_index, trial_basis_index),
+            &super::Table::new(
+                test_table,
+                test_map_type,
+                test_geometry,
+                point_index,
+                test_basis_index,
+            ),
+            &super::Table::new(
+                trial_table,
+                trial_map_type,
+                trial_geometry,
+                point_index,
+                trial_basis_index,
+            ),
             &super::Geometry::new(test_geometry, point_index),
             &super::Geometry::new(trial_geometry, point_index),
         )
@@ -0,0 +1,134 @@
+//! Integrands for local operators
+use ndelement::types::MapType;
+use rlst::RlstScalar;
+
+use super::{dot, surface_gradient, Access2D, GeometryAccess, LocalIntegrand};
+
+/// Check if basis functions pushed forward using a map are vector-valued tangential functions
+fn is_piola(map_type: MapType) -> bool {
+    matches!(
+        map_type,
+        MapType::ContravariantPiola | MapType::CovariantPiola
+    )
+}
+
+/// Integrand for a mass matrix
+pub struct MassLocalIntegrand<T: RlstScalar> {
+    _t: std::marker::PhantomData<T>,
+}
+
+impl<T: RlstScalar> MassLocalIntegrand<T> {
+    /// Create new
+    pub fn new() -> Self {
+        Self {
+            _t: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T: RlstScalar> Default for MassLocalIntegrand<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<T: RlstScalar> LocalIntegrand for MassLocalIntegrand<T> {
+    type T = T;
+
+    fn evaluate(
+        &self,
+        test_table: &impl Access2D<T = T>,
+        trial_table: &impl Access2D<T = T>,
+        _geometry: &impl GeometryAccess<T = T>,
+    ) -> T {
+        unsafe { dot(test_table, trial_table) }
+    }
+
+    fn supports_map_types(&self, test_map_type: MapType, trial_map_type: MapType) -> bool {
+        is_piola(test_map_type) == is_piola(trial_map_type)
+    }
+}
+
+/// Integrand for a Laplace-Beltrami operator
+///
+/// This is the dot product of the surface gradients of scalar-valued test and trial functions.
+pub struct LaplaceBeltramiLocalIntegrand<T: RlstScalar> {
+    _t: std::marker::PhantomData<T>,
+}
+
+impl<T: RlstScalar> LaplaceBeltramiLocalIntegrand<T> {
+    /// Create new
+    pub fn new() -> Self {
+        Self {
+            _t: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T: RlstScalar> Default for LaplaceBeltramiLocalIntegrand<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<T: RlstScalar> LocalIntegrand for LaplaceBeltramiLocalIntegrand<T> {
+    type T = T;
+
+    fn evaluate(
+        &self,
+        test_table: &impl Access2D<T = T>,
+        trial_table: &impl Access2D<T = T>,
+        geometry: &impl GeometryAccess<T = T>,
+    ) -> T {
+        // The surface gradient does not depend on the orientation of the cell, so the test geometry can be used for
+        // both functions
+        unsafe {
+            let test = surface_gradient(test_table, geometry);
+            let trial = surface_gradient(trial_table, geometry);
+            test[0] * trial[0] + test[1] * trial[1] + test[2] * trial[2]
+        }
+    }
+
+    fn supports_map_types(&self, test_map_type: MapType, trial_map_type: MapType) -> bool {
+        test_map_type == MapType::IdentityMap && trial_map_type == MapType::IdentityMap
+    }
+}
+
+/// Integrand for a surface divergence operator
+///
+/// This is the surface divergence of a test function in H(div) times a scalar-valued trial function.
+pub struct SurfaceDivergenceLocalIntegrand<T: RlstScalar> {
+    _t: std::marker::PhantomData<T>,
+}
+
+impl<T: RlstScalar> SurfaceDivergenceLocalIntegrand<T> {
+    /// Create new
+    pub fn new() -> Self {
+        Self {
+            _t: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T: RlstScalar> Default for SurfaceDivergenceLocalIntegrand<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<T: RlstScalar> LocalIntegrand for SurfaceDivergenceLocalIntegrand<T> {
+    type T = T;
+
+    fn evaluate(
+        &self,
+        test_table: &impl Access2D<T = T>,
+        trial_table: &impl Access2D<T = T>,
+        _geometry: &impl GeometryAccess<T = T>,
+    ) -> T {
+        unsafe { test_table.surface_divergence() * trial_table.value(0) }
+    }
+
+    fn supports_map_types(&self, test_map_type: MapType, trial_map_type: MapType) -> bool {
+        test_map_type == MapType::ContravariantPiola && trial_map_type == MapType::IdentityMap
+    }
+}
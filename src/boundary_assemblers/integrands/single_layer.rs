@@ -1,7 +1,7 @@
 //! Single layer integrand
 use rlst::RlstScalar;
 
-use super::{Access1D, Access2D, BoundaryIntegrand, GeometryAccess};
+use super::{dot, Access1D, Access2D, BoundaryIntegrand, GeometryAccess};
 
 /// Integrand for a single layer boundary operator
 pub struct SingleLayerBoundaryIntegrand<T: RlstScalar> {
@@ -28,7 +28,11 @@ unsafe impl<T: RlstScalar> BoundaryIntegrand for SingleLayerBoundaryIntegrand<T>
         _test_geometry: &impl GeometryAccess<T = T>,
         _trial_geometry: &impl GeometryAccess<T = T>,
     ) -> T {
-        unsafe { k.get(0) * test_table.get(0, 0) * trial_table.get(0, 0) }
+        unsafe { k.get(0) * dot(test_table, trial_table) }
+    }
+
+    fn is_symmetric(&self) -> bool {
+        true
     }
 }
 
@@ -0,0 +1,286 @@
+//! Quadrature for nearly-singular integrals using hierarchical subdivision
+//!
+//! When two cells are very close compared to their size, the integrand varies rapidly and a single Gauss rule on each
+//! cell is inaccurate. In this case, the cells are recursively subdivided until every pair of sub-cells is well
+//! separated, and each pair of sub-cells is integrated using the regular quadrature rules.
+use crate::helpers::lagrange_points;
+use ndelement::types::ReferenceCellType;
+use ndgrid::traits::{Entity, GeometryMap, Grid};
+use num::Float;
+
+/// Check if cells of a type can be subdivided
+///
+/// Pairs of cells of other types are never treated as nearly singular, and are integrated using the regular quadrature
+/// rules.
+pub(crate) fn can_subdivide(cell_type: ReferenceCellType) -> bool {
+    matches!(
+        cell_type,
+        ReferenceCellType::Triangle | ReferenceCellType::Quadrilateral
+    )
+}
+
+/// A sub-cell of a reference cell
+///
+/// The sub-cell is the image of the reference cell under the affine map `x -> origin + x[0] * axes[0] + x[1] * axes[1]`.
+#[derive(Clone, Copy)]
+struct SubCell<T: Float> {
+    origin: [T; 2],
+    axes: [[T; 2]; 2],
+    level: usize,
+}
+
+impl<T: Float> SubCell<T> {
+    /// The whole reference cell
+    fn new() -> Self {
+        Self {
+            origin: [T::zero(); 2],
+            axes: [[T::one(), T::zero()], [T::zero(), T::one()]],
+            level: 0,
+        }
+    }
+
+    /// Map a point on the reference cell into this sub-cell
+    fn map(&self, point: &[T]) -> [T; 2] {
+        [
+            self.origin[0] + point[0] * self.axes[0][0] + point[1] * self.axes[1][0],
+            self.origin[1] + point[0] * self.axes[0][1] + point[1] * self.axes[1][1],
+        ]
+    }
+
+    /// The ratio between the area of this sub-cell and the area of the reference cell
+    fn scale(&self) -> T {
+        Float::abs(self.axes[0][0] * self.axes[1][1] - self.axes[0][1] * self.axes[1][0])
+    }
+
+    /// Split this sub-cell into four sub-cells
+    ///
+    /// Sub-cells of cells that cannot be subdivided (see [can_subdivide]) are not split, and have no children.
+    fn children(&self, cell_type: ReferenceCellType) -> Vec<Self> {
+        let half = T::from(0.5).unwrap();
+        let a0 = [self.axes[0][0] * half, self.axes[0][1] * half];
+        let a1 = [self.axes[1][0] * half, self.axes[1][1] * half];
+        let shift = |p: [T; 2], v: [T; 2]| [p[0] + v[0], p[1] + v[1]];
+        let level = self.level + 1;
+        match cell_type {
+            ReferenceCellType::Triangle => {
+                let neg = |v: [T; 2]| [-v[0], -v[1]];
+                vec![
+                    Self {
+                        origin: self.origin,
+                        axes: [a0, a1],
+                        level,
+                    },
+                    Self {
+                        origin: shift(self.origin, a0),
+                        axes: [a0, a1],
+                        level,
+                    },
+                    Self {
+                        origin: shift(self.origin, a1),
+                        axes: [a0, a1],
+                        level,
+                    },
+                    Self {
+                        origin: shift(shift(self.origin, a0), a1),
+                        axes: [neg(a0), neg(a1)],
+                        level,
+                    },
+                ]
+            }
+            ReferenceCellType::Quadrilateral => vec![
+                Self {
+                    origin: self.origin,
+                    axes: [a0, a1],
+                    level,
+                },
+                Self {
+                    origin: shift(self.origin, a0),
+                    axes: [a0, a1],
+                    level,
+                },
+                Self {
+                    origin: shift(self.origin, a1),
+                    axes: [a0, a1],
+                    level,
+                },
+                Self {
+                    origin: shift(shift(self.origin, a0), a1),
+                    axes: [a0, a1],
+                    level,
+                },
+            ],
+            _ => vec![],
+        }
+    }
+}
+
+/// Points at which the geometry of each sub-cell is sampled
+///
+/// The vertices and edge midpoints of each sub-cell are mapped to physical space.
+fn sample_points<G: Grid<EntityDescriptor = ReferenceCellType>>(
+    grid: &G,
+    cell_type: ReferenceCellType,
+    cell: usize,
+    subcells: &[SubCell<G::T>],
+) -> Vec<Vec<[G::T; 3]>> {
+    let reference_points = lagrange_points::<G::T>(cell_type, 2);
+    let npts = reference_points.len() / 2;
+    let points = subcells
+        .iter()
+        .flat_map(|s| reference_points.chunks(2).flat_map(|p| s.map(p)))
+        .collect::<Vec<_>>();
+    let evaluator = grid.geometry_map(cell_type, &points);
+    let mut mapped_points = vec![G::T::zero(); 3 * npts * subcells.len()];
+    evaluator.points(cell, &mut mapped_points);
+    mapped_points
+        .chunks(3 * npts)
+        .map(|s| s.chunks(3).map(|p| [p[0], p[1], p[2]]).collect())
+        .collect()
+}
+
+/// The distance between two points
+fn distance<T: Float>(p: &[T; 3], q: &[T; 3]) -> T {
+    Float::sqrt(
+        p.iter()
+            .zip(q)
+            .map(|(i, j)| (*i - *j) * (*i - *j))
+            .fold(T::zero(), |a, b| a + b),
+    )
+}
+
+/// The largest distance between two points in a set
+fn diameter<T: Float>(points: &[[T; 3]]) -> T {
+    points
+        .iter()
+        .flat_map(|p| points.iter().map(move |q| distance(p, q)))
+        .fold(T::zero(), Float::max)
+}
+
+/// The distance between two sets of points divided by the larger of their diameters
+pub(crate) fn separation_ratio<T: Float>(a: &[[T; 3]], b: &[[T; 3]]) -> f64 {
+    let d = a
+        .iter()
+        .flat_map(|p| b.iter().map(move |q| distance(p, q)))
+        .fold(T::infinity(), Float::min);
+    num::cast::<T, f64>(d / Float::max(diameter(a), diameter(b))).unwrap()
+}
+
+/// Get the physical points at which the geometry of each cell in a grid is sampled
+///
+/// The points are indexed by the local index of each cell, and can be passed to [separation_ratio] to check whether a
+/// pair of cells is nearly singular. Cells that cannot be subdivided have no sample points.
+pub(crate) fn cell_sample_points<G: Grid<EntityDescriptor = ReferenceCellType>>(
+    grid: &G,
+) -> Vec<Vec<[G::T; 3]>> {
+    let ncells = grid
+        .entity_types(2)
+        .iter()
+        .map(|t| grid.entity_count(*t))
+        .sum::<usize>();
+    let mut samples = vec![vec![]; ncells];
+    for cell_type in grid.entity_types(2) {
+        if !can_subdivide(*cell_type) {
+            continue;
+        }
+        let points = lagrange_points::<G::T>(*cell_type, 2);
+        let npts = points.len() / 2;
+        let evaluator = grid.geometry_map(*cell_type, &points);
+        let mut mapped_points = vec![G::T::zero(); 3 * npts];
+        for cell in grid.entity_iter(2) {
+            if cell.entity_type() == *cell_type {
+                evaluator.points(cell.local_index(), &mut mapped_points);
+                samples[cell.local_index()] = mapped_points
+                    .chunks(3)
+                    .map(|p| [p[0], p[1], p[2]])
+                    .collect();
+            }
+        }
+    }
+    samples
+}
+
+/// A quadrature rule for a pair of cells
+///
+/// The test and trial points are paired, so the rule has the same form as the rules used for singular integrals.
+pub(crate) struct PairedQuadratureRule<T: Float> {
+    /// Points on the test cell, stored as [x0, y0, x1, y1, ...]
+    pub(crate) test_points: Vec<T>,
+    /// Points on the trial cell, stored as [x0, y0, x1, y1, ...]
+    pub(crate) trial_points: Vec<T>,
+    /// Weights
+    pub(crate) weights: Vec<T>,
+}
+
+/// Create a quadrature rule for a nearly-singular pair of cells
+///
+/// The pair of cells is recursively subdivided, splitting the larger sub-cell in each pair, until the distance between
+/// each pair of sub-cells divided by the larger of their diameters is at least `admissibility` or `max_level`
+/// subdivisions have been made. Each pair of sub-cells is then integrated using the regular quadrature rules given
+/// by `test_rule` and `trial_rule`, which contain points stored as [x0, y0, x1, y1, ...] and weights.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn subdivision_rule<
+    TestG: Grid<EntityDescriptor = ReferenceCellType>,
+    TrialG: Grid<T = TestG::T, EntityDescriptor = ReferenceCellType>,
+>(
+    test_grid: &TestG,
+    test_cell_type: ReferenceCellType,
+    test_cell: usize,
+    trial_grid: &TrialG,
+    trial_cell_type: ReferenceCellType,
+    trial_cell: usize,
+    test_rule: (&[TestG::T], &[TestG::T]),
+    trial_rule: (&[TestG::T], &[TestG::T]),
+    admissibility: f64,
+    max_level: usize,
+) -> PairedQuadratureRule<TestG::T> {
+    let mut pending = vec![(SubCell::new(), SubCell::new())];
+    let mut accepted = vec![];
+
+    while !pending.is_empty() {
+        let test_subcells = pending.iter().map(|(s, _)| *s).collect::<Vec<_>>();
+        let trial_subcells = pending.iter().map(|(_, s)| *s).collect::<Vec<_>>();
+        let test_samples = sample_points(test_grid, test_cell_type, test_cell, &test_subcells);
+        let trial_samples = sample_points(trial_grid, trial_cell_type, trial_cell, &trial_subcells);
+
+        let mut next = vec![];
+        for ((test_s, trial_s), (test_p, trial_p)) in
+            pending.iter().zip(test_samples.iter().zip(&trial_samples))
+        {
+            let can_split_test = test_s.level < max_level && can_subdivide(test_cell_type);
+            let can_split_trial = trial_s.level < max_level && can_subdivide(trial_cell_type);
+            if (!can_split_test && !can_split_trial)
+                || separation_ratio(test_p, trial_p) >= admissibility
+            {
+                accepted.push((*test_s, *trial_s));
+            } else if can_split_trial && (!can_split_test || diameter(trial_p) >= diameter(test_p))
+            {
+                for child in trial_s.children(trial_cell_type) {
+                    next.push((*test_s, child));
+                }
+            } else {
+                for child in test_s.children(test_cell_type) {
+                    next.push((child, *trial_s));
+                }
+            }
+        }
+        pending = next;
+    }
+
+    let mut rule = PairedQuadratureRule {
+        test_points: vec![],
+        trial_points: vec![],
+        weights: vec![],
+    };
+    for (test_s, trial_s) in &accepted {
+        for (test_p, test_w) in test_rule.0.chunks(2).zip(test_rule.1) {
+            let test_point = test_s.map(test_p);
+            for (trial_p, trial_w) in trial_rule.0.chunks(2).zip(trial_rule.1) {
+                rule.test_points.extend_from_slice(&test_point);
+                rule.trial_points.extend_from_slice(&trial_s.map(trial_p));
+                rule.weights
+                    .push(*test_w * test_s.scale() * *trial_w * trial_s.scale());
+            }
+        }
+    }
+    rule
+}
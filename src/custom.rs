@@ -0,0 +1,229 @@
+//! User-defined operators
+//!
+//! Operators are defined by a kernel closure that evaluates the Green's function G(x, y) and its gradient with respect
+//! to y, and an integrand closure (see [ClosureBoundaryIntegrand]) that combines the kernel with the test and trial basis
+//! functions. The point x is a point on the test cell and y is a point on the trial cell.
+use green_kernels::{traits::Kernel, types::GreenKernelEvalType};
+use rayon::prelude::*;
+use rlst::RlstScalar;
+
+pub use crate::boundary_assemblers::integrands::{
+    BasisValues, ClosureBoundaryIntegrand, IntegrandFunction, KernelValues, PointGeometry,
+};
+
+/// A function that evaluates a kernel at a pair of points
+pub type KernelFunction<T> =
+    Box<dyn Fn(&[<T as RlstScalar>::Real], &[<T as RlstScalar>::Real]) -> T + Send + Sync>;
+
+/// A function that evaluates the gradient of a kernel with respect to the second point at a pair of points
+pub type KernelGradientFunction<T> =
+    Box<dyn Fn(&[<T as RlstScalar>::Real], &[<T as RlstScalar>::Real]) -> [T; 3] + Send + Sync>;
+
+/// A kernel defined by closures
+pub struct ClosureKernel<T: RlstScalar> {
+    value: KernelFunction<T>,
+    gradient: Option<KernelGradientFunction<T>>,
+}
+
+impl<T: RlstScalar> ClosureKernel<T> {
+    /// Create new
+    pub fn new(value: impl Fn(&[T::Real], &[T::Real]) -> T + Send + Sync + 'static) -> Self {
+        Self {
+            value: Box::new(value),
+            gradient: None,
+        }
+    }
+
+    /// Add a function that evaluates the gradient of the kernel with respect to the second point
+    pub fn with_gradient(
+        mut self,
+        gradient: impl Fn(&[T::Real], &[T::Real]) -> [T; 3] + Send + Sync + 'static,
+    ) -> Self {
+        self.gradient = Some(Box::new(gradient));
+        self
+    }
+
+    /// Check if the gradient of the kernel can be evaluated
+    pub fn has_gradient(&self) -> bool {
+        self.gradient.is_some()
+    }
+}
+
+impl<T: RlstScalar> Kernel for ClosureKernel<T> {
+    type T = T;
+
+    fn evaluate_st(
+        &self,
+        eval_type: GreenKernelEvalType,
+        sources: &[T::Real],
+        targets: &[T::Real],
+        charges: &[T],
+        result: &mut [T],
+    ) {
+        let range = self.range_component_count(eval_type);
+        let mut values = vec![T::zero(); range];
+        for (target, r) in targets.chunks_exact(3).zip(result.chunks_exact_mut(range)) {
+            for (source, charge) in sources.chunks_exact(3).zip(charges) {
+                self.greens_fct(eval_type, source, target, &mut values);
+                for (r_i, v) in r.iter_mut().zip(&values) {
+                    *r_i = *r_i + *charge * *v;
+                }
+            }
+        }
+    }
+
+    fn evaluate_mt(
+        &self,
+        eval_type: GreenKernelEvalType,
+        sources: &[T::Real],
+        targets: &[T::Real],
+        charges: &[T],
+        result: &mut [T],
+    ) {
+        let range = self.range_component_count(eval_type);
+        targets
+            .par_chunks_exact(3)
+            .zip(result.par_chunks_exact_mut(range))
+            .for_each(|(target, r)| self.evaluate_st(eval_type, sources, target, charges, r));
+    }
+
+    fn assemble_st(
+        &self,
+        eval_type: GreenKernelEvalType,
+        sources: &[T::Real],
+        targets: &[T::Real],
+        result: &mut [T],
+    ) {
+        let nsources = sources.len() / 3;
+        let range = self.range_component_count(eval_type);
+        for (target, r) in targets
+            .chunks_exact(3)
+            .zip(result.chunks_exact_mut(nsources * range))
+        {
+            self.assemble_pairwise_st(eval_type, sources, &target.repeat(nsources), r);
+        }
+    }
+
+    fn assemble_mt(
+        &self,
+        eval_type: GreenKernelEvalType,
+        sources: &[T::Real],
+        targets: &[T::Real],
+        result: &mut [T],
+    ) {
+        let nsources = sources.len() / 3;
+        let range = self.range_component_count(eval_type);
+        targets
+            .par_chunks_exact(3)
+            .zip(result.par_chunks_exact_mut(nsources * range))
+            .for_each(|(target, r)| self.assemble_st(eval_type, sources, target, r));
+    }
+
+    fn assemble_pairwise_st(
+        &self,
+        eval_type: GreenKernelEvalType,
+        sources: &[T::Real],
+        targets: &[T::Real],
+        result: &mut [T],
+    ) {
+        let range = self.range_component_count(eval_type);
+        for ((source, target), r) in sources
+            .chunks_exact(3)
+            .zip(targets.chunks_exact(3))
+            .zip(result.chunks_exact_mut(range))
+        {
+            self.greens_fct(eval_type, source, target, r);
+        }
+    }
+
+    fn greens_fct(
+        &self,
+        eval_type: GreenKernelEvalType,
+        source: &[T::Real],
+        target: &[T::Real],
+        result: &mut [T],
+    ) {
+        result[0] = (self.value)(source, target);
+        if let GreenKernelEvalType::ValueDeriv = eval_type {
+            let gradient = self
+                .gradient
+                .as_ref()
+                .expect("The gradient of the kernel has not been given")(
+                source, target
+            );
+            result[1..4].copy_from_slice(&gradient);
+        }
+    }
+
+    fn space_dimension(&self) -> usize {
+        3
+    }
+
+    fn domain_component_count(&self) -> usize {
+        1
+    }
+
+    fn range_component_count(&self, eval_type: GreenKernelEvalType) -> usize {
+        match eval_type {
+            GreenKernelEvalType::Value => 1,
+            GreenKernelEvalType::ValueDeriv => 4,
+        }
+    }
+}
+
+/// Assemblers for user-defined operators.
+pub mod assembler {
+    use green_kernels::types::GreenKernelEvalType;
+    use rlst::{MatrixInverse, RlstScalar};
+
+    use super::{ClosureBoundaryIntegrand, ClosureKernel};
+    use crate::boundary_assemblers::{
+        helpers::KernelEvaluator, BoundaryAssembler, BoundaryAssemblerOptions,
+    };
+    use crate::error::BemppError;
+
+    /// User-defined operator assembler type.
+    pub type ClosureAssembler<'o, T> =
+        BoundaryAssembler<'o, T, ClosureBoundaryIntegrand<T>, ClosureKernel<T>>;
+
+    /// Assembler for an operator defined by an integrand and a kernel.
+    pub fn boundary_assembler<T: RlstScalar + MatrixInverse>(
+        integrand: ClosureBoundaryIntegrand<T>,
+        kernel: ClosureKernel<T>,
+        options: &BoundaryAssemblerOptions,
+    ) -> ClosureAssembler<T> {
+        try_boundary_assembler(integrand, kernel, options).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Assembler for an operator defined by an integrand and a kernel, returning an error if the integrand uses the
+    /// gradient of the kernel and the kernel has no gradient.
+    pub fn try_boundary_assembler<T: RlstScalar + MatrixInverse>(
+        integrand: ClosureBoundaryIntegrand<T>,
+        kernel: ClosureKernel<T>,
+        options: &BoundaryAssemblerOptions,
+    ) -> Result<ClosureAssembler<T>, BemppError> {
+        let (eval_type, deriv_size) = if integrand.uses_kernel_gradient() {
+            if !kernel.has_gradient() {
+                return Err(BemppError::InvalidIntegrand(
+                    "the integrand uses the gradient of the kernel, but no gradient was given"
+                        .to_string(),
+                ));
+            }
+            (GreenKernelEvalType::ValueDeriv, 4)
+        } else {
+            (GreenKernelEvalType::Value, 1)
+        };
+        let table_derivs = if integrand.uses_basis_derivatives() {
+            1
+        } else {
+            0
+        };
+        Ok(BoundaryAssembler::new(
+            integrand,
+            KernelEvaluator::new(kernel, eval_type),
+            options,
+            deriv_size,
+            table_derivs,
+        ))
+    }
+}
@@ -0,0 +1,90 @@
+//! Errors
+use crate::boundary_assemblers::QuadratureOptionsError;
+use ndelement::types::ReferenceCellType;
+
+/// An error caused by invalid input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BemppError {
+    /// A matrix has the wrong shape
+    WrongShape {
+        /// The expected shape
+        expected: [usize; 2],
+        /// The actual shape
+        actual: [usize; 2],
+    },
+    /// An operation that can only be used with function spaces stored in serial was used with a parallel space
+    NotSerial(String),
+    /// Quadrature options are missing information
+    Quadrature(QuadratureOptionsError),
+    /// A quadrature rule could not be created
+    QuadratureRule(String),
+    /// A cell type is not supported
+    UnsupportedCellType(ReferenceCellType),
+    /// A topological dimension is not supported
+    UnsupportedTopologicalDimension(usize),
+    /// A grid cannot be used for an operation
+    InvalidGrid(String),
+    /// A subset of the cells of a grid is invalid
+    InvalidCellSubset(String),
+    /// The subdomains of a multi-domain grid are invalid
+    InvalidDomains(String),
+    /// The polynomial degrees of the cells of a grid are invalid
+    InvalidCellDegrees(String),
+    /// A finite element is not supported
+    UnsupportedElement(String),
+    /// A user-defined integrand cannot be used with the given kernel
+    InvalidIntegrand(String),
+    /// A pair of function spaces cannot be used together
+    IncompatibleSpaces(String),
+}
+
+impl std::fmt::Display for BemppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BemppError::WrongShape { expected, actual } => write!(
+                f,
+                "Matrix has wrong shape: expected {expected:?}, found {actual:?}"
+            ),
+            BemppError::NotSerial(operation) => write!(
+                f,
+                "{operation} can only be used for function spaces stored in serial"
+            ),
+            BemppError::Quadrature(e) => write!(f, "{e}"),
+            BemppError::QuadratureRule(reason) => {
+                write!(f, "Could not create quadrature rule: {reason}")
+            }
+            BemppError::UnsupportedCellType(cell_type) => write!(
+                f,
+                "Unsupported cell type {cell_type:?}: only triangles and quadrilaterals are currently supported"
+            ),
+            BemppError::UnsupportedTopologicalDimension(tdim) => write!(
+                f,
+                "Function spaces are not implemented for grids with topological dimension {tdim}"
+            ),
+            BemppError::InvalidGrid(reason) => write!(f, "Invalid grid: {reason}"),
+            BemppError::InvalidCellSubset(reason) => write!(f, "Invalid subset of cells: {reason}"),
+            BemppError::InvalidDomains(reason) => write!(f, "Invalid subdomains: {reason}"),
+            BemppError::InvalidCellDegrees(reason) => write!(f, "Invalid cell degrees: {reason}"),
+            BemppError::UnsupportedElement(reason) => write!(f, "Unsupported element: {reason}"),
+            BemppError::InvalidIntegrand(reason) => write!(f, "Invalid integrand: {reason}"),
+            BemppError::IncompatibleSpaces(reason) => {
+                write!(f, "Incompatible function spaces: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BemppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BemppError::Quadrature(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<QuadratureOptionsError> for BemppError {
+    fn from(e: QuadratureOptionsError) -> Self {
+        BemppError::Quadrature(e)
+    }
+}
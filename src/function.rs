@@ -1,22 +1,37 @@
 //! Functions and function spaces
 
 //mod function_space;
+pub mod barycentric;
+pub mod hp;
+pub mod transfer;
 
+use crate::error::BemppError;
+use crate::helpers::reference_vertices;
+use crate::multi_domain::MultiDomainGrid;
 use mpi::request::WaitGuard;
 use mpi::traits::{Communicator, Destination, Source};
 use ndelement::ciarlet::CiarletElement;
+use ndelement::reference_cell;
 use ndelement::traits::ElementFamily;
+use ndelement::types::MapType;
 use ndelement::{traits::FiniteElement, types::ReferenceCellType};
 use ndgrid::traits::ParallelGrid;
 use ndgrid::traits::{Entity, Topology};
 use ndgrid::{traits::Grid, types::Ownership};
-use rlst::{MatrixInverse, RlstScalar};
+use num::Zero;
+use rlst::{
+    rlst_dynamic_array2, rlst_dynamic_array4, MatrixInverse, RandomAccessByRef, RandomAccessMut,
+    RlstScalar,
+};
 use std::collections::HashMap;
 use std::marker::PhantomData;
 
 type DofList = Vec<Vec<usize>>;
 type OwnerData = Vec<(usize, usize, usize, usize)>;
 
+/// The number stored in the DOFs of a cell for a basis function that is not included in a function space
+const EXCLUDED_DOF: usize = usize::MAX;
+
 /// A function space
 pub trait FunctionSpaceTrait {
     /// Communicator
@@ -51,22 +66,88 @@ pub trait FunctionSpaceTrait {
     fn global_size(&self) -> usize;
 
     /// Get the local DOF numbers associated with a cell
+    ///
+    /// The entries for the basis functions for which [FunctionSpaceTrait::excluded_dofs] is true are not DOF numbers,
+    /// and must be skipped.
     fn cell_dofs(&self, cell: usize) -> Option<&[usize]>;
 
     /// Get the local DOF numbers associated with a cell
     ///
+    /// The entries for the basis functions for which [FunctionSpaceTrait::excluded_dofs] is true are not DOF numbers,
+    /// and must be skipped.
+    ///
     /// # Safety
     /// The function uses unchecked array access
     unsafe fn cell_dofs_unchecked(&self, cell: usize) -> &[usize];
 
     /// Compute a colouring of the cells so that no two cells that share an entity with DOFs associated with it are assigned the same colour
-    fn cell_colouring(&self) -> HashMap<ReferenceCellType, Vec<Vec<usize>>>;
+    ///
+    /// An error is returned if the cells of the grid cannot be coloured.
+    fn cell_colouring(&self) -> Result<HashMap<ReferenceCellType, Vec<Vec<usize>>>, BemppError>;
 
     /// Get the global DOF index associated with a local DOF index
     fn global_dof_index(&self, local_dof_index: usize) -> usize;
 
     /// Get ownership of a local DOF
     fn ownership(&self, local_dof_index: usize) -> Ownership;
+
+    /// Check if the normal to a cell is reversed in this space
+    ///
+    /// If this is true, the assemblers use the negative of the normal to the cell in the grid, and the surface curls of
+    /// the basis functions on the cell are negated.
+    fn reversed_normal(&self, _cell: usize) -> bool {
+        false
+    }
+
+    /// Get the map used to push forward the basis functions on cells of a given type to the physical cells
+    fn map_type(&self, _cell_type: ReferenceCellType) -> MapType {
+        MapType::IdentityMap
+    }
+
+    /// Check which basis functions on a cell are negated in this space
+    ///
+    /// The DOFs associated with the edges of a space whose element is mapped using a Piola map are oriented using the
+    /// global numbering of the vertices: the tangent to an edge points from the lower numbered vertex to the higher
+    /// numbered vertex. The basis functions on a cell whose local orientation differs from this are negated. The
+    /// returned slice is indexed by the local index of the basis function on the cell, and is empty if no basis
+    /// functions on the cell are negated.
+    fn reversed_dofs(&self, _cell: usize) -> &[bool] {
+        &[]
+    }
+
+    /// Check which basis functions on a cell are not included in this space
+    ///
+    /// A space whose functions are zero on the boundary of an open surface or of a subset of the cells does not include
+    /// the basis functions associated with the vertices and edges on this boundary. The returned slice is indexed by
+    /// the local index of the basis function on the cell, and is empty if every basis function on the cell is included.
+    fn excluded_dofs(&self, _cell: usize) -> &[bool] {
+        &[]
+    }
+
+    /// Get the polynomial degree of the element used on a cell
+    ///
+    /// This is `None` if the space uses the same element on every cell of each type.
+    fn cell_degree(&self, _cell: usize) -> Option<usize> {
+        None
+    }
+
+    /// Get the polynomial degrees of the elements used on cells of a given type
+    ///
+    /// This is `[None]` if the space uses the same element on every cell of each type.
+    fn cell_type_degrees(&self, _cell_type: ReferenceCellType) -> Vec<Option<usize>> {
+        vec![None]
+    }
+
+    /// Get the finite element used on cells of a given type and polynomial degree
+    ///
+    /// If `degree` is `None`, this is the element returned by [FunctionSpaceTrait::element].
+    fn element_with_degree(
+        &self,
+        cell_type: ReferenceCellType,
+        _degree: Option<usize>,
+    ) -> &Self::FiniteElement {
+        self.element(cell_type)
+    }
 }
 
 /// Implementation of a general function space.
@@ -78,12 +159,18 @@ pub struct FunctionSpace<
 > {
     grid: &'a GridImpl,
     elements: HashMap<ReferenceCellType, CiarletElement<T>>,
+    hp_elements: HashMap<(ReferenceCellType, usize), CiarletElement<T>>,
+    cell_degrees: Vec<usize>,
+    type_degrees: HashMap<ReferenceCellType, Vec<usize>>,
     entity_dofs: [Vec<Vec<usize>>; 4],
     cell_dofs: Vec<Vec<usize>>,
     local_size: usize,
     global_size: usize,
     global_dof_numbers: Vec<usize>,
     ownership: Vec<Ownership>,
+    reversed_normals: Vec<bool>,
+    reversed_dofs: Vec<Vec<bool>>,
+    excluded_dofs: Vec<Vec<bool>>,
     _marker: std::marker::PhantomData<C>,
 }
 
@@ -111,19 +198,410 @@ impl<
             CellType = ReferenceCellType,
         >,
     ) -> Self {
+        Self::try_new(grid, e_family).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Create new function space, returning an error if the grid is not supported
+    pub fn try_new(
+        grid: &'a GridImpl,
+        e_family: &impl ElementFamily<
+            T = T,
+            FiniteElement = CiarletElement<T>,
+            CellType = ReferenceCellType,
+        >,
+    ) -> Result<Self, BemppError> {
+        Self::create(grid, e_family, None, false)
+    }
+
+    /// Create new function space whose functions are zero on the boundary of an open surface
+    ///
+    /// The DOFs associated with the vertices and edges on the boundary of the grid are not included. This can be used
+    /// to solve the hypersingular equation on a screen. If the grid is a closed surface, this space is the same as the
+    /// space created by [FunctionSpace::new]. The grid must not be distributed between processes.
+    pub fn new_zero_trace(
+        grid: &'a GridImpl,
+        e_family: &impl ElementFamily<
+            T = T,
+            FiniteElement = CiarletElement<T>,
+            CellType = ReferenceCellType,
+        >,
+    ) -> Self {
+        Self::try_new_zero_trace(grid, e_family).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Create new function space whose functions are zero on the boundary of an open surface, returning an error if
+    /// the input is invalid
+    pub fn try_new_zero_trace(
+        grid: &'a GridImpl,
+        e_family: &impl ElementFamily<
+            T = T,
+            FiniteElement = CiarletElement<T>,
+            CellType = ReferenceCellType,
+        >,
+    ) -> Result<Self, BemppError> {
+        Self::create(grid, e_family, Some(vec![true; cell_count(grid)]), true)
+    }
+
+    /// Create new function space on a subset of the cells of a grid
+    ///
+    /// The basis functions of the space are only supported on the cells in `cells`, which contains local cell indices.
+    /// If `exclude_boundary` is true, the DOFs associated with the vertices and edges on the boundary of the subset
+    /// are not included, so every function in the space is zero on the boundary of the subset: this is only supported
+    /// if the grid is not distributed between processes. The other cells of the grid have no DOFs, and the space can be used with spaces on the full grid in the assemblers.
+    pub fn new_on_cells(
+        grid: &'a GridImpl,
+        e_family: &impl ElementFamily<
+            T = T,
+            FiniteElement = CiarletElement<T>,
+            CellType = ReferenceCellType,
+        >,
+        cells: &[usize],
+        exclude_boundary: bool,
+    ) -> Self {
+        Self::try_new_on_cells(grid, e_family, cells, exclude_boundary)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Create new function space on a subset of the cells of a grid, returning an error if the input is invalid
+    pub fn try_new_on_cells(
+        grid: &'a GridImpl,
+        e_family: &impl ElementFamily<
+            T = T,
+            FiniteElement = CiarletElement<T>,
+            CellType = ReferenceCellType,
+        >,
+        cells: &[usize],
+        exclude_boundary: bool,
+    ) -> Result<Self, BemppError> {
+        let ncells = cell_count(grid);
+        let mut in_subset = vec![false; ncells];
+        for cell in cells {
+            if *cell >= ncells {
+                return Err(BemppError::InvalidCellSubset(format!(
+                    "cell {cell} is not in the grid, which has {ncells} cells"
+                )));
+            }
+            in_subset[*cell] = true;
+        }
+        Self::create(grid, e_family, Some(in_subset), exclude_boundary)
+    }
+
+    /// Create new function space on the cells of a grid with a given tag
+    ///
+    /// `cell_tags` contains a tag, such as the physical group read from a mesh file, for each cell of the grid, indexed
+    /// by local cell index. The space is defined on the cells whose tag is `tag`, as in [FunctionSpace::new_on_cells].
+    pub fn new_on_tagged_cells(
+        grid: &'a GridImpl,
+        e_family: &impl ElementFamily<
+            T = T,
+            FiniteElement = CiarletElement<T>,
+            CellType = ReferenceCellType,
+        >,
+        cell_tags: &[usize],
+        tag: usize,
+        exclude_boundary: bool,
+    ) -> Self {
+        Self::try_new_on_tagged_cells(grid, e_family, cell_tags, tag, exclude_boundary)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Create new function space on the cells of a grid with a given tag, returning an error if the input is invalid
+    pub fn try_new_on_tagged_cells(
+        grid: &'a GridImpl,
+        e_family: &impl ElementFamily<
+            T = T,
+            FiniteElement = CiarletElement<T>,
+            CellType = ReferenceCellType,
+        >,
+        cell_tags: &[usize],
+        tag: usize,
+        exclude_boundary: bool,
+    ) -> Result<Self, BemppError> {
+        let ncells = cell_count(grid);
+        if cell_tags.len() != ncells {
+            return Err(BemppError::InvalidCellSubset(format!(
+                "{} cell tags were given for a grid with {ncells} cells",
+                cell_tags.len()
+            )));
+        }
+        Self::create(
+            grid,
+            e_family,
+            Some(cell_tags.iter().map(|t| *t == tag).collect()),
+            exclude_boundary,
+        )
+    }
+
+    /// Create new trace space on the boundary of a subdomain of a multi-domain grid
+    ///
+    /// The space is defined on the cells on the boundary of `domain`, as in [FunctionSpace::new_on_cells], and the
+    /// normals to the cells are reversed where needed so that they point out of `domain`. Each subdomain has its own
+    /// DOFs, so the DOFs on a junction are repeated in the spaces on each subdomain that meets at the junction, as is
+    /// needed to assemble local multi-trace operators.
+    pub fn new_on_subdomain(
+        multi_domain_grid: &MultiDomainGrid<'a, GridImpl>,
+        e_family: &impl ElementFamily<
+            T = T,
+            FiniteElement = CiarletElement<T>,
+            CellType = ReferenceCellType,
+        >,
+        domain: usize,
+    ) -> Self {
+        Self::try_new_on_subdomain(multi_domain_grid, e_family, domain)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Create new trace space on the boundary of a subdomain of a multi-domain grid, returning an error if the input
+    /// is invalid
+    pub fn try_new_on_subdomain(
+        multi_domain_grid: &MultiDomainGrid<'a, GridImpl>,
+        e_family: &impl ElementFamily<
+            T = T,
+            FiniteElement = CiarletElement<T>,
+            CellType = ReferenceCellType,
+        >,
+        domain: usize,
+    ) -> Result<Self, BemppError> {
+        let grid = multi_domain_grid.grid();
+        let ncells = cell_count(grid);
+        let mut in_subset = vec![false; ncells];
+        for cell in multi_domain_grid.boundary_cells(domain) {
+            in_subset[cell] = true;
+        }
+        if !in_subset.contains(&true) {
+            return Err(BemppError::InvalidDomains(format!(
+                "there are no cells on the boundary of subdomain {domain}"
+            )));
+        }
+        let mut space = Self::create(grid, e_family, Some(in_subset), false)?;
+        space.reversed_normals = (0..ncells)
+            .map(|cell| multi_domain_grid.normal_points_into(cell, domain))
+            .collect();
+        Ok(space)
+    }
+
+    /// Create new function space with a per-cell polynomial degree
+    ///
+    /// `cell_degrees` contains the polynomial degree of each cell, indexed by local cell index, and `e_family` returns
+    /// the element family of each degree. The DOFs associated with an entity are shared by the cells around it that
+    /// have the same degree, so with a continuous family the functions in the space are continuous between cells of
+    /// the same degree but not between cells of different degrees: a conforming space can be created using
+    /// [hp::lagrange_hp_space]. For these spaces, [FunctionSpaceTrait::element] returns the element of the highest
+    /// degree used on the cells of each type.
+    pub fn new_hp<
+        F: ElementFamily<T = T, FiniteElement = CiarletElement<T>, CellType = ReferenceCellType>,
+    >(
+        grid: &'a GridImpl,
+        e_family: impl Fn(usize) -> F,
+        cell_degrees: &[usize],
+    ) -> Self {
+        Self::try_new_hp(grid, e_family, cell_degrees).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Create new function space with a per-cell polynomial degree, returning an error if the input is invalid
+    pub fn try_new_hp<
+        F: ElementFamily<T = T, FiniteElement = CiarletElement<T>, CellType = ReferenceCellType>,
+    >(
+        grid: &'a GridImpl,
+        e_family: impl Fn(usize) -> F,
+        cell_degrees: &[usize],
+    ) -> Result<Self, BemppError> {
+        if grid.topology_dim() != 2 {
+            return Err(BemppError::UnsupportedTopologicalDimension(
+                grid.topology_dim(),
+            ));
+        }
+        if grid.comm().size() != 1 {
+            return Err(BemppError::NotSerial(String::from(
+                "Creating a function space with a per-cell polynomial degree",
+            )));
+        }
+        let ncells = cell_count(grid);
+        if cell_degrees.len() != ncells {
+            return Err(BemppError::InvalidCellDegrees(format!(
+                "degrees were given for {} cells, but the grid has {ncells} cells",
+                cell_degrees.len()
+            )));
+        }
+
+        let (cell_dofs, entity_dofs, dofmap_size, owner_data) =
+            assign_hp_dofs(0, grid.local_grid(), &e_family, cell_degrees)?;
+
+        let mut type_degrees = HashMap::<ReferenceCellType, Vec<usize>>::new();
+        for cell in grid.entity_iter(2) {
+            type_degrees
+                .entry(cell.entity_type())
+                .or_default()
+                .push(cell_degrees[cell.local_index()]);
+        }
+        let mut elements = HashMap::new();
+        let mut hp_elements = HashMap::new();
+        for (cell_type, degrees) in type_degrees.iter_mut() {
+            degrees.sort();
+            degrees.dedup();
+            for degree in degrees.iter() {
+                hp_elements.insert((*cell_type, *degree), e_family(*degree).element(*cell_type));
+            }
+            elements.insert(
+                *cell_type,
+                e_family(*degrees.last().unwrap()).element(*cell_type),
+            );
+        }
+
+        let mut space = Self::from_local_dofs(
+            grid,
+            elements,
+            cell_dofs,
+            entity_dofs,
+            dofmap_size,
+            owner_data,
+        );
+        space.hp_elements = hp_elements;
+        space.cell_degrees = cell_degrees.to_vec();
+        space.type_degrees = type_degrees;
+        space.orient_edge_dofs()?;
+        Ok(space)
+    }
+
+    /// Create new function space on the cells for which `cells` is true, or on every cell if `cells` is `None`
+    fn create(
+        grid: &'a GridImpl,
+        e_family: &impl ElementFamily<
+            T = T,
+            FiniteElement = CiarletElement<T>,
+            CellType = ReferenceCellType,
+        >,
+        cells: Option<Vec<bool>>,
+        exclude_boundary: bool,
+    ) -> Result<Self, BemppError> {
+        if grid.topology_dim() > 2 || (exclude_boundary && grid.topology_dim() != 2) {
+            return Err(BemppError::UnsupportedTopologicalDimension(
+                grid.topology_dim(),
+            ));
+        }
+        // The boundary is found using the local grid, so it is only correct if the grid is not distributed
+        if exclude_boundary && grid.comm().size() != 1 {
+            return Err(BemppError::NotSerial(String::from(
+                "Excluding the DOFs on the boundary of a grid or subset",
+            )));
+        }
+
         let comm = grid.comm();
         let rank = comm.rank();
-        let size = comm.size();
 
         // Create local space on current process
-        let (cell_dofs, entity_dofs, dofmap_size, owner_data) =
-            assign_dofs(rank as usize, grid.local_grid(), e_family);
+        let (cell_dofs, entity_dofs, dofmap_size, owner_data) = match cells {
+            None => assign_dofs(rank as usize, grid.local_grid(), e_family)?,
+            Some(cells) => {
+                let boundary = if exclude_boundary {
+                    subset_boundary_entities(grid.local_grid(), &cells)
+                } else {
+                    [vec![], vec![]]
+                };
+                assign_dofs_on_cells(
+                    rank as usize,
+                    grid.local_grid(),
+                    e_family,
+                    &cells,
+                    |dim, entity| dim < 2 && exclude_boundary && boundary[dim][entity],
+                )?
+            }
+        };
 
         let mut elements = HashMap::new();
         for cell in grid.entity_types(grid.topology_dim()) {
             elements.insert(*cell, e_family.element(*cell));
         }
 
+        let excluded_dofs = if exclude_boundary {
+            cell_dofs
+                .iter()
+                .map(|dofs| {
+                    if dofs.contains(&EXCLUDED_DOF) {
+                        dofs.iter().map(|d| *d == EXCLUDED_DOF).collect()
+                    } else {
+                        vec![]
+                    }
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let mut space = Self::from_local_dofs(
+            grid,
+            elements,
+            cell_dofs,
+            entity_dofs,
+            dofmap_size,
+            owner_data,
+        );
+        space.excluded_dofs = excluded_dofs;
+        space.orient_edge_dofs()?;
+        Ok(space)
+    }
+
+    /// Find the basis functions that are negated on each cell so that the DOFs on the edges are oriented using the
+    /// global numbering of the vertices
+    ///
+    /// For an element mapped using the contravariant Piola map, the DOF on an edge is the flux through the edge in the
+    /// direction given by the cross product of the cell normal and the edge's tangent. For an element mapped using the
+    /// covariant Piola map, the DOF on an edge is the integral of the tangential component along the edge.
+    fn orient_edge_dofs(&mut self) -> Result<(), BemppError> {
+        if self.grid.topology_dim() != 2 {
+            return Ok(());
+        }
+        let mut element_signs = HashMap::new();
+        for cell_type in self.grid.entity_types(2) {
+            for degree in self.cell_type_degrees(*cell_type) {
+                let element = self.element_with_degree(*cell_type, degree);
+                if let Some(signs) = reference_edge_signs(*cell_type, element)? {
+                    element_signs.insert((*cell_type, degree), signs);
+                }
+            }
+        }
+        if element_signs.is_empty() {
+            return Ok(());
+        }
+
+        let mut reversed_dofs = vec![vec![]; cell_count(self.grid)];
+        for cell in self.grid.entity_iter(2) {
+            let cell_type = cell.entity_type();
+            let degree = self.cell_degree(cell.local_index());
+            let Some(signs) = element_signs.get(&(cell_type, degree)) else {
+                continue;
+            };
+            let element = self.element_with_degree(cell_type, degree);
+            let vertices = cell
+                .topology()
+                .sub_entity_iter(0)
+                .map(|v| self.grid.entity(0, v).unwrap().global_index())
+                .collect::<Vec<_>>();
+            let mut reversed = vec![false; element.dim()];
+            for (i, edge) in reference_cell::edges(cell_type).iter().enumerate() {
+                if let Some(dof) = element.entity_dofs(1, i).and_then(|dofs| dofs.first()) {
+                    reversed[*dof] = signs[i] != (vertices[edge[0]] > vertices[edge[1]]);
+                }
+            }
+            reversed_dofs[cell.local_index()] = reversed;
+        }
+        self.reversed_dofs = reversed_dofs;
+        Ok(())
+    }
+
+    /// Create new function space from the DOFs assigned to the entities on the current process
+    fn from_local_dofs(
+        grid: &'a GridImpl,
+        elements: HashMap<ReferenceCellType, CiarletElement<T>>,
+        cell_dofs: DofList,
+        entity_dofs: [DofList; 4],
+        dofmap_size: usize,
+        owner_data: OwnerData,
+    ) -> Self {
+        let comm = grid.comm();
+        let rank = comm.rank();
+        let size = comm.size();
+
         // Assign global DOF numbers
         let mut global_dof_numbers = vec![0; dofmap_size];
         let mut ghost_indices = vec![vec![]; size as usize];
@@ -226,12 +704,18 @@ impl<
         Self {
             grid,
             elements,
+            hp_elements: HashMap::new(),
+            cell_degrees: vec![],
+            type_degrees: HashMap::new(),
             entity_dofs,
             cell_dofs,
             local_size: dofmap_size,
             global_size,
             global_dof_numbers,
             ownership,
+            reversed_normals: vec![],
+            reversed_dofs: vec![],
+            excluded_dofs: vec![],
             _marker: PhantomData,
         }
     }
@@ -278,39 +762,50 @@ impl<
             None
         }
     }
-    fn cell_colouring(&self) -> HashMap<ReferenceCellType, Vec<Vec<usize>>> {
+    fn cell_colouring(&self) -> Result<HashMap<ReferenceCellType, Vec<Vec<usize>>>, BemppError> {
+        if self.grid.topology_dim() != 2 {
+            return Err(BemppError::UnsupportedTopologicalDimension(
+                self.grid.topology_dim(),
+            ));
+        }
         let mut colouring = HashMap::new();
         //: HashMap<ReferenceCellType, Vec<Vec<usize>>>
         for cell in self.grid.entity_types(2) {
             colouring.insert(*cell, vec![]);
         }
-        let mut edim = 0;
-        while self.elements[&self.grid.entity_types(2)[0]]
-            .entity_dofs(edim, 0)
-            .unwrap()
-            .is_empty()
-        {
-            edim += 1;
-        }
+        // Cells are coloured using the entities of the lowest dimension that have DOFs associated with them
+        let element = &self.elements[&self.grid.entity_types(2)[0]];
+        let edim = (0..3)
+            .find(|d| {
+                element
+                    .entity_dofs(*d, 0)
+                    .is_some_and(|dofs| !dofs.is_empty())
+            })
+            .ok_or_else(|| {
+                BemppError::UnsupportedElement(
+                    "cells can only be coloured for elements with DOFs".to_string(),
+                )
+            })?;
 
         let mut entity_colours = vec![
             vec![];
-            if edim == 0 {
-                self.grid.entity_count(ReferenceCellType::Point)
-            } else if edim == 1 {
-                self.grid.entity_count(ReferenceCellType::Interval)
-            } else if edim == 2 && self.grid.topology_dim() == 2 {
-                self.grid
+            match edim {
+                0 => self.grid.entity_count(ReferenceCellType::Point),
+                1 => self.grid.entity_count(ReferenceCellType::Interval),
+                _ => self
+                    .grid
                     .entity_types(2)
                     .iter()
                     .map(|&i| self.grid.entity_count(i))
-                    .sum::<usize>()
-            } else {
-                unimplemented!();
+                    .sum::<usize>(),
             }
         ];
 
         for cell in self.grid.entity_iter(2) {
+            if self.cell_dofs[cell.local_index()].is_empty() {
+                // Cells outside the subset that the space is defined on do not need to be coloured
+                continue;
+            }
             let cell_type = cell.entity_type();
             let indices = cell.topology().sub_entity_iter(edim).collect::<Vec<_>>();
 
@@ -347,7 +842,7 @@ impl<
                 entity_colours[*v].push(c);
             }
         }
-        colouring
+        Ok(colouring)
     }
     fn global_dof_index(&self, local_dof_index: usize) -> usize {
         self.global_dof_numbers[local_dof_index]
@@ -355,13 +850,195 @@ impl<
     fn ownership(&self, local_dof_index: usize) -> Ownership {
         self.ownership[local_dof_index]
     }
+    fn reversed_normal(&self, cell: usize) -> bool {
+        self.reversed_normals.get(cell).copied().unwrap_or(false)
+    }
+    fn map_type(&self, cell_type: ReferenceCellType) -> MapType {
+        self.elements[&cell_type].map_type()
+    }
+    fn reversed_dofs(&self, cell: usize) -> &[bool] {
+        self.reversed_dofs.get(cell).map_or(&[], |r| &r[..])
+    }
+    fn excluded_dofs(&self, cell: usize) -> &[bool] {
+        self.excluded_dofs.get(cell).map_or(&[], |e| &e[..])
+    }
+    fn cell_degree(&self, cell: usize) -> Option<usize> {
+        self.cell_degrees.get(cell).copied()
+    }
+    fn cell_type_degrees(&self, cell_type: ReferenceCellType) -> Vec<Option<usize>> {
+        if self.cell_degrees.is_empty() {
+            vec![None]
+        } else {
+            self.type_degrees
+                .get(&cell_type)
+                .map_or(vec![], |d| d.iter().map(|d| Some(*d)).collect())
+        }
+    }
+    fn element_with_degree(
+        &self,
+        cell_type: ReferenceCellType,
+        degree: Option<usize>,
+    ) -> &CiarletElement<T> {
+        match degree {
+            Some(d) => &self.hp_elements[&(cell_type, d)],
+            None => &self.elements[&cell_type],
+        }
+    }
 
     fn comm(&self) -> &C {
         self.grid.comm()
     }
 }
 
+/// A function space whose basis functions are linear combinations of the basis functions of another function space
+///
+/// This is used to represent spaces on a refined grid whose DOFs are associated with the entities of a coarser grid,
+/// such as the dual spaces in [barycentric].
+pub struct MappedFunctionSpace<Space: FunctionSpaceTrait> {
+    space: Space,
+    coefficients: Vec<Vec<(usize, Space::T)>>,
+    global_size: usize,
+}
+
+impl<Space: FunctionSpaceTrait> MappedFunctionSpace<Space> {
+    /// Create new mapped function space
+    ///
+    /// For each DOF of `space`, `coefficients` contains the DOFs of the mapped space whose basis functions include the
+    /// basis function of `space`, and the coefficient it is multiplied by.
+    pub fn new(
+        space: Space,
+        coefficients: Vec<Vec<(usize, Space::T)>>,
+        global_size: usize,
+    ) -> Self {
+        if !space.is_serial() {
+            panic!(
+                "Mapped function spaces can only be created from function spaces stored in serial"
+            );
+        }
+        assert_eq!(coefficients.len(), space.global_size());
+        for c in coefficients.iter().flatten() {
+            assert!(c.0 < global_size);
+        }
+        Self {
+            space,
+            coefficients,
+            global_size,
+        }
+    }
+
+    /// Get the function space that this space is mapped from
+    pub fn space(&self) -> &Space {
+        &self.space
+    }
+
+    /// Get the DOFs of this space and the coefficients that a DOF of the underlying space contributes to
+    pub fn coefficients(&self, dof: usize) -> &[(usize, Space::T)] {
+        &self.coefficients[dof]
+    }
+
+    /// Get the number of DOFs
+    pub fn global_size(&self) -> usize {
+        self.global_size
+    }
+}
+
+/// Check if the basis function with local index `index` is excluded, given the value returned by
+/// [FunctionSpaceTrait::excluded_dofs] for its cell
+pub(crate) fn is_excluded(excluded: &[bool], index: usize) -> bool {
+    excluded.get(index).copied().unwrap_or(false)
+}
+
+/// The number of cells in a grid
+pub(crate) fn cell_count(grid: &impl Grid<EntityDescriptor = ReferenceCellType>) -> usize {
+    grid.entity_types(grid.topology_dim())
+        .iter()
+        .map(|t| grid.entity_count(*t))
+        .sum()
+}
+
+/// Find the vertices and edges on the boundary of a subset of the cells of a grid
+///
+/// An edge is on the boundary if it is an edge of exactly one cell in the subset, and a vertex is on the boundary if
+/// it is a vertex of a boundary edge. The returned arrays are indexed by the local indices of the vertices and edges.
+fn subset_boundary_entities(
+    grid: &impl Grid<EntityDescriptor = ReferenceCellType>,
+    cells: &[bool],
+) -> [Vec<bool>; 2] {
+    let mut edge_cell_counts = vec![0; grid.entity_count(ReferenceCellType::Interval)];
+    for cell in grid.entity_iter(2) {
+        if cells[cell.local_index()] {
+            for e in cell.topology().sub_entity_iter(1) {
+                edge_cell_counts[e] += 1;
+            }
+        }
+    }
+    let mut vertices = vec![false; grid.entity_count(ReferenceCellType::Point)];
+    let mut edges = vec![false; edge_cell_counts.len()];
+    for edge in grid.entity_iter(1) {
+        if edge_cell_counts[edge.local_index()] == 1 {
+            edges[edge.local_index()] = true;
+            for v in edge.topology().sub_entity_iter(0) {
+                vertices[v] = true;
+            }
+        }
+    }
+    [vertices, edges]
+}
+
+/// Check if the DOF on each edge of the reference cell is negative in the direction of the reference edge
+///
+/// The DOF on an edge is evaluated at the midpoint of the edge, in the direction normal to the edge for an element
+/// mapped using the contravariant Piola map, or in the direction of the edge for an element mapped using the
+/// covariant Piola map. This returns `None` if the element is mapped using another map.
+fn reference_edge_signs<T: RlstScalar + MatrixInverse>(
+    cell_type: ReferenceCellType,
+    element: &CiarletElement<T>,
+) -> Result<Option<Vec<bool>>, BemppError> {
+    let contravariant = match element.map_type() {
+        MapType::ContravariantPiola => true,
+        MapType::CovariantPiola => false,
+        _ => return Ok(None),
+    };
+    let edges = reference_cell::edges(cell_type);
+    let vertices = reference_vertices::<T::Real>(cell_type);
+    let half = num::cast::<f64, T::Real>(0.5).unwrap();
+    let mut points = rlst_dynamic_array2!(T::Real, [2, edges.len()]);
+    for (i, edge) in edges.iter().enumerate() {
+        for j in 0..2 {
+            *points.get_mut([j, i]).unwrap() =
+                half * (vertices[2 * edge[0] + j] + vertices[2 * edge[1] + j]);
+        }
+    }
+    let mut table = rlst_dynamic_array4!(T, element.tabulate_array_shape(0, edges.len()));
+    element.tabulate(&points, 0, &mut table);
+
+    let mut signs = vec![false; edges.len()];
+    for (i, edge) in edges.iter().enumerate() {
+        let dofs = element.entity_dofs(1, i).unwrap();
+        if dofs.len() > 1 {
+            return Err(BemppError::UnsupportedElement(String::from(
+                "elements mapped using a Piola map can have at most one DOF associated with each edge",
+            )));
+        }
+        if let Some(dof) = dofs.first() {
+            let tangent = [0, 1].map(|j| vertices[2 * edge[1] + j] - vertices[2 * edge[0] + j]);
+            let direction = if contravariant {
+                [-tangent[1], tangent[0]]
+            } else {
+                tangent
+            };
+            let value = (0..2).fold(T::Real::zero(), |a, j| {
+                a + table.get([0, i, *dof, j]).unwrap().re() * direction[j]
+            });
+            signs[i] = value < T::Real::zero();
+        }
+    }
+    Ok(Some(signs))
+}
+
 /// Assign DOFs to entities.
+///
+/// An error is returned if the topological dimension of the grid is greater than 2.
 pub fn assign_dofs<
     T: RlstScalar + MatrixInverse,
     GridImpl: Grid<T = T::Real, EntityDescriptor = ReferenceCellType> + Sync,
@@ -373,7 +1050,91 @@ pub fn assign_dofs<
         FiniteElement = CiarletElement<T>,
         CellType = ReferenceCellType,
     >,
-) -> (DofList, [DofList; 4], usize, OwnerData) {
+) -> Result<(DofList, [DofList; 4], usize, OwnerData), BemppError> {
+    assign_dofs_on_cells(
+        rank,
+        grid,
+        e_family,
+        &vec![true; cell_count(grid)],
+        |_, _| false,
+    )
+}
+
+/// Assign DOFs to entities, using an element of a different polynomial degree on each cell.
+///
+/// `e_family` returns the element family of each degree, and `cell_degrees` contains the degree of each cell. The DOFs
+/// associated with an entity are shared by the cells around it that have the same degree, and the DOFs associated
+/// with each entity are ordered by degree. An error is returned if the topological dimension of the grid is greater
+/// than 2.
+pub fn assign_hp_dofs<
+    T: RlstScalar + MatrixInverse,
+    GridImpl: Grid<T = T::Real, EntityDescriptor = ReferenceCellType> + Sync,
+    F: ElementFamily<T = T, FiniteElement = CiarletElement<T>, CellType = ReferenceCellType>,
+>(
+    rank: usize,
+    grid: &GridImpl,
+    e_family: impl Fn(usize) -> F,
+    cell_degrees: &[usize],
+) -> Result<(DofList, [DofList; 4], usize, OwnerData), BemppError> {
+    let mut degrees = cell_degrees.to_vec();
+    degrees.sort();
+    degrees.dedup();
+
+    let mut size = 0;
+    let mut cell_dofs = vec![vec![]; cell_degrees.len()];
+    let mut entity_dofs: [DofList; 4] = [vec![], vec![], vec![], vec![]];
+    let mut owner_data = vec![];
+    for degree in degrees {
+        let cells = cell_degrees
+            .iter()
+            .map(|d| *d == degree)
+            .collect::<Vec<_>>();
+        let (d_cell_dofs, d_entity_dofs, d_size, d_owner_data) =
+            assign_dofs_on_cells(rank, grid, &e_family(degree), &cells, |_, _| false)?;
+
+        // The DOFs of this degree come after the DOFs of lower degrees associated with the same entity
+        for (process, dim, entity, dof_i) in d_owner_data {
+            let offset = entity_dofs[dim].get(entity).map_or(0, Vec::len);
+            owner_data.push((process, dim, entity, offset + dof_i));
+        }
+        for (cell, dofs) in d_cell_dofs.into_iter().enumerate() {
+            if cells[cell] {
+                cell_dofs[cell] = dofs.iter().map(|d| d + size).collect();
+            }
+        }
+        for (dim, d_dofs) in d_entity_dofs.into_iter().enumerate() {
+            if entity_dofs[dim].is_empty() {
+                entity_dofs[dim] = vec![vec![]; d_dofs.len()];
+            }
+            for (e, dofs) in d_dofs.into_iter().enumerate() {
+                entity_dofs[dim][e].extend(dofs.iter().map(|d| d + size));
+            }
+        }
+        size += d_size;
+    }
+    Ok((cell_dofs, entity_dofs, size, owner_data))
+}
+
+/// Assign DOFs to the entities of a subset of the cells of a grid.
+///
+/// Only the cells for which `cells` is true are given DOFs, and the other cells have an empty list of DOFs. The DOFs
+/// associated with the entity of dimension `d` and local index `e` are not included if `excluded(d, e)` is true, and
+/// the entries for them in the DOFs of each cell are not DOF numbers: these entries are recorded by
+/// [FunctionSpaceTrait::excluded_dofs] for the spaces created from these DOFs.
+pub(crate) fn assign_dofs_on_cells<
+    T: RlstScalar + MatrixInverse,
+    GridImpl: Grid<T = T::Real, EntityDescriptor = ReferenceCellType> + Sync,
+>(
+    rank: usize,
+    grid: &GridImpl,
+    e_family: &impl ElementFamily<
+        T = T,
+        FiniteElement = CiarletElement<T>,
+        CellType = ReferenceCellType,
+    >,
+    cells: &[bool],
+    excluded: impl Fn(usize, usize) -> bool,
+) -> Result<(DofList, [DofList; 4], usize, OwnerData), BemppError> {
     let mut size = 0;
     let mut entity_dofs: [Vec<Vec<usize>>; 4] = [vec![], vec![], vec![], vec![]];
     let mut owner_data = vec![];
@@ -395,7 +1156,7 @@ pub fn assign_dofs<
         })
         .collect::<Vec<_>>();
     if tdim > 2 {
-        unimplemented!("DOF maps not implemented for cells with tdim > 2.");
+        return Err(BemppError::UnsupportedTopologicalDimension(tdim));
     }
 
     for d in 0..tdim + 1 {
@@ -412,7 +1173,10 @@ pub fn assign_dofs<
         }
     }
     for cell in grid.entity_iter(tdim) {
-        cell_dofs[cell.local_index()] = vec![0; element_dims[&cell.entity_type()]];
+        if !cells[cell.local_index()] {
+            continue;
+        }
+        cell_dofs[cell.local_index()] = vec![EXCLUDED_DOF; element_dims[&cell.entity_type()]];
         let element = &elements[&cell.entity_type()];
         let topology = cell.topology();
 
@@ -420,7 +1184,7 @@ pub fn assign_dofs<
         for (d, edofs_d) in entity_dofs.iter_mut().take(tdim + 1).enumerate() {
             for (i, e) in topology.sub_entity_iter(d).enumerate() {
                 let e_dofs = element.entity_dofs(d, i).unwrap();
-                if !e_dofs.is_empty() {
+                if !e_dofs.is_empty() && !excluded(d, e) {
                     if edofs_d[e].is_empty() {
                         for (dof_i, _d) in e_dofs.iter().enumerate() {
                             edofs_d[e].push(size);
@@ -441,5 +1205,5 @@ pub fn assign_dofs<
             }
         }
     }
-    (cell_dofs, entity_dofs, size, owner_data)
+    Ok((cell_dofs, entity_dofs, size, owner_data))
 }
@@ -0,0 +1,372 @@
+//! Function spaces on barycentric refinements
+//!
+//! The spaces in this module are defined on a refined grid (usually a barycentric refinement created using
+//! [crate::refinement::refine_barycentric]), but their DOFs are associated with the entities of the coarse grid. Each
+//! space is a [MappedFunctionSpace] whose basis functions are linear combinations of the basis functions of a
+//! discontinuous space on the refined grid. These spaces can be used to assemble well-conditioned Gram matrices
+//! between a space and its dual, as required by Calderón preconditioners.
+use crate::function::{is_excluded, FunctionSpace, FunctionSpaceTrait, MappedFunctionSpace};
+use crate::helpers::{lagrange_points, reference_vertices, vertex_coordinates};
+use crate::refinement::RefinementMap;
+use mpi::traits::Communicator;
+use ndelement::ciarlet::{LagrangeElementFamily, RaviartThomasElementFamily};
+use ndelement::reference_cell;
+use ndelement::traits::FiniteElement;
+use ndelement::types::{Continuity, ReferenceCellType};
+use ndgrid::traits::{Entity, Grid, ParallelGrid, Topology};
+use ndgrid::types::RealScalar;
+use num::{Float, One, Zero};
+use rlst::{
+    rlst_dynamic_array2, rlst_dynamic_array4, MatrixInverse, RandomAccessByRef, RandomAccessMut,
+    RlstScalar,
+};
+use std::collections::HashMap;
+
+/// A point on the reference triangle that is used as a vertex of a cell in a barycentric refinement
+enum ReferenceNode {
+    /// A vertex of the triangle
+    Vertex(usize),
+    /// The midpoint of an edge of the triangle
+    EdgeMidpoint(usize),
+    /// The midpoint of the triangle
+    Midpoint,
+}
+
+/// Identify the reference node at a point on the reference triangle
+fn reference_node<T: RealScalar>(point: &[T]) -> ReferenceNode {
+    let tol = num::cast::<f64, T>(1e-10).unwrap();
+    let vertices = reference_vertices::<T>(ReferenceCellType::Triangle);
+    let close = |p: [T; 2]| Float::abs(point[0] - p[0]) < tol && Float::abs(point[1] - p[1]) < tol;
+    let half = num::cast::<f64, T>(0.5).unwrap();
+    for i in 0..3 {
+        if close([vertices[2 * i], vertices[2 * i + 1]]) {
+            return ReferenceNode::Vertex(i);
+        }
+    }
+    for (i, e) in reference_cell::edges(ReferenceCellType::Triangle)
+        .iter()
+        .enumerate()
+    {
+        if close([0, 1].map(|j| half * (vertices[2 * e[0] + j] + vertices[2 * e[1] + j]))) {
+            return ReferenceNode::EdgeMidpoint(i);
+        }
+    }
+    ReferenceNode::Midpoint
+}
+
+/// Check that a coarse space can be used to create a space on a refinement of its grid
+fn check_coarse_space<Space: FunctionSpaceTrait>(
+    coarse_space: &Space,
+    map: &RefinementMap<<Space::T as RlstScalar>::Real>,
+) {
+    if !coarse_space.is_serial() {
+        panic!("Spaces on refined grids can only be created from function spaces stored in serial");
+    }
+    let ncells = coarse_space
+        .grid()
+        .entity_types(2)
+        .iter()
+        .map(|t| coarse_space.grid().entity_count(*t))
+        .sum::<usize>();
+    if ncells != map.coarse_cell_count() {
+        panic!("Refinement map does not match the grid of the coarse space");
+    }
+}
+
+/// Get the vertices of each cell of a triangle grid, and a map from each pair of vertices to the edge between them
+fn triangle_topology<G: Grid<EntityDescriptor = ReferenceCellType>>(
+    grid: &G,
+) -> (Vec<Vec<usize>>, HashMap<(usize, usize), usize>) {
+    let edges = reference_cell::edges(ReferenceCellType::Triangle);
+    let mut cell_vertices = vec![vec![]; grid.entity_count(ReferenceCellType::Triangle)];
+    let mut edge_indices = HashMap::new();
+    for cell in grid.entity_iter(2) {
+        if cell.entity_type() != ReferenceCellType::Triangle {
+            panic!("Unsupported cell type: {:?}", cell.entity_type());
+        }
+        let vertices = cell.topology().sub_entity_iter(0).collect::<Vec<_>>();
+        for (e, edge) in edges.iter().zip(cell.topology().sub_entity_iter(1)) {
+            edge_indices.insert(edge_key(vertices[e[0]], vertices[e[1]]), edge);
+        }
+        cell_vertices[cell.local_index()] = vertices;
+    }
+    (cell_vertices, edge_indices)
+}
+
+/// Key used to identify an edge by its vertices
+fn edge_key(v0: usize, v1: usize) -> (usize, usize) {
+    if v0 < v1 {
+        (v0, v1)
+    } else {
+        (v1, v0)
+    }
+}
+
+/// Create a space on a refined grid that represents a Lagrange space on the coarse grid
+///
+/// The returned space is mapped from a discontinuous Lagrange space of the given degree on the refined grid, and has
+/// the same DOFs as `coarse_space`. `degree` must be at least the degree of `coarse_space`. For example, this can be
+/// used to represent continuous piecewise linear functions on the coarse grid on a barycentric refinement.
+pub fn refined_space<
+    'a,
+    C: Communicator,
+    T: RlstScalar + MatrixInverse,
+    GridImpl: ParallelGrid<C> + Grid<T = T::Real, EntityDescriptor = ReferenceCellType>,
+>(
+    coarse_space: &impl FunctionSpaceTrait<T = T>,
+    fine_grid: &'a GridImpl,
+    map: &RefinementMap<T::Real>,
+    degree: usize,
+) -> MappedFunctionSpace<FunctionSpace<'a, C, T, GridImpl>> {
+    check_coarse_space(coarse_space, map);
+    let coarse_grid = coarse_space.grid();
+
+    let family = LagrangeElementFamily::<T>::new(degree, Continuity::Discontinuous);
+    let space = FunctionSpace::new(fine_grid, &family);
+    let mut coefficients = vec![vec![]; space.global_size()];
+    let tol = num::cast::<f64, T::Real>(1e-12).unwrap();
+
+    for cell in fine_grid.entity_iter(2) {
+        let fine_cell = cell.local_index();
+        let parent = map.parent(fine_cell);
+        let parent_type = coarse_grid.entity(2, parent).unwrap().entity_type();
+
+        // Evaluate the coarse basis functions at the points that define the DOFs of the refined cell
+        let points = lagrange_points::<T::Real>(cell.entity_type(), degree);
+        let npts = points.len() / 2;
+        let mut parent_points = rlst_dynamic_array2!(T::Real, [2, npts]);
+        for i in 0..npts {
+            let p = map.parent_reference_point(fine_cell, &points[2 * i..2 * i + 2]);
+            for (j, c) in p.iter().enumerate() {
+                *parent_points.get_mut([j, i]).unwrap() = *c;
+            }
+        }
+        let element = coarse_space.element(parent_type);
+        let mut table = rlst_dynamic_array4!(T, element.tabulate_array_shape(0, npts));
+        element.tabulate(&parent_points, 0, &mut table);
+
+        let coarse_dofs = coarse_space.cell_dofs(parent).unwrap();
+        let coarse_excluded = coarse_space.excluded_dofs(parent);
+        for (i, dof) in space.cell_dofs(fine_cell).unwrap().iter().enumerate() {
+            for (j, coarse_dof) in coarse_dofs.iter().enumerate() {
+                let value = *table.get([0, i, j, 0]).unwrap();
+                if !is_excluded(coarse_excluded, j) && value.abs() > tol {
+                    coefficients[space.global_dof_index(*dof)]
+                        .push((coarse_space.global_dof_index(*coarse_dof), value));
+                }
+            }
+        }
+    }
+
+    MappedFunctionSpace::new(space, coefficients, coarse_space.global_size())
+}
+
+/// Create a space of piecewise constant functions on the dual grid (DUAL0)
+///
+/// Each basis function is equal to 1 on the cells of the barycentric refinement that touch a vertex of the coarse grid,
+/// and 0 elsewhere. The DOFs are numbered in the same way as the DOFs of `coarse_space`, which must be a continuous
+/// piecewise linear (P1) space on the coarse grid.
+pub fn dual0_space<
+    'a,
+    C: Communicator,
+    T: RlstScalar + MatrixInverse,
+    GridImpl: ParallelGrid<C> + Grid<T = T::Real, EntityDescriptor = ReferenceCellType>,
+>(
+    coarse_space: &impl FunctionSpaceTrait<T = T>,
+    fine_grid: &'a GridImpl,
+    map: &RefinementMap<T::Real>,
+) -> MappedFunctionSpace<FunctionSpace<'a, C, T, GridImpl>> {
+    check_coarse_space(coarse_space, map);
+    let (coarse_cells, _) = triangle_topology(coarse_space.grid());
+
+    let family = LagrangeElementFamily::<T>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(fine_grid, &family);
+    let mut coefficients = vec![vec![]; space.global_size()];
+
+    for cell in fine_grid.entity_iter(2) {
+        let fine_cell = cell.local_index();
+        let parent = map.parent(fine_cell);
+        let reference_vertices = map.reference_vertices(fine_cell);
+        let vertex = (0..reference_vertices.len() / 2)
+            .find_map(
+                |i| match reference_node(&reference_vertices[2 * i..2 * i + 2]) {
+                    ReferenceNode::Vertex(v) => Some(v),
+                    _ => None,
+                },
+            )
+            .expect("Grid is not a barycentric refinement");
+        let coarse_dof = coarse_space
+            .get_local_dof_numbers(0, coarse_cells[parent][vertex])
+            .first()
+            .expect("Coarse space must have a DOF associated with each vertex");
+
+        let dof = space.cell_dofs(fine_cell).unwrap()[0];
+        coefficients[space.global_dof_index(dof)]
+            .push((coarse_space.global_dof_index(*coarse_dof), T::one()));
+    }
+
+    MappedFunctionSpace::new(space, coefficients, coarse_space.global_size())
+}
+
+/// Create a Buffa–Christiansen space
+///
+/// Buffa–Christiansen functions are div-conforming functions on the barycentric refinement that are associated with
+/// the edges of the coarse grid. The function associated with an edge flows from the lower numbered vertex of the edge
+/// to the higher numbered vertex, through the dual edge that crosses it. The DOFs are numbered in the same way as the
+/// DOFs of `coarse_space`, which must be a lowest order Raviart–Thomas space on the coarse grid.
+///
+/// The basis functions are linear combinations of the lowest order Raviart–Thomas functions on the barycentric
+/// refinement, and the DOF associated with each edge of the refinement is taken to be the flux through the edge in
+/// the direction given by the cross product of the cell normal and the edge's tangent, where the tangent points from the
+/// lower numbered vertex to the higher numbered vertex. The grid must be a closed surface.
+pub fn buffa_christiansen_space<
+    'a,
+    C: Communicator,
+    T: RlstScalar + MatrixInverse,
+    GridImpl: ParallelGrid<C> + Grid<T = T::Real, EntityDescriptor = ReferenceCellType>,
+>(
+    coarse_space: &impl FunctionSpaceTrait<T = T>,
+    fine_grid: &'a GridImpl,
+    map: &RefinementMap<T::Real>,
+) -> MappedFunctionSpace<FunctionSpace<'a, C, T, GridImpl>> {
+    check_coarse_space(coarse_space, map);
+    let (coarse_cells, coarse_edges) = triangle_topology(coarse_space.grid());
+    let (fine_cells, fine_edges) = triangle_topology(fine_grid);
+    let coordinates = vertex_coordinates(fine_grid);
+
+    let family = RaviartThomasElementFamily::<T>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(fine_grid, &family);
+    let mut coefficients = vec![vec![]; space.global_size()];
+
+    // Find the vertex of the refined grid at each vertex and edge midpoint of the coarse grid, and the refined cells
+    // around each vertex of the refined grid
+    let edges = reference_cell::edges(ReferenceCellType::Triangle);
+    let mut vertex_to_fine = HashMap::new();
+    let mut midpoint_to_fine = HashMap::new();
+    let mut vertex_cells = vec![vec![]; coordinates.len()];
+    for (fine_cell, vertices) in fine_cells.iter().enumerate() {
+        let parent_vertices = &coarse_cells[map.parent(fine_cell)];
+        let reference_vertices = map.reference_vertices(fine_cell);
+        for (i, v) in vertices.iter().enumerate() {
+            vertex_cells[*v].push(fine_cell);
+            match reference_node(&reference_vertices[2 * i..2 * i + 2]) {
+                ReferenceNode::Vertex(j) => {
+                    vertex_to_fine.insert(parent_vertices[j], *v);
+                }
+                ReferenceNode::EdgeMidpoint(j) => {
+                    midpoint_to_fine.insert(
+                        edge_key(parent_vertices[edges[j][0]], parent_vertices[edges[j][1]]),
+                        *v,
+                    );
+                }
+                ReferenceNode::Midpoint => {}
+            }
+        }
+    }
+
+    let fine_dof = |v0: usize, v1: usize| {
+        space.global_dof_index(space.get_local_dof_numbers(1, fine_edges[&edge_key(v0, v1)])[0])
+    };
+    let half = num::cast::<f64, T>(0.5).unwrap();
+
+    for (&(v_start, v_end), coarse_edge) in &coarse_edges {
+        let coarse_dof = coarse_space.global_dof_index(
+            *coarse_space
+                .get_local_dof_numbers(1, *coarse_edge)
+                .first()
+                .expect("Coarse space must have a DOF associated with each edge"),
+        );
+        let midpoint = midpoint_to_fine[&(v_start, v_end)];
+
+        // Inside the dual cell of each vertex, the flux is spread evenly over the refined cells around the vertex: the
+        // flux through the k-th edge anticlockwise from the coarse edge is (k - N) / 2N (or (N - k) / 2N for the
+        // end vertex), where 2N is the number of refined cells around the vertex
+        for (coarse_vertex, sign) in [(v_start, 1.0), (v_end, -1.0)] {
+            let vertex = vertex_to_fine[&coarse_vertex];
+            let fan = vertex_fan(vertex, midpoint, &vertex_cells[vertex], &fine_cells);
+            let n = fan.len() / 2;
+            for (k, (w, _)) in fan.iter().enumerate().skip(1) {
+                if k != n {
+                    let flux = sign * (k as f64 - n as f64) / (2 * n) as f64;
+                    let orientation = if vertex < *w { 1.0 } else { -1.0 };
+                    coefficients[fine_dof(vertex, *w)]
+                        .push((coarse_dof, num::cast::<f64, T>(flux * orientation).unwrap()));
+                }
+            }
+
+            // Half of the flux passes through each of the two edges of the dual edge that crosses the coarse edge
+            if coarse_vertex == v_start {
+                for (c, cell) in [(fan[1].0, fan[0].1), (fan[2 * n - 1].0, fan[2 * n - 1].1)] {
+                    let (p, q) = edge_key(midpoint, c);
+                    let normal = cell_normal(&fine_cells[cell], &coordinates);
+                    let tangent = [0, 1, 2].map(|j| coordinates[q][j] - coordinates[p][j]);
+                    let direction = [0, 1, 2]
+                        .map(|j| coordinates[vertex_to_fine[&v_end]][j] - coordinates[vertex][j]);
+                    let flux_direction = cross(&normal, &tangent);
+                    let value = if dot(&flux_direction, &direction) > T::Real::zero() {
+                        half
+                    } else {
+                        -half
+                    };
+                    coefficients[fine_dof(p, q)].push((coarse_dof, value));
+                }
+            }
+        }
+    }
+
+    MappedFunctionSpace::new(space, coefficients, coarse_space.global_size())
+}
+
+/// The vertices adjacent to a vertex in anticlockwise order starting at `start`, and the cell anticlockwise of each of
+/// these vertices
+fn vertex_fan(
+    vertex: usize,
+    start: usize,
+    cells: &[usize],
+    cell_vertices: &[Vec<usize>],
+) -> Vec<(usize, usize)> {
+    let mut next = HashMap::new();
+    for cell in cells {
+        let vertices = &cell_vertices[*cell];
+        let position = vertices.iter().position(|v| *v == vertex).unwrap();
+        next.insert(
+            vertices[(position + 1) % 3],
+            (vertices[(position + 2) % 3], *cell),
+        );
+    }
+    let mut fan = vec![];
+    let mut current = start;
+    loop {
+        let (following, cell) = next.get(&current).unwrap_or_else(|| {
+            panic!("Buffa–Christiansen spaces can only be created on closed surfaces")
+        });
+        fan.push((current, *cell));
+        current = *following;
+        if current == start {
+            break;
+        }
+    }
+    assert_eq!(fan.len(), cells.len());
+    fan
+}
+
+/// The (unnormalised) normal to a triangle
+fn cell_normal<T: RealScalar>(vertices: &[usize], coordinates: &[[T; 3]]) -> [T; 3] {
+    let a = [0, 1, 2].map(|j| coordinates[vertices[1]][j] - coordinates[vertices[0]][j]);
+    let b = [0, 1, 2].map(|j| coordinates[vertices[2]][j] - coordinates[vertices[0]][j]);
+    cross(&a, &b)
+}
+
+/// Cross product of two vectors
+fn cross<T: RealScalar>(a: &[T; 3], b: &[T; 3]) -> [T; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Dot product of two vectors
+fn dot<T: RealScalar>(a: &[T; 3], b: &[T; 3]) -> T {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
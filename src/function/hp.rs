@@ -0,0 +1,157 @@
+//! Continuous function spaces with a per-cell polynomial degree
+//!
+//! A space created using [FunctionSpace::new_hp] uses an element of a different degree on each cell, and the DOFs on
+//! the entities between cells of different degrees are not shared, so its functions are not continuous between these
+//! cells. The spaces in this module are [MappedFunctionSpace]s whose basis functions are linear combinations of the
+//! basis functions of such a space, chosen so that every function in the space is continuous.
+use crate::function::{FunctionSpace, FunctionSpaceTrait, MappedFunctionSpace};
+use crate::helpers::{lagrange_points, reference_vertices};
+use mpi::traits::Communicator;
+use ndelement::ciarlet::LagrangeElementFamily;
+use ndelement::reference_cell;
+use ndelement::traits::FiniteElement;
+use ndelement::types::{Continuity, ReferenceCellType};
+use ndgrid::traits::{Entity, Grid, ParallelGrid, Topology};
+use num::One;
+use rlst::{
+    rlst_dynamic_array2, rlst_dynamic_array4, MatrixInverse, RandomAccessByRef, RandomAccessMut,
+    RlstScalar,
+};
+
+/// Create a continuous Lagrange space with a per-cell polynomial degree
+///
+/// `cell_degrees` contains the polynomial degree of each cell, indexed by local cell index, and every degree must be at
+/// least 1. The returned space is mapped from the space created by [FunctionSpace::new_hp] using continuous Lagrange
+/// elements. The minimum rule is used on the edges between cells of different degrees: the functions on each edge
+/// are polynomials of the lowest degree of the cells around the edge, so the DOFs of the higher degree cells on the
+/// edge are constrained to interpolate these polynomials. The DOFs of the returned space are the DOFs of the
+/// unconstrained space that are not constrained, in the same order.
+pub fn lagrange_hp_space<
+    'a,
+    C: Communicator,
+    T: RlstScalar + MatrixInverse,
+    GridImpl: ParallelGrid<C> + Grid<T = T::Real, EntityDescriptor = ReferenceCellType>,
+>(
+    grid: &'a GridImpl,
+    cell_degrees: &[usize],
+) -> MappedFunctionSpace<FunctionSpace<'a, C, T, GridImpl>> {
+    if cell_degrees.contains(&0) {
+        panic!("Continuous Lagrange spaces must have degree at least 1");
+    }
+    let space = FunctionSpace::new_hp(
+        grid,
+        |degree| LagrangeElementFamily::<T>::new(degree, Continuity::Standard),
+        cell_degrees,
+    );
+    let tol = num::cast::<f64, T::Real>(1e-12).unwrap();
+
+    // Each DOF of the unconstrained space is either free or constrained to be a linear combination of free DOFs. The
+    // DOFs at each vertex are ordered by degree, and are all equal to the DOF of lowest degree.
+    let mut constraints: Vec<Option<Vec<(usize, T)>>> = vec![None; space.local_size()];
+    for vertex in 0..grid.entity_count(ReferenceCellType::Point) {
+        let dofs = space.get_local_dof_numbers(0, vertex);
+        for dof in dofs.iter().skip(1) {
+            constraints[*dof] = Some(vec![(dofs[0], T::one())]);
+        }
+    }
+
+    // The cells around each edge, and the local index of the edge in each cell
+    let mut edge_cells = vec![vec![]; grid.entity_count(ReferenceCellType::Interval)];
+    for cell in grid.entity_iter(2) {
+        for (i, e) in cell.topology().sub_entity_iter(1).enumerate() {
+            edge_cells[e].push((cell.local_index(), i));
+        }
+    }
+
+    for cells in &edge_cells {
+        let min_degree = cells.iter().map(|(c, _)| cell_degrees[*c]).min().unwrap();
+        let (low_cell, _) = *cells
+            .iter()
+            .find(|(c, _)| cell_degrees[*c] == min_degree)
+            .unwrap();
+        let low_cell_entity = grid.entity(2, low_cell).unwrap();
+        let low_type = low_cell_entity.entity_type();
+        let low_vertices = low_cell_entity
+            .topology()
+            .sub_entity_iter(0)
+            .collect::<Vec<_>>();
+        let low_reference_vertices = reference_vertices::<T::Real>(low_type);
+        let low_element = space.element_with_degree(low_type, Some(min_degree));
+        let low_dofs = space.cell_dofs(low_cell).unwrap();
+
+        for (cell, local_edge) in cells {
+            let degree = cell_degrees[*cell];
+            if degree == min_degree {
+                continue;
+            }
+            let cell_entity = grid.entity(2, *cell).unwrap();
+            let cell_type = cell_entity.entity_type();
+            let element = space.element_with_degree(cell_type, Some(degree));
+            let edge_dofs = element.entity_dofs(1, *local_edge).unwrap();
+            let points = lagrange_points::<T::Real>(cell_type, degree);
+            let vertices = cell_entity
+                .topology()
+                .sub_entity_iter(0)
+                .collect::<Vec<_>>();
+            let cell_reference_vertices = reference_vertices::<T::Real>(cell_type);
+
+            // Find the points on the lower degree cell that correspond to the points that define the DOFs on the edge
+            let edge = &reference_cell::edges(cell_type)[*local_edge];
+            let (a, b) = (edge[0], edge[1]);
+            let low_local_vertex = |v: usize| low_vertices.iter().position(|w| *w == v).unwrap();
+            let (low_a, low_b) = (low_local_vertex(vertices[a]), low_local_vertex(vertices[b]));
+            let direction = [0, 1]
+                .map(|j| cell_reference_vertices[2 * b + j] - cell_reference_vertices[2 * a + j]);
+            let mut low_points = rlst_dynamic_array2!(T::Real, [2, edge_dofs.len()]);
+            for (k, local_dof) in edge_dofs.iter().enumerate() {
+                let p = &points[2 * local_dof..2 * local_dof + 2];
+                let t = ((p[0] - cell_reference_vertices[2 * a]) * direction[0]
+                    + (p[1] - cell_reference_vertices[2 * a + 1]) * direction[1])
+                    / (direction[0] * direction[0] + direction[1] * direction[1]);
+                for j in 0..2 {
+                    *low_points.get_mut([j, k]).unwrap() = low_reference_vertices[2 * low_a + j]
+                        + t * (low_reference_vertices[2 * low_b + j]
+                            - low_reference_vertices[2 * low_a + j]);
+                }
+            }
+            let mut table =
+                rlst_dynamic_array4!(T, low_element.tabulate_array_shape(0, edge_dofs.len()));
+            low_element.tabulate(&low_points, 0, &mut table);
+
+            let dofs = space.cell_dofs(*cell).unwrap();
+            for (k, local_dof) in edge_dofs.iter().enumerate() {
+                let mut combination = vec![];
+                for (j, low_dof) in low_dofs.iter().enumerate() {
+                    let value = *table.get([0, k, j, 0]).unwrap();
+                    if value.abs() > tol {
+                        // The DOFs of the lower degree cell on the edge are free, but its DOFs at the vertices of the
+                        // edge may be constrained to DOFs of an even lower degree
+                        match &constraints[*low_dof] {
+                            Some(c) => combination.extend(c.iter().map(|(d, v)| (*d, *v * value))),
+                            None => combination.push((*low_dof, value)),
+                        }
+                    }
+                }
+                constraints[dofs[*local_dof]] = Some(combination);
+            }
+        }
+    }
+
+    let mut free_dofs = vec![0; constraints.len()];
+    let mut nfree = 0;
+    for (dof, c) in constraints.iter().enumerate() {
+        if c.is_none() {
+            free_dofs[dof] = nfree;
+            nfree += 1;
+        }
+    }
+    let mut coefficients = vec![vec![]; space.global_size()];
+    for (dof, c) in constraints.iter().enumerate() {
+        coefficients[space.global_dof_index(dof)] = match c {
+            Some(c) => c.iter().map(|(d, v)| (free_dofs[*d], *v)).collect(),
+            None => vec![(free_dofs[dof], T::one())],
+        };
+    }
+
+    MappedFunctionSpace::new(space, coefficients, nfree)
+}
@@ -0,0 +1,289 @@
+//! Transfer operators between function spaces
+//!
+//! Each operator is a sparse matrix that maps the coefficients of a function in one space to the coefficients of a
+//! function in another space. The coefficients in the target space are found by evaluating the function at the points
+//! that define the DOFs of the target space, so both spaces must be Lagrange spaces. If a DOF of the target space
+//! is shared between cells, the values found on each cell are averaged: this interpolates continuous functions, and
+//! averages discontinuous functions.
+use crate::boundary_assemblers::helpers::{equal_grids, CsrMatrixData, SparseMatrixData};
+use crate::error::BemppError;
+use crate::function::{cell_count, is_excluded, FunctionSpaceTrait};
+use crate::helpers::lagrange_points;
+use crate::refinement::RefinementMap;
+use ndelement::traits::FiniteElement;
+use ndelement::types::MapType;
+use ndgrid::traits::{Entity, Grid};
+use rlst::{
+    rlst_dynamic_array2, rlst_dynamic_array4, CsrMatrix, MatrixInverse, RandomAccessByRef,
+    RandomAccessMut, RlstScalar,
+};
+
+/// Create the matrix that interpolates functions in one space into another space on the same grid
+///
+/// The matrix has shape `[to_space.global_size(), from_space.global_size()]`. For example, this can be used to
+/// interpolate continuous piecewise linear (P1) functions into piecewise constant (DP0) functions, or to average
+/// discontinuous piecewise linear (DP1) functions to get P1 functions.
+pub fn interpolation_matrix<T: RlstScalar + MatrixInverse>(
+    from_space: &impl FunctionSpaceTrait<T = T>,
+    to_space: &impl FunctionSpaceTrait<T = T>,
+) -> CsrMatrix<T> {
+    try_interpolation_matrix(from_space, to_space).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Create the matrix that interpolates functions in one space into another space on the same grid, returning an error
+/// if the spaces cannot be used
+pub fn try_interpolation_matrix<T: RlstScalar + MatrixInverse>(
+    from_space: &impl FunctionSpaceTrait<T = T>,
+    to_space: &impl FunctionSpaceTrait<T = T>,
+) -> Result<CsrMatrix<T>, BemppError> {
+    check_spaces(from_space, to_space)?;
+    if !equal_grids(from_space.grid(), to_space.grid()) {
+        return Err(BemppError::IncompatibleSpaces(
+            "interpolation is only implemented for function spaces on the same grid".to_string(),
+        ));
+    }
+    let cell_pairs = from_space
+        .grid()
+        .entity_iter(2)
+        .map(|cell| (cell.local_index(), cell.local_index()))
+        .collect::<Vec<_>>();
+    Ok(CsrMatrixData::from_entries(transfer_matrix(
+        from_space,
+        to_space,
+        &cell_pairs,
+        |_, point| [point[0], point[1]],
+    ))
+    .into_csr_matrix())
+}
+
+/// Create the matrix that maps functions in a space on a coarse grid to a space on a refinement of the grid
+///
+/// The matrix has shape `[fine_space.global_size(), coarse_space.global_size()]`. If the fine space contains the coarse
+/// space, as when both spaces use Lagrange elements of the same degree and the fine grid is a uniform refinement of the
+/// coarse grid, this represents each coarse function exactly.
+pub fn prolongation_matrix<T: RlstScalar + MatrixInverse>(
+    coarse_space: &impl FunctionSpaceTrait<T = T>,
+    fine_space: &impl FunctionSpaceTrait<T = T>,
+    map: &RefinementMap<T::Real>,
+) -> CsrMatrix<T> {
+    try_prolongation_matrix(coarse_space, fine_space, map).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Create the matrix that maps functions in a space on a coarse grid to a space on a refinement of the grid, returning
+/// an error if the spaces cannot be used
+pub fn try_prolongation_matrix<T: RlstScalar + MatrixInverse>(
+    coarse_space: &impl FunctionSpaceTrait<T = T>,
+    fine_space: &impl FunctionSpaceTrait<T = T>,
+    map: &RefinementMap<T::Real>,
+) -> Result<CsrMatrix<T>, BemppError> {
+    Ok(CsrMatrixData::from_entries(prolongation(coarse_space, fine_space, map)?).into_csr_matrix())
+}
+
+/// Create the matrix that maps functions in a space on a refinement of a grid to a space on the coarse grid
+///
+/// This is the transpose of the matrix created by [prolongation_matrix], and has shape
+/// `[coarse_space.global_size(), fine_space.global_size()]`.
+pub fn restriction_matrix<T: RlstScalar + MatrixInverse>(
+    coarse_space: &impl FunctionSpaceTrait<T = T>,
+    fine_space: &impl FunctionSpaceTrait<T = T>,
+    map: &RefinementMap<T::Real>,
+) -> CsrMatrix<T> {
+    try_restriction_matrix(coarse_space, fine_space, map).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Create the matrix that maps functions in a space on a refinement of a grid to a space on the coarse grid, returning
+/// an error if the spaces cannot be used
+pub fn try_restriction_matrix<T: RlstScalar + MatrixInverse>(
+    coarse_space: &impl FunctionSpaceTrait<T = T>,
+    fine_space: &impl FunctionSpaceTrait<T = T>,
+    map: &RefinementMap<T::Real>,
+) -> Result<CsrMatrix<T>, BemppError> {
+    let prolongation = prolongation(coarse_space, fine_space, map)?;
+    let mut restriction = SparseMatrixData::new([prolongation.shape[1], prolongation.shape[0]]);
+    restriction.rows = prolongation.cols;
+    restriction.cols = prolongation.rows;
+    restriction.data = prolongation.data;
+    Ok(CsrMatrixData::from_entries(restriction).into_csr_matrix())
+}
+
+/// Compute the entries of a prolongation matrix
+fn prolongation<T: RlstScalar + MatrixInverse>(
+    coarse_space: &impl FunctionSpaceTrait<T = T>,
+    fine_space: &impl FunctionSpaceTrait<T = T>,
+    map: &RefinementMap<T::Real>,
+) -> Result<SparseMatrixData<T>, BemppError> {
+    check_spaces(coarse_space, fine_space)?;
+    if cell_count(coarse_space.grid()) != map.coarse_cell_count()
+        || cell_count(fine_space.grid()) != map.fine_cell_count()
+    {
+        return Err(BemppError::IncompatibleSpaces(
+            "the refinement map does not match the grids of the function spaces".to_string(),
+        ));
+    }
+    let cell_pairs = (0..map.fine_cell_count())
+        .map(|cell| (cell, map.parent(cell)))
+        .collect::<Vec<_>>();
+    Ok(transfer_matrix(
+        coarse_space,
+        fine_space,
+        &cell_pairs,
+        |cell, point| map.parent_reference_point(cell, point),
+    ))
+}
+
+/// Check that a pair of spaces can be used to create a transfer operator
+fn check_spaces<
+    T: RlstScalar + MatrixInverse,
+    FromSpace: FunctionSpaceTrait<T = T>,
+    ToSpace: FunctionSpaceTrait<T = T>,
+>(
+    from_space: &FromSpace,
+    to_space: &ToSpace,
+) -> Result<(), BemppError> {
+    if !from_space.is_serial() || !to_space.is_serial() {
+        return Err(BemppError::NotSerial("Transfer operators".to_string()));
+    }
+    let scalar = from_space
+        .grid()
+        .entity_types(2)
+        .iter()
+        .all(|t| from_space.map_type(*t) == MapType::IdentityMap)
+        && to_space
+            .grid()
+            .entity_types(2)
+            .iter()
+            .all(|t| to_space.map_type(*t) == MapType::IdentityMap);
+    if !scalar {
+        return Err(BemppError::IncompatibleSpaces(
+            "transfer operators can only be created for spaces of scalar-valued functions"
+                .to_string(),
+        ));
+    }
+    if !is_lagrange(from_space) || !is_lagrange(to_space) {
+        return Err(BemppError::IncompatibleSpaces(
+            "transfer operators can only be created for Lagrange spaces".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check that every element used by a space is a Lagrange element
+///
+/// The DOFs of a Lagrange element are point evaluations at the points given by [lagrange_points], so tabulating its
+/// basis functions at these points gives the identity matrix.
+fn is_lagrange<T: RlstScalar + MatrixInverse>(space: &impl FunctionSpaceTrait<T = T>) -> bool {
+    let tol = num::cast::<f64, T::Real>(1e-10).unwrap();
+    space.grid().entity_types(2).iter().all(|cell_type| {
+        space
+            .cell_type_degrees(*cell_type)
+            .into_iter()
+            .all(|degree| {
+                let element = space.element_with_degree(*cell_type, degree);
+                let points = lagrange_points::<T::Real>(*cell_type, element.embedded_superdegree());
+                let npts = points.len() / 2;
+                let shape = element.tabulate_array_shape(0, npts);
+                if npts != element.dim() || shape[3] != 1 {
+                    return false;
+                }
+                let mut reference_points = rlst_dynamic_array2!(T::Real, [2, npts]);
+                for i in 0..npts {
+                    for j in 0..2 {
+                        *reference_points.get_mut([j, i]).unwrap() = points[2 * i + j];
+                    }
+                }
+                let mut table = rlst_dynamic_array4!(T, shape);
+                element.tabulate(&reference_points, 0, &mut table);
+                (0..npts).all(|i| {
+                    (0..npts).all(|j| {
+                        let expected = if i == j { T::one() } else { T::zero() };
+                        (*table.get([0, i, j, 0]).unwrap() - expected).abs() < tol
+                    })
+                })
+            })
+    })
+}
+
+/// Compute the entries of a transfer matrix
+///
+/// Each item of `cell_pairs` is a cell of the grid of `to_space` and the cell of the grid of `from_space` that contains
+/// it, and `reference_point` maps a point on the reference cell of the first cell to the reference cell of the second.
+fn transfer_matrix<
+    T: RlstScalar + MatrixInverse,
+    FromSpace: FunctionSpaceTrait<T = T>,
+    ToSpace: FunctionSpaceTrait<T = T>,
+>(
+    from_space: &FromSpace,
+    to_space: &ToSpace,
+    cell_pairs: &[(usize, usize)],
+    reference_point: impl Fn(usize, &[T::Real]) -> [T::Real; 2],
+) -> SparseMatrixData<T> {
+    let tol = num::cast::<f64, T::Real>(1e-12).unwrap();
+
+    // Pairs of cells where one of the spaces has no DOFs are skipped
+    let cell_pairs = cell_pairs
+        .iter()
+        .copied()
+        .filter(|(to_cell, from_cell)| {
+            !to_space.cell_dofs(*to_cell).unwrap_or(&[]).is_empty()
+                && !from_space.cell_dofs(*from_cell).unwrap_or(&[]).is_empty()
+        })
+        .collect::<Vec<_>>();
+
+    // The number of cells that each DOF of the target space is shared between
+    let mut counts = vec![0; to_space.global_size()];
+    for (to_cell, _) in &cell_pairs {
+        let to_excluded = to_space.excluded_dofs(*to_cell);
+        for (i, dof) in to_space.cell_dofs(*to_cell).unwrap().iter().enumerate() {
+            if !is_excluded(to_excluded, i) {
+                counts[to_space.global_dof_index(*dof)] += 1;
+            }
+        }
+    }
+
+    let mut matrix = SparseMatrixData::new([to_space.global_size(), from_space.global_size()]);
+    for (to_cell, from_cell) in &cell_pairs {
+        let to_type = to_space.grid().entity(2, *to_cell).unwrap().entity_type();
+        let from_type = from_space
+            .grid()
+            .entity(2, *from_cell)
+            .unwrap()
+            .entity_type();
+
+        // Evaluate the basis functions of the source space at the points that define the DOFs of the target space
+        let to_element = to_space.element_with_degree(to_type, to_space.cell_degree(*to_cell));
+        let points = lagrange_points::<T::Real>(to_type, to_element.embedded_superdegree());
+        let npts = points.len() / 2;
+        debug_assert!(npts == to_element.dim());
+        let mut from_points = rlst_dynamic_array2!(T::Real, [2, npts]);
+        for i in 0..npts {
+            let p = reference_point(*to_cell, &points[2 * i..2 * i + 2]);
+            for (j, c) in p.iter().enumerate() {
+                *from_points.get_mut([j, i]).unwrap() = *c;
+            }
+        }
+        let from_element =
+            from_space.element_with_degree(from_type, from_space.cell_degree(*from_cell));
+        let mut table = rlst_dynamic_array4!(T, from_element.tabulate_array_shape(0, npts));
+        from_element.tabulate(&from_points, 0, &mut table);
+
+        let from_dofs = from_space.cell_dofs(*from_cell).unwrap();
+        let to_excluded = to_space.excluded_dofs(*to_cell);
+        let from_excluded = from_space.excluded_dofs(*from_cell);
+        for (i, to_dof) in to_space.cell_dofs(*to_cell).unwrap().iter().enumerate() {
+            if is_excluded(to_excluded, i) {
+                continue;
+            }
+            let row = to_space.global_dof_index(*to_dof);
+            let scale = T::one() / num::cast::<usize, T>(counts[row]).unwrap();
+            for (j, from_dof) in from_dofs.iter().enumerate() {
+                let value = *table.get([0, i, j, 0]).unwrap();
+                if !is_excluded(from_excluded, j) && value.abs() > tol {
+                    matrix.rows.push(row);
+                    matrix.cols.push(from_space.global_dof_index(*from_dof));
+                    matrix.data.push(value * scale);
+                }
+            }
+        }
+    }
+    matrix
+}
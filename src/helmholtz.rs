@@ -2,8 +2,10 @@
 
 /// Assemblers for Helmholtz problems
 pub mod assembler {
-    use green_kernels::{helmholtz_3d::Helmholtz3dKernel, types::GreenKernelEvalType};
-    use rlst::{MatrixInverse, RlstScalar};
+    use green_kernels::{
+        helmholtz_3d::Helmholtz3dKernel, traits::Kernel, types::GreenKernelEvalType,
+    };
+    use rlst::{c32, MatrixInverse, RlstScalar};
 
     use crate::boundary_assemblers::{
         helpers::KernelEvaluator,
@@ -40,15 +42,34 @@ pub mod assembler {
         Helmholtz3dKernel<T>,
     >;
 
+    /// Create a Helmholtz kernel evaluator that can also evaluate the kernel in single precision.
+    fn kernel<T: RlstScalar<Complex = T>>(
+        wavenumber: T::Real,
+        eval_type: GreenKernelEvalType,
+    ) -> KernelEvaluator<T, Helmholtz3dKernel<T>> {
+        let single_kernel =
+            Helmholtz3dKernel::<c32>::new(num::cast::<T::Real, f32>(wavenumber).unwrap());
+        KernelEvaluator::new(Helmholtz3dKernel::new(wavenumber), eval_type).with_single_precision(
+            move |eval_type, sources, targets, result: &mut [T], buffer: &mut Vec<f32>| {
+                buffer.resize(2 * result.len(), 0.0);
+                // SAFETY: c32 has the same layout as [f32; 2], so the buffer holds result.len() complex values
+                let single_result = unsafe {
+                    std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut c32, result.len())
+                };
+                single_kernel.assemble_st(eval_type, sources, targets, single_result);
+                for (r, s) in result.iter_mut().zip(single_result.iter()) {
+                    *r = T::complex(s.re, s.im);
+                }
+            },
+        )
+    }
+
     /// Assembler for the Helmholtz single layer operator.
     pub fn single_layer<T: RlstScalar<Complex = T> + MatrixInverse>(
         wavenumber: T::Real,
         options: &BoundaryAssemblerOptions,
     ) -> SingleLayer3dAssembler<T> {
-        let kernel = KernelEvaluator::new(
-            Helmholtz3dKernel::new(wavenumber),
-            GreenKernelEvalType::Value,
-        );
+        let kernel = kernel(wavenumber, GreenKernelEvalType::Value);
 
         BoundaryAssembler::new(SingleLayerBoundaryIntegrand::new(), kernel, options, 1, 0)
     }
@@ -58,10 +79,7 @@ pub mod assembler {
         wavenumber: T::Real,
         options: &BoundaryAssemblerOptions,
     ) -> DoubleLayer3dAssembler<T> {
-        let kernel = KernelEvaluator::new(
-            Helmholtz3dKernel::new(wavenumber),
-            GreenKernelEvalType::ValueDeriv,
-        );
+        let kernel = kernel(wavenumber, GreenKernelEvalType::ValueDeriv);
 
         BoundaryAssembler::new(DoubleLayerBoundaryIntegrand::new(), kernel, options, 4, 0)
     }
@@ -71,10 +89,7 @@ pub mod assembler {
         wavenumber: T::Real,
         options: &BoundaryAssemblerOptions,
     ) -> AdjointDoubleLayer3dAssembler<T> {
-        let kernel = KernelEvaluator::new(
-            Helmholtz3dKernel::new(wavenumber),
-            GreenKernelEvalType::ValueDeriv,
-        );
+        let kernel = kernel(wavenumber, GreenKernelEvalType::ValueDeriv);
 
         BoundaryAssembler::new(
             AdjointDoubleLayerBoundaryIntegrand::new(),
@@ -90,10 +105,7 @@ pub mod assembler {
         wavenumber: T::Real,
         options: &BoundaryAssemblerOptions,
     ) -> Hypersingular3dAssembler<T> {
-        let kernel = KernelEvaluator::new(
-            Helmholtz3dKernel::new(wavenumber),
-            GreenKernelEvalType::ValueDeriv,
-        );
+        let kernel = kernel(wavenumber, GreenKernelEvalType::ValueDeriv);
 
         let integrand = BoundaryIntegrandSum::new(
             HypersingularCurlCurlBoundaryIntegrand::new(),
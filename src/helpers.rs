@@ -0,0 +1,286 @@
+//! Common utility functions for grids
+use ndelement::types::ReferenceCellType;
+use ndgrid::traits::{Entity, GeometryMap, Grid, Topology};
+use ndgrid::types::RealScalar;
+use num::{Float, Zero};
+use std::collections::HashMap;
+
+/// The vertices of a reference cell, stored as [x0, y0, x1, y1, ...]
+pub(crate) fn reference_vertices<T: RealScalar>(cell_type: ReferenceCellType) -> Vec<T> {
+    let zero = T::zero();
+    let one = T::from(1.0).unwrap();
+    match cell_type {
+        ReferenceCellType::Triangle => vec![zero, zero, one, zero, zero, one],
+        ReferenceCellType::Quadrilateral => vec![zero, zero, one, zero, zero, one, one, one],
+        _ => {
+            unimplemented!("Only triangles and quadrilaterals are currently supported");
+        }
+    }
+}
+
+/// Get the coordinates of every vertex in a grid
+///
+/// The coordinates are indexed by the local index of each vertex.
+pub(crate) fn vertex_coordinates<G: Grid<EntityDescriptor = ReferenceCellType>>(
+    grid: &G,
+) -> Vec<[G::T; 3]> {
+    assert_eq!(grid.geometry_dim(), 3);
+    assert_eq!(grid.topology_dim(), 2);
+
+    let mut coordinates = vec![[G::T::zero(); 3]; grid.entity_count(ReferenceCellType::Point)];
+    for cell_type in grid.entity_types(2) {
+        let points = reference_vertices::<G::T>(*cell_type);
+        let npts = points.len() / 2;
+        let evaluator = grid.geometry_map(*cell_type, &points);
+        let mut mapped_points = vec![G::T::zero(); 3 * npts];
+        for cell in grid.entity_iter(2) {
+            if cell.entity_type() == *cell_type {
+                evaluator.points(cell.local_index(), &mut mapped_points);
+                for (i, v) in cell.topology().sub_entity_iter(0).enumerate() {
+                    for (j, c) in coordinates[v].iter_mut().enumerate() {
+                        *c = mapped_points[3 * i + j];
+                    }
+                }
+            }
+        }
+    }
+    coordinates
+}
+
+/// Find the vertices of a grid that coincide with vertices of another grid
+///
+/// Two vertices coincide if the distance between them is less than `tolerance`. The vertices of `grid1` are placed in a
+/// spatial hash with boxes of width `tolerance`, so each vertex of `grid0` is only compared with the vertices in the
+/// neighbouring boxes. The returned map takes the local index of a vertex of `grid0` to the local index of the
+/// coincident vertex of `grid1`.
+pub(crate) fn coincident_vertices<
+    G0: Grid<EntityDescriptor = ReferenceCellType>,
+    G1: Grid<T = G0::T, EntityDescriptor = ReferenceCellType>,
+>(
+    grid0: &G0,
+    grid1: &G1,
+    tolerance: G0::T,
+) -> HashMap<usize, usize> {
+    let hash_key =
+        |p: &[G0::T; 3]| p.map(|c| num::cast::<G0::T, i64>(Float::floor(c / tolerance)).unwrap());
+
+    let coordinates1 = vertex_coordinates(grid1);
+    let mut boxes = HashMap::<[i64; 3], Vec<usize>>::new();
+    for (v, p) in coordinates1.iter().enumerate() {
+        boxes.entry(hash_key(p)).or_default().push(v);
+    }
+
+    let mut coincident = HashMap::new();
+    for (v0, p) in vertex_coordinates(grid0).iter().enumerate() {
+        let key = hash_key(p);
+        'neighbours: for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(vertices) = boxes.get(&[key[0] + dx, key[1] + dy, key[2] + dz]) {
+                        for v1 in vertices {
+                            let distance_squared = p
+                                .iter()
+                                .zip(&coordinates1[*v1])
+                                .map(|(a, b)| (*a - *b) * (*a - *b))
+                                .fold(G0::T::zero(), |a, b| a + b);
+                            if distance_squared < tolerance * tolerance {
+                                coincident.insert(v0, *v1);
+                                break 'neighbours;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    coincident
+}
+
+/// Find the pairs of spheres from two sets that are close to each other
+///
+/// A sphere of the first set and a sphere of the second set are close if the distance between them is less than
+/// `ratio` times the larger of their diameters. The centres of the second set of spheres are placed in a spatial hash
+/// with boxes as wide as the largest diameter in this set, so each sphere of the first set is only compared with the
+/// spheres in nearby boxes. The returned vector contains the indices of the spheres of the second set that are close
+/// to each sphere of the first set.
+pub(crate) fn close_spheres<T: Float>(
+    centres0: &[[T; 3]],
+    radii0: &[T],
+    centres1: &[[T; 3]],
+    radii1: &[T],
+    ratio: T,
+) -> Vec<Vec<usize>> {
+    let two = T::from(2.0).unwrap();
+    let max_radius1 = radii1.iter().fold(T::zero(), |a, b| Float::max(a, *b));
+    let width = if max_radius1 > T::zero() {
+        two * max_radius1
+    } else {
+        T::one()
+    };
+    let hash_key = |p: &[T; 3]| p.map(|c| num::cast::<T, i64>(Float::floor(c / width)).unwrap());
+
+    let mut boxes = HashMap::<[i64; 3], Vec<usize>>::new();
+    for (i, c) in centres1.iter().enumerate() {
+        boxes.entry(hash_key(c)).or_default().push(i);
+    }
+
+    let is_close = |c0: &[T; 3], r0: T, i1: usize| {
+        let centre_distance = Float::sqrt(
+            c0.iter()
+                .zip(&centres1[i1])
+                .map(|(a, b)| (*a - *b) * (*a - *b))
+                .fold(T::zero(), |a, b| a + b),
+        );
+        centre_distance - r0 - radii1[i1] < ratio * two * Float::max(r0, radii1[i1])
+    };
+
+    centres0
+        .iter()
+        .zip(radii0)
+        .map(|(c0, r0)| {
+            // The centres of all the close spheres are within this distance of the centre of this sphere
+            let search_radius = *r0 + max_radius1 + ratio * two * Float::max(*r0, max_radius1);
+            let nboxes = num::cast::<T, usize>(Float::ceil(search_radius / width)).filter(|n| {
+                n.checked_mul(2)
+                    .and_then(|m| (m + 1).checked_pow(3))
+                    .is_some_and(|count| count <= boxes.len())
+            });
+            let mut close = vec![];
+            if let Some(n) = nboxes {
+                let n = n as i64;
+                let key = hash_key(c0);
+                for dx in -n..=n {
+                    for dy in -n..=n {
+                        for dz in -n..=n {
+                            if let Some(indices) =
+                                boxes.get(&[key[0] + dx, key[1] + dy, key[2] + dz])
+                            {
+                                close.extend(indices.iter().filter(|i1| is_close(c0, *r0, **i1)));
+                            }
+                        }
+                    }
+                }
+            } else {
+                // If more boxes would be searched than are in the hash, every sphere is checked
+                close.extend((0..centres1.len()).filter(|i1| is_close(c0, *r0, *i1)));
+            }
+            close
+        })
+        .collect()
+}
+
+/// Get a sphere containing each cell in a grid
+///
+/// The centres and radii are indexed by the local index of each cell. The spheres are computed using the vertices and
+/// edge midpoints of each cell, so may be slightly too small for cells with curved geometry.
+pub(crate) fn cell_bounding_spheres<G: Grid<EntityDescriptor = ReferenceCellType>>(
+    grid: &G,
+) -> (Vec<[G::T; 3]>, Vec<G::T>) {
+    assert_eq!(grid.geometry_dim(), 3);
+    assert_eq!(grid.topology_dim(), 2);
+
+    let ncells = grid
+        .entity_types(2)
+        .iter()
+        .map(|t| grid.entity_count(*t))
+        .sum::<usize>();
+    let mut centres = vec![[G::T::zero(); 3]; ncells];
+    let mut radii = vec![G::T::zero(); ncells];
+    for cell_type in grid.entity_types(2) {
+        let points = lagrange_points::<G::T>(*cell_type, 2);
+        let npts = points.len() / 2;
+        let evaluator = grid.geometry_map(*cell_type, &points);
+        let mut mapped_points = vec![G::T::zero(); 3 * npts];
+        for cell in grid.entity_iter(2) {
+            if cell.entity_type() == *cell_type {
+                let index = cell.local_index();
+                evaluator.points(index, &mut mapped_points);
+                let mut centre = [G::T::zero(); 3];
+                for p in mapped_points.chunks(3) {
+                    for (c, p_j) in centre.iter_mut().zip(p) {
+                        *c += *p_j / G::T::from(npts).unwrap();
+                    }
+                }
+                radii[index] = mapped_points
+                    .chunks(3)
+                    .map(|p| {
+                        Float::sqrt(
+                            p.iter()
+                                .zip(&centre)
+                                .map(|(p_j, c)| (*p_j - *c) * (*p_j - *c))
+                                .fold(G::T::zero(), |a, b| a + b),
+                        )
+                    })
+                    .fold(G::T::zero(), |a, b| if b > a { b } else { a });
+                centres[index] = centre;
+            }
+        }
+    }
+    (centres, radii)
+}
+
+/// The points that define the DOFs of a Lagrange element on a reference cell, stored as [x0, y0, x1, y1, ...]
+///
+/// The points are ordered in the same way as the DOFs of the element: the vertices, then the points on the interior of
+/// each edge, then the points on the interior of the cell. The single point of a degree 0 element is the midpoint of the
+/// cell.
+pub(crate) fn lagrange_points<T: RealScalar>(
+    cell_type: ReferenceCellType,
+    degree: usize,
+) -> Vec<T> {
+    let third = T::from(1.0 / 3.0).unwrap();
+    let half = T::from(0.5).unwrap();
+    if degree == 0 {
+        return match cell_type {
+            ReferenceCellType::Triangle => vec![third, third],
+            ReferenceCellType::Quadrilateral => vec![half, half],
+            _ => {
+                unimplemented!("Only triangles and quadrilaterals are currently supported");
+            }
+        };
+    }
+
+    let vertices = reference_vertices::<T>(cell_type);
+    let edges: &[[usize; 2]] = match cell_type {
+        ReferenceCellType::Triangle => &[[1, 2], [0, 2], [0, 1]],
+        ReferenceCellType::Quadrilateral => &[[0, 1], [0, 2], [1, 3], [2, 3]],
+        _ => {
+            unimplemented!("Only triangles and quadrilaterals are currently supported");
+        }
+    };
+    let n = T::from(degree).unwrap();
+
+    let mut points = vertices.clone();
+    for e in edges {
+        for i in 1..degree {
+            let t = T::from(i).unwrap() / n;
+            for j in 0..2 {
+                points.push(
+                    vertices[2 * e[0] + j] + t * (vertices[2 * e[1] + j] - vertices[2 * e[0] + j]),
+                );
+            }
+        }
+    }
+    match cell_type {
+        ReferenceCellType::Triangle => {
+            for j in 1..degree {
+                for i in 1..degree - j {
+                    points.push(T::from(i).unwrap() / n);
+                    points.push(T::from(j).unwrap() / n);
+                }
+            }
+        }
+        ReferenceCellType::Quadrilateral => {
+            for i in 1..degree {
+                for j in 1..degree {
+                    points.push(T::from(i).unwrap() / n);
+                    points.push(T::from(j).unwrap() / n);
+                }
+            }
+        }
+        _ => {
+            unimplemented!("Only triangles and quadrilaterals are currently supported");
+        }
+    }
+    points
+}
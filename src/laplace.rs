@@ -2,7 +2,7 @@
 
 /// Assemblers for Laplace problems.
 pub mod assembler {
-    use green_kernels::{laplace_3d::Laplace3dKernel, types::GreenKernelEvalType};
+    use green_kernels::{laplace_3d::Laplace3dKernel, traits::Kernel, types::GreenKernelEvalType};
     use rlst::{MatrixInverse, RlstScalar};
 
     use crate::boundary_assemblers::{
@@ -30,11 +30,31 @@ pub mod assembler {
     pub type Hypersingular3dAssembler<'o, T> =
         BoundaryAssembler<'o, T, HypersingularCurlCurlBoundaryIntegrand<T>, Laplace3dKernel<T>>;
 
+    /// Create a Laplace kernel evaluator that can also evaluate the kernel in single precision.
+    fn kernel<T: RlstScalar<Real = T>>(
+        eval_type: GreenKernelEvalType,
+    ) -> KernelEvaluator<T, Laplace3dKernel<T>> {
+        KernelEvaluator::new(Laplace3dKernel::new(), eval_type).with_single_precision(
+            |eval_type, sources, targets, result: &mut [T], single_result: &mut Vec<f32>| {
+                single_result.resize(result.len(), 0.0);
+                Laplace3dKernel::<f32>::new().assemble_st(
+                    eval_type,
+                    sources,
+                    targets,
+                    single_result,
+                );
+                for (r, s) in result.iter_mut().zip(single_result.iter()) {
+                    *r = num::cast::<f32, T>(*s).unwrap();
+                }
+            },
+        )
+    }
+
     /// Assembler for the Laplace single layer operator.
     pub fn single_layer<T: RlstScalar<Real = T> + MatrixInverse>(
         options: &BoundaryAssemblerOptions,
     ) -> SingleLayer3dAssembler<T> {
-        let kernel = KernelEvaluator::new(Laplace3dKernel::new(), GreenKernelEvalType::Value);
+        let kernel = kernel(GreenKernelEvalType::Value);
         BoundaryAssembler::new(SingleLayerBoundaryIntegrand::new(), kernel, options, 1, 0)
     }
 
@@ -42,7 +62,7 @@ pub mod assembler {
     pub fn double_layer<T: RlstScalar<Real = T> + MatrixInverse>(
         options: &BoundaryAssemblerOptions,
     ) -> DoubleLayer3dAssembler<T> {
-        let kernel = KernelEvaluator::new(Laplace3dKernel::new(), GreenKernelEvalType::ValueDeriv);
+        let kernel = kernel(GreenKernelEvalType::ValueDeriv);
 
         BoundaryAssembler::new(DoubleLayerBoundaryIntegrand::new(), kernel, options, 4, 0)
     }
@@ -51,7 +71,7 @@ pub mod assembler {
     pub fn adjoint_double_layer<T: RlstScalar<Real = T> + MatrixInverse>(
         options: &BoundaryAssemblerOptions,
     ) -> AdjointDoubleLayer3dAssembler<T> {
-        let kernel = KernelEvaluator::new(Laplace3dKernel::new(), GreenKernelEvalType::ValueDeriv);
+        let kernel = kernel(GreenKernelEvalType::ValueDeriv);
 
         BoundaryAssembler::new(
             AdjointDoubleLayerBoundaryIntegrand::new(),
@@ -66,7 +86,7 @@ pub mod assembler {
     pub fn hypersingular<T: RlstScalar<Real = T> + MatrixInverse>(
         options: &BoundaryAssemblerOptions,
     ) -> Hypersingular3dAssembler<T> {
-        let kernel = KernelEvaluator::new(Laplace3dKernel::new(), GreenKernelEvalType::ValueDeriv);
+        let kernel = kernel(GreenKernelEvalType::ValueDeriv);
 
         BoundaryAssembler::new(
             HypersingularCurlCurlBoundaryIntegrand::new(),
@@ -3,11 +3,19 @@
 #![warn(missing_docs)]
 
 //pub mod bindings;
+pub mod adaptivity;
 pub mod boundary_assemblers;
+pub mod custom;
+pub mod error;
 pub mod function;
 pub mod helmholtz;
+pub(crate) mod helpers;
 pub mod laplace;
+pub mod multi_domain;
+pub mod refinement;
 pub mod shapes;
+pub mod solvers;
+pub mod sparse;
 
 #[cfg(test)]
 mod test {
@@ -0,0 +1,110 @@
+//! Grids on the interfaces between several subdomains
+//!
+//! In a transmission problem with several subdomains, each cell of the grid lies on the interface between two
+//! subdomains. A [MultiDomainGrid] stores the pair of subdomains separated by each cell, and can be used to create a
+//! trace space on the boundary of each subdomain with [FunctionSpace::new_on_subdomain](crate::function::FunctionSpace::new_on_subdomain).
+use crate::error::BemppError;
+use crate::function::cell_count;
+use ndelement::types::ReferenceCellType;
+use ndgrid::traits::{Entity, Grid, Topology};
+use std::collections::BTreeSet;
+
+/// A grid whose cells are on the interfaces between subdomains
+pub struct MultiDomainGrid<'a, GridImpl: Grid<EntityDescriptor = ReferenceCellType>> {
+    grid: &'a GridImpl,
+    cell_domains: Vec<[usize; 2]>,
+}
+
+impl<'a, GridImpl: Grid<EntityDescriptor = ReferenceCellType>> MultiDomainGrid<'a, GridImpl> {
+    /// Create new
+    ///
+    /// `cell_domains` contains the two subdomains separated by each cell of the grid, indexed by local cell index. The
+    /// first subdomain is the one that the normal to the cell points out of, and the second subdomain is the one that
+    /// the normal points into.
+    pub fn new(grid: &'a GridImpl, cell_domains: Vec<[usize; 2]>) -> Self {
+        Self::try_new(grid, cell_domains).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Create new, returning an error if the input is invalid
+    pub fn try_new(grid: &'a GridImpl, cell_domains: Vec<[usize; 2]>) -> Result<Self, BemppError> {
+        if grid.topology_dim() != 2 {
+            return Err(BemppError::UnsupportedTopologicalDimension(
+                grid.topology_dim(),
+            ));
+        }
+        let ncells = cell_count(grid);
+        if cell_domains.len() != ncells {
+            return Err(BemppError::InvalidDomains(format!(
+                "subdomains were given for {} cells, but the grid has {ncells} cells",
+                cell_domains.len()
+            )));
+        }
+        for (cell, domains) in cell_domains.iter().enumerate() {
+            if domains[0] == domains[1] {
+                return Err(BemppError::InvalidDomains(format!(
+                    "cell {cell} separates subdomain {} from itself",
+                    domains[0]
+                )));
+            }
+        }
+        Ok(Self { grid, cell_domains })
+    }
+
+    /// Get the grid
+    pub fn grid(&self) -> &'a GridImpl {
+        self.grid
+    }
+
+    /// Get the two subdomains separated by a cell
+    pub fn cell_domains(&self, cell: usize) -> [usize; 2] {
+        self.cell_domains[cell]
+    }
+
+    /// Get the subdomains, in increasing order
+    pub fn domains(&self) -> Vec<usize> {
+        self.cell_domains
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Get the local indices of the cells on the boundary of a subdomain
+    pub fn boundary_cells(&self, domain: usize) -> Vec<usize> {
+        self.cell_domains
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.contains(&domain))
+            .map(|(cell, _)| cell)
+            .collect()
+    }
+
+    /// Check if the normal to a cell points into a subdomain
+    ///
+    /// The trace spaces on the boundary of a subdomain use normals that point out of the subdomain, so the normal to
+    /// the cell is reversed in these spaces if this is true.
+    pub fn normal_points_into(&self, cell: usize, domain: usize) -> bool {
+        self.cell_domains[cell][1] == domain
+    }
+
+    /// Get the local indices of the edges on junctions
+    ///
+    /// A junction is an edge where three or more subdomains meet.
+    pub fn junction_edges(&self) -> Vec<usize> {
+        let mut edge_domains =
+            vec![BTreeSet::new(); self.grid.entity_count(ReferenceCellType::Interval)];
+        for cell in self.grid.entity_iter(2) {
+            for e in cell.topology().sub_entity_iter(1) {
+                edge_domains[e].extend(self.cell_domains[cell.local_index()]);
+            }
+        }
+        edge_domains
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.len() > 2)
+            .map(|(e, _)| e)
+            .collect()
+    }
+}
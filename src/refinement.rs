@@ -0,0 +1,639 @@
+//! Grid refinement
+use crate::error::BemppError;
+use crate::helpers::{lagrange_points, vertex_coordinates};
+use crate::shapes::{create_higher_order_grid_on_root, higher_order_points_and_cells};
+use mpi::traits::{Communicator, Equivalence};
+use ndelement::{ciarlet::CiarletElement, types::ReferenceCellType};
+use ndgrid::traits::{
+    Entity, Geometry, GeometryMap, Grid, ParallelGrid as ParallelGridTrait, Topology,
+};
+use ndgrid::{types::RealScalar, ParallelGrid, SingleElementGrid};
+use num::Zero;
+use std::collections::{HashMap, HashSet};
+
+/// Points on the reference triangle used when refining: the vertices followed by the midpoints of the edges
+const TRIANGLE_NODES: [[f64; 2]; 6] = [
+    [0.0, 0.0],
+    [1.0, 0.0],
+    [0.0, 1.0],
+    [0.5, 0.5],
+    [0.0, 0.5],
+    [0.5, 0.0],
+];
+/// The edges of a triangle that each of the midpoints in [TRIANGLE_NODES] is on
+const TRIANGLE_EDGES: [[usize; 2]; 3] = [[1, 2], [0, 2], [0, 1]];
+/// The children of a triangle in a red (uniform) refinement
+const TRIANGLE_RED_CHILDREN: [[usize; 3]; 4] = [[0, 5, 4], [1, 3, 5], [2, 4, 3], [3, 4, 5]];
+/// The children of a triangle in a green refinement, for each choice of refined edge
+const TRIANGLE_GREEN_CHILDREN: [[[usize; 3]; 2]; 3] = [
+    [[0, 1, 3], [0, 3, 2]],
+    [[1, 2, 4], [1, 4, 0]],
+    [[2, 0, 5], [2, 5, 1]],
+];
+/// The children of a triangle in a barycentric refinement, where node 6 is the midpoint of the triangle
+const TRIANGLE_BARYCENTRIC_CHILDREN: [[usize; 3]; 6] = [
+    [0, 5, 6],
+    [5, 1, 6],
+    [1, 3, 6],
+    [3, 2, 6],
+    [2, 4, 6],
+    [4, 0, 6],
+];
+
+/// Points on the reference quadrilateral used when refining: the vertices, the midpoints of the edges, then the midpoint
+const QUADRILATERAL_NODES: [[f64; 2]; 9] = [
+    [0.0, 0.0],
+    [1.0, 0.0],
+    [0.0, 1.0],
+    [1.0, 1.0],
+    [0.5, 0.0],
+    [0.0, 0.5],
+    [1.0, 0.5],
+    [0.5, 1.0],
+    [0.5, 0.5],
+];
+/// The edges of a quadrilateral that each of the edge midpoints in [QUADRILATERAL_NODES] is on
+const QUADRILATERAL_EDGES: [[usize; 2]; 4] = [[0, 1], [0, 2], [1, 3], [2, 3]];
+/// The children of a quadrilateral in a uniform refinement
+const QUADRILATERAL_CHILDREN: [[usize; 4]; 4] =
+    [[0, 4, 5, 8], [4, 1, 8, 6], [5, 8, 2, 7], [8, 6, 7, 3]];
+
+/// The type of a refined grid
+pub type RefinedGrid<'a, C, T> = ParallelGrid<'a, C, SingleElementGrid<T, CiarletElement<T>>>;
+
+/// Map between the cells of a grid and the cells of a refinement of that grid
+pub struct RefinementMap<T> {
+    parents: Vec<usize>,
+    children: Vec<Vec<usize>>,
+    reference_vertices: Vec<Vec<T>>,
+}
+
+impl<T> RefinementMap<T> {
+    /// Get the cell of the coarse grid that contains a cell of the refined grid
+    pub fn parent(&self, cell: usize) -> usize {
+        self.parents[cell]
+    }
+
+    /// Get the cells of the refined grid that are contained in a cell of the coarse grid
+    pub fn children(&self, cell: usize) -> &[usize] {
+        &self.children[cell]
+    }
+
+    /// Get the vertices of a cell of the refined grid on the reference cell of its parent
+    ///
+    /// The coordinates are stored as [x0, y0, x1, y1, ...]
+    pub fn reference_vertices(&self, cell: usize) -> &[T] {
+        &self.reference_vertices[cell]
+    }
+
+    /// The number of cells in the coarse grid
+    pub fn coarse_cell_count(&self) -> usize {
+        self.children.len()
+    }
+
+    /// The number of cells in the refined grid
+    pub fn fine_cell_count(&self) -> usize {
+        self.parents.len()
+    }
+}
+
+impl<T: RealScalar> RefinementMap<T> {
+    /// Map a point on the reference cell of a cell of the refined grid to the reference cell of its parent
+    pub fn parent_reference_point(&self, cell: usize, point: &[T]) -> [T; 2] {
+        map_to_parent(&self.reference_vertices[cell], point)
+    }
+}
+
+/// Map a point on the reference cell of a child to the reference cell of its parent
+///
+/// `v` contains the vertices of the child on the reference cell of its parent, stored as [x0, y0, x1, y1, ...]
+fn map_to_parent<T: RealScalar>(v: &[T], point: &[T]) -> [T; 2] {
+    match v.len() {
+        6 => [0, 1].map(|j| v[j] + point[0] * (v[2 + j] - v[j]) + point[1] * (v[4 + j] - v[j])),
+        8 => [0, 1].map(|j| {
+            v[j] + point[0] * (v[2 + j] - v[j])
+                + point[1] * (v[4 + j] - v[j])
+                + point[0] * point[1] * (v[j] - v[2 + j] - v[4 + j] + v[6 + j])
+        }),
+        _ => {
+            panic!("Unsupported cell type");
+        }
+    }
+}
+
+/// Refine a grid uniformly
+///
+/// Each triangle is split into four triangles by adding lines connecting the midpoints of each edge (red
+/// refinement), and each quadrilateral is split into four quadrilaterals by adding lines connecting the midpoints
+/// of opposite edges. The new points are computed using the geometry of the grid, and if `projection` is not
+/// `None`, each new point is then moved by the projection.
+///
+/// The input grid must be stored in serial, and the refined grid is created on the communicator `comm`, which must
+/// contain a single process. The refined grid has the same geometry degree as the input grid: the points that define
+/// the geometry of each new cell are placed on the geometry of its parent, then moved by the projection.
+pub fn refine_uniformly<
+    'a,
+    T: RealScalar + Equivalence,
+    C: Communicator,
+    G: ParallelGridTrait<C> + Grid<T = T, EntityDescriptor = ReferenceCellType>,
+>(
+    grid: &G,
+    projection: Option<&dyn Fn(&mut [T; 3])>,
+    comm: &'a C,
+) -> (RefinedGrid<'a, C, T>, RefinementMap<T>) {
+    try_refine_uniformly(grid, projection, comm).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Refine a grid uniformly, returning an error if the grid cannot be refined
+///
+/// See [refine_uniformly].
+pub fn try_refine_uniformly<
+    'a,
+    T: RealScalar + Equivalence,
+    C: Communicator,
+    G: ParallelGridTrait<C> + Grid<T = T, EntityDescriptor = ReferenceCellType>,
+>(
+    grid: &G,
+    projection: Option<&dyn Fn(&mut [T; 3])>,
+    comm: &'a C,
+) -> Result<(RefinedGrid<'a, C, T>, RefinementMap<T>), BemppError> {
+    let cell_type = check_grid(grid, comm)?;
+    let mut refined = RefinedCells::new(vertex_coordinates(grid));
+    let mut edge_points = HashMap::new();
+
+    let nodes = reference_nodes::<T>(cell_type);
+    let evaluator = grid.geometry_map(cell_type, &nodes);
+    let mut mapped_nodes = vec![T::zero(); 3 * nodes.len() / 2];
+
+    for cell in grid.entity_iter(2) {
+        let parent = cell.local_index();
+        evaluator.points(parent, &mut mapped_nodes);
+        let vertices = cell.topology().sub_entity_iter(0).collect::<Vec<_>>();
+
+        match cell_type {
+            ReferenceCellType::Triangle => {
+                let mut node_indices = vertices.clone();
+                for (i, e) in TRIANGLE_EDGES.iter().enumerate() {
+                    node_indices.push(refined.edge_midpoint(
+                        &mut edge_points,
+                        (vertices[e[0]], vertices[e[1]]),
+                        &mapped_nodes[3 * (3 + i)..3 * (4 + i)],
+                        projection,
+                    ));
+                }
+                for child in &TRIANGLE_RED_CHILDREN {
+                    refined.add_cell(parent, child, &node_indices, &nodes);
+                }
+            }
+            ReferenceCellType::Quadrilateral => {
+                let mut node_indices = vertices.clone();
+                for (i, e) in QUADRILATERAL_EDGES.iter().enumerate() {
+                    node_indices.push(refined.edge_midpoint(
+                        &mut edge_points,
+                        (vertices[e[0]], vertices[e[1]]),
+                        &mapped_nodes[3 * (4 + i)..3 * (5 + i)],
+                        projection,
+                    ));
+                }
+                node_indices.push(refined.add_point(&mapped_nodes[24..27], projection));
+                for child in &QUADRILATERAL_CHILDREN {
+                    refined.add_cell(parent, child, &node_indices, &nodes);
+                }
+            }
+            _ => {
+                return Err(BemppError::UnsupportedCellType(cell_type));
+            }
+        }
+    }
+
+    Ok(refined.create_grid(grid, cell_type, projection, comm))
+}
+
+/// Refine the marked cells of a triangle grid
+///
+/// Every marked cell is split into four triangles by adding lines connecting the midpoints of each edge (red
+/// refinement). To keep the refined grid conforming, every cell with two refined edges is also red refined, and
+/// each remaining cell with one refined edge is split into two triangles by adding a line from the midpoint of that
+/// edge to the opposite vertex (green refinement). The new points are computed using the geometry of the grid, and
+/// if `projection` is not `None`, each new point is then moved by the projection.
+///
+/// The input grid must be stored in serial, and the refined grid is created on the communicator `comm`, which must
+/// contain a single process. The refined grid has the same geometry degree as the input grid: the points that define
+/// the geometry of each new cell are placed on the geometry of its parent, then moved by the projection.
+pub fn refine_marked<
+    'a,
+    T: RealScalar + Equivalence,
+    C: Communicator,
+    G: ParallelGridTrait<C> + Grid<T = T, EntityDescriptor = ReferenceCellType>,
+>(
+    grid: &G,
+    marked_cells: &[usize],
+    projection: Option<&dyn Fn(&mut [T; 3])>,
+    comm: &'a C,
+) -> (RefinedGrid<'a, C, T>, RefinementMap<T>) {
+    try_refine_marked(grid, marked_cells, projection, comm).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Refine the marked cells of a triangle grid, returning an error if the grid cannot be refined
+///
+/// See [refine_marked].
+pub fn try_refine_marked<
+    'a,
+    T: RealScalar + Equivalence,
+    C: Communicator,
+    G: ParallelGridTrait<C> + Grid<T = T, EntityDescriptor = ReferenceCellType>,
+>(
+    grid: &G,
+    marked_cells: &[usize],
+    projection: Option<&dyn Fn(&mut [T; 3])>,
+    comm: &'a C,
+) -> Result<(RefinedGrid<'a, C, T>, RefinementMap<T>), BemppError> {
+    let cell_type = check_grid(grid, comm)?;
+    if cell_type != ReferenceCellType::Triangle {
+        return Err(BemppError::InvalidGrid(
+            "refinement of marked cells is only implemented for triangle grids".to_string(),
+        ));
+    }
+
+    let cells = grid
+        .entity_iter(2)
+        .map(|cell| {
+            (
+                cell.local_index(),
+                cell.topology().sub_entity_iter(0).collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut cell_vertices = vec![vec![]; cells.len()];
+    for (index, vertices) in cells {
+        cell_vertices[index] = vertices;
+    }
+
+    // Mark the edges of the marked cells, then mark additional edges until no cell has exactly two marked edges
+    let mut marked_edges = HashSet::new();
+    for cell in marked_cells {
+        for e in &TRIANGLE_EDGES {
+            marked_edges.insert(edge_key(
+                cell_vertices[*cell][e[0]],
+                cell_vertices[*cell][e[1]],
+            ));
+        }
+    }
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for vertices in &cell_vertices {
+            let keys = TRIANGLE_EDGES.map(|e| edge_key(vertices[e[0]], vertices[e[1]]));
+            if keys.iter().filter(|k| marked_edges.contains(*k)).count() == 2 {
+                for k in keys {
+                    marked_edges.insert(k);
+                }
+                changed = true;
+            }
+        }
+    }
+
+    let mut refined = RefinedCells::new(vertex_coordinates(grid));
+    let mut edge_points = HashMap::new();
+
+    let nodes = reference_nodes::<T>(cell_type);
+    let evaluator = grid.geometry_map(cell_type, &nodes);
+    let mut mapped_nodes = vec![T::zero(); 3 * nodes.len() / 2];
+
+    for (parent, vertices) in cell_vertices.iter().enumerate() {
+        let refined_edges = TRIANGLE_EDGES
+            .iter()
+            .map(|e| marked_edges.contains(&edge_key(vertices[e[0]], vertices[e[1]])))
+            .collect::<Vec<_>>();
+        let mut node_indices = vertices.clone();
+        if refined_edges.contains(&true) {
+            evaluator.points(parent, &mut mapped_nodes);
+            for (i, (e, r)) in TRIANGLE_EDGES.iter().zip(&refined_edges).enumerate() {
+                node_indices.push(if *r {
+                    refined.edge_midpoint(
+                        &mut edge_points,
+                        (vertices[e[0]], vertices[e[1]]),
+                        &mapped_nodes[3 * (3 + i)..3 * (4 + i)],
+                        projection,
+                    )
+                } else {
+                    usize::MAX
+                });
+            }
+        }
+        match refined_edges.iter().filter(|r| **r).count() {
+            0 => {
+                refined.add_cell(parent, &[0, 1, 2], &node_indices, &nodes);
+            }
+            1 => {
+                let edge = refined_edges.iter().position(|r| *r).unwrap();
+                for child in &TRIANGLE_GREEN_CHILDREN[edge] {
+                    refined.add_cell(parent, child, &node_indices, &nodes);
+                }
+            }
+            3 => {
+                for child in &TRIANGLE_RED_CHILDREN {
+                    refined.add_cell(parent, child, &node_indices, &nodes);
+                }
+            }
+            _ => {
+                panic!("Refinement closure failed.");
+            }
+        }
+    }
+
+    Ok(refined.create_grid(grid, cell_type, projection, comm))
+}
+
+/// Refine a triangle grid barycentrically
+///
+/// Each triangle is split into six triangles by adding lines from the midpoint of the triangle to each vertex and to
+/// the midpoint of each edge. The new points are computed using the geometry of the grid, and if `projection` is not
+/// `None`, each new point is then moved by the projection.
+///
+/// Barycentric refinements are used to define the dual spaces in [crate::function::barycentric].
+///
+/// The input grid must be stored in serial, and the refined grid is created on the communicator `comm`, which must
+/// contain a single process. The refined grid has the same geometry degree as the input grid: the points that define
+/// the geometry of each new cell are placed on the geometry of its parent, then moved by the projection.
+pub fn refine_barycentric<
+    'a,
+    T: RealScalar + Equivalence,
+    C: Communicator,
+    G: ParallelGridTrait<C> + Grid<T = T, EntityDescriptor = ReferenceCellType>,
+>(
+    grid: &G,
+    projection: Option<&dyn Fn(&mut [T; 3])>,
+    comm: &'a C,
+) -> (RefinedGrid<'a, C, T>, RefinementMap<T>) {
+    try_refine_barycentric(grid, projection, comm).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Refine a triangle grid barycentrically, returning an error if the grid cannot be refined
+///
+/// See [refine_barycentric].
+pub fn try_refine_barycentric<
+    'a,
+    T: RealScalar + Equivalence,
+    C: Communicator,
+    G: ParallelGridTrait<C> + Grid<T = T, EntityDescriptor = ReferenceCellType>,
+>(
+    grid: &G,
+    projection: Option<&dyn Fn(&mut [T; 3])>,
+    comm: &'a C,
+) -> Result<(RefinedGrid<'a, C, T>, RefinementMap<T>), BemppError> {
+    let cell_type = check_grid(grid, comm)?;
+    if cell_type != ReferenceCellType::Triangle {
+        return Err(BemppError::InvalidGrid(
+            "barycentric refinement is only implemented for triangle grids".to_string(),
+        ));
+    }
+    let mut refined = RefinedCells::new(vertex_coordinates(grid));
+    let mut edge_points = HashMap::new();
+
+    let mut nodes = reference_nodes::<T>(cell_type);
+    let third = T::from(1.0 / 3.0).unwrap();
+    nodes.extend_from_slice(&[third, third]);
+    let evaluator = grid.geometry_map(cell_type, &nodes);
+    let mut mapped_nodes = vec![T::zero(); 3 * nodes.len() / 2];
+
+    for cell in grid.entity_iter(2) {
+        let parent = cell.local_index();
+        evaluator.points(parent, &mut mapped_nodes);
+        let vertices = cell.topology().sub_entity_iter(0).collect::<Vec<_>>();
+
+        let mut node_indices = vertices.clone();
+        for (i, e) in TRIANGLE_EDGES.iter().enumerate() {
+            node_indices.push(refined.edge_midpoint(
+                &mut edge_points,
+                (vertices[e[0]], vertices[e[1]]),
+                &mapped_nodes[3 * (3 + i)..3 * (4 + i)],
+                projection,
+            ));
+        }
+        node_indices.push(refined.add_point(&mapped_nodes[18..21], projection));
+        for child in &TRIANGLE_BARYCENTRIC_CHILDREN {
+            refined.add_cell(parent, child, &node_indices, &nodes);
+        }
+    }
+
+    Ok(refined.create_grid(grid, cell_type, projection, comm))
+}
+
+/// Check that a grid can be refined onto a communicator, and return its cell type
+fn check_grid<
+    T: RealScalar,
+    C: Communicator,
+    G: ParallelGridTrait<C> + Grid<T = T, EntityDescriptor = ReferenceCellType>,
+>(
+    grid: &G,
+    comm: &C,
+) -> Result<ReferenceCellType, BemppError> {
+    if grid.comm().size() != 1 {
+        return Err(BemppError::InvalidGrid(
+            "refinement can only be used for grids stored in serial".to_string(),
+        ));
+    }
+    if comm.size() != 1 {
+        return Err(BemppError::InvalidGrid(
+            "refined grids can only be created on a communicator with a single process".to_string(),
+        ));
+    }
+    if grid.entity_types(2).len() != 1 {
+        return Err(BemppError::InvalidGrid(
+            "refinement is only implemented for grids with a single cell type".to_string(),
+        ));
+    }
+    let cell_type = grid.entity_types(2)[0];
+    match cell_type {
+        ReferenceCellType::Triangle | ReferenceCellType::Quadrilateral => Ok(cell_type),
+        _ => Err(BemppError::UnsupportedCellType(cell_type)),
+    }
+}
+
+/// The reference nodes used when refining a cell, stored as [x0, y0, x1, y1, ...]
+fn reference_nodes<T: RealScalar>(cell_type: ReferenceCellType) -> Vec<T> {
+    match cell_type {
+        ReferenceCellType::Triangle => TRIANGLE_NODES.iter(),
+        ReferenceCellType::Quadrilateral => QUADRILATERAL_NODES.iter(),
+        _ => {
+            panic!("Unsupported cell type: {cell_type:?}");
+        }
+    }
+    .flat_map(|p| p.map(|x| T::from(x).unwrap()))
+    .collect()
+}
+
+/// Key used to identify an edge by its vertices
+fn edge_key(v0: usize, v1: usize) -> (usize, usize) {
+    if v0 < v1 {
+        (v0, v1)
+    } else {
+        (v1, v0)
+    }
+}
+
+/// The points and cells of a refined grid
+struct RefinedCells<T: RealScalar> {
+    points: Vec<[T; 3]>,
+    cells: Vec<Vec<usize>>,
+    parents: Vec<usize>,
+    reference_vertices: Vec<Vec<T>>,
+    /// The reference nodes of its parent that are the vertices of each cell
+    child_nodes: Vec<Vec<usize>>,
+}
+
+impl<T: RealScalar + Equivalence> RefinedCells<T> {
+    /// Create new
+    fn new(points: Vec<[T; 3]>) -> Self {
+        Self {
+            points,
+            cells: vec![],
+            parents: vec![],
+            reference_vertices: vec![],
+            child_nodes: vec![],
+        }
+    }
+
+    /// Add a point, and return its index
+    fn add_point(&mut self, point: &[T], projection: Option<&dyn Fn(&mut [T; 3])>) -> usize {
+        let mut pt = [point[0], point[1], point[2]];
+        if let Some(p) = projection {
+            p(&mut pt);
+        }
+        self.points.push(pt);
+        self.points.len() - 1
+    }
+
+    /// Get the index of the point at the midpoint of an edge, adding it if it has not already been added
+    fn edge_midpoint(
+        &mut self,
+        edge_points: &mut HashMap<(usize, usize), usize>,
+        edge: (usize, usize),
+        point: &[T],
+        projection: Option<&dyn Fn(&mut [T; 3])>,
+    ) -> usize {
+        let key = edge_key(edge.0, edge.1);
+        if let Some(index) = edge_points.get(&key) {
+            *index
+        } else {
+            let index = self.add_point(point, projection);
+            edge_points.insert(key, index);
+            index
+        }
+    }
+
+    /// Add a cell whose vertices are the given reference nodes of its parent
+    fn add_cell(&mut self, parent: usize, child: &[usize], node_indices: &[usize], nodes: &[T]) {
+        self.cells
+            .push(child.iter().map(|n| node_indices[*n]).collect());
+        self.parents.push(parent);
+        self.reference_vertices.push(
+            child
+                .iter()
+                .flat_map(|n| [nodes[2 * n], nodes[2 * n + 1]])
+                .collect(),
+        );
+        self.child_nodes.push(child.to_vec());
+    }
+
+    /// Create the refined grid and the map between its cells and the cells of the coarse grid
+    ///
+    /// The refined grid has the same geometry degree as the coarse grid `grid`. The points that define the geometry of
+    /// each refined cell (other than its vertices) are placed on the geometry of its parent, then moved by the
+    /// projection.
+    fn create_grid<'a, C: Communicator, G: Grid<T = T, EntityDescriptor = ReferenceCellType>>(
+        self,
+        grid: &G,
+        cell_type: ReferenceCellType,
+        projection: Option<&dyn Fn(&mut [T; 3])>,
+        comm: &'a C,
+    ) -> (RefinedGrid<'a, C, T>, RefinementMap<T>) {
+        let degree = grid
+            .entity_iter(2)
+            .map(|cell| cell.geometry().degree())
+            .max()
+            .unwrap_or(1);
+        let cell_points = if degree > 1 {
+            self.higher_order_cell_points(grid, cell_type, degree)
+        } else {
+            vec![]
+        };
+        let Self {
+            points,
+            cells,
+            parents,
+            reference_vertices,
+            ..
+        } = self;
+        let ncells = cells.len();
+        let refined_grid = create_higher_order_grid_on_root(cell_type, degree, comm, move || {
+            if degree > 1 {
+                higher_order_points_and_cells(cell_type, degree, points, &cells, |cell, _, i| {
+                    let mut point = cell_points[cell][i];
+                    if let Some(p) = projection {
+                        p(&mut point);
+                    }
+                    point
+                })
+            } else {
+                (points, cells)
+            }
+        });
+
+        // Cells are added to the grid with ids equal to their position in the list of cells
+        let mut local_indices = vec![0; ncells];
+        for cell in refined_grid.entity_iter(2) {
+            local_indices[cell.id().unwrap()] = cell.local_index();
+        }
+
+        let mut map = RefinementMap {
+            parents: vec![0; ncells],
+            children: vec![vec![]; grid.entity_count(cell_type)],
+            reference_vertices: vec![vec![]; ncells],
+        };
+        for (id, (parent, vertices)) in parents.into_iter().zip(reference_vertices).enumerate() {
+            let cell = local_indices[id];
+            map.parents[cell] = parent;
+            map.children[parent].push(cell);
+            map.reference_vertices[cell] = vertices;
+        }
+        (refined_grid, map)
+    }
+
+    /// Place the points that define the geometry of each refined cell on the geometry of its parent
+    ///
+    /// The points of each cell are ordered in the same way as the DOFs of a Lagrange element of degree `degree`.
+    fn higher_order_cell_points<G: Grid<T = T, EntityDescriptor = ReferenceCellType>>(
+        &self,
+        grid: &G,
+        cell_type: ReferenceCellType,
+        degree: usize,
+    ) -> Vec<Vec<[T; 3]>> {
+        let reference_points = lagrange_points::<T>(cell_type, degree);
+        let mut mapped_points = vec![T::zero(); 3 * reference_points.len() / 2];
+
+        // Cells that are in the same position in their parents use the same points on the reference cell of the parent
+        let mut positions = HashMap::new();
+        for (cell, nodes) in self.child_nodes.iter().enumerate() {
+            positions.entry(nodes).or_insert_with(Vec::new).push(cell);
+        }
+
+        let mut cell_points = vec![vec![]; self.cells.len()];
+        for cells in positions.values() {
+            let parent_points = reference_points
+                .chunks(2)
+                .flat_map(|p| map_to_parent(&self.reference_vertices[cells[0]], p))
+                .collect::<Vec<_>>();
+            let evaluator = grid.geometry_map(cell_type, &parent_points);
+            for cell in cells {
+                evaluator.points(self.parents[*cell], &mut mapped_points);
+                cell_points[*cell] = mapped_points
+                    .chunks(3)
+                    .map(|p| [p[0], p[1], p[2]])
+                    .collect();
+            }
+        }
+        cell_points
+    }
+}
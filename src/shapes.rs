@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+use crate::helpers::lagrange_points;
 use mpi::traits::{Communicator, Equivalence};
 use ndelement::{ciarlet::CiarletElement, types::ReferenceCellType};
 use ndgrid::{
@@ -16,101 +17,95 @@ use num::Float;
 /// A regular sphere is created by starting with a regular octahedron. The shape is then refined `refinement_level` times.
 /// Each time the grid is refined, each triangle is split into four triangles (by adding lines connecting the midpoints of
 /// each edge). The new points are then scaled so that they are a distance of 1 from the origin.
+///
+/// If `degree` is greater than 1, the cells are curved and all the points that define each cell are on the sphere.
 pub fn regular_sphere<T: RealScalar + Equivalence, C: Communicator>(
     refinement_level: u32,
     degree: usize,
     comm: &C,
 ) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
-    if comm.rank() == 0 {
-        let mut b = SingleElementGridBuilder::new_with_capacity(
-            3,
-            2 + usize::pow(4, refinement_level + 1),
-            8 * usize::pow(4, refinement_level),
-            (ReferenceCellType::Triangle, degree),
-        );
+    create_projected_grid_on_root(
+        ReferenceCellType::Triangle,
+        degree,
+        comm,
+        || sphere_points_and_cells(refinement_level),
+        project_to_unit_sphere,
+    )
+}
 
-        let mut points = Vec::<[T; 3]>::with_capacity(2 + usize::pow(4, refinement_level + 1));
+/// Create the points and cells of a regular sphere.
+fn sphere_points_and_cells<T: RealScalar>(refinement_level: u32) -> (Vec<[T; 3]>, Vec<[usize; 3]>) {
+    let mut points = Vec::<[T; 3]>::with_capacity(2 + usize::pow(4, refinement_level + 1));
 
-        let zero = T::from(0.0).unwrap();
-        let one = T::from(1.0).unwrap();
-        let half = T::from(0.5).unwrap();
-
-        points.push([zero, zero, one]);
-        points.push([one, zero, zero]);
-        points.push([zero, one, zero]);
-        points.push([-one, zero, zero]);
-        points.push([zero, -one, zero]);
-        points.push([zero, zero, -one]);
-
-        let mut point_n = 6;
-
-        let mut cells = vec![
-            [0, 1, 2],
-            [0, 2, 3],
-            [0, 3, 4],
-            [0, 4, 1],
-            [5, 2, 1],
-            [5, 3, 2],
-            [5, 4, 3],
-            [5, 1, 4],
-        ];
-        let mut v = [[zero, zero, zero], [zero, zero, zero], [zero, zero, zero]];
-
-        for level in 0..refinement_level {
-            let mut edge_points = HashMap::new();
-            let mut new_cells = Vec::with_capacity(8 * usize::pow(6, level));
-            for c in &cells {
-                for i in 0..3 {
-                    for j in 0..3 {
-                        v[i][j] = points[c[i]][j];
-                    }
+    let zero = T::from(0.0).unwrap();
+    let one = T::from(1.0).unwrap();
+    let half = T::from(0.5).unwrap();
+
+    points.push([zero, zero, one]);
+    points.push([one, zero, zero]);
+    points.push([zero, one, zero]);
+    points.push([-one, zero, zero]);
+    points.push([zero, -one, zero]);
+    points.push([zero, zero, -one]);
+
+    let mut point_n = 6;
+
+    let mut cells = vec![
+        [0, 1, 2],
+        [0, 2, 3],
+        [0, 3, 4],
+        [0, 4, 1],
+        [5, 2, 1],
+        [5, 3, 2],
+        [5, 4, 3],
+        [5, 1, 4],
+    ];
+    let mut v = [[zero, zero, zero], [zero, zero, zero], [zero, zero, zero]];
+
+    for level in 0..refinement_level {
+        let mut edge_points = HashMap::new();
+        let mut new_cells = Vec::with_capacity(8 * usize::pow(6, level));
+        for c in &cells {
+            for i in 0..3 {
+                for j in 0..3 {
+                    v[i][j] = points[c[i]][j];
                 }
-                let edges = [[1, 2], [0, 2], [0, 1]]
-                    .iter()
-                    .map(|[i, j]| {
-                        let mut pt_i = c[*i];
-                        let mut pt_j = c[*j];
-                        if pt_i > pt_j {
-                            std::mem::swap(&mut pt_i, &mut pt_j);
+            }
+            let edges = [[1, 2], [0, 2], [0, 1]]
+                .iter()
+                .map(|[i, j]| {
+                    let mut pt_i = c[*i];
+                    let mut pt_j = c[*j];
+                    if pt_i > pt_j {
+                        std::mem::swap(&mut pt_i, &mut pt_j);
+                    }
+                    *edge_points.entry((pt_i, pt_j)).or_insert_with(|| {
+                        let v_i = v[*i];
+                        let v_j = v[*j];
+                        let mut new_pt = [
+                            half * (v_i[0] + v_j[0]),
+                            half * (v_i[1] + v_j[1]),
+                            half * (v_i[2] + v_j[2]),
+                        ];
+                        let size = Float::sqrt(new_pt.iter().map(|&x| x * x).sum::<T>());
+                        for i in new_pt.iter_mut() {
+                            *i /= size;
                         }
-                        *edge_points.entry((pt_i, pt_j)).or_insert_with(|| {
-                            let v_i = v[*i];
-                            let v_j = v[*j];
-                            let mut new_pt = [
-                                half * (v_i[0] + v_j[0]),
-                                half * (v_i[1] + v_j[1]),
-                                half * (v_i[2] + v_j[2]),
-                            ];
-                            let size = Float::sqrt(new_pt.iter().map(|&x| x * x).sum::<T>());
-                            for i in new_pt.iter_mut() {
-                                *i /= size;
-                            }
-                            points.push(new_pt);
-                            let out = point_n;
-                            point_n += 1;
-                            out
-                        })
+                        points.push(new_pt);
+                        let out = point_n;
+                        point_n += 1;
+                        out
                     })
-                    .collect::<Vec<_>>();
-                new_cells.push([c[0], edges[2], edges[1]]);
-                new_cells.push([c[1], edges[0], edges[2]]);
-                new_cells.push([c[2], edges[1], edges[0]]);
-                new_cells.push([edges[0], edges[1], edges[2]]);
-            }
-            cells = new_cells;
-        }
-        for (i, v) in points.iter().enumerate() {
-            b.add_point(i, v);
-        }
-        for (i, v) in cells.iter().enumerate() {
-            b.add_cell(i, v);
+                })
+                .collect::<Vec<_>>();
+            new_cells.push([c[0], edges[2], edges[1]]);
+            new_cells.push([c[1], edges[0], edges[2]]);
+            new_cells.push([c[2], edges[1], edges[0]]);
+            new_cells.push([edges[0], edges[1], edges[2]]);
         }
-
-        b.create_parallel_grid_root(comm)
-    } else {
-        SingleElementGridBuilder::new(3, (ReferenceCellType::Triangle, degree))
-            .create_parallel_grid(comm, 0)
+        cells = new_cells;
     }
+    (points, cells)
 }
 
 /// Create a square grid with triangle cells
@@ -221,3 +216,606 @@ pub fn screen_quadrilaterals<T: RealScalar + Equivalence, C: Communicator>(
             .create_parallel_grid(comm, 0)
     }
 }
+
+/// Create a grid of the surface of the unit cube with triangle cells
+///
+/// Each face of the cube \[0,1\]^3 is split into 2^`refinement_level` by 2^`refinement_level` squares,
+/// and each square is split into two triangles.
+pub fn cube<T: RealScalar + Equivalence, C: Communicator>(
+    refinement_level: u32,
+    degree: usize,
+    comm: &C,
+) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
+    create_grid_on_root(ReferenceCellType::Triangle, degree, comm, || {
+        cube_points_and_cells(refinement_level, ReferenceCellType::Triangle)
+    })
+}
+
+/// Create a grid of the surface of the unit cube with quadrilateral cells
+///
+/// Each face of the cube \[0,1\]^3 is split into 2^`refinement_level` by 2^`refinement_level` squares.
+pub fn cube_quadrilaterals<T: RealScalar + Equivalence, C: Communicator>(
+    refinement_level: u32,
+    degree: usize,
+    comm: &C,
+) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
+    create_grid_on_root(ReferenceCellType::Quadrilateral, degree, comm, || {
+        cube_points_and_cells(refinement_level, ReferenceCellType::Quadrilateral)
+    })
+}
+
+/// Create a cubed sphere
+///
+/// A cubed sphere is created by splitting each face of a cube into 2^`refinement_level` by
+/// 2^`refinement_level` quadrilaterals using an equiangular spacing. The points are then scaled
+/// so that they are a distance of 1 from the origin.
+///
+/// If `degree` is greater than 1, the cells are curved and all the points that define each cell are on the sphere.
+pub fn cubed_sphere<T: RealScalar + Equivalence, C: Communicator>(
+    refinement_level: u32,
+    degree: usize,
+    comm: &C,
+) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
+    create_projected_grid_on_root(
+        ReferenceCellType::Quadrilateral,
+        degree,
+        comm,
+        || cubed_sphere_points_and_cells(refinement_level),
+        project_to_unit_sphere,
+    )
+}
+
+/// Create an ellipsoid
+///
+/// The ellipsoid is centred at the origin and has the semi-axes `semi_axes` along the x-, y- and
+/// z-axes. It is created by scaling the points of a regular sphere (see [regular_sphere]) with
+/// the same refinement level.
+///
+/// If `degree` is greater than 1, the cells are curved and all the points that define each cell are on the ellipsoid.
+pub fn ellipsoid<T: RealScalar + Equivalence, C: Communicator>(
+    refinement_level: u32,
+    degree: usize,
+    semi_axes: [T; 3],
+    comm: &C,
+) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
+    if semi_axes.iter().any(|a| *a <= T::from(0.0).unwrap()) {
+        panic!("Semi-axes of an ellipsoid must be positive");
+    }
+    create_projected_grid_on_root(
+        ReferenceCellType::Triangle,
+        degree,
+        comm,
+        || {
+            let (mut points, cells) = sphere_points_and_cells::<T>(refinement_level);
+            for p in points.iter_mut() {
+                for (x, a) in p.iter_mut().zip(&semi_axes) {
+                    *x *= *a;
+                }
+            }
+            (points, cells)
+        },
+        |p| {
+            // Scale the point radially so that it is on the ellipsoid
+            let size = Float::sqrt(
+                p.iter()
+                    .zip(&semi_axes)
+                    .map(|(x, a)| (*x / *a) * (*x / *a))
+                    .sum::<T>(),
+            );
+            p.map(|x| x / size)
+        },
+    )
+}
+
+/// Create a capped cylinder
+///
+/// The cylinder has radius `radius` and its axis is the segment from (0, 0, 0) to (0, 0, `height`).
+/// The circles at each end of the cylinder are split into 8 * 2^`refinement_level` segments. The
+/// number of layers along the side and the number of rings on each cap are chosen so that the
+/// cells are close to the same size as the segments.
+///
+/// The cells of the cylinder are flat, even if `degree` is greater than 1.
+pub fn cylinder<T: RealScalar + Equivalence, C: Communicator>(
+    refinement_level: u32,
+    degree: usize,
+    radius: T,
+    height: T,
+    comm: &C,
+) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
+    let zero = T::from(0.0).unwrap();
+    if radius <= zero || height <= zero {
+        panic!("Radius and height of a cylinder must be positive");
+    }
+    create_grid_on_root(ReferenceCellType::Triangle, degree, comm, || {
+        cylinder_points_and_cells(refinement_level, radius, height)
+    })
+}
+
+/// Create a torus
+///
+/// The torus is centred at the origin and is rotationally symmetric about the z-axis. The distance from
+/// the origin to the centre of the tube is `major_radius` and the radius of the tube is `minor_radius`.
+/// The tube is split into 8 * 2^`refinement_level` segments around the z-axis, and the number of segments
+/// around the tube is chosen so that the cells are close to square.
+///
+/// If `degree` is greater than 1, the cells are curved and all the points that define each cell are on the torus.
+pub fn torus<T: RealScalar + Equivalence, C: Communicator>(
+    refinement_level: u32,
+    degree: usize,
+    major_radius: T,
+    minor_radius: T,
+    comm: &C,
+) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
+    let zero = T::from(0.0).unwrap();
+    if minor_radius <= zero || minor_radius >= major_radius {
+        panic!("The radii of a torus must satisfy 0 < minor_radius < major_radius");
+    }
+    create_projected_grid_on_root(
+        ReferenceCellType::Triangle,
+        degree,
+        comm,
+        || torus_points_and_cells(refinement_level, major_radius, minor_radius),
+        |p| {
+            // Move the point radially away from the nearest point on the centre line of the tube
+            let r = Float::sqrt(p[0] * p[0] + p[1] * p[1]);
+            let centre = [p[0] * major_radius / r, p[1] * major_radius / r, zero];
+            let size = Float::sqrt(
+                (0..3)
+                    .map(|i| (p[i] - centre[i]) * (p[i] - centre[i]))
+                    .sum::<T>(),
+            );
+            [0, 1, 2].map(|i| centre[i] + minor_radius * (p[i] - centre[i]) / size)
+        },
+    )
+}
+
+/// Create a grid containing multiple disjoint spheres
+///
+/// Each sphere is a regular sphere (see [regular_sphere]) with the given refinement level that is scaled
+/// to have radius `radii[i]` and translated to be centred at `centres[i]`.
+///
+/// If `degree` is greater than 1, the cells are curved and all the points that define each cell are on the spheres.
+pub fn multiple_spheres<T: RealScalar + Equivalence, C: Communicator>(
+    refinement_level: u32,
+    degree: usize,
+    centres: &[[T; 3]],
+    radii: &[T],
+    comm: &C,
+) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
+    if centres.is_empty() {
+        panic!("Cannot create a grid with 0 spheres");
+    }
+    if centres.len() != radii.len() {
+        panic!("The number of centres and radii must be equal");
+    }
+    let zero = T::from(0.0).unwrap();
+    for (i, (c_i, r_i)) in centres.iter().zip(radii).enumerate() {
+        if *r_i <= zero {
+            panic!("Radii of spheres must be positive");
+        }
+        for (c_j, r_j) in centres.iter().zip(radii).skip(i + 1) {
+            let distance = Float::sqrt(
+                c_i.iter()
+                    .zip(c_j)
+                    .map(|(a, b)| (*a - *b) * (*a - *b))
+                    .sum::<T>(),
+            );
+            if distance <= *r_i + *r_j {
+                panic!("Spheres must be disjoint");
+            }
+        }
+    }
+    create_projected_grid_on_root(
+        ReferenceCellType::Triangle,
+        degree,
+        comm,
+        || {
+            let (sphere_points, sphere_cells) = sphere_points_and_cells::<T>(refinement_level);
+            let mut points = Vec::with_capacity(centres.len() * sphere_points.len());
+            let mut cells = Vec::with_capacity(centres.len() * sphere_cells.len());
+            for (centre, radius) in centres.iter().zip(radii) {
+                let offset = points.len();
+                for p in &sphere_points {
+                    points.push([0, 1, 2].map(|i| centre[i] + *radius * p[i]));
+                }
+                for c in &sphere_cells {
+                    cells.push(c.map(|v| v + offset));
+                }
+            }
+            (points, cells)
+        },
+        |p| {
+            // The points of each flat cell are inside the sphere that the cell approximates, and outside the others
+            let distance =
+                |c: &[T; 3]| Float::sqrt((0..3).map(|i| (p[i] - c[i]) * (p[i] - c[i])).sum::<T>());
+            let (centre, radius) = centres
+                .iter()
+                .zip(radii)
+                .min_by(|(c0, r0), (c1, r1)| {
+                    (distance(c0) / **r0)
+                        .partial_cmp(&(distance(c1) / **r1))
+                        .unwrap()
+                })
+                .unwrap();
+            let direction = project_to_unit_sphere([0, 1, 2].map(|i| p[i] - centre[i]));
+            [0, 1, 2].map(|i| centre[i] + *radius * direction[i])
+        },
+    )
+}
+
+/// Create a grid on process 0 and distribute it to all processes
+///
+/// The function `points_and_cells` is only called on process 0. It returns the vertices of the grid and the vertices
+/// of each cell. If `degree` is greater than 1, the other points of each cell are placed on the flat cell with these
+/// vertices.
+pub(crate) fn create_grid_on_root<
+    T: RealScalar + Equivalence,
+    C: Communicator,
+    Cell: AsRef<[usize]>,
+>(
+    cell_type: ReferenceCellType,
+    degree: usize,
+    comm: &C,
+    points_and_cells: impl FnOnce() -> (Vec<[T; 3]>, Vec<Cell>),
+) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
+    create_projected_grid_on_root(cell_type, degree, comm, points_and_cells, |p: [T; 3]| p)
+}
+
+/// Create a grid on process 0 and distribute it to all processes, moving the points of each cell onto a surface
+///
+/// This is the same as [create_grid_on_root], except that if `degree` is greater than 1 the points that are added to
+/// each cell are moved onto the exact surface using `projection`.
+pub(crate) fn create_projected_grid_on_root<
+    T: RealScalar + Equivalence,
+    C: Communicator,
+    Cell: AsRef<[usize]>,
+>(
+    cell_type: ReferenceCellType,
+    degree: usize,
+    comm: &C,
+    points_and_cells: impl FnOnce() -> (Vec<[T; 3]>, Vec<Cell>),
+    projection: impl Fn([T; 3]) -> [T; 3],
+) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
+    create_higher_order_grid_on_root(cell_type, degree, comm, || {
+        let (points, cells) = points_and_cells();
+        if degree > 1 {
+            let reference_points = lagrange_points::<T>(cell_type, degree);
+            higher_order_points_and_cells(cell_type, degree, points, &cells, |_, vertices, i| {
+                projection(flat_cell_point(
+                    cell_type,
+                    vertices,
+                    &reference_points[2 * i..2 * i + 2],
+                ))
+            })
+        } else {
+            (points, cells.iter().map(|c| c.as_ref().to_vec()).collect())
+        }
+    })
+}
+
+/// Create a grid on process 0 and distribute it to all processes, using the given points for every cell
+///
+/// The function `points_and_cells` is only called on process 0. It returns the points of the grid and the points of each
+/// cell, which are ordered in the same way as the DOFs of a Lagrange element of degree `degree`.
+pub(crate) fn create_higher_order_grid_on_root<T: RealScalar + Equivalence, C: Communicator>(
+    cell_type: ReferenceCellType,
+    degree: usize,
+    comm: &C,
+    points_and_cells: impl FnOnce() -> (Vec<[T; 3]>, Vec<Vec<usize>>),
+) -> ParallelGrid<C, SingleElementGrid<T, CiarletElement<T>>> {
+    if comm.rank() == 0 {
+        let (points, cells) = points_and_cells();
+        let mut b = SingleElementGridBuilder::new_with_capacity(
+            3,
+            points.len(),
+            cells.len(),
+            (cell_type, degree),
+        );
+        for (i, v) in points.iter().enumerate() {
+            b.add_point(i, v);
+        }
+        for (i, v) in cells.iter().enumerate() {
+            b.add_cell(i, v);
+        }
+
+        b.create_parallel_grid_root(comm)
+    } else {
+        SingleElementGridBuilder::new(3, (cell_type, degree)).create_parallel_grid(comm, 0)
+    }
+}
+
+/// Add the points on the edges and interiors of cells that are needed to define a grid with a higher geometry degree
+///
+/// `cell_point(cell, vertices, i)` gives the position of point `i` of a cell whose vertices are at `vertices`, where the
+/// points of each cell are ordered in the same way as the DOFs of a Lagrange element. Points on an edge are only
+/// computed once and are shared by all the cells that contain the edge.
+pub(crate) fn higher_order_points_and_cells<T: RealScalar, Cell: AsRef<[usize]>>(
+    cell_type: ReferenceCellType,
+    degree: usize,
+    mut points: Vec<[T; 3]>,
+    cells: &[Cell],
+    cell_point: impl Fn(usize, &[[T; 3]], usize) -> [T; 3],
+) -> (Vec<[T; 3]>, Vec<Vec<usize>>) {
+    let npts = lagrange_points::<T>(cell_type, degree).len() / 2;
+    let (nvertices, edges): (usize, &[[usize; 2]]) = match cell_type {
+        ReferenceCellType::Triangle => (3, &[[1, 2], [0, 2], [0, 1]]),
+        ReferenceCellType::Quadrilateral => (4, &[[0, 1], [0, 2], [1, 3], [2, 3]]),
+        _ => {
+            unimplemented!("Only triangles and quadrilaterals are currently supported");
+        }
+    };
+    let npts_per_edge = degree - 1;
+
+    let mut edge_points = HashMap::new();
+    let mut new_cells = Vec::with_capacity(cells.len());
+    for (cell_index, cell) in cells.iter().enumerate() {
+        let vertices = cell.as_ref();
+        let v = vertices.iter().map(|i| points[*i]).collect::<Vec<_>>();
+
+        let mut new_cell = vertices.to_vec();
+        for (e_i, e) in edges.iter().enumerate() {
+            // The points on each edge are stored in order from the lower numbered vertex to the higher numbered vertex
+            let forward = vertices[e[0]] < vertices[e[1]];
+            let key = if forward {
+                (vertices[e[0]], vertices[e[1]])
+            } else {
+                (vertices[e[1]], vertices[e[0]])
+            };
+            let start = *edge_points.entry(key).or_insert_with(|| {
+                let start = points.len();
+                let mut new_points = (0..npts_per_edge)
+                    .map(|k| cell_point(cell_index, &v, nvertices + e_i * npts_per_edge + k))
+                    .collect::<Vec<_>>();
+                if !forward {
+                    new_points.reverse();
+                }
+                points.extend(new_points);
+                start
+            });
+            if forward {
+                new_cell.extend(start..start + npts_per_edge);
+            } else {
+                new_cell.extend((start..start + npts_per_edge).rev());
+            }
+        }
+        for i in nvertices + edges.len() * npts_per_edge..npts {
+            new_cell.push(points.len());
+            points.push(cell_point(cell_index, &v, i));
+        }
+        new_cells.push(new_cell);
+    }
+    (points, new_cells)
+}
+
+/// Map a point on the reference cell to the flat cell with the given vertices
+fn flat_cell_point<T: RealScalar>(cell_type: ReferenceCellType, v: &[[T; 3]], p: &[T]) -> [T; 3] {
+    let one = T::from(1.0).unwrap();
+    match cell_type {
+        ReferenceCellType::Triangle => {
+            [0, 1, 2].map(|j| v[0][j] + p[0] * (v[1][j] - v[0][j]) + p[1] * (v[2][j] - v[0][j]))
+        }
+        _ => [0, 1, 2].map(|j| {
+            (one - p[0]) * (one - p[1]) * v[0][j]
+                + p[0] * (one - p[1]) * v[1][j]
+                + (one - p[0]) * p[1] * v[2][j]
+                + p[0] * p[1] * v[3][j]
+        }),
+    }
+}
+
+/// Move a point radially onto the unit sphere
+fn project_to_unit_sphere<T: RealScalar>(p: [T; 3]) -> [T; 3] {
+    let size = Float::sqrt(p.iter().map(|&x| x * x).sum::<T>());
+    p.map(|x| x / size)
+}
+
+/// Create the points and cells of the surface of the cube \[0,n\]^3, where n is 2^`refinement_level`
+///
+/// The points are given as integer coordinates. The cells are oriented so that their normals point outwards.
+fn cube_lattice_points_and_cells(
+    refinement_level: u32,
+    cell_type: ReferenceCellType,
+) -> (Vec<[usize; 3]>, Vec<Vec<usize>>) {
+    let n = usize::pow(2, refinement_level);
+    // Each face is given by an origin and two axes whose cross product is the outward normal
+    let faces = [
+        ([0, 0, 0], [0, 1, 0], [1, 0, 0]),
+        ([0, 0, 1], [1, 0, 0], [0, 1, 0]),
+        ([0, 0, 0], [1, 0, 0], [0, 0, 1]),
+        ([0, 1, 0], [0, 0, 1], [1, 0, 0]),
+        ([0, 0, 0], [0, 0, 1], [0, 1, 0]),
+        ([1, 0, 0], [0, 1, 0], [0, 0, 1]),
+    ];
+    let mut points = Vec::with_capacity(6 * n * n + 2);
+    let mut point_indices = HashMap::new();
+    let mut cells = vec![];
+    for (origin, u, v) in faces {
+        let mut index = |a: usize, b: usize| {
+            let pt = [0, 1, 2].map(|i| origin[i] * n + a * u[i] + b * v[i]);
+            *point_indices.entry(pt).or_insert_with(|| {
+                points.push(pt);
+                points.len() - 1
+            })
+        };
+        for a in 0..n {
+            for b in 0..n {
+                match cell_type {
+                    ReferenceCellType::Triangle => {
+                        cells.push(vec![index(a, b), index(a + 1, b), index(a + 1, b + 1)]);
+                        cells.push(vec![index(a, b), index(a + 1, b + 1), index(a, b + 1)]);
+                    }
+                    ReferenceCellType::Quadrilateral => {
+                        cells.push(vec![
+                            index(a, b),
+                            index(a + 1, b),
+                            index(a, b + 1),
+                            index(a + 1, b + 1),
+                        ]);
+                    }
+                    _ => {
+                        panic!("Unsupported cell type: {cell_type:?}");
+                    }
+                }
+            }
+        }
+    }
+    (points, cells)
+}
+
+/// Create the points and cells of the surface of the unit cube
+fn cube_points_and_cells<T: RealScalar>(
+    refinement_level: u32,
+    cell_type: ReferenceCellType,
+) -> (Vec<[T; 3]>, Vec<Vec<usize>>) {
+    let (lattice_points, cells) = cube_lattice_points_and_cells(refinement_level, cell_type);
+    let n = T::from(usize::pow(2, refinement_level)).unwrap();
+    let points = lattice_points
+        .iter()
+        .map(|p| p.map(|x| T::from(x).unwrap() / n))
+        .collect::<Vec<_>>();
+    (points, cells)
+}
+
+/// Create the points and cells of a cubed sphere
+fn cubed_sphere_points_and_cells<T: RealScalar>(
+    refinement_level: u32,
+) -> (Vec<[T; 3]>, Vec<Vec<usize>>) {
+    let (lattice_points, cells) =
+        cube_lattice_points_and_cells(refinement_level, ReferenceCellType::Quadrilateral);
+    let n = T::from(usize::pow(2, refinement_level)).unwrap();
+    let one = T::from(1.0).unwrap();
+    let two = T::from(2.0).unwrap();
+    let quarter_pi = T::from(std::f64::consts::FRAC_PI_4).unwrap();
+    let points = lattice_points
+        .iter()
+        .map(|p| {
+            // Equiangular map from [0, n] to [-1, 1]
+            let mut pt = p.map(|x| Float::tan(quarter_pi * (two * T::from(x).unwrap() / n - one)));
+            let size = Float::sqrt(pt.iter().map(|&x| x * x).sum::<T>());
+            for x in pt.iter_mut() {
+                *x /= size;
+            }
+            pt
+        })
+        .collect::<Vec<_>>();
+    (points, cells)
+}
+
+/// Create the points and cells of a capped cylinder
+fn cylinder_points_and_cells<T: RealScalar>(
+    refinement_level: u32,
+    radius: T,
+    height: T,
+) -> (Vec<[T; 3]>, Vec<[usize; 3]>) {
+    let ntheta = 8 * usize::pow(2, refinement_level);
+    let two_pi = T::from(2.0 * std::f64::consts::PI).unwrap();
+    let segment_length = two_pi * radius / T::from(ntheta).unwrap();
+    let nlayers = std::cmp::max(
+        1,
+        num::cast::<T, usize>(Float::ceil(height / segment_length)).unwrap(),
+    );
+    let nrings = std::cmp::max(
+        1,
+        (ntheta as f64 / (2.0 * std::f64::consts::PI)).round() as usize,
+    );
+
+    let zero = T::from(0.0).unwrap();
+    let angles = (0..ntheta)
+        .map(|i| two_pi * T::from(i).unwrap() / T::from(ntheta).unwrap())
+        .collect::<Vec<_>>();
+
+    let mut points = vec![];
+    let mut cells = vec![];
+
+    // Side
+    for j in 0..nlayers + 1 {
+        let z = height * T::from(j).unwrap() / T::from(nlayers).unwrap();
+        for a in &angles {
+            points.push([radius * Float::cos(*a), radius * Float::sin(*a), z]);
+        }
+    }
+    for j in 0..nlayers {
+        for i in 0..ntheta {
+            let i1 = (i + 1) % ntheta;
+            cells.push([j * ntheta + i, j * ntheta + i1, (j + 1) * ntheta + i1]);
+            cells.push([j * ntheta + i, (j + 1) * ntheta + i1, (j + 1) * ntheta + i]);
+        }
+    }
+
+    // Caps
+    for (z, outer_ring_start, upwards) in [(zero, 0, false), (height, nlayers * ntheta, true)] {
+        let centre = points.len();
+        points.push([zero, zero, z]);
+        let mut ring_starts = vec![centre];
+        for k in 1..nrings {
+            ring_starts.push(points.len());
+            let r = radius * T::from(k).unwrap() / T::from(nrings).unwrap();
+            for a in &angles {
+                points.push([r * Float::cos(*a), r * Float::sin(*a), z]);
+            }
+        }
+        ring_starts.push(outer_ring_start);
+
+        for i in 0..ntheta {
+            let i1 = (i + 1) % ntheta;
+            let (v0, v1) = (ring_starts[1] + i, ring_starts[1] + i1);
+            cells.push(if upwards {
+                [centre, v0, v1]
+            } else {
+                [centre, v1, v0]
+            });
+            for k in 1..nrings {
+                let (inner, outer) = (ring_starts[k], ring_starts[k + 1]);
+                if upwards {
+                    cells.push([inner + i, outer + i, outer + i1]);
+                    cells.push([inner + i, outer + i1, inner + i1]);
+                } else {
+                    cells.push([inner + i, outer + i1, outer + i]);
+                    cells.push([inner + i, inner + i1, outer + i1]);
+                }
+            }
+        }
+    }
+    (points, cells)
+}
+
+/// Create the points and cells of a torus
+fn torus_points_and_cells<T: RealScalar>(
+    refinement_level: u32,
+    major_radius: T,
+    minor_radius: T,
+) -> (Vec<[T; 3]>, Vec<[usize; 3]>) {
+    let ntheta = 8 * usize::pow(2, refinement_level);
+    let nphi = std::cmp::max(
+        4,
+        num::cast::<T, usize>(Float::round(
+            T::from(ntheta).unwrap() * minor_radius / major_radius,
+        ))
+        .unwrap(),
+    );
+    let two_pi = T::from(2.0 * std::f64::consts::PI).unwrap();
+
+    let mut points = Vec::with_capacity(ntheta * nphi);
+    for j in 0..nphi {
+        let phi = two_pi * T::from(j).unwrap() / T::from(nphi).unwrap();
+        for i in 0..ntheta {
+            let theta = two_pi * T::from(i).unwrap() / T::from(ntheta).unwrap();
+            let r = major_radius + minor_radius * Float::cos(phi);
+            points.push([
+                r * Float::cos(theta),
+                r * Float::sin(theta),
+                minor_radius * Float::sin(phi),
+            ]);
+        }
+    }
+    let mut cells = Vec::with_capacity(2 * ntheta * nphi);
+    for j in 0..nphi {
+        let j1 = (j + 1) % nphi;
+        for i in 0..ntheta {
+            let i1 = (i + 1) % ntheta;
+            cells.push([j * ntheta + i, j * ntheta + i1, j1 * ntheta + i1]);
+            cells.push([j * ntheta + i, j1 * ntheta + i1, j1 * ntheta + i]);
+        }
+    }
+    (points, cells)
+}
@@ -0,0 +1,348 @@
+//! Iterative solvers
+mod bicgstab;
+mod cg;
+mod gmres;
+
+pub use bicgstab::bicgstab;
+pub use cg::cg;
+pub use gmres::gmres;
+
+use num::{Float, Zero};
+use rayon::prelude::*;
+use rlst::{CsrMatrix, DynamicArray, RawAccess, RlstScalar, Shape};
+
+/// The number of rows of a dense matrix that are multiplied by a vector by each thread
+const MATVEC_ROWS: usize = 256;
+
+/// A linear operator
+pub trait LinearOperator {
+    /// Scalar type
+    type T: RlstScalar;
+
+    /// The shape of the operator
+    fn shape(&self) -> [usize; 2];
+
+    /// Apply the operator to `x` and store the result in `y`
+    fn apply(&self, x: &[Self::T], y: &mut [Self::T]);
+}
+
+impl<T: RlstScalar> LinearOperator for DynamicArray<T, 2> {
+    type T = T;
+
+    fn shape(&self) -> [usize; 2] {
+        Shape::shape(self)
+    }
+
+    fn apply(&self, x: &[T], y: &mut [T]) {
+        let shape = LinearOperator::shape(self);
+        assert_eq!(x.len(), shape[1]);
+        assert_eq!(y.len(), shape[0]);
+        let data = self.data();
+        // Each thread computes a block of rows, and the matrix is read one column of the block at a time as it is
+        // stored in column-major order
+        y.par_chunks_mut(MATVEC_ROWS)
+            .enumerate()
+            .for_each(|(block, y_block)| {
+                let start = block * MATVEC_ROWS;
+                for y_i in y_block.iter_mut() {
+                    *y_i = T::zero();
+                }
+                for (j, x_j) in x.iter().enumerate() {
+                    let column = &data[start + shape[0] * j..start + shape[0] * j + y_block.len()];
+                    for (y_i, a_ij) in y_block.iter_mut().zip(column) {
+                        *y_i += *a_ij * *x_j;
+                    }
+                }
+            });
+    }
+}
+
+impl<T: RlstScalar> LinearOperator for CsrMatrix<T> {
+    type T = T;
+
+    fn shape(&self) -> [usize; 2] {
+        Shape::shape(self)
+    }
+
+    fn apply(&self, x: &[T], y: &mut [T]) {
+        let shape = LinearOperator::shape(self);
+        assert_eq!(x.len(), shape[1]);
+        assert_eq!(y.len(), shape[0]);
+        let indptr = self.indptr();
+        let indices = self.indices();
+        let data = self.data();
+        y.par_iter_mut().enumerate().for_each(|(i, y_i)| {
+            *y_i = T::zero();
+            for k in indptr[i]..indptr[i + 1] {
+                *y_i += data[k] * x[indices[k]];
+            }
+        });
+    }
+}
+
+impl<Op: LinearOperator + ?Sized> LinearOperator for &Op {
+    type T = Op::T;
+
+    fn shape(&self) -> [usize; 2] {
+        (**self).shape()
+    }
+
+    fn apply(&self, x: &[Self::T], y: &mut [Self::T]) {
+        (**self).apply(x, y)
+    }
+}
+
+/// The sum of two linear operators
+///
+/// This can be used to solve using an operator whose singular part is assembled into a sparse matrix and whose
+/// non-singular part is stored as a dense matrix.
+pub struct SumOperator<A: LinearOperator, B: LinearOperator<T = A::T>> {
+    a: A,
+    b: B,
+}
+
+impl<A: LinearOperator, B: LinearOperator<T = A::T>> SumOperator<A, B> {
+    /// Create new sum of operators
+    pub fn new(a: A, b: B) -> Self {
+        if a.shape() != b.shape() {
+            panic!("Operators must have the same shape");
+        }
+        Self { a, b }
+    }
+}
+
+impl<A: LinearOperator, B: LinearOperator<T = A::T>> LinearOperator for SumOperator<A, B> {
+    type T = A::T;
+
+    fn shape(&self) -> [usize; 2] {
+        self.a.shape()
+    }
+
+    fn apply(&self, x: &[Self::T], y: &mut [Self::T]) {
+        let mut temp = y.to_vec();
+        self.a.apply(x, y);
+        self.b.apply(x, &mut temp);
+        for (y_i, t_i) in y.iter_mut().zip(&temp) {
+            *y_i += *t_i;
+        }
+    }
+}
+
+/// A linear operator defined by a function that computes its action
+pub struct MatrixFreeOperator<T: RlstScalar, F: Fn(&[T], &mut [T])> {
+    shape: [usize; 2],
+    action: F,
+    _t: std::marker::PhantomData<T>,
+}
+
+impl<T: RlstScalar, F: Fn(&[T], &mut [T])> MatrixFreeOperator<T, F> {
+    /// Create new matrix-free operator
+    ///
+    /// `action(x, y)` must store the result of applying the operator to `x` in `y`.
+    pub fn new(shape: [usize; 2], action: F) -> Self {
+        Self {
+            shape,
+            action,
+            _t: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T: RlstScalar, F: Fn(&[T], &mut [T])> LinearOperator for MatrixFreeOperator<T, F> {
+    type T = T;
+
+    fn shape(&self) -> [usize; 2] {
+        self.shape
+    }
+
+    fn apply(&self, x: &[T], y: &mut [T]) {
+        assert_eq!(x.len(), self.shape[1]);
+        assert_eq!(y.len(), self.shape[0]);
+        (self.action)(x, y)
+    }
+}
+
+/// Options for an iterative solver
+pub struct SolverOptions<'a, T: RlstScalar> {
+    /// Relative tolerance: the solver stops when the norm of the residual divided by the norm of the right-hand side
+    /// is below this value
+    tolerance: T::Real,
+    /// Maximum number of iterations
+    max_iterations: usize,
+    /// Number of iterations after which GMRES is restarted
+    restart: usize,
+    /// Preconditioner applied on the left
+    left_preconditioner: Option<&'a dyn LinearOperator<T = T>>,
+    /// Preconditioner applied on the right
+    right_preconditioner: Option<&'a dyn LinearOperator<T = T>>,
+    /// Function called after each iteration with the iteration number and relative residual
+    callback: Option<&'a dyn Fn(usize, T::Real)>,
+}
+
+impl<T: RlstScalar> Default for SolverOptions<'_, T> {
+    fn default() -> Self {
+        Self {
+            tolerance: num::cast::<f64, T::Real>(1e-5).unwrap(),
+            max_iterations: 1000,
+            restart: 30,
+            left_preconditioner: None,
+            right_preconditioner: None,
+            callback: None,
+        }
+    }
+}
+
+impl<'a, T: RlstScalar> SolverOptions<'a, T> {
+    /// Set the relative tolerance.
+    pub fn set_tolerance(&mut self, tolerance: T::Real) {
+        self.tolerance = tolerance;
+    }
+
+    /// Get the relative tolerance.
+    pub fn get_tolerance(&self) -> T::Real {
+        self.tolerance
+    }
+
+    /// Set the maximum number of iterations.
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Get the maximum number of iterations.
+    pub fn get_max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+
+    /// Set the number of iterations after which GMRES is restarted.
+    pub fn set_restart(&mut self, restart: usize) {
+        self.restart = restart;
+    }
+
+    /// Get the number of iterations after which GMRES is restarted.
+    pub fn get_restart(&self) -> usize {
+        self.restart
+    }
+
+    /// Set the left preconditioner.
+    pub fn set_left_preconditioner(&mut self, preconditioner: &'a dyn LinearOperator<T = T>) {
+        self.left_preconditioner = Some(preconditioner);
+    }
+
+    /// Set the right preconditioner.
+    pub fn set_right_preconditioner(&mut self, preconditioner: &'a dyn LinearOperator<T = T>) {
+        self.right_preconditioner = Some(preconditioner);
+    }
+
+    /// Set the function called after each iteration.
+    pub fn set_callback(&mut self, callback: &'a dyn Fn(usize, T::Real)) {
+        self.callback = Some(callback);
+    }
+}
+
+/// The result of an iterative solve
+#[derive(Debug, Clone)]
+pub struct SolverResult<T: RlstScalar> {
+    /// Did the solver converge?
+    pub converged: bool,
+    /// Number of iterations performed
+    pub iterations: usize,
+    /// The relative residual before the first iteration and after each iteration
+    pub residuals: Vec<T::Real>,
+}
+
+impl<T: RlstScalar> SolverResult<T> {
+    /// Create a new result with an initial relative residual
+    fn new(initial_residual: T::Real) -> Self {
+        Self {
+            converged: false,
+            iterations: 0,
+            residuals: vec![initial_residual],
+        }
+    }
+
+    /// Record the relative residual after an iteration
+    fn push(&mut self, residual: T::Real, options: &SolverOptions<T>) {
+        self.iterations += 1;
+        self.residuals.push(residual);
+        if let Some(callback) = options.callback {
+            callback(self.iterations, residual);
+        }
+    }
+}
+
+/// Check that an operator and vectors can be used in a solve
+fn check_shapes<T: RlstScalar, Op: LinearOperator<T = T>>(
+    operator: &Op,
+    rhs: &[T],
+    solution: &[T],
+) {
+    let shape = operator.shape();
+    if shape[0] != shape[1] {
+        panic!("Iterative solvers can only be used with square operators");
+    }
+    assert_eq!(rhs.len(), shape[0]);
+    assert_eq!(solution.len(), shape[1]);
+}
+
+/// Apply an optional preconditioner to a vector in place
+fn precondition<T: RlstScalar>(preconditioner: Option<&dyn LinearOperator<T = T>>, x: &mut [T]) {
+    if let Some(p) = preconditioner {
+        let mut temp = vec![T::zero(); x.len()];
+        p.apply(x, &mut temp);
+        x.copy_from_slice(&temp);
+    }
+}
+
+/// Compute y = P_L A P_R x, where P_L and P_R are the (optional) left and right preconditioners
+fn apply_preconditioned<T: RlstScalar, Op: LinearOperator<T = T>>(
+    operator: &Op,
+    options: &SolverOptions<T>,
+    x: &[T],
+    y: &mut [T],
+) {
+    let mut temp = x.to_vec();
+    precondition(options.right_preconditioner, &mut temp);
+    operator.apply(&temp, y);
+    precondition(options.left_preconditioner, y);
+}
+
+/// Compute the residual b - Ax
+fn residual<T: RlstScalar, Op: LinearOperator<T = T>>(operator: &Op, rhs: &[T], x: &[T]) -> Vec<T> {
+    let mut r = rhs.to_vec();
+    operator.apply(x, &mut r);
+    for (r_i, b_i) in r.iter_mut().zip(rhs) {
+        *r_i = *b_i - *r_i;
+    }
+    r
+}
+
+/// The inner product of two vectors, conjugating the first
+fn dot<T: RlstScalar>(x: &[T], y: &[T]) -> T {
+    x.iter()
+        .zip(y)
+        .fold(T::zero(), |a, (x_i, y_i)| a + x_i.conj() * *y_i)
+}
+
+/// The 2-norm of a vector
+fn norm<T: RlstScalar>(x: &[T]) -> T::Real {
+    Float::sqrt(
+        x.iter()
+            .fold(T::Real::zero(), |a, x_i| a + x_i.abs() * x_i.abs()),
+    )
+}
+
+/// Compute y += a x
+fn axpy<T: RlstScalar>(a: T, x: &[T], y: &mut [T]) {
+    for (y_i, x_i) in y.iter_mut().zip(x) {
+        *y_i += a * *x_i;
+    }
+}
+
+/// Divide a residual by the norm of the right-hand side, treating a zero right-hand side as having norm 1
+fn relative<T: RlstScalar>(residual: T::Real, rhs_norm: T::Real) -> T::Real {
+    if rhs_norm == T::Real::zero() {
+        residual
+    } else {
+        residual / rhs_norm
+    }
+}
@@ -0,0 +1,96 @@
+//! Biconjugate gradient stabilised method
+use super::{
+    apply_preconditioned, axpy, check_shapes, dot, norm, precondition, relative, residual,
+    LinearOperator, SolverOptions, SolverResult,
+};
+use num::{One, Zero};
+use rlst::RlstScalar;
+
+/// Solve a linear system using the biconjugate gradient stabilised method (BiCGStab)
+///
+/// The values in `solution` are used as the initial guess, and are overwritten by the solution. Convergence is measured
+/// using the norm of the preconditioned residual.
+pub fn bicgstab<T: RlstScalar, Op: LinearOperator<T = T>>(
+    operator: &Op,
+    rhs: &[T],
+    solution: &mut [T],
+    options: &SolverOptions<T>,
+) -> SolverResult<T> {
+    check_shapes(operator, rhs, solution);
+    let n = rhs.len();
+
+    let mut preconditioned_rhs = rhs.to_vec();
+    precondition(options.left_preconditioner, &mut preconditioned_rhs);
+    let rhs_norm = norm(&preconditioned_rhs);
+
+    // Solve the preconditioned system for the correction to the initial guess
+    let mut r = residual(operator, rhs, solution);
+    precondition(options.left_preconditioner, &mut r);
+    let mut result = SolverResult::new(relative::<T>(norm(&r), rhs_norm));
+    if relative::<T>(norm(&r), rhs_norm) < options.tolerance {
+        result.converged = true;
+        return result;
+    }
+
+    let r0 = r.clone();
+    let mut correction = vec![T::zero(); n];
+    let mut rho = T::one();
+    let mut alpha = T::one();
+    let mut omega = T::one();
+    let mut v = vec![T::zero(); n];
+    let mut p = vec![T::zero(); n];
+    let mut s = vec![T::zero(); n];
+    let mut t = vec![T::zero(); n];
+
+    while result.iterations < options.max_iterations {
+        let rho_new = dot(&r0, &r);
+        if rho_new == T::zero() || omega == T::zero() {
+            break;
+        }
+        let beta = (rho_new / rho) * (alpha / omega);
+        for ((p_i, r_i), v_i) in p.iter_mut().zip(&r).zip(&v) {
+            *p_i = *r_i + beta * (*p_i - omega * *v_i);
+        }
+        apply_preconditioned(operator, options, &p, &mut v);
+        let r0_v = dot(&r0, &v);
+        if r0_v == T::zero() {
+            break;
+        }
+        alpha = rho_new / r0_v;
+        for ((s_i, r_i), v_i) in s.iter_mut().zip(&r).zip(&v) {
+            *s_i = *r_i - alpha * *v_i;
+        }
+        axpy(alpha, &p, &mut correction);
+
+        let s_norm = relative::<T>(norm(&s), rhs_norm);
+        if s_norm < options.tolerance {
+            result.push(s_norm, options);
+            result.converged = true;
+            break;
+        }
+
+        apply_preconditioned(operator, options, &s, &mut t);
+        let t_t = dot(&t, &t);
+        omega = if t_t == T::zero() {
+            T::zero()
+        } else {
+            dot(&t, &s) / t_t
+        };
+        axpy(omega, &s, &mut correction);
+        for ((r_i, s_i), t_i) in r.iter_mut().zip(&s).zip(&t) {
+            *r_i = *s_i - omega * *t_i;
+        }
+        rho = rho_new;
+
+        let residual_norm = relative::<T>(norm(&r), rhs_norm);
+        result.push(residual_norm, options);
+        if residual_norm < options.tolerance {
+            result.converged = true;
+            break;
+        }
+    }
+
+    precondition(options.right_preconditioner, &mut correction);
+    axpy(T::one(), &correction, solution);
+    result
+}
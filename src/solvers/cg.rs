@@ -0,0 +1,67 @@
+//! Conjugate gradient method
+use super::{
+    axpy, check_shapes, dot, norm, precondition, relative, residual, LinearOperator, SolverOptions,
+    SolverResult,
+};
+use num::Zero;
+use rlst::RlstScalar;
+
+/// Solve a linear system with a Hermitian positive definite operator using the conjugate gradient method
+///
+/// The values in `solution` are used as the initial guess, and are overwritten by the solution. If a left
+/// preconditioner is given, it is used as the preconditioner in the preconditioned conjugate gradient method, and must
+/// also be Hermitian positive definite. Right preconditioners are not supported. Convergence is measured using the norm
+/// of the (unpreconditioned) residual.
+pub fn cg<T: RlstScalar, Op: LinearOperator<T = T>>(
+    operator: &Op,
+    rhs: &[T],
+    solution: &mut [T],
+    options: &SolverOptions<T>,
+) -> SolverResult<T> {
+    check_shapes(operator, rhs, solution);
+    if options.right_preconditioner.is_some() {
+        panic!("Right preconditioners cannot be used with the conjugate gradient method");
+    }
+    let rhs_norm = norm(rhs);
+
+    let mut r = residual(operator, rhs, solution);
+    let mut result = SolverResult::new(relative::<T>(norm(&r), rhs_norm));
+    if relative::<T>(norm(&r), rhs_norm) < options.tolerance {
+        result.converged = true;
+        return result;
+    }
+
+    let mut z = r.clone();
+    precondition(options.left_preconditioner, &mut z);
+    let mut p = z.clone();
+    let mut rz = dot(&r, &z);
+    let mut ap = vec![T::zero(); rhs.len()];
+
+    while result.iterations < options.max_iterations {
+        operator.apply(&p, &mut ap);
+        let p_ap = dot(&p, &ap);
+        if p_ap == T::zero() {
+            break;
+        }
+        let alpha = rz / p_ap;
+        axpy(alpha, &p, solution);
+        axpy(-alpha, &ap, &mut r);
+
+        let residual_norm = relative::<T>(norm(&r), rhs_norm);
+        result.push(residual_norm, options);
+        if residual_norm < options.tolerance {
+            result.converged = true;
+            break;
+        }
+
+        z.copy_from_slice(&r);
+        precondition(options.left_preconditioner, &mut z);
+        let rz_new = dot(&r, &z);
+        let beta = rz_new / rz;
+        for (p_i, z_i) in p.iter_mut().zip(&z) {
+            *p_i = *z_i + beta * *p_i;
+        }
+        rz = rz_new;
+    }
+    result
+}
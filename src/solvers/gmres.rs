@@ -0,0 +1,117 @@
+//! Restarted GMRES
+use super::{
+    apply_preconditioned, axpy, check_shapes, dot, norm, precondition, relative, residual,
+    LinearOperator, SolverOptions, SolverResult,
+};
+use num::{Float, One, Zero};
+use rlst::RlstScalar;
+
+/// Solve a linear system using restarted GMRES
+///
+/// The values in `solution` are used as the initial guess, and are overwritten by the solution. Convergence is measured
+/// using the norm of the preconditioned residual.
+pub fn gmres<T: RlstScalar, Op: LinearOperator<T = T>>(
+    operator: &Op,
+    rhs: &[T],
+    solution: &mut [T],
+    options: &SolverOptions<T>,
+) -> SolverResult<T> {
+    check_shapes(operator, rhs, solution);
+    let n = rhs.len();
+    let restart = options.restart.max(1);
+
+    let mut preconditioned_rhs = rhs.to_vec();
+    precondition(options.left_preconditioner, &mut preconditioned_rhs);
+    let rhs_norm = norm(&preconditioned_rhs);
+
+    let mut r = residual(operator, rhs, solution);
+    precondition(options.left_preconditioner, &mut r);
+    let mut beta = norm(&r);
+    let mut result = SolverResult::new(relative::<T>(beta, rhs_norm));
+
+    while result.iterations < options.max_iterations {
+        if relative::<T>(beta, rhs_norm) < options.tolerance {
+            result.converged = true;
+            break;
+        }
+
+        // Arnoldi process, with Givens rotations used to keep the Hessenberg matrix upper triangular
+        let mut basis = vec![r
+            .iter()
+            .map(|r_i| *r_i / T::from_real(beta))
+            .collect::<Vec<_>>()];
+        let mut h = vec![vec![T::zero(); restart]; restart + 1];
+        let mut rotations = Vec::<(T::Real, T)>::with_capacity(restart);
+        let mut g = vec![T::zero(); restart + 1];
+        g[0] = T::from_real(beta);
+
+        let mut k = 0;
+        while k < restart && result.iterations < options.max_iterations {
+            let mut w = vec![T::zero(); n];
+            apply_preconditioned(operator, options, &basis[k], &mut w);
+            for (i, v) in basis.iter().enumerate() {
+                h[i][k] = dot(v, &w);
+                axpy(-h[i][k], v, &mut w);
+            }
+            let w_norm = norm(&w);
+            h[k + 1][k] = T::from_real(w_norm);
+
+            for (i, (c, s)) in rotations.iter().enumerate() {
+                let (a, b) = (h[i][k], h[i + 1][k]);
+                h[i][k] = T::from_real(*c) * a + *s * b;
+                h[i + 1][k] = -s.conj() * a + T::from_real(*c) * b;
+            }
+            let (c, s) = givens_rotation(h[k][k], h[k + 1][k]);
+            h[k][k] = T::from_real(c) * h[k][k] + s * h[k + 1][k];
+            h[k + 1][k] = T::zero();
+            g[k + 1] = -s.conj() * g[k];
+            g[k] = T::from_real(c) * g[k];
+            rotations.push((c, s));
+
+            k += 1;
+            beta = g[k].abs();
+            result.push(relative::<T>(beta, rhs_norm), options);
+
+            if w_norm == T::Real::zero() || relative::<T>(beta, rhs_norm) < options.tolerance {
+                break;
+            }
+            basis.push(w.iter().map(|w_i| *w_i / T::from_real(w_norm)).collect());
+        }
+
+        // Solve the upper triangular system and update the solution
+        let mut y = vec![T::zero(); k];
+        for i in (0..k).rev() {
+            let mut value = g[i];
+            for j in i + 1..k {
+                value -= h[i][j] * y[j];
+            }
+            y[i] = value / h[i][i];
+        }
+        let mut update = vec![T::zero(); n];
+        for (y_i, v) in y.iter().zip(&basis) {
+            axpy(*y_i, v, &mut update);
+        }
+        precondition(options.right_preconditioner, &mut update);
+        axpy(T::one(), &update, solution);
+
+        r = residual(operator, rhs, solution);
+        precondition(options.left_preconditioner, &mut r);
+        beta = norm(&r);
+    }
+    if relative::<T>(beta, rhs_norm) < options.tolerance {
+        result.converged = true;
+    }
+    result
+}
+
+/// Compute a Givens rotation (c, s) such that [c, s; -conj(s), c] [a; b] = [r; 0]
+fn givens_rotation<T: RlstScalar>(a: T, b: T) -> (T::Real, T) {
+    let a_abs = a.abs();
+    if a_abs == T::Real::zero() {
+        (T::Real::zero(), T::one())
+    } else {
+        let r = Float::sqrt(a_abs * a_abs + b.abs() * b.abs());
+        let phase = a / T::from_real(a_abs);
+        (a_abs / r, phase * b.conj() / T::from_real(r))
+    }
+}
@@ -0,0 +1,364 @@
+//! Sparse operators
+//!
+//! Local operators only couple basis functions that are supported on the same cell, so they are assembled into sparse
+//! matrices by integrating over each cell.
+use crate::boundary_assemblers::helpers::{
+    equal_grids, AssemblerGeometry, CsrMatrixData, RlstArray,
+};
+use crate::boundary_assemblers::integrands::LocalIntegrand;
+use crate::boundary_assemblers::{
+    apply_dof_signs, regular_quadrature_rule, BoundaryAssemblerOptions, QuadratureOptionsError,
+};
+use crate::error::BemppError;
+use crate::function::{is_excluded, FunctionSpaceTrait};
+use itertools::izip;
+use ndelement::traits::FiniteElement;
+use ndelement::types::ReferenceCellType;
+use ndgrid::traits::{Entity, GeometryMap, Grid};
+use num::Zero;
+use rlst::{
+    rlst_dynamic_array2, rlst_dynamic_array4, CsrMatrix, DefaultIterator, DefaultIteratorMut,
+    MatrixInverse, RawAccess, RawAccessMut, RlstScalar, Shape,
+};
+use std::collections::HashMap;
+
+/// A cell type and the polynomial degrees of the test and trial elements used on cells of that type
+type CellKey = (ReferenceCellType, Option<usize>, Option<usize>);
+
+/// Sparse assembler
+///
+/// Assembles local operators into sparse matrices
+pub struct SparseAssembler<'o, T: RlstScalar + MatrixInverse, Integrand: LocalIntegrand<T = T>> {
+    pub(crate) integrand: Integrand,
+    pub(crate) options: &'o BoundaryAssemblerOptions,
+    pub(crate) table_derivs: usize,
+}
+
+impl<'o, T: RlstScalar + MatrixInverse, Integrand: LocalIntegrand<T = T>>
+    SparseAssembler<'o, T, Integrand>
+{
+    /// Create new sparse assembler
+    pub(crate) fn new(
+        integrand: Integrand,
+        options: &'o BoundaryAssemblerOptions,
+        table_derivs: usize,
+    ) -> Self {
+        Self {
+            integrand,
+            options,
+            table_derivs,
+        }
+    }
+
+    /// Assemble into a CSR matrix.
+    pub fn assemble<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+    ) -> CsrMatrix<T> {
+        self.try_assemble(trial_space, test_space)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Assemble into a CSR matrix, returning an error if the input is invalid.
+    pub fn try_assemble<
+        TestSpace: FunctionSpaceTrait<T = T> + Sync,
+        TrialSpace: FunctionSpaceTrait<T = T> + Sync,
+    >(
+        &self,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+    ) -> Result<CsrMatrix<T>, BemppError> {
+        if !equal_grids(test_space.grid(), trial_space.grid()) {
+            return Err(BemppError::IncompatibleSpaces(
+                "local operators can only be assembled for function spaces on the same grid"
+                    .to_string(),
+            ));
+        }
+        let grid = test_space.grid();
+        for cell_type in grid.entity_types(2) {
+            if !matches!(
+                cell_type,
+                ReferenceCellType::Triangle | ReferenceCellType::Quadrilateral
+            ) {
+                return Err(BemppError::UnsupportedCellType(*cell_type));
+            }
+            let test_map_type = test_space.map_type(*cell_type);
+            let trial_map_type = trial_space.map_type(*cell_type);
+            if !self
+                .integrand
+                .supports_map_types(test_map_type, trial_map_type)
+            {
+                return Err(BemppError::IncompatibleSpaces(format!(
+                    "the operator cannot be used with test functions mapped using {test_map_type:?} and trial functions mapped using {trial_map_type:?}"
+                )));
+            }
+        }
+        self.options.validate(grid.entity_types(2))?;
+
+        // The cells are split by the elements used on them, and cells that are not included in one of the spaces are
+        // skipped
+        let mut cells = HashMap::<CellKey, Vec<usize>>::new();
+        for cell in grid.entity_iter(2) {
+            let index = cell.local_index();
+            if test_space.cell_dofs(index).unwrap_or(&[]).is_empty()
+                || trial_space.cell_dofs(index).unwrap_or(&[]).is_empty()
+            {
+                continue;
+            }
+            cells
+                .entry((
+                    cell.entity_type(),
+                    test_space.cell_degree(index),
+                    trial_space.cell_degree(index),
+                ))
+                .or_default()
+                .push(index);
+        }
+
+        // The sparsity pattern contains every pair of DOFs on the same cell
+        let shape = [test_space.global_size(), trial_space.global_size()];
+        let mut pattern = vec![vec![]; shape[0]];
+        for cell in cells.values().flatten() {
+            let trial_dofs = trial_space.cell_dofs(*cell).unwrap();
+            let test_excluded = test_space.excluded_dofs(*cell);
+            let trial_excluded = trial_space.excluded_dofs(*cell);
+            for (i, test_dof) in test_space.cell_dofs(*cell).unwrap().iter().enumerate() {
+                if is_excluded(test_excluded, i) {
+                    continue;
+                }
+                let row = test_space.global_dof_index(*test_dof);
+                for (j, trial_dof) in trial_dofs.iter().enumerate() {
+                    if !is_excluded(trial_excluded, j) {
+                        pattern[row].push(trial_space.global_dof_index(*trial_dof));
+                    }
+                }
+            }
+        }
+        let mut matrix = CsrMatrixData::from_pattern(shape, pattern);
+
+        // The basis functions are tabulated once for each element, and the cells are split into blocks that are
+        // assembled in parallel
+        let mut tables = HashMap::new();
+        let mut cell_blocks = vec![];
+        for (key, cells) in cells {
+            tables.insert(key, self.tabulate(key, trial_space, test_space)?);
+            for block in cells.chunks(self.options.batch_size.max(1)) {
+                cell_blocks.push((key, block.to_vec()));
+            }
+        }
+
+        matrix.add_in_parallel(cell_blocks, |(key, cells)| {
+            self.assemble_cells(key.0, trial_space, test_space, &tables[&key], &cells)
+        });
+        Ok(matrix.into_csr_matrix())
+    }
+
+    /// Create the quadrature rule and tabulate the test and trial elements used on cells with the given key
+    fn tabulate<TestSpace: FunctionSpaceTrait<T = T>, TrialSpace: FunctionSpaceTrait<T = T>>(
+        &self,
+        (cell_type, test_degree, trial_degree): CellKey,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+    ) -> Result<CellTables<T>, BemppError> {
+        let npts = self
+            .options
+            .get_regular_quadrature_degree(cell_type)
+            .ok_or(QuadratureOptionsError::MissingRegularDegree(cell_type))?;
+        let (points, weights) = regular_quadrature_rule::<T>(cell_type, npts);
+
+        let test_element = test_space.element_with_degree(cell_type, test_degree);
+        let mut test_table = rlst_dynamic_array4!(
+            T,
+            test_element.tabulate_array_shape(self.table_derivs, npts)
+        );
+        test_element.tabulate(&points, self.table_derivs, &mut test_table);
+
+        let trial_element = trial_space.element_with_degree(cell_type, trial_degree);
+        let mut trial_table = rlst_dynamic_array4!(
+            T,
+            trial_element.tabulate_array_shape(self.table_derivs, npts)
+        );
+        trial_element.tabulate(&points, self.table_derivs, &mut trial_table);
+
+        Ok(CellTables {
+            points,
+            weights,
+            test_table,
+            trial_table,
+        })
+    }
+
+    /// Compute the contributions from a block of cells of one type that use the same test and trial elements
+    fn assemble_cells<
+        TestSpace: FunctionSpaceTrait<T = T>,
+        TrialSpace: FunctionSpaceTrait<T = T>,
+    >(
+        &self,
+        cell_type: ReferenceCellType,
+        trial_space: &TrialSpace,
+        test_space: &TestSpace,
+        tables: &CellTables<T>,
+        cells: &[usize],
+    ) -> Vec<(usize, usize, T)> {
+        let CellTables {
+            points,
+            weights,
+            test_table,
+            trial_table,
+        } = tables;
+        let npts = weights.len();
+        let test_map_type = test_space.map_type(cell_type);
+        let trial_map_type = trial_space.map_type(cell_type);
+
+        let evaluator = test_space.grid().geometry_map(cell_type, points.data());
+        let mut mapped_pts = rlst_dynamic_array2!(T::Real, [3, npts]);
+        let mut normals = rlst_dynamic_array2!(T::Real, [3, npts]);
+        let mut jacobians = rlst_dynamic_array2!(T::Real, [6, npts]);
+        let mut jdets = vec![T::Real::zero(); npts];
+        let mut reversed_normals = rlst_dynamic_array2!(T::Real, [3, npts]);
+        let mut reversed_jacobians = rlst_dynamic_array2!(T::Real, [6, npts]);
+
+        let mut local_mat =
+            rlst_dynamic_array2!(T, [test_table.shape()[2], trial_table.shape()[2]]);
+        let mut entries = vec![];
+
+        for cell in cells {
+            evaluator.points(*cell, mapped_pts.data_mut());
+            evaluator.jacobians_dets_normals(
+                *cell,
+                jacobians.data_mut(),
+                &mut jdets,
+                normals.data_mut(),
+            );
+            // The basis functions of a space in which the normal to the cell is reversed are pushed forward using the
+            // reversed geometry
+            reverse(&normals, &mut reversed_normals);
+            reverse(&jacobians, &mut reversed_jacobians);
+            let geometry = AssemblerGeometry::new(&mapped_pts, &normals, &jacobians, &jdets);
+            let reversed_geometry =
+                AssemblerGeometry::new(&mapped_pts, &reversed_normals, &reversed_jacobians, &jdets);
+            let test_geometry = if test_space.reversed_normal(*cell) {
+                &reversed_geometry
+            } else {
+                &geometry
+            };
+            let trial_geometry = if trial_space.reversed_normal(*cell) {
+                &reversed_geometry
+            } else {
+                &geometry
+            };
+
+            for (trial_i, mut col) in local_mat.col_iter_mut().enumerate() {
+                for (test_i, entry) in col.iter_mut().enumerate() {
+                    *entry = T::zero();
+                    for (index, (wt, jdet)) in izip!(weights, &jdets).enumerate() {
+                        *entry += self.integrand.evaluate_local(
+                            test_table,
+                            trial_table,
+                            test_map_type,
+                            trial_map_type,
+                            index,
+                            test_i,
+                            trial_i,
+                            test_geometry,
+                            trial_geometry,
+                        ) * num::cast::<T::Real, T>(*wt * *jdet).unwrap();
+                    }
+                }
+            }
+            apply_dof_signs(
+                &mut local_mat,
+                test_space.reversed_dofs(*cell),
+                trial_space.reversed_dofs(*cell),
+            );
+
+            let test_dofs = unsafe { test_space.cell_dofs_unchecked(*cell) };
+            let trial_dofs = unsafe { trial_space.cell_dofs_unchecked(*cell) };
+            let test_excluded = test_space.excluded_dofs(*cell);
+            let trial_excluded = trial_space.excluded_dofs(*cell);
+            for (j, (trial_dof, col)) in izip!(trial_dofs, local_mat.col_iter()).enumerate() {
+                if is_excluded(trial_excluded, j) {
+                    continue;
+                }
+                for (i, (test_dof, entry)) in izip!(test_dofs, col.iter()).enumerate() {
+                    if is_excluded(test_excluded, i) {
+                        continue;
+                    }
+                    entries.push((
+                        test_space.global_dof_index(*test_dof),
+                        trial_space.global_dof_index(*trial_dof),
+                        entry,
+                    ));
+                }
+            }
+        }
+        entries
+    }
+}
+
+/// The quadrature rule and the tabulated test and trial elements used on cells with the same key
+struct CellTables<T: RlstScalar> {
+    points: RlstArray<T::Real, 2>,
+    weights: Vec<T::Real>,
+    test_table: RlstArray<T, 4>,
+    trial_table: RlstArray<T, 4>,
+}
+
+/// Copy an array, negating each entry
+fn reverse<T: RlstScalar>(values: &RlstArray<T, 2>, reversed: &mut RlstArray<T, 2>) {
+    for (r, v) in reversed.data_mut().iter_mut().zip(values.data()) {
+        *r = -*v;
+    }
+}
+
+/// Assemblers for sparse operators.
+pub mod assembler {
+    use rlst::{MatrixInverse, RlstScalar};
+
+    use super::SparseAssembler;
+    use crate::boundary_assemblers::{
+        integrands::{
+            LaplaceBeltramiLocalIntegrand, MassLocalIntegrand, SurfaceDivergenceLocalIntegrand,
+        },
+        BoundaryAssemblerOptions,
+    };
+
+    /// Mass matrix assembler type.
+    pub type MassAssembler<'o, T> = SparseAssembler<'o, T, MassLocalIntegrand<T>>;
+
+    /// Laplace-Beltrami assembler type.
+    pub type LaplaceBeltramiAssembler<'o, T> =
+        SparseAssembler<'o, T, LaplaceBeltramiLocalIntegrand<T>>;
+
+    /// Surface divergence assembler type.
+    pub type SurfaceDivergenceAssembler<'o, T> =
+        SparseAssembler<'o, T, SurfaceDivergenceLocalIntegrand<T>>;
+
+    /// Assembler for the mass matrix, whose entries are the integrals of the dot products of the test and trial
+    /// functions.
+    pub fn mass<T: RlstScalar + MatrixInverse>(
+        options: &BoundaryAssemblerOptions,
+    ) -> MassAssembler<T> {
+        SparseAssembler::new(MassLocalIntegrand::new(), options, 0)
+    }
+
+    /// Assembler for the Laplace-Beltrami operator, whose entries are the integrals of the dot products of the surface
+    /// gradients of scalar-valued test and trial functions.
+    pub fn laplace_beltrami<T: RlstScalar + MatrixInverse>(
+        options: &BoundaryAssemblerOptions,
+    ) -> LaplaceBeltramiAssembler<T> {
+        SparseAssembler::new(LaplaceBeltramiLocalIntegrand::new(), options, 1)
+    }
+
+    /// Assembler for the surface divergence operator, whose entries are the integrals of the surface divergences of
+    /// test functions in H(div) times scalar-valued trial functions.
+    pub fn surface_divergence<T: RlstScalar + MatrixInverse>(
+        options: &BoundaryAssemblerOptions,
+    ) -> SurfaceDivergenceAssembler<T> {
+        SparseAssembler::new(SurfaceDivergenceLocalIntegrand::new(), options, 1)
+    }
+}
@@ -0,0 +1,30 @@
+//! Helper functions shared between tests
+#![allow(dead_code)]
+
+use ndelement::types::ReferenceCellType;
+use ndgrid::traits::Grid;
+use rlst::{CsrMatrix, Shape};
+
+/// Compute the Euler characteristic (V - E + F) of a grid
+pub fn euler_characteristic(grid: &impl Grid<EntityDescriptor = ReferenceCellType>) -> i64 {
+    let nvertices = grid.entity_count(ReferenceCellType::Point) as i64;
+    let nedges = grid.entity_count(ReferenceCellType::Interval) as i64;
+    let ncells = grid
+        .entity_types(2)
+        .iter()
+        .map(|t| grid.entity_count(*t))
+        .sum::<usize>() as i64;
+    nvertices - nedges + ncells
+}
+
+/// Convert a CSR matrix to a dense matrix stored in column-major order
+pub fn to_dense(matrix: &CsrMatrix<f64>) -> Vec<f64> {
+    let [nrows, ncols] = matrix.shape();
+    let mut dense = vec![0.0; nrows * ncols];
+    for row in 0..nrows {
+        for k in matrix.indptr()[row]..matrix.indptr()[row + 1] {
+            dense[row + nrows * matrix.indices()[k]] += matrix.data()[k];
+        }
+    }
+    dense
+}
@@ -0,0 +1,163 @@
+use approx::*;
+use bempp::adaptivity::{
+    dorfler_marking, hypersingular_indicators, single_layer_indicators, solve_laplace_adaptively,
+    AdaptiveOptions, LaplaceEquation,
+};
+use bempp::function::transfer::interpolation_matrix;
+use bempp::function::{FunctionSpace, FunctionSpaceTrait};
+use bempp::shapes::{regular_sphere, screen_triangles};
+use mpi::environment::Universe;
+use ndelement::ciarlet::LagrangeElementFamily;
+use ndelement::types::Continuity;
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+#[test]
+fn test_indicators_of_constant_functions() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+
+    let dp0 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous),
+    );
+    for i in single_layer_indicators(&dp0, &vec![2.0; dp0.global_size()]) {
+        assert_relative_eq!(i, 0.0, epsilon = 1e-12);
+    }
+
+    let p1 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    for i in hypersingular_indicators(&p1, &vec![2.0; p1.global_size()]) {
+        assert_relative_eq!(i, 0.0, epsilon = 1e-12);
+    }
+}
+
+#[test]
+fn test_indicators_of_nonsmooth_function() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+
+    let dp0 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous),
+    );
+    let mut coefficients = vec![0.0; dp0.global_size()];
+    coefficients[0] = 1.0;
+    let indicators = single_layer_indicators(&dp0, &coefficients);
+    assert!(indicators.iter().any(|i| *i > 1e-3));
+}
+
+#[test]
+fn test_indicators_on_zero_trace_space() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = screen_triangles::<f64, _>(4, &comm);
+    let family = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let p1 = FunctionSpace::new(&grid, &family);
+    let zero_trace_p1 = FunctionSpace::new_zero_trace(&grid, &family);
+
+    // Interpolating into the full space extends a zero-trace function by zero on the boundary
+    let coefficients = (0..zero_trace_p1.global_size())
+        .map(|i| 1.0 + (i as f64).sin())
+        .collect::<Vec<_>>();
+    let embedding = interpolation_matrix(&zero_trace_p1, &p1);
+    let mut extended = vec![0.0; p1.global_size()];
+    for (row, value) in extended.iter_mut().enumerate() {
+        for k in embedding.indptr()[row]..embedding.indptr()[row + 1] {
+            *value += embedding.data()[k] * coefficients[embedding.indices()[k]];
+        }
+    }
+    assert_eq!(extended.iter().filter(|v| **v == 0.0).count(), 16);
+
+    for (a, b) in hypersingular_indicators(&zero_trace_p1, &coefficients)
+        .iter()
+        .zip(hypersingular_indicators(&p1, &extended))
+    {
+        assert_relative_eq!(*a, b, epsilon = 1e-12);
+    }
+}
+
+#[test]
+fn test_dorfler_marking() {
+    let indicators = [3.0, 1.0, 2.0, 0.5];
+    assert_eq!(dorfler_marking(&indicators, 0.5), vec![0]);
+    assert_eq!(dorfler_marking(&indicators, 0.8), vec![0, 2]);
+    assert_eq!(dorfler_marking(&indicators, 1.0), vec![0, 2, 1, 3]);
+
+    // NaN indicators are marked first instead of causing a panic
+    let indicators = [3.0, f64::NAN, 2.0];
+    assert_eq!(dorfler_marking(&indicators, 0.5)[0], 1);
+}
+
+#[test]
+fn test_adaptive_single_layer_on_screen() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = screen_triangles::<f64, _>(2, &comm);
+
+    let mut options = AdaptiveOptions::default();
+    options.set_max_steps(4);
+    let solution = solve_laplace_adaptively(
+        LaplaceEquation::SingleLayer,
+        grid,
+        |_| 1.0,
+        None,
+        &options,
+        &comm,
+    );
+
+    assert_eq!(solution.history.len(), 4);
+    for step in &solution.history {
+        assert!(step.converged);
+        assert!(step.estimate.is_finite());
+    }
+    for (a, b) in solution.history.iter().zip(solution.history.iter().skip(1)) {
+        assert!(b.cells > a.cells);
+    }
+    assert_eq!(solution.coefficients.len(), solution.history[3].dofs);
+    assert_eq!(solution.indicators.len(), solution.history[3].cells);
+}
+
+#[test]
+fn test_adaptive_hypersingular_on_screen() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = screen_triangles::<f64, _>(4, &comm);
+
+    let mut options = AdaptiveOptions::default();
+    options.set_max_steps(3);
+    let solution = solve_laplace_adaptively(
+        LaplaceEquation::Hypersingular,
+        grid,
+        |_| 1.0,
+        None,
+        &options,
+        &comm,
+    );
+
+    assert_eq!(solution.history.len(), 3);
+    for step in &solution.history {
+        assert!(step.converged);
+        assert!(step.estimate.is_finite());
+    }
+    for (a, b) in solution.history.iter().zip(solution.history.iter().skip(1)) {
+        assert!(b.cells > a.cells);
+    }
+    // Only the 3 by 3 interior vertices of the initial grid have DOFs
+    assert_eq!(solution.history[0].dofs, 9);
+
+    // The solution is in the space of functions that are zero on the boundary of the screen
+    let p1 = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let zero_trace = FunctionSpace::new_zero_trace(&solution.grid, &p1);
+    assert_eq!(solution.coefficients.len(), zero_trace.global_size());
+    assert_eq!(solution.indicators.len(), solution.history[2].cells);
+}
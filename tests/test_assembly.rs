@@ -0,0 +1,407 @@
+use approx::*;
+use bempp::boundary_assemblers::{
+    adjoint_double_layer_from_double_layer, BoundaryAssemblerOptions, DenseAccumulation,
+};
+use bempp::error::BemppError;
+use bempp::function::{FunctionSpace, FunctionSpaceTrait};
+use bempp::shapes::{cube_quadrilaterals, multiple_spheres, regular_sphere};
+use bempp::{helmholtz, laplace};
+use cauchy::c64;
+use mpi::environment::Universe;
+use mpi::topology::SimpleCommunicator;
+use ndelement::ciarlet::{CiarletElement, LagrangeElementFamily};
+use ndelement::types::{Continuity, MapType, ReferenceCellType};
+use ndgrid::traits::{Builder, ParallelBuilder};
+use ndgrid::types::Ownership;
+use ndgrid::{ParallelGrid, SingleElementGrid, SingleElementGridBuilder};
+use num::Zero;
+use rlst::{RawAccess, RlstScalar, Shape};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+#[test]
+fn test_tiled_accumulation() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(2, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    options.set_batch_size(16);
+    let single_layer = laplace::assembler::single_layer(&options).assemble(&space, &space);
+    let double_layer = laplace::assembler::double_layer(&options).assemble(&space, &space);
+
+    options.set_dense_accumulation(DenseAccumulation::Tiled);
+    assert_eq!(options.get_dense_accumulation(), DenseAccumulation::Tiled);
+    let tiled_single_layer = laplace::assembler::single_layer(&options).assemble(&space, &space);
+    let tiled_double_layer = laplace::assembler::double_layer(&options).assemble(&space, &space);
+
+    for (a, b) in single_layer.data().iter().zip(tiled_single_layer.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-13);
+    }
+    for (a, b) in double_layer.data().iter().zip(tiled_double_layer.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-13);
+    }
+}
+
+#[test]
+fn test_tiled_accumulation_quadrilaterals() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = cube_quadrilaterals(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    let matrix = laplace::assembler::single_layer(&options).assemble(&space, &space);
+    options.set_dense_accumulation(DenseAccumulation::Tiled);
+    let tiled_matrix = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    for (a, b) in matrix.data().iter().zip(tiled_matrix.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-13);
+    }
+}
+
+#[test]
+fn test_singular_drop_tolerance() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    assert_eq!(options.get_singular_drop_tolerance(), None);
+    let matrix = laplace::assembler::single_layer(&options).assemble_singular(&space, &space);
+    assert!(!matrix.data().is_empty());
+
+    let tolerance = matrix.data().iter().map(|a| a.abs()).sum::<f64>() / matrix.data().len() as f64;
+    options.set_singular_drop_tolerance(Some(tolerance));
+    let dropped = laplace::assembler::single_layer(&options).assemble_singular(&space, &space);
+    assert!(dropped.data().len() < matrix.data().len());
+    assert_eq!(
+        dropped.data().len(),
+        matrix
+            .data()
+            .iter()
+            .filter(|a| a.abs() >= tolerance)
+            .count()
+    );
+}
+
+#[test]
+fn test_singular_part_of_small_grid() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    // The entries of the singular part are all much smaller than 1e-10 on this grid
+    let grid = multiple_spheres(0, 1, &[[0.0, 0.0, 0.0]], &[1e-4], &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let options = BoundaryAssemblerOptions::default();
+    let matrix = laplace::assembler::single_layer(&options).assemble_singular(&space, &space);
+    assert!(!matrix.data().is_empty());
+    for a in matrix.data() {
+        assert!(a.abs() < 1e-10);
+    }
+    assert!(matrix.data().iter().all(|a| *a > 0.0));
+}
+
+/// Check that two matrices are equal up to the accuracy of the singular quadrature
+fn check_close<T: RlstScalar>(a: &[T], b: &[T]) {
+    let scale = a
+        .iter()
+        .map(|x| x.abs())
+        .fold(T::Real::zero(), |m, x| if x > m { x } else { m });
+    for (x, y) in a.iter().zip(b) {
+        assert!((*x - *y).abs() < scale * num::cast::<f64, T::Real>(1e-4).unwrap());
+    }
+}
+
+#[test]
+fn test_symmetric_assembly() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    let single_layer = laplace::assembler::single_layer(&options).assemble(&space, &space);
+    let hypersingular = laplace::assembler::hypersingular(&options).assemble(&space, &space);
+    let double_layer = laplace::assembler::double_layer(&options).assemble(&space, &space);
+
+    options.set_symmetric_assembly(true);
+    assert!(options.get_symmetric_assembly());
+    let symmetric_single_layer =
+        laplace::assembler::single_layer(&options).assemble(&space, &space);
+    let symmetric_hypersingular =
+        laplace::assembler::hypersingular(&options).assemble(&space, &space);
+    let symmetric_double_layer =
+        laplace::assembler::double_layer(&options).assemble(&space, &space);
+
+    check_close(single_layer.data(), symmetric_single_layer.data());
+    check_close(hypersingular.data(), symmetric_hypersingular.data());
+    // The double layer operator is not symmetric, so is assembled in the same way
+    for (a, b) in double_layer
+        .data()
+        .iter()
+        .zip(symmetric_double_layer.data())
+    {
+        assert_eq!(a, b);
+    }
+
+    let n = symmetric_single_layer.shape()[0];
+    for i in 0..n {
+        for j in 0..n {
+            assert_eq!(
+                symmetric_single_layer.data()[i + n * j],
+                symmetric_single_layer.data()[j + n * i]
+            );
+        }
+    }
+
+    let singular = laplace::assembler::single_layer(&options).assemble_singular(&space, &space);
+    options.set_symmetric_assembly(false);
+    let nonsymmetric_singular =
+        laplace::assembler::single_layer(&options).assemble_singular(&space, &space);
+    assert_eq!(singular.data().len(), nonsymmetric_singular.data().len());
+    check_close(nonsymmetric_singular.data(), singular.data());
+}
+
+#[test]
+fn test_symmetric_assembly_into_nonzero_output() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    let matrix = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    // The values already in the output are not symmetric, and should not be mirrored
+    let initial = (0..matrix.data().len())
+        .map(|i| i as f64)
+        .collect::<Vec<_>>();
+    let mut output = initial.clone();
+    options.set_symmetric_assembly(true);
+    laplace::assembler::single_layer(&options).assemble_into_memory(&space, &space, &mut output);
+
+    let added = output
+        .iter()
+        .zip(&initial)
+        .map(|(a, b)| a - b)
+        .collect::<Vec<_>>();
+    check_close(matrix.data(), &added);
+}
+
+#[test]
+fn test_symmetric_assembly_helmholtz() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(1, 1, &comm);
+    let element = LagrangeElementFamily::<c64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    let matrix = helmholtz::assembler::hypersingular(2.0, &options).assemble(&space, &space);
+    options.set_symmetric_assembly(true);
+    let symmetric_matrix =
+        helmholtz::assembler::hypersingular(2.0, &options).assemble(&space, &space);
+
+    check_close(matrix.data(), symmetric_matrix.data());
+}
+
+#[test]
+fn test_adjoint_double_layer_from_double_layer() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(1, 1, &comm);
+    let element = LagrangeElementFamily::<c64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let options = BoundaryAssemblerOptions::default();
+    let double_layer = helmholtz::assembler::double_layer(2.0, &options).assemble(&space, &space);
+    let adjoint_double_layer =
+        helmholtz::assembler::adjoint_double_layer(2.0, &options).assemble(&space, &space);
+
+    check_close(
+        adjoint_double_layer.data(),
+        adjoint_double_layer_from_double_layer(&double_layer).data(),
+    );
+}
+
+/// A function space that wraps another function space, so that it has a different type
+struct WrappedSpace<Space: FunctionSpaceTrait>(Space);
+
+impl<Space: FunctionSpaceTrait> FunctionSpaceTrait for WrappedSpace<Space> {
+    type C = Space::C;
+    type T = Space::T;
+    type Grid = Space::Grid;
+    type FiniteElement = Space::FiniteElement;
+
+    fn comm(&self) -> &Self::C {
+        self.0.comm()
+    }
+    fn grid(&self) -> &Self::Grid {
+        self.0.grid()
+    }
+    fn element(&self, cell_type: ReferenceCellType) -> &Self::FiniteElement {
+        self.0.element(cell_type)
+    }
+    fn is_serial(&self) -> bool {
+        self.0.is_serial()
+    }
+    fn get_local_dof_numbers(&self, entity_dim: usize, entity_number: usize) -> &[usize] {
+        self.0.get_local_dof_numbers(entity_dim, entity_number)
+    }
+    fn local_size(&self) -> usize {
+        self.0.local_size()
+    }
+    fn global_size(&self) -> usize {
+        self.0.global_size()
+    }
+    fn cell_dofs(&self, cell: usize) -> Option<&[usize]> {
+        self.0.cell_dofs(cell)
+    }
+    unsafe fn cell_dofs_unchecked(&self, cell: usize) -> &[usize] {
+        self.0.cell_dofs_unchecked(cell)
+    }
+    fn cell_colouring(&self) -> Result<HashMap<ReferenceCellType, Vec<Vec<usize>>>, BemppError> {
+        self.0.cell_colouring()
+    }
+    fn global_dof_index(&self, local_dof_index: usize) -> usize {
+        self.0.global_dof_index(local_dof_index)
+    }
+    fn ownership(&self, local_dof_index: usize) -> Ownership {
+        self.0.ownership(local_dof_index)
+    }
+    fn reversed_normal(&self, cell: usize) -> bool {
+        self.0.reversed_normal(cell)
+    }
+    fn map_type(&self, cell_type: ReferenceCellType) -> MapType {
+        self.0.map_type(cell_type)
+    }
+    fn reversed_dofs(&self, cell: usize) -> &[bool] {
+        self.0.reversed_dofs(cell)
+    }
+    fn cell_degree(&self, cell: usize) -> Option<usize> {
+        self.0.cell_degree(cell)
+    }
+    fn cell_type_degrees(&self, cell_type: ReferenceCellType) -> Vec<Option<usize>> {
+        self.0.cell_type_degrees(cell_type)
+    }
+    fn element_with_degree(
+        &self,
+        cell_type: ReferenceCellType,
+        degree: Option<usize>,
+    ) -> &Self::FiniteElement {
+        self.0.element_with_degree(cell_type, degree)
+    }
+}
+
+#[test]
+fn test_different_space_types() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(1, 1, &comm);
+    let p0 = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let p1 = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let test_space = FunctionSpace::new(&grid, &p0);
+    let trial_space = FunctionSpace::new(&grid, &p1);
+    let wrapped_trial_space = WrappedSpace(FunctionSpace::new(&grid, &p1));
+
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = laplace::assembler::double_layer(&options);
+    let matrix = assembler.assemble(&trial_space, &test_space);
+    let wrapped_matrix = assembler.assemble(&wrapped_trial_space, &test_space);
+    assert_eq!(
+        wrapped_matrix.shape(),
+        [test_space.global_size(), trial_space.global_size()]
+    );
+    for (a, b) in matrix.data().iter().zip(wrapped_matrix.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-14);
+    }
+
+    let singular = assembler.assemble_singular(&trial_space, &test_space);
+    let wrapped_singular = assembler.assemble_singular(&wrapped_trial_space, &test_space);
+    assert_eq!(singular.data().len(), wrapped_singular.data().len());
+    for (a, b) in singular.data().iter().zip(wrapped_singular.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-14);
+    }
+}
+
+#[test]
+fn test_touching_grids() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid0 = regular_sphere(1, 1, &comm);
+    let grid1 = regular_sphere(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space0 = FunctionSpace::new(&grid0, &element);
+    let space1 = FunctionSpace::new(&grid1, &element);
+
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = laplace::assembler::single_layer(&options);
+    let matrix = assembler.assemble(&space0, &space0);
+    let two_grid_matrix = assembler.assemble(&space1, &space0);
+    for (a, b) in matrix.data().iter().zip(two_grid_matrix.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-12);
+    }
+}
+
+/// Create a grid of the unit square from two triangles
+fn square_grid(
+    cells: [[usize; 3]; 2],
+    comm: &SimpleCommunicator,
+) -> ParallelGrid<SimpleCommunicator, SingleElementGrid<f64, CiarletElement<f64>>> {
+    let mut b = SingleElementGridBuilder::<f64>::new(3, (ReferenceCellType::Triangle, 1));
+    b.add_point(0, &[0.0, 0.0, 0.0]);
+    b.add_point(1, &[1.0, 0.0, 0.0]);
+    b.add_point(2, &[0.0, 1.0, 0.0]);
+    b.add_point(3, &[1.0, 1.0, 0.0]);
+    for (i, c) in cells.iter().enumerate() {
+        b.add_cell(i, c);
+    }
+    b.create_parallel_grid_root(comm)
+}
+
+#[test]
+fn test_touching_grids_with_renumbered_vertices() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid0 = square_grid([[0, 1, 2], [1, 3, 2]], &comm);
+    // The same cells, with the vertices of each cell numbered differently
+    let grid1 = square_grid([[1, 2, 0], [3, 2, 1]], &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space0 = FunctionSpace::new(&grid0, &element);
+    let space1 = FunctionSpace::new(&grid1, &element);
+
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = laplace::assembler::single_layer(&options);
+    let matrix = assembler.assemble(&space0, &space0);
+    let two_grid_matrix = assembler.assemble(&space1, &space0);
+    // The coincident cells are integrated at the same points, but the rules for the edge-adjacent cells differ
+    for i in 0..2 {
+        assert_relative_eq!(
+            matrix.data()[3 * i],
+            two_grid_matrix.data()[3 * i],
+            epsilon = 1e-12
+        );
+    }
+    check_close(matrix.data(), two_grid_matrix.data());
+
+    let mut options = BoundaryAssemblerOptions::default();
+    options.set_coincidence_tolerance(0.0);
+    assert_eq!(options.get_coincidence_tolerance(), 0.0);
+    let singular = laplace::assembler::single_layer(&options).assemble_singular(&space1, &space0);
+    assert!(singular.data().is_empty());
+}
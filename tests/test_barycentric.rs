@@ -0,0 +1,136 @@
+use approx::*;
+use bempp::boundary_assemblers::BoundaryAssemblerOptions;
+use bempp::function::barycentric::{buffa_christiansen_space, dual0_space, refined_space};
+use bempp::function::{FunctionSpace, FunctionSpaceTrait};
+use bempp::laplace;
+use bempp::refinement::refine_barycentric;
+use bempp::shapes::regular_sphere;
+use mpi::environment::Universe;
+use ndelement::ciarlet::{LagrangeElementFamily, RaviartThomasElementFamily};
+use ndelement::types::{Continuity, ReferenceCellType};
+use ndgrid::traits::Grid;
+use rlst::RandomAccessByRef;
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+#[test]
+fn test_barycentric_refinement() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(0, 1, &comm);
+    let (refined, map) = refine_barycentric(&grid, None, &comm);
+
+    assert_eq!(refined.entity_count(ReferenceCellType::Triangle), 48);
+    assert_eq!(refined.entity_count(ReferenceCellType::Point), 6 + 12 + 8);
+    assert_eq!(refined.entity_count(ReferenceCellType::Interval), 72);
+    for cell in 0..map.coarse_cell_count() {
+        assert_eq!(map.children(cell).len(), 6);
+    }
+}
+
+#[test]
+fn test_dual0() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(0, 1, &comm);
+    let (refined, map) = refine_barycentric(&grid, None, &comm);
+
+    let p1 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    let dual0 = dual0_space(&p1, &refined, &map);
+    assert_eq!(dual0.global_size(), p1.global_size());
+
+    // Every vertex of the octahedron is adjacent to 4 triangles, so each dual cell contains 8 refined cells
+    let mut counts = vec![0.0; dual0.global_size()];
+    for dof in 0..dual0.space().global_size() {
+        assert_eq!(dual0.coefficients(dof).len(), 1);
+        for (i, c) in dual0.coefficients(dof) {
+            counts[*i] += c;
+        }
+    }
+    for c in counts {
+        assert_relative_eq!(c, 8.0);
+    }
+}
+
+#[test]
+fn test_refined_p1_partition_of_unity() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let (refined, map) = refine_barycentric(&grid, None, &comm);
+
+    let p1 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    let space = refined_space(&p1, &refined, &map, 1);
+    assert_eq!(space.global_size(), p1.global_size());
+    for dof in 0..space.space().global_size() {
+        let total = space.coefficients(dof).iter().map(|(_, c)| c).sum::<f64>();
+        assert_relative_eq!(total, 1.0, epsilon = 1e-12);
+    }
+}
+
+#[test]
+fn test_buffa_christiansen() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(0, 1, &comm);
+    let (refined, map) = refine_barycentric(&grid, None, &comm);
+
+    let rt = FunctionSpace::new(
+        &grid,
+        &RaviartThomasElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    let bc = buffa_christiansen_space(&rt, &refined, &map);
+    assert_eq!(bc.global_size(), rt.global_size());
+
+    // Every vertex of the octahedron is adjacent to 4 triangles, so each function is a combination of 6 edges around
+    // each vertex of its edge and the 2 edges of the dual edge
+    let mut counts = vec![0; bc.global_size()];
+    for dof in 0..bc.space().global_size() {
+        for (i, _) in bc.coefficients(dof) {
+            counts[*i] += 1;
+        }
+    }
+    for c in counts {
+        assert_eq!(c, 14);
+    }
+}
+
+#[test]
+fn test_mapped_assembly_dp0() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(0, 1, &comm);
+    let (refined, map) = refine_barycentric(&grid, None, &comm);
+
+    let dp0 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous),
+    );
+    let mapped_dp0 = refined_space(&dp0, &refined, &map, 0);
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = laplace::assembler::single_layer(&options);
+
+    let matrix = assembler.assemble(&dp0, &dp0);
+    let mapped_matrix = assembler.assemble_mapped(&mapped_dp0, &mapped_dp0);
+
+    for i in 0..dp0.global_size() {
+        for j in 0..dp0.global_size() {
+            assert_relative_eq!(
+                *matrix.get([i, j]).unwrap(),
+                *mapped_matrix.get([i, j]).unwrap(),
+                epsilon = 1e-3
+            );
+        }
+    }
+}
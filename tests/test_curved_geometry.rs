@@ -0,0 +1,166 @@
+use approx::*;
+use bempp::boundary_assemblers::BoundaryAssemblerOptions;
+use bempp::function::FunctionSpace;
+use bempp::laplace;
+use bempp::shapes::{cubed_sphere, regular_sphere};
+use mpi::environment::Universe;
+use mpi::topology::SimpleCommunicator;
+use ndelement::ciarlet::LagrangeElementFamily;
+use ndelement::types::{Continuity, ReferenceCellType};
+use ndgrid::traits::{Entity, GeometryMap, Grid, ParallelGrid};
+use rlst::RawAccess;
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+/// Check that the geometry of every cell of a grid passes through the given points on the unit sphere
+fn check_points_on_unit_sphere(
+    grid: &impl Grid<T = f64, EntityDescriptor = ReferenceCellType>,
+    cell_type: ReferenceCellType,
+    points: &[f64],
+) {
+    let evaluator = grid.geometry_map(cell_type, points);
+    let mut mapped_points = vec![0.0; 3 * points.len() / 2];
+    for cell in grid.entity_iter(2) {
+        evaluator.points(cell.local_index(), &mut mapped_points);
+        for p in mapped_points.chunks(3) {
+            assert_relative_eq!(p.iter().map(|x| x * x).sum::<f64>(), 1.0, epsilon = 1e-12);
+        }
+    }
+}
+
+/// The error when the Laplace single layer operator is used to compute the integral of 1 / (4 pi |x - y|) over a grid
+/// of the unit sphere
+///
+/// For the unit sphere, the exact value of this integral is 4 pi.
+fn single_layer_error<
+    G: ParallelGrid<SimpleCommunicator> + Grid<T = f64, EntityDescriptor = ReferenceCellType>,
+>(
+    grid: &G,
+    options: &BoundaryAssemblerOptions,
+) -> f64 {
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(grid, &element);
+    let matrix = laplace::assembler::single_layer(options).assemble(&space, &space);
+    (matrix.data().iter().sum::<f64>() - 4.0 * std::f64::consts::PI).abs()
+}
+
+#[test]
+fn test_curved_regular_sphere_points() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let third = 1.0 / 3.0;
+    for (degree, points) in [
+        (
+            2,
+            [
+                [0.0, 0.0],
+                [1.0, 0.0],
+                [0.0, 1.0],
+                [0.5, 0.5],
+                [0.0, 0.5],
+                [0.5, 0.0],
+            ]
+            .concat(),
+        ),
+        (
+            3,
+            [
+                [0.0, 0.0],
+                [1.0, 0.0],
+                [0.0, 1.0],
+                [2.0 * third, third],
+                [third, 2.0 * third],
+                [0.0, third],
+                [0.0, 2.0 * third],
+                [third, 0.0],
+                [2.0 * third, 0.0],
+                [third, third],
+            ]
+            .concat(),
+        ),
+    ] {
+        let grid = regular_sphere::<f64, _>(1, degree, &comm);
+        assert_eq!(grid.entity_count(ReferenceCellType::Triangle), 32);
+        check_points_on_unit_sphere(&grid, ReferenceCellType::Triangle, &points);
+    }
+}
+
+#[test]
+fn test_curved_cubed_sphere_points() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = cubed_sphere::<f64, _>(1, 2, &comm);
+    assert_eq!(grid.entity_count(ReferenceCellType::Quadrilateral), 24);
+    check_points_on_unit_sphere(
+        &grid,
+        ReferenceCellType::Quadrilateral,
+        &[
+            [0.0, 0.0],
+            [1.0, 0.0],
+            [0.0, 1.0],
+            [1.0, 1.0],
+            [0.5, 0.0],
+            [0.0, 0.5],
+            [1.0, 0.5],
+            [0.5, 1.0],
+            [0.5, 0.5],
+        ]
+        .concat(),
+    );
+}
+
+#[test]
+fn test_curved_triangles_convergence() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let options = BoundaryAssemblerOptions::accurate();
+
+    let flat_errors = (1..3)
+        .map(|level| single_layer_error(&regular_sphere(level, 1, &comm), &options))
+        .collect::<Vec<_>>();
+    let curved_errors = (1..3)
+        .map(|level| single_layer_error(&regular_sphere(level, 2, &comm), &options))
+        .collect::<Vec<_>>();
+
+    for (flat, curved) in flat_errors.iter().zip(&curved_errors) {
+        assert!(curved < flat);
+    }
+    // Curved cells are more accurate than flat cells with one more level of refinement
+    assert!(curved_errors[0] < flat_errors[1]);
+    assert!(curved_errors[1] < curved_errors[0]);
+}
+
+#[test]
+fn test_curved_quadrilaterals_convergence() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let options = BoundaryAssemblerOptions::accurate();
+
+    let flat_error = single_layer_error(&cubed_sphere(1, 1, &comm), &options);
+    let curved_error = single_layer_error(&cubed_sphere(1, 2, &comm), &options);
+    assert!(curved_error < flat_error);
+    assert!(curved_error < 1e-2);
+}
+
+#[test]
+fn test_curved_double_layer() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let options = BoundaryAssemblerOptions::accurate();
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+
+    // The double layer potential of a constant density is +/- 1/2 on the surface, so the integral of the double layer
+    // kernel over the unit sphere twice is +/- 2 pi
+    let errors = [1, 2].map(|degree| {
+        let grid = regular_sphere(1, degree, &comm);
+        let space = FunctionSpace::new(&grid, &element);
+        let matrix = laplace::assembler::double_layer(&options).assemble(&space, &space);
+        (matrix.data().iter().sum::<f64>().abs() - 2.0 * std::f64::consts::PI).abs()
+    });
+    assert!(errors[1] < errors[0]);
+}
@@ -0,0 +1,113 @@
+use approx::*;
+use bempp::boundary_assemblers::BoundaryAssemblerOptions;
+use bempp::custom::{self, ClosureBoundaryIntegrand, ClosureKernel};
+use bempp::error::BemppError;
+use bempp::function::FunctionSpace;
+use bempp::laplace;
+use bempp::shapes::regular_sphere;
+use mpi::environment::Universe;
+use ndelement::ciarlet::LagrangeElementFamily;
+use ndelement::types::Continuity;
+use rlst::RawAccess;
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+/// The Laplace Green's function and its gradient with respect to the second point
+fn laplace_kernel() -> ClosureKernel<f64> {
+    ClosureKernel::new(|x: &[f64], y: &[f64]| {
+        let r = ((x[0] - y[0]).powi(2) + (x[1] - y[1]).powi(2) + (x[2] - y[2]).powi(2)).sqrt();
+        1.0 / (4.0 * std::f64::consts::PI * r)
+    })
+    .with_gradient(|x: &[f64], y: &[f64]| {
+        let r = ((x[0] - y[0]).powi(2) + (x[1] - y[1]).powi(2) + (x[2] - y[2]).powi(2)).sqrt();
+        let c = 1.0 / (4.0 * std::f64::consts::PI * r.powi(3));
+        [c * (x[0] - y[0]), c * (x[1] - y[1]), c * (x[2] - y[2])]
+    })
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[test]
+fn test_custom_laplace_operators() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let space = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    let options = BoundaryAssemblerOptions::default();
+
+    let single_layer = custom::assembler::boundary_assembler(
+        ClosureBoundaryIntegrand::<f64>::new(|k, test, trial, _, _| k.value() * test.dot(trial)),
+        laplace_kernel(),
+        &options,
+    )
+    .assemble(&space, &space);
+    let expected = laplace::assembler::single_layer(&options).assemble(&space, &space);
+    for (a, b) in single_layer.data().iter().zip(expected.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-12);
+    }
+
+    let double_layer = custom::assembler::boundary_assembler(
+        ClosureBoundaryIntegrand::<f64>::new(|k, test, trial, _, trial_geometry| {
+            let g = k.gradient();
+            let n = trial_geometry.normal();
+            (g[0] * n[0] + g[1] * n[1] + g[2] * n[2]) * test.dot(trial)
+        })
+        .with_kernel_gradient(),
+        laplace_kernel(),
+        &options,
+    )
+    .assemble(&space, &space);
+    let expected = laplace::assembler::double_layer(&options).assemble(&space, &space);
+    for (a, b) in double_layer.data().iter().zip(expected.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-12);
+    }
+
+    let hypersingular = custom::assembler::boundary_assembler(
+        ClosureBoundaryIntegrand::<f64>::new(|k, test, trial, test_geometry, trial_geometry| {
+            let test_curl = cross(test_geometry.normal(), test.surface_gradient());
+            let trial_curl = cross(trial_geometry.normal(), trial.surface_gradient());
+            k.value()
+                * (test_curl[0] * trial_curl[0]
+                    + test_curl[1] * trial_curl[1]
+                    + test_curl[2] * trial_curl[2])
+        })
+        .with_basis_derivatives(),
+        laplace_kernel(),
+        &options,
+    )
+    .assemble(&space, &space);
+    let expected = laplace::assembler::hypersingular(&options).assemble(&space, &space);
+    for (a, b) in hypersingular.data().iter().zip(expected.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-12);
+    }
+}
+
+#[test]
+fn test_custom_kernel_without_gradient() {
+    let options = BoundaryAssemblerOptions::default();
+    let kernel = ClosureKernel::<f64>::new(|_: &[f64], _: &[f64]| 1.0);
+    assert!(!kernel.has_gradient());
+    assert!(matches!(
+        custom::assembler::try_boundary_assembler(
+            ClosureBoundaryIntegrand::<f64>::new(|k, _, _, _, _| k.gradient()[0])
+                .with_kernel_gradient(),
+            kernel,
+            &options,
+        ),
+        Err(BemppError::InvalidIntegrand(_))
+    ));
+}
@@ -0,0 +1,68 @@
+use bempp::boundary_assemblers::{BoundaryAssemblerOptions, QuadratureOptionsError};
+use bempp::error::BemppError;
+use bempp::function::{FunctionSpace, FunctionSpaceTrait};
+use bempp::laplace;
+use bempp::shapes::regular_sphere;
+use mpi::environment::Universe;
+use ndelement::ciarlet::LagrangeElementFamily;
+use ndelement::types::{Continuity, ReferenceCellType};
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+#[test]
+fn test_try_assemble() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(0, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::try_new(&grid, &element).unwrap();
+
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = laplace::assembler::single_layer(&options);
+    assert!(assembler.try_assemble(&space, &space).is_ok());
+    assert!(assembler.try_assemble_singular(&space, &space).is_ok());
+
+    let n = space.global_size();
+    let mut output = vec![0.0; n * n - 1];
+    assert_eq!(
+        assembler.try_assemble_into_memory(&space, &space, &mut output),
+        Err(BemppError::WrongShape {
+            expected: [n, n],
+            actual: [n * n - 1, 1]
+        })
+    );
+}
+
+#[test]
+fn test_try_assemble_with_missing_quadrature_degree() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(0, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    options
+        .singular_quadrature_degrees
+        .remove(&(ReferenceCellType::Triangle, ReferenceCellType::Triangle));
+    let assembler = laplace::assembler::single_layer(&options);
+
+    let error = assembler.try_assemble_singular(&space, &space).unwrap_err();
+    assert!(matches!(
+        error,
+        BemppError::Quadrature(QuadratureOptionsError::MissingSingularDegree(
+            ReferenceCellType::Triangle,
+            ReferenceCellType::Triangle,
+            _
+        ))
+    ));
+    assert!(assembler.try_assemble(&space, &space).is_err());
+    assert!(error
+        .to_string()
+        .starts_with("No singular quadrature degree set"));
+}
@@ -0,0 +1,298 @@
+use approx::*;
+use bempp::boundary_assemblers::BoundaryAssemblerOptions;
+use bempp::error::BemppError;
+use bempp::function::hp::lagrange_hp_space;
+use bempp::function::{FunctionSpace, FunctionSpaceTrait, MappedFunctionSpace};
+use bempp::laplace;
+use bempp::shapes::{regular_sphere, screen_triangles};
+use mpi::environment::Universe;
+use mpi::topology::SimpleCommunicator;
+use ndelement::ciarlet::{CiarletElement, LagrangeElementFamily};
+use ndelement::traits::FiniteElement;
+use ndelement::types::{Continuity, ReferenceCellType};
+use ndgrid::traits::{Entity, Grid, Topology};
+use ndgrid::{ParallelGrid, SingleElementGrid};
+use rlst::{
+    rlst_dynamic_array2, rlst_dynamic_array4, RandomAccessByRef, RandomAccessMut, RawAccess,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+type TestGrid = ParallelGrid<SimpleCommunicator, SingleElementGrid<f64, CiarletElement<f64>>>;
+
+#[test]
+fn test_hp_dof_counts() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = screen_triangles::<f64, _>(4, &comm);
+    let ncells = grid.entity_count(ReferenceCellType::Triangle);
+    let cell_degrees = (0..ncells).map(|c| 1 + c % 2).collect::<Vec<_>>();
+
+    let space = FunctionSpace::new_hp(
+        &grid,
+        |degree| LagrangeElementFamily::<f64>::new(degree, Continuity::Discontinuous),
+        &cell_degrees,
+    );
+    assert_eq!(
+        space.global_size(),
+        cell_degrees
+            .iter()
+            .map(|d| (d + 1) * (d + 2) / 2)
+            .sum::<usize>()
+    );
+
+    // The DOFs on each sub-entity are only shared between cells of the same degree
+    let space = FunctionSpace::new_hp(
+        &grid,
+        |degree| LagrangeElementFamily::<f64>::new(degree, Continuity::Standard),
+        &cell_degrees,
+    );
+    let mut vertices = HashSet::new();
+    let mut edges = HashSet::new();
+    for cell in grid.entity_iter(2) {
+        let degree = cell_degrees[cell.local_index()];
+        assert_eq!(space.cell_degree(cell.local_index()), Some(degree));
+        assert_eq!(
+            space.cell_dofs(cell.local_index()).unwrap().len(),
+            (degree + 1) * (degree + 2) / 2
+        );
+        for v in cell.topology().sub_entity_iter(0) {
+            vertices.insert((v, degree));
+        }
+        if degree == 2 {
+            for e in cell.topology().sub_entity_iter(1) {
+                edges.insert(e);
+            }
+        }
+    }
+    assert_eq!(space.global_size(), vertices.len() + edges.len());
+    assert_eq!(
+        space.cell_type_degrees(ReferenceCellType::Triangle),
+        vec![Some(1), Some(2)]
+    );
+    assert_eq!(
+        space
+            .element_with_degree(ReferenceCellType::Triangle, Some(1))
+            .dim(),
+        3
+    );
+    assert_eq!(space.element(ReferenceCellType::Triangle).dim(), 6);
+}
+
+#[test]
+fn test_hp_uniform_degree() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let ncells = grid.entity_count(ReferenceCellType::Triangle);
+
+    let family = LagrangeElementFamily::<f64>::new(2, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &family);
+    let hp_space = FunctionSpace::new_hp(
+        &grid,
+        |degree| LagrangeElementFamily::<f64>::new(degree, Continuity::Standard),
+        &vec![2; ncells],
+    );
+    assert_eq!(hp_space.global_size(), space.global_size());
+    assert_eq!(
+        hp_space.cell_type_degrees(ReferenceCellType::Triangle),
+        vec![Some(2)]
+    );
+    assert_eq!(
+        space.cell_type_degrees(ReferenceCellType::Triangle),
+        vec![None]
+    );
+}
+
+#[test]
+fn test_hp_invalid_degrees() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(0, 1, &comm);
+    let ncells = grid.entity_count(ReferenceCellType::Triangle);
+
+    assert!(matches!(
+        FunctionSpace::try_new_hp(
+            &grid,
+            |degree| LagrangeElementFamily::<f64>::new(degree, Continuity::Standard),
+            &vec![1; ncells + 1],
+        ),
+        Err(BemppError::InvalidCellDegrees(_))
+    ));
+}
+
+#[test]
+fn test_hp_assembly() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let ncells = grid.entity_count(ReferenceCellType::Triangle);
+    let cell_degrees = (0..ncells).map(|c| c % 2).collect::<Vec<_>>();
+
+    let hp_space = FunctionSpace::new_hp(
+        &grid,
+        |degree| LagrangeElementFamily::<f64>::new(degree, Continuity::Discontinuous),
+        &cell_degrees,
+    );
+    let family = LagrangeElementFamily::<f64>::new(1, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &family);
+
+    // Each DP1 basis function on a degree 1 cell is a basis function of the hp space, and the DP0 basis function on a
+    // degree 0 cell is the sum of the DP1 basis functions on the cell
+    let mut dp1_dofs = vec![vec![]; hp_space.global_size()];
+    for cell in 0..ncells {
+        let hp_dofs = hp_space.cell_dofs(cell).unwrap();
+        let dofs = space.cell_dofs(cell).unwrap();
+        if cell_degrees[cell] == 0 {
+            dp1_dofs[hp_dofs[0]].extend_from_slice(dofs);
+        } else {
+            for (hp_dof, dof) in hp_dofs.iter().zip(dofs) {
+                dp1_dofs[*hp_dof].push(*dof);
+            }
+        }
+    }
+
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = laplace::assembler::single_layer(&options);
+    let matrix = assembler.assemble(&space, &space);
+    let hp_matrix = assembler.assemble(&hp_space, &hp_space);
+
+    let n = space.global_size();
+    let hp_n = hp_space.global_size();
+    for j in 0..hp_n {
+        for i in 0..hp_n {
+            let expected = dp1_dofs[i]
+                .iter()
+                .map(|a| {
+                    dp1_dofs[j]
+                        .iter()
+                        .map(|b| matrix.data()[a + n * b])
+                        .sum::<f64>()
+                })
+                .sum::<f64>();
+            assert_relative_eq!(hp_matrix.data()[i + hp_n * j], expected, epsilon = 1e-12);
+        }
+    }
+}
+
+#[test]
+fn test_lagrange_hp_space_uniform_degree() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = screen_triangles::<f64, _>(4, &comm);
+    let ncells = grid.entity_count(ReferenceCellType::Triangle);
+
+    let family = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &family);
+    let hp_space = lagrange_hp_space::<_, f64, _>(&grid, &vec![1; ncells]);
+
+    assert_eq!(hp_space.global_size(), space.global_size());
+    for dof in 0..hp_space.space().global_size() {
+        assert_eq!(hp_space.coefficients(dof).len(), 1);
+    }
+}
+
+/// Evaluate the basis functions of a mapped space on a cell at a point on an edge of the cell
+///
+/// The point is `(1 - t) * v0 + t * v1`, where `v0` and `v1` are the vertices of the edge. The returned map contains
+/// the value of each basis function of the mapped space that is non-zero at the point.
+fn edge_values(
+    space: &MappedFunctionSpace<FunctionSpace<SimpleCommunicator, f64, TestGrid>>,
+    grid: &TestGrid,
+    cell: usize,
+    v0: usize,
+    v1: usize,
+    t: f64,
+) -> HashMap<usize, f64> {
+    let reference_vertices = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+    let vertices = grid
+        .entity(2, cell)
+        .unwrap()
+        .topology()
+        .sub_entity_iter(0)
+        .collect::<Vec<_>>();
+    let a = vertices.iter().position(|v| *v == v0).unwrap();
+    let b = vertices.iter().position(|v| *v == v1).unwrap();
+    let mut point = rlst_dynamic_array2!(f64, [2, 1]);
+    for j in 0..2 {
+        *point.get_mut([j, 0]).unwrap() =
+            (1.0 - t) * reference_vertices[a][j] + t * reference_vertices[b][j];
+    }
+
+    let element = space
+        .space()
+        .element_with_degree(ReferenceCellType::Triangle, space.space().cell_degree(cell));
+    let mut table = rlst_dynamic_array4!(f64, element.tabulate_array_shape(0, 1));
+    element.tabulate(&point, 0, &mut table);
+
+    let mut values = HashMap::new();
+    for (i, dof) in space.space().cell_dofs(cell).unwrap().iter().enumerate() {
+        let value = *table.get([0, 0, i, 0]).unwrap();
+        for (mapped_dof, c) in space.coefficients(space.space().global_dof_index(*dof)) {
+            *values.entry(*mapped_dof).or_insert(0.0) += c * value;
+        }
+    }
+    values.retain(|_, v| v.abs() > 1e-12);
+    values
+}
+
+#[test]
+fn test_lagrange_hp_space_continuity() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = screen_triangles::<f64, _>(4, &comm);
+    let ncells = grid.entity_count(ReferenceCellType::Triangle);
+    let cell_degrees = (0..ncells).map(|c| 1 + c % 3).collect::<Vec<_>>();
+    let space = lagrange_hp_space::<_, f64, _>(&grid, &cell_degrees);
+
+    let mut edge_cells = vec![vec![]; grid.entity_count(ReferenceCellType::Interval)];
+    for cell in grid.entity_iter(2) {
+        for e in cell.topology().sub_entity_iter(1) {
+            edge_cells[e].push(cell.local_index());
+        }
+    }
+
+    // The functions on each edge are polynomials of the lowest degree of the cells around the edge, and there is one
+    // interior DOF on each cell of degree 3
+    let nvertices = grid.entity_count(ReferenceCellType::Point);
+    let nedge_dofs = edge_cells
+        .iter()
+        .map(|cells| cells.iter().map(|c| cell_degrees[*c]).min().unwrap() - 1)
+        .sum::<usize>();
+    let ninterior_dofs = cell_degrees.iter().filter(|d| **d == 3).count();
+    assert_eq!(space.global_size(), nvertices + nedge_dofs + ninterior_dofs);
+
+    let mut nmixed = 0;
+    for (edge, cells) in edge_cells.iter().enumerate() {
+        if cells.len() != 2 {
+            continue;
+        }
+        if cell_degrees[cells[0]] != cell_degrees[cells[1]] {
+            nmixed += 1;
+        }
+        let vertices = grid
+            .entity(1, edge)
+            .unwrap()
+            .topology()
+            .sub_entity_iter(0)
+            .collect::<Vec<_>>();
+        for t in [0.0, 0.25, 0.6, 1.0] {
+            let values0 = edge_values(&space, &grid, cells[0], vertices[0], vertices[1], t);
+            let values1 = edge_values(&space, &grid, cells[1], vertices[0], vertices[1], t);
+            assert_eq!(
+                values0.keys().collect::<HashSet<_>>(),
+                values1.keys().collect::<HashSet<_>>()
+            );
+            for (dof, value) in &values0 {
+                assert_relative_eq!(*value, values1[dof], epsilon = 1e-12);
+            }
+        }
+    }
+    assert!(nmixed > 0);
+}
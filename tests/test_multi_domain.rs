@@ -0,0 +1,249 @@
+use approx::*;
+use bempp::boundary_assemblers::BoundaryAssemblerOptions;
+use bempp::error::BemppError;
+use bempp::function::{FunctionSpace, FunctionSpaceTrait};
+use bempp::multi_domain::MultiDomainGrid;
+use bempp::{helmholtz, laplace};
+use cauchy::c64;
+use mpi::environment::Universe;
+use mpi::topology::SimpleCommunicator;
+use ndelement::ciarlet::{CiarletElement, LagrangeElementFamily};
+use ndelement::types::{Continuity, ReferenceCellType};
+use ndgrid::traits::{Builder, ParallelBuilder};
+use ndgrid::{ParallelGrid, SingleElementGrid, SingleElementGridBuilder};
+use rlst::{RawAccess, RlstScalar};
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+/// The faces of the cubes [0, 1]^3 and [1, 2] x [0, 1]^2, and the subdomains separated by each face
+///
+/// The exterior is subdomain 0 and the two cubes are subdomains 1 and 2. The faces are not oriented consistently, so
+/// the normals to some faces point into the cubes.
+#[allow(clippy::type_complexity)]
+fn two_cubes(
+    comm: &SimpleCommunicator,
+) -> (
+    ParallelGrid<SimpleCommunicator, SingleElementGrid<f64, CiarletElement<f64>>>,
+    Vec<[usize; 2]>,
+) {
+    let point = |x: usize, y: usize, z: usize| x + 3 * y + 6 * z;
+    let mut cells = vec![];
+    for x in 0..3 {
+        cells.push([
+            point(x, 0, 0),
+            point(x, 1, 0),
+            point(x, 0, 1),
+            point(x, 1, 1),
+        ]);
+    }
+    for x in 0..2 {
+        for y in 0..2 {
+            cells.push([
+                point(x, y, 0),
+                point(x + 1, y, 0),
+                point(x, y, 1),
+                point(x + 1, y, 1),
+            ]);
+        }
+        for z in 0..2 {
+            cells.push([
+                point(x, 0, z),
+                point(x + 1, 0, z),
+                point(x, 1, z),
+                point(x + 1, 1, z),
+            ]);
+        }
+    }
+
+    let mut b = SingleElementGridBuilder::<f64>::new(3, (ReferenceCellType::Quadrilateral, 1));
+    let mut points = vec![[0.0; 3]; 12];
+    for z in 0..2 {
+        for y in 0..2 {
+            for x in 0..3 {
+                points[point(x, y, z)] = [x as f64, y as f64, z as f64];
+            }
+        }
+    }
+    for (i, p) in points.iter().enumerate() {
+        b.add_point(i, p);
+    }
+
+    let mut cell_domains = vec![];
+    for (i, c) in cells.iter().enumerate() {
+        b.add_cell(i, c);
+
+        let v = c.map(|v| points[v]);
+        let t0 = [0, 1, 2].map(|j| v[1][j] - v[0][j]);
+        let t1 = [0, 1, 2].map(|j| v[2][j] - v[0][j]);
+        let normal = [
+            t0[1] * t1[2] - t0[2] * t1[1],
+            t0[2] * t1[0] - t0[0] * t1[2],
+            t0[0] * t1[1] - t0[1] * t1[0],
+        ];
+        let midpoint = [0, 1, 2].map(|j| v.iter().map(|p| p[j]).sum::<f64>() / 4.0);
+        cell_domains.push(if midpoint[0] == 1.0 {
+            if normal[0] > 0.0 {
+                [1, 2]
+            } else {
+                [2, 1]
+            }
+        } else {
+            let cube = if midpoint[0] < 1.0 { 0 } else { 1 };
+            let centre = [cube as f64 + 0.5, 0.5, 0.5];
+            if (0..3)
+                .map(|j| normal[j] * (midpoint[j] - centre[j]))
+                .sum::<f64>()
+                > 0.0
+            {
+                [cube + 1, 0]
+            } else {
+                [0, cube + 1]
+            }
+        });
+    }
+    (b.create_parallel_grid_root(comm), cell_domains)
+}
+
+#[test]
+fn test_multi_domain_grid() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let (grid, cell_domains) = two_cubes(&comm);
+    let multi_domain_grid = MultiDomainGrid::new(&grid, cell_domains.clone());
+
+    assert_eq!(multi_domain_grid.domains(), vec![0, 1, 2]);
+    assert_eq!(multi_domain_grid.boundary_cells(0).len(), 10);
+    assert_eq!(multi_domain_grid.boundary_cells(1).len(), 6);
+    assert_eq!(multi_domain_grid.boundary_cells(2).len(), 6);
+    // The edges of the face between the two cubes are on the junction of all three subdomains
+    assert_eq!(multi_domain_grid.junction_edges().len(), 4);
+
+    assert!(matches!(
+        MultiDomainGrid::try_new(&grid, cell_domains[1..].to_vec()),
+        Err(BemppError::InvalidDomains(_))
+    ));
+    let mut invalid_domains = cell_domains.clone();
+    invalid_domains[0] = [1, 1];
+    assert!(matches!(
+        MultiDomainGrid::try_new(&grid, invalid_domains),
+        Err(BemppError::InvalidDomains(_))
+    ));
+
+    let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    for (domain, size) in [(0, 12), (1, 8), (2, 8)] {
+        let space = FunctionSpace::new_on_subdomain(&multi_domain_grid, &element, domain);
+        assert_eq!(space.global_size(), size);
+    }
+    assert!(matches!(
+        FunctionSpace::try_new_on_subdomain(&multi_domain_grid, &element, 3),
+        Err(BemppError::InvalidDomains(_))
+    ));
+}
+
+#[test]
+fn test_subdomain_normals() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let (grid, cell_domains) = two_cubes(&comm);
+    let multi_domain_grid = MultiDomainGrid::new(&grid, cell_domains);
+    let options = BoundaryAssemblerOptions::default();
+
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let mut row_sums = vec![];
+    for domain in 0..3 {
+        let cells = multi_domain_grid.boundary_cells(domain);
+        let space = FunctionSpace::new_on_subdomain(&multi_domain_grid, &element, domain);
+        let unoriented_space = FunctionSpace::new_on_cells(&grid, &element, &cells, false);
+        let n = space.global_size();
+
+        // Reversing the normal to a trial cell negates the double layer entries in its column
+        let assembler = laplace::assembler::double_layer(&options);
+        let matrix = assembler.assemble(&space, &space);
+        let unoriented_matrix = assembler.assemble(&unoriented_space, &unoriented_space);
+        for cell in &cells {
+            let j = space.cell_dofs(*cell).unwrap()[0];
+            let sign = if space.reversed_normal(*cell) {
+                -1.0
+            } else {
+                1.0
+            };
+            for i in 0..n {
+                assert_relative_eq!(
+                    matrix.data()[i + n * j],
+                    sign * unoriented_matrix.data()[i + n * j],
+                    epsilon = 1e-14
+                );
+            }
+        }
+
+        // Each face has area 1, so each row of the double layer sums to +-1/2 if the normals point out of the subdomain
+        row_sums.push(
+            (0..n)
+                .map(|i| (0..n).map(|j| matrix.data()[i + n * j]).sum::<f64>())
+                .collect::<Vec<_>>(),
+        );
+    }
+    let sign = row_sums[1][0].signum();
+    for s in &row_sums[1..] {
+        for value in s {
+            assert_relative_eq!(*value, 0.5 * sign, epsilon = 1e-2);
+        }
+    }
+    // The normals on the boundary of the exterior point into the cubes
+    for value in &row_sums[0] {
+        assert_relative_eq!(*value, -0.5 * sign, epsilon = 1e-2);
+    }
+}
+
+#[test]
+fn test_subdomain_hypersingular() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let (grid, cell_domains) = two_cubes(&comm);
+    let multi_domain_grid = MultiDomainGrid::new(&grid, cell_domains);
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = helmholtz::assembler::hypersingular::<c64>(2.0, &options);
+
+    // With a discontinuous space, each DOF is on a single cell
+    let element = LagrangeElementFamily::<c64>::new(1, Continuity::Discontinuous);
+    let cells = multi_domain_grid.boundary_cells(1);
+    let space = FunctionSpace::new_on_subdomain(&multi_domain_grid, &element, 1);
+    let unoriented_space = FunctionSpace::new_on_cells(&grid, &element, &cells, false);
+    let n = space.global_size();
+
+    let matrix = assembler.assemble(&space, &space);
+    let unoriented_matrix = assembler.assemble(&unoriented_space, &unoriented_space);
+
+    let mut signs = vec![0.0; n];
+    for cell in &cells {
+        for dof in space.cell_dofs(*cell).unwrap() {
+            signs[*dof] = if space.reversed_normal(*cell) {
+                -1.0
+            } else {
+                1.0
+            };
+        }
+    }
+    // Reversing the normal to a cell negates the normals and surface curls, so the hypersingular entries are
+    // multiplied by the signs of the test and trial cells
+    for j in 0..n {
+        for i in 0..n {
+            let expected = unoriented_matrix.data()[i + n * j] * c64::from(signs[i] * signs[j]);
+            assert_relative_eq!(
+                matrix.data()[i + n * j].re(),
+                expected.re(),
+                epsilon = 1e-14
+            );
+            assert_relative_eq!(
+                matrix.data()[i + n * j].im(),
+                expected.im(),
+                epsilon = 1e-14
+            );
+        }
+    }
+}
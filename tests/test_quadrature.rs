@@ -0,0 +1,399 @@
+use approx::*;
+use bempp::boundary_assemblers::{
+    automatic_singular_quadrature_degree, Adjacency, BoundaryAssemblerOptions, KernelType,
+    QuadratureOptionsError,
+};
+use bempp::function::{FunctionSpace, FunctionSpaceTrait};
+use bempp::shapes::{cube_quadrilaterals, multiple_spheres, regular_sphere};
+use bempp::{helmholtz, laplace};
+use cauchy::c64;
+use mpi::environment::Universe;
+use ndelement::ciarlet::LagrangeElementFamily;
+use ndelement::types::{Continuity, ReferenceCellType};
+use rlst::RawAccess;
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+/// The largest difference between two sets of matrix entries
+fn max_difference(a: &[f64], b: &[f64]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(i, j)| (i - j).abs())
+        .fold(0.0, f64::max)
+}
+
+#[test]
+fn test_distance_adaptive_quadrature_with_one_threshold() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+
+    // 37 points on a triangle integrate polynomials of degree 13
+    let mut options = BoundaryAssemblerOptions::default();
+    options.set_regular_quadrature_degree(ReferenceCellType::Triangle, 37);
+    let matrix = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    // If every pair is within the first threshold, the result should be unchanged
+    let mut adaptive_options = BoundaryAssemblerOptions::default();
+    adaptive_options.set_distance_adaptive_quadrature(true);
+    adaptive_options.set_distance_quadrature_thresholds(vec![(f64::INFINITY, 13)]);
+    let adaptive_matrix =
+        laplace::assembler::single_layer(&adaptive_options).assemble(&space, &space);
+
+    for (a, b) in matrix.data().iter().zip(adaptive_matrix.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-12);
+    }
+}
+
+#[test]
+fn test_distance_adaptive_quadrature_with_one_threshold_quadrilaterals() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = cube_quadrilaterals(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+
+    // 7 by 7 Gauss-Jacobi points on a quadrilateral integrate polynomials of degree 13
+    let mut options = BoundaryAssemblerOptions::default();
+    options.set_regular_quadrature_degree(ReferenceCellType::Quadrilateral, 49);
+    let matrix = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    let mut adaptive_options = BoundaryAssemblerOptions::default();
+    adaptive_options.set_distance_adaptive_quadrature(true);
+    adaptive_options.set_distance_quadrature_thresholds(vec![(f64::INFINITY, 13)]);
+    assert!(adaptive_options
+        .validate(&[ReferenceCellType::Quadrilateral])
+        .is_ok());
+    let adaptive_matrix =
+        laplace::assembler::single_layer(&adaptive_options).assemble(&space, &space);
+
+    for (a, b) in matrix.data().iter().zip(adaptive_matrix.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-12);
+    }
+}
+
+#[test]
+fn test_distance_adaptive_quadrature() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(2, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    options.set_regular_quadrature_degree(ReferenceCellType::Triangle, 79);
+    let reference = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    options.set_regular_quadrature_degree(ReferenceCellType::Triangle, 6);
+    let low_order = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    options.set_distance_adaptive_quadrature(true);
+    assert!(options.get_distance_adaptive_quadrature());
+    let adaptive = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    let low_order_error = max_difference(low_order.data(), reference.data());
+    let adaptive_error = max_difference(adaptive.data(), reference.data());
+    assert!(adaptive_error < low_order_error);
+}
+
+#[test]
+fn test_distance_adaptive_quadrature_quadrilaterals() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = cube_quadrilaterals(2, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    options.set_regular_quadrature_degree(ReferenceCellType::Quadrilateral, 121);
+    let reference = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    options.set_regular_quadrature_degree(ReferenceCellType::Quadrilateral, 4);
+    let low_order = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    // The default thresholds give valid rules on quadrilaterals
+    options.set_distance_adaptive_quadrature(true);
+    assert!(options
+        .validate(&[ReferenceCellType::Quadrilateral])
+        .is_ok());
+    let adaptive = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    let low_order_error = max_difference(low_order.data(), reference.data());
+    let adaptive_error = max_difference(adaptive.data(), reference.data());
+    assert!(adaptive_error < low_order_error);
+}
+
+#[test]
+fn test_near_field_subdivision() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    // Two spheres separated by a small gap
+    let grid = multiple_spheres(
+        1,
+        1,
+        &[[0.0, 0.0, 0.0], [2.02, 0.0, 0.0]],
+        &[1.0, 1.0],
+        &comm,
+    );
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+
+    // The reference uses a very high order rule on every pair of non-adjacent cells, including the pairs across the gap
+    let mut options = BoundaryAssemblerOptions::default();
+    options.set_regular_quadrature_degree(ReferenceCellType::Triangle, 171);
+    options.set_near_field_subdivision(false);
+    let reference = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    options.set_regular_quadrature_degree(ReferenceCellType::Triangle, 12);
+    options.set_near_field_subdivision(true);
+    let subdivided = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    options.set_near_field_subdivision(false);
+    let not_subdivided = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    let subdivided_error = max_difference(subdivided.data(), reference.data());
+    let not_subdivided_error = max_difference(not_subdivided.data(), reference.data());
+    assert!(not_subdivided_error > 0.0);
+    assert!(subdivided_error < not_subdivided_error);
+}
+
+#[test]
+fn test_mixed_precision() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(2, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    let matrix = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    // If no pairs are beyond the cutoff, the result should be unchanged
+    options.set_mixed_precision(true);
+    options.set_mixed_precision_cutoff(f64::INFINITY);
+    let unchanged = laplace::assembler::single_layer(&options).assemble(&space, &space);
+    for (a, b) in matrix.data().iter().zip(unchanged.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-14);
+    }
+
+    // Single precision kernels give an error close to single precision rounding for every pair beyond the cutoff
+    options.set_mixed_precision_cutoff(0.5);
+    let mixed = laplace::assembler::single_layer(&options).assemble(&space, &space);
+    let scale = matrix.data().iter().map(|a| a.abs()).fold(0.0, f64::max);
+    let error = max_difference(mixed.data(), matrix.data());
+    assert!(error > 0.0);
+    assert!(error < 1e-5 * scale);
+}
+
+#[test]
+fn test_mixed_precision_helmholtz() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(2, 1, &comm);
+    let element = LagrangeElementFamily::<c64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    let matrix = helmholtz::assembler::single_layer(3.0, &options).assemble(&space, &space);
+    options.set_mixed_precision(true);
+    options.set_mixed_precision_cutoff(0.5);
+    let mixed = helmholtz::assembler::single_layer(3.0, &options).assemble(&space, &space);
+
+    let scale = matrix.data().iter().map(|a| a.norm()).fold(0.0, f64::max);
+    let error = matrix
+        .data()
+        .iter()
+        .zip(mixed.data())
+        .map(|(a, b)| (a - b).norm())
+        .fold(0.0, f64::max);
+    assert!(error > 0.0);
+    assert!(error < 1e-5 * scale);
+}
+
+#[test]
+fn test_automatic_singular_quadrature_degree() {
+    for degree in 0..4 {
+        let vertex = automatic_singular_quadrature_degree(Adjacency::Vertex, degree, 1);
+        let edge = automatic_singular_quadrature_degree(Adjacency::Edge, degree, 1);
+        let coincident = automatic_singular_quadrature_degree(Adjacency::Coincident, degree, 1);
+        assert!(vertex < edge);
+        assert!(edge < coincident);
+        assert!(
+            automatic_singular_quadrature_degree(Adjacency::Coincident, degree, 2) >= coincident
+        );
+    }
+    assert_eq!(
+        automatic_singular_quadrature_degree(Adjacency::Coincident, 0, 1),
+        4
+    );
+}
+
+#[test]
+fn test_adjacency_quadrature_degrees() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+    let cell_types = (ReferenceCellType::Triangle, ReferenceCellType::Triangle);
+
+    let options = BoundaryAssemblerOptions::default();
+    let matrix = laplace::assembler::single_layer(&options).assemble_singular(&space, &space);
+
+    // Setting the degree for each adjacency to the default degree should not change the result
+    let mut adjacency_options = BoundaryAssemblerOptions::default();
+    for adjacency in [Adjacency::Vertex, Adjacency::Edge, Adjacency::Coincident] {
+        assert_eq!(
+            adjacency_options.get_adjacency_quadrature_degree(cell_types, adjacency),
+            Some(4)
+        );
+        adjacency_options.set_adjacency_quadrature_degree(cell_types, adjacency, 4);
+    }
+    let adjacency_matrix =
+        laplace::assembler::single_layer(&adjacency_options).assemble_singular(&space, &space);
+    for (a, b) in matrix.data().iter().zip(adjacency_matrix.data()) {
+        assert_relative_eq!(a, b, epsilon = 1e-12);
+    }
+
+    adjacency_options.set_adjacency_quadrature_degree(cell_types, Adjacency::Vertex, 2);
+    assert_eq!(
+        adjacency_options.get_adjacency_quadrature_degree(cell_types, Adjacency::Vertex),
+        Some(2)
+    );
+
+    // Automatically chosen degrees should give similar results to the defaults
+    let mut automatic_options = BoundaryAssemblerOptions::default();
+    automatic_options.set_automatic_singular_quadrature(true);
+    let automatic_matrix =
+        laplace::assembler::single_layer(&automatic_options).assemble_singular(&space, &space);
+    for (a, b) in matrix.data().iter().zip(automatic_matrix.data()) {
+        assert_relative_eq!(a, b, max_relative = 1e-2);
+    }
+}
+
+#[test]
+fn test_quadrature_presets() {
+    let fast = BoundaryAssemblerOptions::fast();
+    let default = BoundaryAssemblerOptions::default();
+    let accurate = BoundaryAssemblerOptions::accurate();
+    for cell_type in [
+        ReferenceCellType::Triangle,
+        ReferenceCellType::Quadrilateral,
+    ] {
+        let fast_degree = fast.get_regular_quadrature_degree(cell_type).unwrap();
+        let default_degree = default.get_regular_quadrature_degree(cell_type).unwrap();
+        let accurate_degree = accurate.get_regular_quadrature_degree(cell_type).unwrap();
+        assert!(fast_degree < default_degree);
+        assert!(default_degree < accurate_degree);
+    }
+}
+
+#[test]
+fn test_quadrature_for_accuracy() {
+    let triangle = ReferenceCellType::Triangle;
+    let cell_types = (triangle, triangle);
+
+    let low = BoundaryAssemblerOptions::for_accuracy(1e-3, 0, 0, 1, KernelType::Laplace).unwrap();
+    let high = BoundaryAssemblerOptions::for_accuracy(1e-8, 0, 0, 1, KernelType::Laplace).unwrap();
+    let high_degree =
+        BoundaryAssemblerOptions::for_accuracy(1e-8, 2, 2, 2, KernelType::Laplace).unwrap();
+    let helmholtz = BoundaryAssemblerOptions::for_accuracy(
+        1e-8,
+        0,
+        0,
+        1,
+        KernelType::Helmholtz {
+            wavenumber_times_mesh_size: 5.0,
+        },
+    )
+    .unwrap();
+
+    let regular = |o: &BoundaryAssemblerOptions| o.get_regular_quadrature_degree(triangle).unwrap();
+    let singular =
+        |o: &BoundaryAssemblerOptions, a| o.get_adjacency_quadrature_degree(cell_types, a).unwrap();
+
+    assert!(regular(&low) < regular(&high));
+    assert!(regular(&high) < regular(&high_degree));
+    assert!(regular(&high) < regular(&helmholtz));
+    assert!(singular(&low, Adjacency::Coincident) < singular(&high, Adjacency::Coincident));
+    assert!(singular(&high, Adjacency::Coincident) < singular(&helmholtz, Adjacency::Coincident));
+    for options in [&low, &high, &high_degree, &helmholtz] {
+        assert!(singular(options, Adjacency::Vertex) < singular(options, Adjacency::Coincident));
+        assert!(options.validate(&[triangle]).is_ok());
+    }
+
+    for tolerance in [0.0, 1.0, -1.0, f64::NAN] {
+        assert!(matches!(
+            BoundaryAssemblerOptions::for_accuracy(tolerance, 0, 0, 1, KernelType::Laplace),
+            Err(QuadratureOptionsError::InvalidTolerance(_))
+        ));
+    }
+}
+
+#[test]
+fn test_missing_quadrature_degree() {
+    let mut options = BoundaryAssemblerOptions::default();
+    options
+        .quadrature_degrees
+        .remove(&ReferenceCellType::Triangle);
+    assert_eq!(
+        options.validate(&[ReferenceCellType::Triangle]),
+        Err(QuadratureOptionsError::MissingRegularDegree(
+            ReferenceCellType::Triangle
+        ))
+    );
+    assert!(options
+        .validate(&[ReferenceCellType::Quadrilateral])
+        .is_ok());
+
+    // Setting the degree adds the missing cell type
+    options.set_regular_quadrature_degree(ReferenceCellType::Triangle, 12);
+    assert!(options.validate(&[ReferenceCellType::Triangle]).is_ok());
+}
+
+#[test]
+fn test_invalid_regular_point_count() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = cube_quadrilaterals(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+
+    // There is no Gauss-Jacobi rule with 37 points on a quadrilateral
+    let mut options = BoundaryAssemblerOptions::default();
+    options.set_regular_quadrature_degree(ReferenceCellType::Quadrilateral, 37);
+    assert_eq!(
+        options.validate(&[ReferenceCellType::Quadrilateral]),
+        Err(QuadratureOptionsError::InvalidPointCount(
+            ReferenceCellType::Quadrilateral,
+            37
+        ))
+    );
+    assert!(options.validate(&[ReferenceCellType::Triangle]).is_ok());
+
+    let assembler = laplace::assembler::single_layer(&options);
+    assert!(assembler.try_assemble(&space, &space).is_err());
+}
+
+#[test]
+#[should_panic(expected = "No regular quadrature degree set for cell type Triangle")]
+fn test_assemble_with_missing_quadrature_degree() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere(0, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+
+    let mut options = BoundaryAssemblerOptions::default();
+    options
+        .quadrature_degrees
+        .remove(&ReferenceCellType::Triangle);
+    laplace::assembler::single_layer(&options).assemble(&space, &space);
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use approx::*;
+use bempp::error::BemppError;
+use bempp::refinement::{refine_marked, refine_uniformly, try_refine_marked, RefinementMap};
+use bempp::shapes::{cube_quadrilaterals, regular_sphere, screen_triangles};
+use common::euler_characteristic;
+use ndelement::types::ReferenceCellType;
+use ndgrid::traits::{Entity, Geometry, GeometryMap, Grid};
+use std::sync::LazyLock;
+
+use mpi::environment::Universe;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+fn check_map(map: &RefinementMap<f64>) {
+    let mut n = 0;
+    for cell in 0..map.coarse_cell_count() {
+        for child in map.children(cell) {
+            assert_eq!(map.parent(*child), cell);
+            n += 1;
+        }
+    }
+    assert_eq!(n, map.fine_cell_count());
+}
+
+#[test]
+fn test_uniform_refinement_triangles() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let (refined, map) = refine_uniformly(&grid, None, &comm);
+
+    assert_eq!(
+        refined.entity_count(ReferenceCellType::Triangle),
+        4 * grid.entity_count(ReferenceCellType::Triangle)
+    );
+    assert_eq!(
+        refined.entity_count(ReferenceCellType::Point),
+        grid.entity_count(ReferenceCellType::Point)
+            + grid.entity_count(ReferenceCellType::Interval)
+    );
+    assert_eq!(euler_characteristic(&refined), 2);
+    check_map(&map);
+    for cell in 0..map.coarse_cell_count() {
+        assert_eq!(map.children(cell).len(), 4);
+    }
+}
+
+#[test]
+fn test_uniform_refinement_with_projection() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(0, 1, &comm);
+    let project = |p: &mut [f64; 3]| {
+        let size = p.iter().map(|x| x * x).sum::<f64>().sqrt();
+        for x in p.iter_mut() {
+            *x /= size;
+        }
+    };
+    let (refined, map) = refine_uniformly(&grid, Some(&project), &comm);
+    let (refined, _) = refine_uniformly(&refined, Some(&project), &comm);
+
+    // Projected uniform refinement of the octahedron gives the same grid as regular_sphere
+    let sphere = regular_sphere::<f64, _>(2, 1, &comm);
+    assert_eq!(
+        refined.entity_count(ReferenceCellType::Triangle),
+        sphere.entity_count(ReferenceCellType::Triangle)
+    );
+    assert_eq!(
+        refined.entity_count(ReferenceCellType::Point),
+        sphere.entity_count(ReferenceCellType::Point)
+    );
+    check_map(&map);
+}
+
+#[test]
+fn test_uniform_refinement_quadrilaterals() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = cube_quadrilaterals::<f64, _>(1, 1, &comm);
+    let (refined, map) = refine_uniformly(&grid, None, &comm);
+
+    assert_eq!(
+        refined.entity_count(ReferenceCellType::Quadrilateral),
+        4 * grid.entity_count(ReferenceCellType::Quadrilateral)
+    );
+    assert_eq!(euler_characteristic(&refined), 2);
+    check_map(&map);
+}
+
+#[test]
+fn test_marked_refinement() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let (refined, map) = refine_marked(&grid, &[0, 5], None, &comm);
+
+    assert!(
+        refined.entity_count(ReferenceCellType::Triangle)
+            > grid.entity_count(ReferenceCellType::Triangle)
+    );
+    assert!(
+        refined.entity_count(ReferenceCellType::Triangle)
+            < 4 * grid.entity_count(ReferenceCellType::Triangle)
+    );
+    // A conforming refinement of a sphere has Euler characteristic 2
+    assert_eq!(euler_characteristic(&refined), 2);
+    check_map(&map);
+    assert_eq!(map.children(0).len(), 4);
+    assert_eq!(map.children(5).len(), 4);
+}
+
+#[test]
+fn test_marked_refinement_screen() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = screen_triangles::<f64, _>(4, &comm);
+    let (refined, map) = refine_marked(&grid, &[0], None, &comm);
+    let (refined2, map2) = refine_marked(&refined, &map.children(0)[..1], None, &comm);
+
+    // A conforming refinement of a square has Euler characteristic 1
+    assert_eq!(euler_characteristic(&refined), 1);
+    assert_eq!(euler_characteristic(&refined2), 1);
+    check_map(&map);
+    check_map(&map2);
+}
+
+#[test]
+fn test_marked_refinement_quadrilaterals() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = cube_quadrilaterals::<f64, _>(0, 1, &comm);
+
+    assert!(matches!(
+        try_refine_marked(&grid, &[0], None, &comm),
+        Err(BemppError::InvalidGrid(_))
+    ));
+}
+
+#[test]
+fn test_uniform_refinement_curved() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(0, 2, &comm);
+    let (refined, map) = refine_uniformly(&grid, None, &comm);
+    check_map(&map);
+
+    // The refined grid keeps the geometry degree, and each refined cell is on the geometry of its parent
+    let point = [0.2, 0.3];
+    let evaluator = refined.geometry_map(ReferenceCellType::Triangle, &point);
+    let mut fine_point = [0.0; 3];
+    let mut coarse_point = [0.0; 3];
+    for cell in refined.entity_iter(2) {
+        assert_eq!(cell.geometry().degree(), 2);
+        evaluator.points(cell.local_index(), &mut fine_point);
+        let parent_point = map.parent_reference_point(cell.local_index(), &point);
+        grid.geometry_map(ReferenceCellType::Triangle, &parent_point)
+            .points(map.parent(cell.local_index()), &mut coarse_point);
+        for (a, b) in fine_point.iter().zip(&coarse_point) {
+            assert_relative_eq!(*a, *b, epsilon = 1e-12);
+        }
+    }
+}
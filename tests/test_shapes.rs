@@ -0,0 +1,259 @@
+mod common;
+
+use approx::*;
+use bempp::shapes::{
+    cube, cube_quadrilaterals, cubed_sphere, cylinder, ellipsoid, multiple_spheres, regular_sphere,
+    torus,
+};
+use common::euler_characteristic;
+use ndelement::types::ReferenceCellType;
+use ndgrid::traits::{Entity, GeometryMap, Grid};
+use std::sync::LazyLock;
+
+use mpi::environment::Universe;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+/// The vertices and edge midpoints of a triangle
+const TRIANGLE_POINTS: [f64; 12] = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.5, 0.5, 0.0, 0.5, 0.5, 0.0];
+/// The vertices of a quadrilateral
+const QUADRILATERAL_POINTS: [f64; 8] = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+
+/// Map the given reference points onto each cell of a grid
+fn cell_points(
+    grid: &impl Grid<T = f64, EntityDescriptor = ReferenceCellType>,
+    cell_type: ReferenceCellType,
+    points: &[f64],
+) -> Vec<Vec<[f64; 3]>> {
+    let evaluator = grid.geometry_map(cell_type, points);
+    let mut mapped_points = vec![0.0; 3 * points.len() / 2];
+    grid.entity_iter(2)
+        .map(|cell| {
+            evaluator.points(cell.local_index(), &mut mapped_points);
+            mapped_points
+                .chunks(3)
+                .map(|p| [p[0], p[1], p[2]])
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_regular_sphere() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    for level in 0..3 {
+        let grid = regular_sphere::<f64, _>(level, 1, &comm);
+        assert_eq!(
+            grid.entity_count(ReferenceCellType::Triangle),
+            8 * usize::pow(4, level)
+        );
+        assert_eq!(euler_characteristic(&grid), 2);
+    }
+}
+
+#[test]
+fn test_cube() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    for level in 0..3 {
+        let grid = cube::<f64, _>(level, 1, &comm);
+        assert_eq!(
+            grid.entity_count(ReferenceCellType::Triangle),
+            12 * usize::pow(4, level)
+        );
+        assert_eq!(euler_characteristic(&grid), 2);
+
+        // Every point is on the surface of the unit cube
+        for points in cell_points(&grid, ReferenceCellType::Triangle, &TRIANGLE_POINTS) {
+            for p in points {
+                assert!(p.iter().all(|x| (-1e-12..=1.0 + 1e-12).contains(x)));
+                assert!(p.iter().any(|x| x.abs() < 1e-12 || (x - 1.0).abs() < 1e-12));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_cube_quadrilaterals() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    for level in 0..3 {
+        let grid = cube_quadrilaterals::<f64, _>(level, 1, &comm);
+        assert_eq!(
+            grid.entity_count(ReferenceCellType::Quadrilateral),
+            6 * usize::pow(4, level)
+        );
+        assert_eq!(euler_characteristic(&grid), 2);
+
+        // Every cell is a square on a face of the unit cube
+        let h = 1.0 / usize::pow(2, level) as f64;
+        for points in cell_points(
+            &grid,
+            ReferenceCellType::Quadrilateral,
+            &QUADRILATERAL_POINTS,
+        ) {
+            let face = (0..3)
+                .find(|i| points.iter().all(|p| p[*i].abs() < 1e-12))
+                .or_else(|| (0..3).find(|i| points.iter().all(|p| (p[*i] - 1.0).abs() < 1e-12)));
+            assert!(face.is_some());
+            for p in &points {
+                assert!(p.iter().all(|x| (-1e-12..=1.0 + 1e-12).contains(x)));
+            }
+            for (a, b) in [(0, 1), (0, 2), (1, 3), (2, 3)] {
+                let length = (0..3)
+                    .map(|i| (points[a][i] - points[b][i]).powi(2))
+                    .sum::<f64>()
+                    .sqrt();
+                assert_relative_eq!(length, h, epsilon = 1e-12);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_cubed_sphere() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    for level in 0..3 {
+        let grid = cubed_sphere::<f64, _>(level, 1, &comm);
+        assert_eq!(
+            grid.entity_count(ReferenceCellType::Quadrilateral),
+            6 * usize::pow(4, level)
+        );
+        assert_eq!(euler_characteristic(&grid), 2);
+
+        // Every vertex is on the unit sphere
+        for points in cell_points(
+            &grid,
+            ReferenceCellType::Quadrilateral,
+            &QUADRILATERAL_POINTS,
+        ) {
+            for p in points {
+                assert_relative_eq!(p.iter().map(|x| x * x).sum::<f64>(), 1.0, epsilon = 1e-12);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ellipsoid() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let semi_axes = [1.0, 2.0, 0.5];
+    let grid = ellipsoid::<f64, _>(2, 1, semi_axes, &comm);
+    assert_eq!(grid.entity_count(ReferenceCellType::Triangle), 128);
+    assert_eq!(euler_characteristic(&grid), 2);
+
+    // Every point of a curved grid is on the ellipsoid, and the grid reaches the end of each semi-axis
+    let grid = ellipsoid::<f64, _>(2, 2, semi_axes, &comm);
+    let points = cell_points(&grid, ReferenceCellType::Triangle, &TRIANGLE_POINTS).concat();
+    for p in &points {
+        assert_relative_eq!(
+            (0..3).map(|i| (p[i] / semi_axes[i]).powi(2)).sum::<f64>(),
+            1.0,
+            epsilon = 1e-12
+        );
+    }
+    for (i, a) in semi_axes.iter().enumerate() {
+        let extent = points.iter().map(|p| p[i]).fold(0.0, f64::max);
+        assert_relative_eq!(extent, *a, epsilon = 1e-12);
+    }
+}
+
+#[test]
+fn test_cylinder() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    for level in 0..3 {
+        let [radius, height] = [0.5, 2.0];
+        let grid = cylinder::<f64, _>(level, 1, radius, height, &comm);
+        assert_eq!(euler_characteristic(&grid), 2);
+
+        // Every vertex is on the side of the cylinder or on one of its caps, and every cell is on a single one of
+        // these surfaces
+        for points in cell_points(&grid, ReferenceCellType::Triangle, &TRIANGLE_POINTS[..6]) {
+            let on_side = |p: &[f64; 3]| {
+                (p[0].hypot(p[1]) - radius).abs() < 1e-12
+                    && (-1e-12..=height + 1e-12).contains(&p[2])
+            };
+            let on_cap = |p: &[f64; 3], z: f64| {
+                (p[2] - z).abs() < 1e-12 && p[0].hypot(p[1]) <= radius + 1e-12
+            };
+            assert!(
+                points.iter().all(on_side)
+                    || points.iter().all(|p| on_cap(p, 0.0))
+                    || points.iter().all(|p| on_cap(p, height))
+            );
+        }
+    }
+}
+
+#[test]
+fn test_torus() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    for level in 0..3 {
+        let [major_radius, minor_radius] = [1.0, 0.25];
+        let grid = torus::<f64, _>(level, 1, major_radius, minor_radius, &comm);
+        assert_eq!(euler_characteristic(&grid), 0);
+
+        // Every point of a curved grid is on the torus
+        let grid = torus::<f64, _>(level, 2, major_radius, minor_radius, &comm);
+        for points in cell_points(&grid, ReferenceCellType::Triangle, &TRIANGLE_POINTS) {
+            for p in points {
+                assert_relative_eq!(
+                    (p[0].hypot(p[1]) - major_radius).hypot(p[2]),
+                    minor_radius,
+                    epsilon = 1e-12
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_multiple_spheres() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let centres = [[0.0, 0.0, 0.0], [3.0, 0.0, 0.0], [0.0, 0.0, 2.5]];
+    let radii = [1.0, 1.5, 0.5];
+    let grid = multiple_spheres::<f64, _>(1, 1, &centres, &radii, &comm);
+    assert_eq!(grid.entity_count(ReferenceCellType::Triangle), 3 * 32);
+    assert_eq!(euler_characteristic(&grid), 6);
+
+    // Every point of a curved grid is on one of the spheres, and each sphere contains the same number of cells
+    let grid = multiple_spheres::<f64, _>(1, 2, &centres, &radii, &comm);
+    let mut cell_counts = [0; 3];
+    for points in cell_points(&grid, ReferenceCellType::Triangle, &TRIANGLE_POINTS) {
+        let on_sphere = |p: &[f64; 3], i: usize| {
+            let distance = (0..3)
+                .map(|j| (p[j] - centres[i][j]).powi(2))
+                .sum::<f64>()
+                .sqrt();
+            (distance - radii[i]).abs() < 1e-12
+        };
+        let sphere = (0..3).find(|i| points.iter().all(|p| on_sphere(p, *i)));
+        assert!(sphere.is_some());
+        cell_counts[sphere.unwrap()] += 1;
+    }
+    assert_eq!(cell_counts, [32; 3]);
+}
+
+#[test]
+#[should_panic]
+fn test_multiple_spheres_overlapping() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let _grid = multiple_spheres::<f64, _>(
+        0,
+        1,
+        &[[0.0, 0.0, 0.0], [1.5, 0.0, 0.0]],
+        &[1.0, 1.0],
+        &comm,
+    );
+}
@@ -0,0 +1,199 @@
+use approx::*;
+use bempp::boundary_assemblers::BoundaryAssemblerOptions;
+use bempp::function::{FunctionSpace, FunctionSpaceTrait};
+use bempp::solvers::{
+    bicgstab, cg, gmres, LinearOperator, MatrixFreeOperator, SolverOptions, SumOperator,
+};
+use bempp::{helmholtz, laplace};
+use cauchy::c64;
+use mpi::environment::Universe;
+use ndelement::ciarlet::LagrangeElementFamily;
+use ndelement::types::Continuity;
+use num::{One, Zero};
+use rlst::{rlst_dynamic_array2, CsrMatrix, RandomAccessMut, RlstScalar};
+use std::cell::Cell;
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+/// Check that x solves Ax = b
+fn check_solution<T: RlstScalar>(
+    operator: &impl LinearOperator<T = T>,
+    x: &[T],
+    b: &[T],
+    tol: f64,
+) {
+    let mut ax = vec![T::zero(); b.len()];
+    operator.apply(x, &mut ax);
+    let error = ax
+        .iter()
+        .zip(b)
+        .map(|(i, j)| (*i - *j).abs() * (*i - *j).abs())
+        .fold(T::Real::zero(), |a, b| a + b)
+        .sqrt();
+    let b_norm = b
+        .iter()
+        .map(|i| i.abs() * i.abs())
+        .fold(T::Real::zero(), |a, b| a + b)
+        .sqrt();
+    assert!(num::cast::<T::Real, f64>(error / b_norm).unwrap() < tol);
+}
+
+/// The inverse of the diagonal of a square matrix, as a sparse matrix
+fn jacobi<T: RlstScalar>(operator: &impl LinearOperator<T = T>) -> CsrMatrix<T> {
+    let n = operator.shape()[0];
+    let mut diagonal = vec![];
+    for i in 0..n {
+        let mut e = vec![T::zero(); n];
+        let mut column = vec![T::zero(); n];
+        e[i] = T::one();
+        operator.apply(&e, &mut column);
+        diagonal.push(T::one() / column[i]);
+    }
+    CsrMatrix::from_aij(
+        [n, n],
+        &(0..n).collect::<Vec<_>>(),
+        &(0..n).collect::<Vec<_>>(),
+        &diagonal,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_cg() {
+    let n = 20;
+    let mut matrix = rlst_dynamic_array2!(f64, [n, n]);
+    for i in 0..n {
+        *matrix.get_mut([i, i]).unwrap() = 2.0;
+        if i > 0 {
+            *matrix.get_mut([i, i - 1]).unwrap() = -1.0;
+            *matrix.get_mut([i - 1, i]).unwrap() = -1.0;
+        }
+    }
+    let rhs = (0..n).map(|i| i as f64).collect::<Vec<_>>();
+    let mut solution = vec![0.0; n];
+    let mut options = SolverOptions::default();
+    options.set_tolerance(1e-10);
+
+    let result = cg(&matrix, &rhs, &mut solution, &options);
+    assert!(result.converged);
+    assert!(result.iterations <= n);
+    assert_eq!(result.residuals.len(), result.iterations + 1);
+    check_solution(&matrix, &solution, &rhs, 1e-9);
+}
+
+#[test]
+fn test_gmres_laplace() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = bempp::shapes::regular_sphere(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+    let options = BoundaryAssemblerOptions::default();
+    let matrix = laplace::assembler::single_layer(&options).assemble(&space, &space);
+
+    let rhs = vec![1.0; space.global_size()];
+    let mut solution = vec![0.0; space.global_size()];
+    let iterations = Cell::new(0);
+    let callback = |i: usize, _residual: f64| iterations.set(i);
+    let mut solver_options = SolverOptions::default();
+    solver_options.set_tolerance(1e-8);
+    solver_options.set_restart(5);
+    solver_options.set_callback(&callback);
+
+    let result = gmres(&matrix, &rhs, &mut solution, &solver_options);
+    assert!(result.converged);
+    assert_eq!(iterations.get(), result.iterations);
+    check_solution(&matrix, &solution, &rhs, 1e-7);
+
+    // Solve again with a Jacobi preconditioner on each side
+    let preconditioner = jacobi(&matrix);
+    for left in [true, false] {
+        let mut solution = vec![0.0; space.global_size()];
+        let mut solver_options = SolverOptions::default();
+        solver_options.set_tolerance(1e-8);
+        if left {
+            solver_options.set_left_preconditioner(&preconditioner);
+        } else {
+            solver_options.set_right_preconditioner(&preconditioner);
+        }
+        let result = gmres(&matrix, &rhs, &mut solution, &solver_options);
+        assert!(result.converged);
+        check_solution(&matrix, &solution, &rhs, 1e-6);
+    }
+}
+
+#[test]
+fn test_complex_solvers_helmholtz() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = bempp::shapes::regular_sphere(1, 1, &comm);
+    let element = LagrangeElementFamily::<c64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+    let options = BoundaryAssemblerOptions::default();
+    let matrix = helmholtz::assembler::single_layer(2.0, &options).assemble(&space, &space);
+
+    let rhs = (0..space.global_size())
+        .map(|i| c64::new(1.0, i as f64 / 10.0))
+        .collect::<Vec<_>>();
+    let mut solver_options = SolverOptions::default();
+    solver_options.set_tolerance(1e-8);
+
+    let mut solution = vec![c64::new(0.0, 0.0); space.global_size()];
+    let result = gmres(&matrix, &rhs, &mut solution, &solver_options);
+    assert!(result.converged);
+    check_solution(&matrix, &solution, &rhs, 1e-7);
+
+    let preconditioner = jacobi(&matrix);
+    solver_options.set_right_preconditioner(&preconditioner);
+    let mut solution = vec![c64::new(0.0, 0.0); space.global_size()];
+    let result = bicgstab(&matrix, &rhs, &mut solution, &solver_options);
+    assert!(result.converged);
+    check_solution(&matrix, &solution, &rhs, 1e-7);
+}
+
+#[test]
+fn test_sum_and_matrix_free_operators() {
+    let n = 10;
+    let mut dense = rlst_dynamic_array2!(f64, [n, n]);
+    for i in 0..n {
+        for j in 0..n {
+            *dense.get_mut([i, j]).unwrap() = 1.0 / (1.0 + i as f64 + j as f64);
+        }
+    }
+    let sparse = CsrMatrix::from_aij(
+        [n, n],
+        &(0..n).collect::<Vec<_>>(),
+        &(0..n).collect::<Vec<_>>(),
+        &vec![3.0; n],
+    )
+    .unwrap();
+    let sum = SumOperator::new(&dense, &sparse);
+    let matrix_free = MatrixFreeOperator::new([n, n], |x: &[f64], y: &mut [f64]| {
+        dense.apply(x, y);
+        for (y_i, x_i) in y.iter_mut().zip(x) {
+            *y_i += 3.0 * x_i;
+        }
+    });
+
+    let rhs = (0..n).map(|i| 1.0 + i as f64).collect::<Vec<_>>();
+    let mut options = SolverOptions::default();
+    options.set_tolerance(1e-10);
+
+    let mut solution0 = vec![0.0; n];
+    assert!(gmres(&sum, &rhs, &mut solution0, &options).converged);
+    check_solution(&sum, &solution0, &rhs, 1e-9);
+
+    let mut solution1 = vec![0.0; n];
+    assert!(bicgstab(&matrix_free, &rhs, &mut solution1, &options).converged);
+    let mut solution2 = vec![0.0; n];
+    assert!(cg(&matrix_free, &rhs, &mut solution2, &options).converged);
+    for ((a, b), c) in solution0.iter().zip(&solution1).zip(&solution2) {
+        assert_relative_eq!(a, b, epsilon = 1e-8);
+        assert_relative_eq!(a, c, epsilon = 1e-8);
+    }
+}
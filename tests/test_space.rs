@@ -1,8 +1,15 @@
+use approx::*;
+use bempp::boundary_assemblers::BoundaryAssemblerOptions;
+use bempp::error::BemppError;
 use bempp::function::{assign_dofs, FunctionSpace, FunctionSpaceTrait};
-use bempp::shapes::{regular_sphere, screen_triangles};
+use bempp::laplace;
+use bempp::shapes::{regular_sphere, screen_quadrilaterals, screen_triangles};
 use ndelement::ciarlet::{LagrangeElementFamily, RaviartThomasElementFamily};
+use ndelement::traits::FiniteElement;
 use ndelement::types::{Continuity, ReferenceCellType};
 use ndgrid::traits::{Entity, Grid, Topology};
+use rlst::RawAccess;
+use std::collections::{HashMap, HashSet};
 use std::sync::LazyLock;
 
 use mpi::environment::Universe;
@@ -19,7 +26,7 @@ fn run_test(
     continuity: Continuity,
 ) {
     let family = LagrangeElementFamily::<f64>::new(degree, continuity);
-    let (cell_dofs, entity_dofs, size, owner_data) = assign_dofs(0, grid, &family);
+    let (cell_dofs, entity_dofs, size, owner_data) = assign_dofs(0, grid, &family).unwrap();
 
     for o in &owner_data {
         assert_eq!(o.0, 0);
@@ -47,7 +54,7 @@ fn run_test_rt(
     continuity: Continuity,
 ) {
     let family = RaviartThomasElementFamily::<f64>::new(degree, continuity);
-    let (cell_dofs, entity_dofs, size, owner_data) = assign_dofs(0, grid, &family);
+    let (cell_dofs, entity_dofs, size, owner_data) = assign_dofs(0, grid, &family).unwrap();
 
     for o in &owner_data {
         assert_eq!(o.0, 0);
@@ -185,7 +192,7 @@ fn test_colouring_p1() {
     let grid = regular_sphere::<f64, _>(2, 1, &comm);
     let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
     let space = FunctionSpace::new(&grid, &element);
-    let colouring = &space.cell_colouring()[&ReferenceCellType::Triangle];
+    let colouring = &space.cell_colouring().unwrap()[&ReferenceCellType::Triangle];
     let cells = grid.entity_iter(2).collect::<Vec<_>>();
     let mut n = 0;
     for i in colouring {
@@ -225,7 +232,7 @@ fn test_colouring_dp0() {
     let grid = regular_sphere::<f64, _>(2, 1, &comm);
     let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
     let space = FunctionSpace::new(&grid, &element);
-    let colouring = &space.cell_colouring()[&ReferenceCellType::Triangle];
+    let colouring = &space.cell_colouring().unwrap()[&ReferenceCellType::Triangle];
     let mut n = 0;
     for i in colouring {
         n += i.len()
@@ -252,7 +259,7 @@ fn test_colouring_rt1() {
     let grid = regular_sphere::<f64, _>(2, 1, &comm);
     let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
     let space = FunctionSpace::new(&grid, &element);
-    let colouring = &space.cell_colouring()[&ReferenceCellType::Triangle];
+    let colouring = &space.cell_colouring().unwrap()[&ReferenceCellType::Triangle];
     let mut n = 0;
     for i in colouring {
         n += i.len()
@@ -316,3 +323,288 @@ fn test_p3_mixed() {
     run_test(&grid, 3, Continuity::Standard);
 }
 */
+
+/// The numbers of vertices and edges of a subset of cells, and the numbers of these on the boundary of the subset
+fn subset_entity_counts(
+    grid: &impl Grid<T = f64, EntityDescriptor = ReferenceCellType>,
+    cells: &[usize],
+) -> ([usize; 2], [usize; 2]) {
+    let mut vertices = HashSet::new();
+    let mut edge_counts = HashMap::new();
+    for cell in cells {
+        let cell = grid.entity(2, *cell).unwrap();
+        vertices.extend(cell.topology().sub_entity_iter(0));
+        for e in cell.topology().sub_entity_iter(1) {
+            *edge_counts.entry(e).or_insert(0) += 1;
+        }
+    }
+    let mut boundary_vertices = HashSet::new();
+    let mut boundary_edges = 0;
+    for (e, count) in &edge_counts {
+        if *count == 1 {
+            boundary_edges += 1;
+            boundary_vertices.extend(grid.entity(1, *e).unwrap().topology().sub_entity_iter(0));
+        }
+    }
+    (
+        [vertices.len(), edge_counts.len()],
+        [boundary_vertices.len(), boundary_edges],
+    )
+}
+
+#[test]
+fn test_subset_space() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(2, 1, &comm);
+    let ncells = grid.entity_count(ReferenceCellType::Triangle);
+    let cells = (0..ncells / 2).collect::<Vec<_>>();
+    let ([nvertices, nedges], [nboundary_vertices, nboundary_edges]) =
+        subset_entity_counts(&grid, &cells);
+    assert!(nboundary_edges > 0);
+
+    for (degree, exclude_boundary, size) in [
+        (1, false, nvertices),
+        (1, true, nvertices - nboundary_vertices),
+        (2, false, nvertices + nedges),
+        (
+            2,
+            true,
+            nvertices + nedges - nboundary_vertices - nboundary_edges,
+        ),
+    ] {
+        let element = LagrangeElementFamily::<f64>::new(degree, Continuity::Standard);
+        let space = FunctionSpace::new_on_cells(&grid, &element, &cells, exclude_boundary);
+        assert_eq!(space.global_size(), size);
+
+        for cell in 0..ncells {
+            let dofs = space.cell_dofs(cell).unwrap();
+            let excluded = space.excluded_dofs(cell);
+            assert!(exclude_boundary || excluded.is_empty());
+            if cell < ncells / 2 {
+                assert_eq!(dofs.len(), space.element(ReferenceCellType::Triangle).dim());
+                for (i, dof) in dofs.iter().enumerate() {
+                    assert!(excluded.get(i) == Some(&true) || *dof < size);
+                }
+            } else {
+                assert!(dofs.is_empty());
+            }
+        }
+        let coloured = space.cell_colouring().unwrap()[&ReferenceCellType::Triangle]
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<HashSet<_>>();
+        assert_eq!(coloured, cells.iter().copied().collect::<HashSet<_>>());
+    }
+}
+
+#[test]
+fn test_tagged_space() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(2, 1, &comm);
+    let ncells = grid.entity_count(ReferenceCellType::Triangle);
+    let tags = (0..ncells).map(|i| i % 3).collect::<Vec<_>>();
+    let cells = (0..ncells).filter(|i| i % 3 == 1).collect::<Vec<_>>();
+
+    let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let tagged_space = FunctionSpace::new_on_tagged_cells(&grid, &element, &tags, 1, false);
+    let space = FunctionSpace::new_on_cells(&grid, &element, &cells, false);
+    assert_eq!(tagged_space.global_size(), space.global_size());
+    for cell in 0..ncells {
+        assert_eq!(tagged_space.cell_dofs(cell), space.cell_dofs(cell));
+    }
+
+    assert!(matches!(
+        FunctionSpace::try_new_on_tagged_cells(&grid, &element, &tags[1..], 1, false),
+        Err(BemppError::InvalidCellSubset(_))
+    ));
+    assert!(matches!(
+        FunctionSpace::try_new_on_cells(&grid, &element, &[ncells], false),
+        Err(BemppError::InvalidCellSubset(_))
+    ));
+}
+
+#[test]
+fn test_subset_space_assembly() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let ncells = grid.entity_count(ReferenceCellType::Triangle);
+    let cells = (0..ncells).filter(|i| i % 2 == 0).collect::<Vec<_>>();
+
+    let element = LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous);
+    let space = FunctionSpace::new(&grid, &element);
+    let subset_space = FunctionSpace::new_on_cells(&grid, &element, &cells, false);
+    assert_eq!(subset_space.global_size(), cells.len());
+
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = laplace::assembler::single_layer(&options);
+    let matrix = assembler.assemble(&space, &space);
+    let subset_matrix = assembler.assemble(&subset_space, &space);
+
+    // The DOF of each cell in the subset is the column of the matrix on the full grid for that cell
+    for (j, cell) in cells.iter().enumerate() {
+        let dof = space.cell_dofs(*cell).unwrap()[0];
+        let subset_dof = subset_space.cell_dofs(*cell).unwrap()[0];
+        assert_eq!(subset_dof, j);
+        for i in 0..ncells {
+            assert_relative_eq!(
+                subset_matrix.data()[i + ncells * subset_dof],
+                matrix.data()[i + ncells * dof],
+                epsilon = 1e-14
+            );
+        }
+    }
+}
+
+#[test]
+fn test_zero_trace_dofmap() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = screen_triangles::<f64, _>(4, &comm);
+    let family = LagrangeElementFamily::<f64>::new(2, Continuity::Standard);
+    let space = FunctionSpace::new_zero_trace(&grid, &family);
+    let size = space.global_size();
+
+    // The 4 * 4 boundary vertices and edges have no DOFs
+    for (dim, cell_type) in [
+        (0, ReferenceCellType::Point),
+        (1, ReferenceCellType::Interval),
+    ] {
+        assert_eq!(
+            (0..grid.entity_count(cell_type))
+                .filter(|e| space.get_local_dof_numbers(dim, *e).is_empty())
+                .count(),
+            16
+        );
+    }
+    let mut nexcluded = 0;
+    for cell in 0..grid.entity_count(ReferenceCellType::Triangle) {
+        let dofs = space.cell_dofs(cell).unwrap();
+        let excluded = space.excluded_dofs(cell);
+        assert!(excluded.is_empty() || excluded.len() == dofs.len());
+        let included = dofs
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| excluded.get(*i) != Some(&true))
+            .map(|(_, n)| *n)
+            .collect::<Vec<_>>();
+        nexcluded += dofs.len() - included.len();
+        for (i, n) in included.iter().enumerate() {
+            assert!(*n < size);
+            for m in included.iter().skip(i + 1) {
+                assert!(*n != *m);
+            }
+        }
+    }
+    assert!(nexcluded > 0);
+}
+
+#[test]
+fn test_zero_trace_space() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let n = 4;
+    for (grid, cell_type) in [
+        (
+            screen_triangles::<f64, _>(n, &comm),
+            ReferenceCellType::Triangle,
+        ),
+        (
+            screen_quadrilaterals::<f64, _>(n, &comm),
+            ReferenceCellType::Quadrilateral,
+        ),
+    ] {
+        let ncells = grid.entity_count(cell_type);
+        let nvertices = grid.entity_count(ReferenceCellType::Point);
+        let nedges = grid.entity_count(ReferenceCellType::Interval);
+        let ninterior = if cell_type == ReferenceCellType::Quadrilateral {
+            ncells
+        } else {
+            0
+        };
+
+        // A screen made of n * n squares has 4n vertices and 4n edges on its boundary
+        for (degree, size) in [
+            (1, nvertices - 4 * n),
+            (2, nvertices + nedges + ninterior - 8 * n),
+        ] {
+            let element = LagrangeElementFamily::<f64>::new(degree, Continuity::Standard);
+            let space = FunctionSpace::new_zero_trace(&grid, &element);
+            assert_eq!(space.global_size(), size);
+            assert_eq!(
+                FunctionSpace::new(&grid, &element).global_size(),
+                size + 4 * n * degree
+            );
+            for cell in 0..ncells {
+                let dofs = space.cell_dofs(cell).unwrap();
+                let excluded = space.excluded_dofs(cell);
+                assert_eq!(dofs.len(), space.element(cell_type).dim());
+                for (i, dof) in dofs.iter().enumerate() {
+                    assert!(excluded.get(i) == Some(&true) || *dof < size);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_zero_trace_space_closed_surface() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let element = LagrangeElementFamily::<f64>::new(2, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+    let zero_trace_space = FunctionSpace::new_zero_trace(&grid, &element);
+    assert_eq!(zero_trace_space.global_size(), space.global_size());
+    for cell in 0..grid.entity_count(ReferenceCellType::Triangle) {
+        assert_eq!(zero_trace_space.cell_dofs(cell), space.cell_dofs(cell));
+        assert!(zero_trace_space.excluded_dofs(cell).is_empty());
+    }
+}
+
+#[test]
+fn test_zero_trace_space_assembly() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = screen_triangles::<f64, _>(4, &comm);
+    let element = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let space = FunctionSpace::new(&grid, &element);
+    let zero_trace_space = FunctionSpace::new_zero_trace(&grid, &element);
+    let size = space.global_size();
+    let zero_trace_size = zero_trace_space.global_size();
+
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = laplace::assembler::hypersingular(&options);
+    let matrix = assembler.assemble(&space, &space);
+    let zero_trace_matrix = assembler.assemble(&zero_trace_space, &zero_trace_space);
+
+    // The matrix for the zero-trace space is the block of the full matrix for the interior vertices
+    let mut dof_map = vec![None; zero_trace_size];
+    for cell in 0..grid.entity_count(ReferenceCellType::Triangle) {
+        let excluded = zero_trace_space.excluded_dofs(cell);
+        for (i, (dof, zero_trace_dof)) in space
+            .cell_dofs(cell)
+            .unwrap()
+            .iter()
+            .zip(zero_trace_space.cell_dofs(cell).unwrap())
+            .enumerate()
+        {
+            if excluded.get(i) != Some(&true) {
+                dof_map[*zero_trace_dof] = Some(*dof);
+            }
+        }
+    }
+    let dof_map = dof_map.iter().map(|d| d.unwrap()).collect::<Vec<_>>();
+    for (i, dof_i) in dof_map.iter().enumerate() {
+        for (j, dof_j) in dof_map.iter().enumerate() {
+            assert_relative_eq!(
+                zero_trace_matrix.data()[i + zero_trace_size * j],
+                matrix.data()[dof_i + size * dof_j],
+                epsilon = 1e-14
+            );
+        }
+    }
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use approx::*;
+use bempp::boundary_assemblers::BoundaryAssemblerOptions;
+use bempp::error::BemppError;
+use bempp::function::{FunctionSpace, FunctionSpaceTrait};
+use bempp::shapes::regular_sphere;
+use bempp::sparse;
+use common::to_dense;
+use mpi::environment::Universe;
+use ndelement::ciarlet::{LagrangeElementFamily, RaviartThomasElementFamily};
+use ndelement::types::Continuity;
+use rlst::Shape;
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+#[test]
+fn test_mass_matrix() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let p1 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    let dp0 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous),
+    );
+
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = sparse::assembler::mass(&options);
+    let p1_mass = to_dense(&assembler.assemble(&p1, &p1));
+    let dp0_mass = to_dense(&assembler.assemble(&dp0, &dp0));
+    let mixed_mass = to_dense(&assembler.assemble(&dp0, &p1));
+
+    // The DP0 mass matrix is diagonal, and the sum of the entries of each mass matrix is the area of the surface
+    let n = p1.global_size();
+    let m = dp0.global_size();
+    let area = (0..m).map(|i| dp0_mass[i + m * i]).sum::<f64>();
+    assert_relative_eq!(dp0_mass.iter().sum::<f64>(), area, epsilon = 1e-12);
+    assert_relative_eq!(p1_mass.iter().sum::<f64>(), area, epsilon = 1e-12);
+
+    // The P1 and DP0 basis functions are both partitions of unity
+    for i in 0..n {
+        let p1_row = (0..n).map(|j| p1_mass[i + n * j]).sum::<f64>();
+        let mixed_row = (0..m).map(|j| mixed_mass[i + n * j]).sum::<f64>();
+        assert_relative_eq!(p1_row, mixed_row, epsilon = 1e-12);
+        for j in 0..n {
+            assert_relative_eq!(p1_mass[i + n * j], p1_mass[j + n * i], epsilon = 1e-14);
+        }
+    }
+}
+
+#[test]
+fn test_laplace_beltrami() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let space = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(2, Continuity::Standard),
+    );
+
+    let options = BoundaryAssemblerOptions::default();
+    let matrix = to_dense(&sparse::assembler::laplace_beltrami(&options).assemble(&space, &space));
+
+    // The surface gradient of a constant function is zero
+    let n = space.global_size();
+    for i in 0..n {
+        assert_abs_diff_eq!(
+            (0..n).map(|j| matrix[i + n * j]).sum::<f64>(),
+            0.0,
+            epsilon = 1e-12
+        );
+        assert!(matrix[i + n * i] > 0.0);
+        for j in 0..n {
+            assert_relative_eq!(matrix[i + n * j], matrix[j + n * i], epsilon = 1e-12);
+        }
+    }
+}
+
+#[test]
+fn test_surface_divergence() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let rt = FunctionSpace::new(
+        &grid,
+        &RaviartThomasElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    let dp0 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous),
+    );
+
+    let options = BoundaryAssemblerOptions::default();
+    let matrix = sparse::assembler::surface_divergence(&options).assemble(&dp0, &rt);
+    assert_eq!(matrix.shape(), [rt.global_size(), dp0.global_size()]);
+    let matrix = to_dense(&matrix);
+
+    // Each RT function is supported on the two cells next to its edge, and the flux out of one cell is the flux into the
+    // other, so the integral of its divergence over the closed surface is zero
+    let n = rt.global_size();
+    let m = dp0.global_size();
+    for i in 0..n {
+        let row = (0..m).map(|j| matrix[i + n * j]).collect::<Vec<_>>();
+        assert_eq!(row.iter().filter(|a| a.abs() > 1e-10).count(), 2);
+        assert_abs_diff_eq!(row.iter().sum::<f64>(), 0.0, epsilon = 1e-12);
+    }
+
+    // The divergence operator can only be used with H(div) test functions and scalar-valued trial functions
+    assert!(matches!(
+        sparse::assembler::surface_divergence(&options).try_assemble(&rt, &dp0),
+        Err(BemppError::IncompatibleSpaces(_))
+    ));
+    assert!(matches!(
+        sparse::assembler::laplace_beltrami(&options).try_assemble(&rt, &rt),
+        Err(BemppError::IncompatibleSpaces(_))
+    ));
+}
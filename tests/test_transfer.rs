@@ -0,0 +1,241 @@
+mod common;
+
+use approx::*;
+use bempp::error::BemppError;
+use bempp::function::transfer::{
+    interpolation_matrix, prolongation_matrix, restriction_matrix, try_interpolation_matrix,
+};
+use bempp::function::{FunctionSpace, FunctionSpaceTrait};
+use bempp::refinement::refine_uniformly;
+use bempp::shapes::{regular_sphere, screen_triangles};
+use common::to_dense;
+use mpi::environment::Universe;
+use ndelement::ciarlet::LagrangeElementFamily;
+use ndelement::types::Continuity;
+use rlst::Shape;
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+#[test]
+fn test_interpolation_p1_to_dp0() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let p1 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    let dp0 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(0, Continuity::Discontinuous),
+    );
+
+    let matrix = interpolation_matrix(&p1, &dp0);
+    assert_eq!(matrix.shape(), [dp0.global_size(), p1.global_size()]);
+
+    // The value of a P1 function at the midpoint of a triangle is the mean of its values at the vertices
+    for row in 0..dp0.global_size() {
+        let entries = &matrix.data()[matrix.indptr()[row]..matrix.indptr()[row + 1]];
+        assert_eq!(entries.len(), 3);
+        for e in entries {
+            assert_relative_eq!(*e, 1.0 / 3.0, epsilon = 1e-12);
+        }
+    }
+}
+
+#[test]
+fn test_averaging_dp1_to_p1() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let p1 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    let dp1 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(1, Continuity::Discontinuous),
+    );
+
+    let averaging = to_dense(&interpolation_matrix(&dp1, &p1));
+    let interpolation = to_dense(&interpolation_matrix(&p1, &dp1));
+
+    // Averaging the DP1 representation of a P1 function gives the original function
+    let n = p1.global_size();
+    let m = dp1.global_size();
+    for i in 0..n {
+        for j in 0..n {
+            let entry = (0..m)
+                .map(|k| averaging[i + n * k] * interpolation[k + m * j])
+                .sum::<f64>();
+            assert_abs_diff_eq!(entry, if i == j { 1.0 } else { 0.0 }, epsilon = 1e-12);
+        }
+    }
+}
+
+#[test]
+fn test_interpolation_degree_3() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let p2 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(2, Continuity::Standard),
+    );
+    let p3 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(3, Continuity::Standard),
+    );
+    let dp3 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(3, Continuity::Discontinuous),
+    );
+
+    // Interpolating a space into itself gives the identity
+    let identity = to_dense(&interpolation_matrix(&p3, &p3));
+    let n = p3.global_size();
+    for i in 0..n {
+        for j in 0..n {
+            assert_abs_diff_eq!(
+                identity[i + n * j],
+                if i == j { 1.0 } else { 0.0 },
+                epsilon = 1e-12
+            );
+        }
+    }
+
+    // Interpolating into a larger space and back gives the original function
+    for (small, large) in [(&p2, &p3), (&p3, &dp3)] {
+        let up = to_dense(&interpolation_matrix(small, large));
+        let down = to_dense(&interpolation_matrix(large, small));
+        let n = small.global_size();
+        let m = large.global_size();
+        for i in 0..n {
+            for j in 0..n {
+                let entry = (0..m).map(|k| down[i + n * k] * up[k + m * j]).sum::<f64>();
+                assert_abs_diff_eq!(entry, if i == j { 1.0 } else { 0.0 }, epsilon = 1e-12);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_prolongation_and_restriction() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(0, 1, &comm);
+    let (refined, map) = refine_uniformly(&grid, None, &comm);
+    let family = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let coarse = FunctionSpace::new(&grid, &family);
+    let fine = FunctionSpace::new(&refined, &family);
+
+    let prolongation = prolongation_matrix(&coarse, &fine, &map);
+    assert_eq!(
+        prolongation.shape(),
+        [fine.global_size(), coarse.global_size()]
+    );
+    let restriction = restriction_matrix(&coarse, &fine, &map);
+    assert_eq!(
+        restriction.shape(),
+        [coarse.global_size(), fine.global_size()]
+    );
+
+    // Constant functions are preserved, and the restriction is the transpose of the prolongation
+    let n = fine.global_size();
+    let m = coarse.global_size();
+    let prolongation = to_dense(&prolongation);
+    let restriction = to_dense(&restriction);
+    for i in 0..n {
+        assert_relative_eq!(
+            (0..m).map(|j| prolongation[i + n * j]).sum::<f64>(),
+            1.0,
+            epsilon = 1e-12
+        );
+        for j in 0..m {
+            assert_relative_eq!(
+                prolongation[i + n * j],
+                restriction[j + m * i],
+                epsilon = 1e-14
+            );
+        }
+    }
+
+    // Each coarse vertex is also a fine vertex, and each fine vertex at the midpoint of a coarse edge takes the mean of
+    // the values at the ends of the edge
+    for i in 0..n {
+        let row = (0..m)
+            .map(|j| prolongation[i + n * j])
+            .filter(|a| a.abs() > 1e-12)
+            .collect::<Vec<_>>();
+        assert!(row.len() == 1 || row.len() == 2);
+        for a in &row {
+            assert_relative_eq!(*a, 1.0 / row.len() as f64, epsilon = 1e-12);
+        }
+    }
+}
+
+#[test]
+fn test_prolongation_on_zero_trace_space() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = screen_triangles::<f64, _>(2, &comm);
+    let (refined, map) = refine_uniformly(&grid, None, &comm);
+    let family = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let coarse = FunctionSpace::new(&grid, &family);
+    let fine = FunctionSpace::new(&refined, &family);
+    let zero_trace_coarse = FunctionSpace::new_zero_trace(&grid, &family);
+    let zero_trace_fine = FunctionSpace::new_zero_trace(&refined, &family);
+
+    let prolongation = to_dense(&prolongation_matrix(&coarse, &fine, &map));
+    let zero_trace_prolongation = prolongation_matrix(&zero_trace_coarse, &zero_trace_fine, &map);
+    assert_eq!(
+        zero_trace_prolongation.shape(),
+        [
+            zero_trace_fine.global_size(),
+            zero_trace_coarse.global_size()
+        ]
+    );
+    let zero_trace_prolongation = to_dense(&zero_trace_prolongation);
+    let coarse_embedding = to_dense(&interpolation_matrix(&zero_trace_coarse, &coarse));
+    let fine_embedding = to_dense(&interpolation_matrix(&zero_trace_fine, &fine));
+
+    // Prolongating a zero-trace function and then extending it by zero is the same as extending it by zero and then
+    // prolongating it
+    let [n, m] = [fine.global_size(), coarse.global_size()];
+    let [zn, zm] = [
+        zero_trace_fine.global_size(),
+        zero_trace_coarse.global_size(),
+    ];
+    for i in 0..n {
+        for j in 0..zm {
+            let a = (0..zn)
+                .map(|k| fine_embedding[i + n * k] * zero_trace_prolongation[k + zn * j])
+                .sum::<f64>();
+            let b = (0..m)
+                .map(|k| prolongation[i + n * k] * coarse_embedding[k + m * j])
+                .sum::<f64>();
+            assert_relative_eq!(a, b, epsilon = 1e-12);
+        }
+    }
+}
+
+#[test]
+fn test_incompatible_grids() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid0 = regular_sphere::<f64, _>(0, 1, &comm);
+    let grid1 = regular_sphere::<f64, _>(1, 1, &comm);
+    let family = LagrangeElementFamily::<f64>::new(1, Continuity::Standard);
+    let space0 = FunctionSpace::new(&grid0, &family);
+    let space1 = FunctionSpace::new(&grid1, &family);
+
+    assert!(matches!(
+        try_interpolation_matrix(&space0, &space1),
+        Err(BemppError::IncompatibleSpaces(_))
+    ));
+}
@@ -0,0 +1,165 @@
+use approx::*;
+use bempp::boundary_assemblers::BoundaryAssemblerOptions;
+use bempp::error::BemppError;
+use bempp::function::{FunctionSpace, FunctionSpaceTrait};
+use bempp::laplace;
+use bempp::shapes::regular_sphere;
+use mpi::environment::Universe;
+use ndelement::ciarlet::{LagrangeElementFamily, RaviartThomasElementFamily};
+use ndelement::types::{Continuity, MapType, ReferenceCellType};
+use ndgrid::traits::{Entity, Grid, Topology};
+use rlst::RawAccess;
+use std::sync::LazyLock;
+
+static MPI_UNIVERSE: LazyLock<Universe> = std::sync::LazyLock::new(|| {
+    mpi::initialize_with_threading(mpi::Threading::Multiple)
+        .unwrap()
+        .0
+});
+
+#[test]
+fn test_rt_edge_signs() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+    let ncells = grid.entity_count(ReferenceCellType::Triangle);
+
+    let rt = FunctionSpace::new(
+        &grid,
+        &RaviartThomasElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    let broken_rt = FunctionSpace::new(
+        &grid,
+        &RaviartThomasElementFamily::<f64>::new(1, Continuity::Discontinuous),
+    );
+    assert_eq!(
+        rt.map_type(ReferenceCellType::Triangle),
+        MapType::ContravariantPiola
+    );
+    assert!(broken_rt.reversed_dofs(0).iter().all(|r| !r));
+
+    // Each RT function is the sum of the broken functions on the two cells around its edge, negated on the cells where
+    // the local orientation of the edge differs from the global orientation
+    let mut broken_dofs = vec![vec![]; rt.global_size()];
+    for cell in 0..ncells {
+        let reversed = rt.reversed_dofs(cell);
+        for (i, (dof, broken_dof)) in rt
+            .cell_dofs(cell)
+            .unwrap()
+            .iter()
+            .zip(broken_rt.cell_dofs(cell).unwrap())
+            .enumerate()
+        {
+            let sign = if reversed.get(i).copied().unwrap_or(false) {
+                -1.0
+            } else {
+                1.0
+            };
+            broken_dofs[*dof].push((*broken_dof, sign));
+        }
+    }
+    for dofs in &broken_dofs {
+        assert_eq!(dofs.len(), 2);
+    }
+
+    let options = BoundaryAssemblerOptions::default();
+    let assembler = laplace::assembler::single_layer(&options);
+    let matrix = assembler.assemble(&rt, &rt);
+    let broken_matrix = assembler.assemble(&broken_rt, &broken_rt);
+
+    let n = rt.global_size();
+    let broken_n = broken_rt.global_size();
+    for j in 0..n {
+        for i in 0..n {
+            let expected = broken_dofs[i]
+                .iter()
+                .map(|(a, s_a)| {
+                    broken_dofs[j]
+                        .iter()
+                        .map(|(b, s_b)| s_a * s_b * broken_matrix.data()[a + broken_n * b])
+                        .sum::<f64>()
+                })
+                .sum::<f64>();
+            assert_relative_eq!(matrix.data()[i + n * j], expected, epsilon = 1e-12);
+        }
+    }
+}
+
+#[test]
+fn test_rt_surface_curls() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(1, 1, &comm);
+
+    let p1 = FunctionSpace::new(
+        &grid,
+        &LagrangeElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+    let rt = FunctionSpace::new(
+        &grid,
+        &RaviartThomasElementFamily::<f64>::new(1, Continuity::Standard),
+    );
+
+    // The surface curl of the P1 function at a vertex is the sum of the RT functions on the edges around the vertex,
+    // with sign 1 if the vertex is the higher numbered vertex of the edge and -1 otherwise, as the flux of the curl
+    // through an edge is the difference between the values of the P1 function at the two ends of the edge
+    let mut curls = vec![vec![]; p1.global_size()];
+    for edge in grid.entity_iter(1) {
+        let rt_dof = rt.global_dof_index(rt.get_local_dof_numbers(1, edge.local_index())[0]);
+        let vertices = edge.topology().sub_entity_iter(0).collect::<Vec<_>>();
+        let global_vertices = vertices
+            .iter()
+            .map(|v| grid.entity(0, *v).unwrap().global_index())
+            .collect::<Vec<_>>();
+        let signs = if global_vertices[0] < global_vertices[1] {
+            [(vertices[0], -1.0), (vertices[1], 1.0)]
+        } else {
+            [(vertices[0], 1.0), (vertices[1], -1.0)]
+        };
+        for (v, sign) in signs {
+            curls[p1.global_dof_index(p1.get_local_dof_numbers(0, v)[0])].push((rt_dof, sign));
+        }
+    }
+
+    let options = BoundaryAssemblerOptions::default();
+    let hypersingular = laplace::assembler::hypersingular(&options).assemble(&p1, &p1);
+    let single_layer = laplace::assembler::single_layer(&options).assemble(&rt, &rt);
+
+    let n = p1.global_size();
+    let rt_n = rt.global_size();
+    for j in 0..n {
+        for i in 0..n {
+            let expected = curls[i]
+                .iter()
+                .map(|(a, s_a)| {
+                    curls[j]
+                        .iter()
+                        .map(|(b, s_b)| s_a * s_b * single_layer.data()[a + rt_n * b])
+                        .sum::<f64>()
+                })
+                .sum::<f64>();
+            assert_relative_eq!(hypersingular.data()[i + n * j], expected, epsilon = 1e-10);
+        }
+    }
+}
+
+#[test]
+fn test_higher_degree_rt() {
+    let _ = *MPI_UNIVERSE;
+    let comm = mpi::topology::SimpleCommunicator::self_comm();
+    let grid = regular_sphere::<f64, _>(0, 1, &comm);
+
+    // The orientation of edges with more than one DOF is not supported
+    assert!(matches!(
+        FunctionSpace::try_new(
+            &grid,
+            &RaviartThomasElementFamily::<f64>::new(2, Continuity::Standard),
+        ),
+        Err(BemppError::UnsupportedElement(_))
+    ));
+    assert!(FunctionSpace::try_new(
+        &grid,
+        &RaviartThomasElementFamily::<f64>::new(2, Continuity::Discontinuous),
+    )
+    .is_ok());
+}